@@ -15,7 +15,15 @@ use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 
 use super::{default_db_path, default_log_path};
-use super::{LogLevel, MatchMode, SortMode, TagsSortMode, DEFAULT_EDITOR};
+use crate::command::audit::{DEFAULT_MAX_AGE_DAYS, DEFAULT_MIN_ENTROPY};
+use crate::command::clipboard::DEFAULT_CLEAR_AFTER;
+use crate::command::gc::DEFAULT_RETENTION_DAYS;
+use crate::command::sync::discovery::DEFAULT_DISCOVERY_PORT;
+use crate::command::sync::transport::DEFAULT_REMOTE_COMMAND;
+use super::{
+    ClipboardBackend, ConflictPolicy, LogLevel, MatchMode, SortMode,
+    TagsSortMode, DEFAULT_EDITOR
+};
 
 ///
 /// コンフィギュレーションデータを集約する構造体
@@ -36,6 +44,15 @@ pub(super) struct Config {
 
     /// tagsサブコマンド用の設定
     tags: Option<TagsInfo>,
+
+    /// syncサブコマンド用の設定
+    sync: Option<SyncInfo>,
+
+    /// gcサブコマンド用の設定
+    gc: Option<GcInfo>,
+
+    /// auditサブコマンド用の設定
+    audit: Option<AuditInfo>,
 }
 
 impl Config {
@@ -103,6 +120,20 @@ impl Config {
             .and_then(|query| query.masked_mode)
     }
 
+    ///
+    /// queryサブコマンドのクリップボードを消去するまでの秒数へのアクセサ
+    ///
+    pub(super) fn query_clear_after(&self) -> Option<u64> {
+        self.query.as_ref().and_then(|query| query.clear_after)
+    }
+
+    ///
+    /// queryサブコマンドのクリップボードのバックエンドへのアクセサ
+    ///
+    pub(super) fn query_clipboard(&self) -> Option<ClipboardBackend> {
+        self.query.as_ref().and_then(|query| query.clipboard)
+    }
+
     ///
     /// searchサブコマンドでサービス名を検索対象に含めるかのアクセサ
     ///
@@ -202,6 +233,63 @@ impl Config {
         self.tags.as_ref().and_then(|tags| tags.match_mode)
     }
 
+    ///
+    /// syncサブコマンドで用いる事前共有鍵ファイルへのパスへのアクセサ
+    ///
+    pub(super) fn sync_psk_file(&self) -> Option<PathBuf> {
+        self.sync.as_ref().and_then(|sync| sync.psk_file.clone())
+    }
+
+    ///
+    /// syncサブコマンドのサーバモードで同期を許可するノードIDへのアクセサ
+    ///
+    pub(super) fn sync_allowed_peers(&self) -> Option<Vec<String>> {
+        self.sync.as_ref().and_then(|sync| sync.allowed_peers.clone())
+    }
+
+    ///
+    /// syncサブコマンドの競合時の解決方針へのアクセサ
+    ///
+    pub(super) fn sync_conflict(&self) -> Option<ConflictPolicy> {
+        self.sync.as_ref().and_then(|sync| sync.conflict)
+    }
+
+    ///
+    /// syncサブコマンドの探索に用いるUDPポート番号へのアクセサ
+    ///
+    pub(super) fn sync_discovery_port(&self) -> Option<u16> {
+        self.sync.as_ref().and_then(|sync| sync.discovery_port)
+    }
+
+    ///
+    /// syncサブコマンドのSSH経由の同期でリモートホストで実行するコマンドへの
+    /// アクセサ
+    ///
+    pub(super) fn sync_remote_command(&self) -> Option<String> {
+        self.sync.as_ref().and_then(|sync| sync.remote_command.clone())
+    }
+
+    ///
+    /// gcサブコマンドの墓標を保持する期間(日数)へのアクセサ
+    ///
+    pub(super) fn gc_retention_days(&self) -> Option<u32> {
+        self.gc.as_ref().and_then(|gc| gc.retention_days)
+    }
+
+    ///
+    /// auditサブコマンドの古いとみなす最終更新からの経過日数へのアクセサ
+    ///
+    pub(super) fn audit_max_age_days(&self) -> Option<u32> {
+        self.audit.as_ref().and_then(|audit| audit.max_age_days)
+    }
+
+    ///
+    /// auditサブコマンドの弱いとみなすエントロピー推定値の閾値へのアクセサ
+    ///
+    pub(super) fn audit_min_entropy(&self) -> Option<u32> {
+        self.audit.as_ref().and_then(|audit| audit.min_entropy)
+    }
+
     ///
     /// コンフィギュレーション情報の保存
     ///
@@ -235,6 +323,8 @@ impl Default for Config {
             query: Some(QueryInfo {
                 match_mode: Some(MatchMode::Contains),
                 masked_mode: Some(false),
+                clear_after: Some(DEFAULT_CLEAR_AFTER),
+                clipboard: Some(ClipboardBackend::Auto),
             }),
             search: Some(SearchInfo {
                 with_service_name: Some(false),
@@ -255,6 +345,20 @@ impl Default for Config {
                 reverse_sort: Some(false),
                 match_mode: Some(MatchMode::Contains),
             }),
+            sync: Some(SyncInfo {
+                psk_file: None,
+                allowed_peers: Some(vec![]),
+                conflict: Some(ConflictPolicy::Prompt),
+                discovery_port: Some(DEFAULT_DISCOVERY_PORT),
+                remote_command: Some(DEFAULT_REMOTE_COMMAND.to_string()),
+            }),
+            gc: Some(GcInfo {
+                retention_days: Some(DEFAULT_RETENTION_DAYS),
+            }),
+            audit: Some(AuditInfo {
+                max_age_days: Some(DEFAULT_MAX_AGE_DAYS),
+                min_entropy: Some(DEFAULT_MIN_ENTROPY),
+            }),
         }
     }
 }
@@ -297,6 +401,12 @@ struct QueryInfo {
 
     /// 秘匿項目をマスク表示するか否か
     masked_mode: Option<bool>,
+
+    /// コピーした値をクリップボードから消去するまでの秒数
+    clear_after: Option<u64>,
+
+    /// クリップボードのバックエンド
+    clipboard: Option<ClipboardBackend>,
 }
 
 ///
@@ -356,6 +466,48 @@ struct TagsInfo {
     match_mode: Option<MatchMode>,
 }
 
+///
+/// syncサブコマンドの設定情報
+///
+#[derive(Debug, Deserialize, Serialize)]
+struct SyncInfo {
+    /// 事前共有鍵を格納したファイルへのパス
+    psk_file: Option<PathBuf>,
+
+    /// サーバモードで同期を許可するノードID（空の場合は制限しない）
+    allowed_peers: Option<Vec<String>>,
+
+    /// 競合時の解決方針
+    conflict: Option<ConflictPolicy>,
+
+    /// 探索に用いるUDPポート番号
+    discovery_port: Option<u16>,
+
+    /// SSH経由の同期でリモートホストで実行するコマンド
+    remote_command: Option<String>,
+}
+
+///
+/// gcサブコマンドの設定情報
+///
+#[derive(Debug, Deserialize, Serialize)]
+struct GcInfo {
+    /// 墓標を保持する期間(日数)
+    retention_days: Option<u32>,
+}
+
+///
+/// auditサブコマンドの設定情報
+///
+#[derive(Debug, Deserialize, Serialize)]
+struct AuditInfo {
+    /// 古いとみなす最終更新からの経過日数
+    max_age_days: Option<u32>,
+
+    /// 弱いとみなすエントロピー推定値(ビット)の閾値
+    min_entropy: Option<u32>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -375,6 +527,8 @@ mod tests {
             Some(MatchMode::Contains)
         );
         assert_eq!(config.query_masked_mode(), Some(false));
+        assert_eq!(config.query_clear_after(), Some(DEFAULT_CLEAR_AFTER));
+        assert_eq!(config.query_clipboard(), Some(ClipboardBackend::Auto));
 
         assert_eq!(
             config.search_with_service_name(),
@@ -409,6 +563,23 @@ mod tests {
         );
         assert_eq!(config.tags_reverse_sort(), Some(false));
         assert_eq!(config.tags_match_mode(), Some(MatchMode::Contains));
+
+        assert_eq!(config.sync_psk_file(), None);
+        assert_eq!(config.sync_allowed_peers(), Some(vec![]));
+        assert_eq!(config.sync_conflict(), Some(ConflictPolicy::Prompt));
+        assert_eq!(
+            config.sync_discovery_port(),
+            Some(DEFAULT_DISCOVERY_PORT)
+        );
+        assert_eq!(
+            config.sync_remote_command(),
+            Some(DEFAULT_REMOTE_COMMAND.to_string())
+        );
+
+        assert_eq!(config.gc_retention_days(), Some(DEFAULT_RETENTION_DAYS));
+
+        assert_eq!(config.audit_max_age_days(), Some(DEFAULT_MAX_AGE_DAYS));
+        assert_eq!(config.audit_min_entropy(), Some(DEFAULT_MIN_ENTROPY));
     }
 
     #[test]
@@ -423,6 +594,8 @@ editor = "vim"
 [query]
 match_mode = "regex"
 masked_mode = true
+clear_after = 20
+clipboard = "wl-copy"
 
 [search]
 with_service_name = true
@@ -442,6 +615,20 @@ with_number = true
 sort_mode = "number_of_regist"
 reverse_sort = true
 match_mode = "fuzzy"
+
+[sync]
+psk_file = "./sync.psk"
+allowed_peers = ["01J1M8Z6Y1Y1Y1Y1Y1Y1Y1Y1Y1"]
+conflict = "prefer-client"
+discovery_port = 3457
+remote_command = "/opt/pwmgr/bin/pwmgr sync --stdio"
+
+[gc]
+retention_days = 7
+
+[audit]
+max_age_days = 180
+min_entropy = 72
 "#;
 
         let config: Config = toml::from_str(toml).expect("toml parse failed");
@@ -459,6 +646,8 @@ match_mode = "fuzzy"
             Some(MatchMode::Regex)
         );
         assert_eq!(config.query_masked_mode(), Some(true));
+        assert_eq!(config.query_clear_after(), Some(20));
+        assert_eq!(config.query_clipboard(), Some(ClipboardBackend::WlCopy));
 
         assert_eq!(
             config.search_with_service_name(),
@@ -490,5 +679,25 @@ match_mode = "fuzzy"
         );
         assert_eq!(config.tags_reverse_sort(), Some(true));
         assert_eq!(config.tags_match_mode(), Some(MatchMode::Fuzzy));
+
+        assert_eq!(
+            config.sync_psk_file(),
+            Some(PathBuf::from("./sync.psk"))
+        );
+        assert_eq!(
+            config.sync_allowed_peers(),
+            Some(vec!["01J1M8Z6Y1Y1Y1Y1Y1Y1Y1Y1Y1".to_string()])
+        );
+        assert_eq!(config.sync_conflict(), Some(ConflictPolicy::PreferClient));
+        assert_eq!(config.sync_discovery_port(), Some(3457));
+        assert_eq!(
+            config.sync_remote_command(),
+            Some("/opt/pwmgr/bin/pwmgr sync --stdio".to_string())
+        );
+
+        assert_eq!(config.gc_retention_days(), Some(7));
+
+        assert_eq!(config.audit_max_age_days(), Some(180));
+        assert_eq!(config.audit_min_entropy(), Some(72));
     }
 }
@@ -15,17 +15,28 @@ use std::fs::File;
 use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, LazyLock};
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use directories::BaseDirs;
+use log::debug;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
 
+#[cfg(unix)]
+use crate::command::agent::{self, AGENT_SOCK_ENV};
+use crate::command::prompt::{
+    new_passphrase, read_passphrase, PASSPHRASE_ENV
+};
+use crate::command::clipboard::{self, DEFAULT_CLEAR_AFTER};
 use crate::command::{
-    add, edit, export, import, list, query, remove, search, sync, tags,
+    add, audit, diff, edit, export, gc, generate, generator, history, import,
+    list, query, rekey, remove, revert, search, sync, tags, totp, undo,
     CommandContext
 };
+use crate::database::types::{CharClass, KdfParams};
 use crate::database::EntryManager;
 use config::Config;
 
@@ -86,6 +97,21 @@ fn default_db_path() -> PathBuf {
     DEFAULT_DATA_PATH.join("database.redb")
 }
 
+///
+/// デフォルトのエージェントのソケットのパスを生成
+///
+/// # 戻り値
+/// ランタイムディレクトリ(XDG_RUNTIME_DIR)が利用可能な場合はその配下の、利
+/// 用できない場合はデータディレクトリ配下のソケットのパス情報
+///
+fn default_agent_sock_path() -> PathBuf {
+    BaseDirs::new()
+        .and_then(|dirs| dirs.runtime_dir().map(|dir| dir.to_path_buf()))
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+        .unwrap_or_else(|| DEFAULT_DATA_PATH.clone())
+        .join("agent.sock")
+}
+
 ///
 /// デフォルトのログ出力先のパスを生成
 ///
@@ -262,19 +288,106 @@ impl Options {
     }
 
     ///
-    /// データベースのオープン
+    /// データベースのオープン(解錠あり)
+    ///
+    /// # 戻り値
+    /// オープンと解錠に成功した場合はデータベースオブジェクトを`Ok()`でラップ
+    /// して返す。失敗した場合はエラー情報を`Err()`でラップして返す。
+    ///
+    /// # 注記
+    /// エージェントが起動しており鍵をキャッシュしている場合はその鍵で解錠す
+    /// る。それ以外の場合、マスタパスフレーズは環境変数PWMGR_PASSPHRASEが設定
+    /// されていればその値を、未設定であれば端末から入力させたものを用い、解錠
+    /// に用いた鍵をエージェントに登録する。秘匿プロパティの平文を扱うサブコマ
+    /// ンドはこちらを用いること。
+    ///
+    pub(crate) fn open(&self) -> Result<EntryManager> {
+        let mut mgr = self.open_locked()?;
+
+        #[cfg(unix)]
+        if !mgr.is_unlocked() {
+            self.unlock_by_agent(&mut mgr);
+        }
+
+        if !mgr.is_unlocked() {
+            let pass = match std::env::var(PASSPHRASE_ENV) {
+                Ok(pass) => Zeroizing::new(pass),
+                Err(_) => read_passphrase("パスフレーズ: ")?,
+            };
+
+            mgr.unlock(&pass)?;
+
+            #[cfg(unix)]
+            self.cache_key_to_agent(&mgr);
+        }
+
+        Ok(mgr)
+    }
+
+    ///
+    /// データベースのオープン(パスフレーズによる解錠)
+    ///
+    /// # 戻り値
+    /// オープンと解錠に成功した場合はデータベースオブジェクトを`Ok()`でラップ
+    /// して返す。失敗した場合はエラー情報を`Err()`でラップして返す。
+    ///
+    /// # 注記
+    /// `open()`と異なりエージェントにキャッシュされた鍵は用いず、常に現在の
+    /// マスタパスフレーズ(環境変数PWMGR_PASSPHRASEもしくは端末からの入力)で
+    /// 解錠する。解錠に用いた鍵のエージェントへの登録も行わない。パスフレー
+    /// ズの確認を必要とするサブコマンド(rekey)はこちらを用いること。
+    ///
+    pub(crate) fn open_by_passphrase(&self) -> Result<EntryManager> {
+        let mut mgr = self.open_locked()?;
+
+        if !mgr.is_unlocked() {
+            let pass = match std::env::var(PASSPHRASE_ENV) {
+                Ok(pass) => Zeroizing::new(pass),
+                Err(_) => read_passphrase("現在のパスフレーズ: ")?,
+            };
+
+            mgr.unlock(&pass)?;
+        }
+
+        Ok(mgr)
+    }
+
+    ///
+    /// データベースのオープン(解錠なし)
     ///
     /// # 戻り値
     /// オープンに成功した場合はデータベースオブジェクトを`Ok()`でラップして返
     /// す。失敗した場合はエラー情報を`Err()`でラップして返す。
     ///
-    pub(crate) fn open(&self) -> Result<EntryManager> {
-        match EntryManager::open(self.db_path()) {
-            Ok(mgr) => Ok(mgr),
-            Err(err) => Err(
+    /// # 注記
+    /// 秘匿プロパティは暗号化されたままとなるため、秘匿プロパティの平文を必要
+    /// としないサブコマンドはこちらを用いる。ただし暗号化の初期化が済んでいな
+    /// いデータベースの場合は、新たに設定するマスタパスフレーズ(環境変数
+    /// PWMGR_PASSPHRASEもしくは端末からの入力)で初期化を行う。
+    ///
+    pub(crate) fn open_locked(&self) -> Result<EntryManager> {
+        let mut mgr = match EntryManager::open(self.db_path()) {
+            Ok(mgr) => mgr,
+            Err(err) => return Err(
                 anyhow!("open failed: {}", err).context("database open")
             )
+        };
+
+        if !mgr.is_initialized()? {
+            let pass = new_passphrase(
+                PASSPHRASE_ENV,
+                "新規に設定するパスフレーズ: "
+            )?;
+
+            mgr.initialize(&pass, KdfParams::default())?;
+        }
+
+        // 書き込みの変更前の状態をサブコマンド名で操作ジャーナルに記録する
+        if let Some(command) = self.command.as_ref().filter(|c| c.is_journaled()) {
+            mgr.set_operation(command.name());
         }
+
+        Ok(mgr)
     }
 
     ///
@@ -297,6 +410,72 @@ impl Options {
         }
     }
 
+    ///
+    /// エージェントのソケットのパスへのアクセサ
+    ///
+    /// # 戻り値
+    /// 環境変数PWMGR_AGENT_SOCKが設定されている場合はその値を、未設定の場合は
+    /// デフォルトのパスを返す。
+    ///
+    #[cfg(unix)]
+    pub(crate) fn agent_sock_path(&self) -> PathBuf {
+        match std::env::var_os(AGENT_SOCK_ENV) {
+            Some(path) => PathBuf::from(path),
+            None => default_agent_sock_path(),
+        }
+    }
+
+    ///
+    /// エージェントでデータベースを識別するための文字列
+    ///
+    #[cfg(unix)]
+    fn agent_db_id(&self) -> String {
+        let path = self.db_path();
+        std::fs::canonicalize(&path)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    ///
+    /// エージェントにキャッシュされた鍵での解錠
+    ///
+    /// # 注記
+    /// エージェントが起動していない場合や、鍵がデータベースに対応しない場合
+    /// (パスフレーズ変更後など)は何もしない。
+    ///
+    #[cfg(unix)]
+    fn unlock_by_agent(&self, mgr: &mut EntryManager) {
+        let key = match agent::fetch_key(&self.agent_sock_path(), &self.agent_db_id()) {
+            Ok(Some(key)) => key,
+            Ok(None) => return,
+            Err(err) => {
+                debug!("agent unavailable: {:#}", err);
+                return;
+            }
+        };
+
+        if let Err(err) = mgr.unlock_with_key(key) {
+            debug!("cached key rejected: {:#}", err);
+        }
+    }
+
+    ///
+    /// 解錠に用いた鍵のエージェントへの登録
+    ///
+    /// # 注記
+    /// 同じデータベースに対する鍵が既に登録されている場合は置き換える。
+    ///
+    #[cfg(unix)]
+    pub(crate) fn cache_key_to_agent(&self, mgr: &EntryManager) {
+        if let Some(key) = mgr.vault_key() {
+            let sock = self.agent_sock_path();
+            if let Err(err) = agent::store_key(&sock, &self.agent_db_id(), key) {
+                debug!("agent unavailable: {:#}", err);
+            }
+        }
+    }
+
     ///
     /// JSON出力の指定有無を返す
     ///
@@ -376,6 +555,10 @@ impl Options {
                     Some(Command::Search(opts)) => Some(opts),
                     Some(Command::List(opts)) => Some(opts),
                     Some(Command::Tags(opts)) => Some(opts),
+                    Some(Command::Sync(opts)) => Some(opts),
+                    Some(Command::Gc(opts)) => Some(opts),
+                    Some(Command::Audit(opts)) => Some(opts),
+                    Some(Command::Totp(opts)) => Some(opts),
                     _ => None,
                 };
 
@@ -410,6 +593,7 @@ impl Options {
                 Command::Search(opts) => Some(opts),
                 Command::Import(opts) => Some(opts),
                 Command::Sync(opts) => Some(opts),
+                Command::Generate(opts) => Some(opts),
                 _ => None
             };
 
@@ -457,6 +641,11 @@ impl Options {
                 Command::Export(opts) => Some(opts),
                 Command::Import(opts) => Some(opts),
                 Command::Sync(opts) => Some(opts),
+                Command::Gc(opts) => Some(opts),
+                Command::Audit(opts) => Some(opts),
+                Command::Totp(opts) => Some(opts),
+                #[cfg(unix)]
+                Command::Agent(opts) => Some(opts),
                 _ => None,
             };
 
@@ -481,7 +670,21 @@ impl Options {
             Some(Command::Export(opts)) => export::build_context(self, opts),
             Some(Command::Import(opts)) => import::build_context(self, opts),
             Some(Command::Remove(opts)) => remove::build_context(self, opts),
+            Some(Command::History(opts)) => history::build_context(self, opts),
+            Some(Command::Revert(opts)) => revert::build_context(self, opts),
+            Some(Command::Diff(opts)) => diff::build_context(self, opts),
+            Some(Command::Undo(opts)) => undo::build_context(self, opts),
+            Some(Command::Generate(opts)) => generate::build_context(self, opts),
+            Some(Command::Totp(opts)) => totp::build_context(self, opts),
             Some(Command::Sync(opts)) => sync::build_context(self, opts),
+            Some(Command::Gc(opts)) => gc::build_context(self, opts),
+            Some(Command::Audit(opts)) => audit::build_context(self, opts),
+            Some(Command::Rekey) => rekey::build_context(self),
+            #[cfg(unix)]
+            Some(Command::Agent(opts)) => agent::build_context(self, opts),
+            Some(Command::ClipboardClear(opts)) => {
+                clipboard::build_context(self, opts)
+            }
             None => Err(anyhow!("command not specified")),
         }
     }
@@ -520,6 +723,25 @@ enum Command {
     #[command(alias = "r", visible_alias = "rm")]
     Remove(RemoveOpts),
 
+    /// エントリの版の一覧
+    History(HistoryOpts),
+
+    /// エントリを過去の版に戻す
+    Revert(RevertOpts),
+
+    /// エントリの版の間の差分
+    Diff(DiffOpts),
+
+    /// 直前の操作の取り消し
+    Undo(UndoOpts),
+
+    /// パスワードの生成
+    #[command(alias = "gen")]
+    Generate(GenerateOpts),
+
+    /// ワンタイムパスワード(TOTP/HOTP)の生成
+    Totp(TotpOpts),
+
     /// バックアップ用YAMLの出力
     Export(ExportOpts),
 
@@ -528,6 +750,75 @@ enum Command {
 
     /// 他ホストとのデータベース同期
     Sync(SyncOpts),
+
+    /// 削除済みエントリ(墓標)の回収
+    Gc(GcOpts),
+
+    /// 弱い・使い回された・古い秘匿情報の監査
+    Audit(AuditOpts),
+
+    /// マスタパスフレーズの変更(全エントリの再暗号化)
+    Rekey,
+
+    /// 解錠済みの鍵をキャッシュするエージェントの操作
+    #[cfg(unix)]
+    Agent(AgentOpts),
+
+    /// コピーした値のクリップボードからの消去(内部用)
+    #[command(hide = true)]
+    ClipboardClear(ClipboardClearOpts),
+}
+
+impl Command {
+    ///
+    /// サブコマンド名の取得
+    ///
+    /// # 注記
+    /// 操作ジャーナルに記録する操作名として用いる。
+    ///
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Query(_) => "query",
+            Self::Search(_) => "search",
+            Self::Add(_) => "add",
+            Self::Edit(_) => "edit",
+            Self::List(_) => "list",
+            Self::Tags(_) => "tags",
+            Self::Remove(_) => "remove",
+            Self::History(_) => "history",
+            Self::Revert(_) => "revert",
+            Self::Diff(_) => "diff",
+            Self::Undo(_) => "undo",
+            Self::Generate(_) => "generate",
+            Self::Totp(_) => "totp",
+            Self::Export(_) => "export",
+            Self::Import(_) => "import",
+            Self::Sync(_) => "sync",
+            Self::Gc(_) => "gc",
+            Self::Audit(_) => "audit",
+            Self::Rekey => "rekey",
+            #[cfg(unix)]
+            Self::Agent(_) => "agent",
+            Self::ClipboardClear(_) => "clipboard-clear",
+        }
+    }
+
+    ///
+    /// 操作ジャーナルへの記録の対象か否かの判定
+    ///
+    /// # 注記
+    /// 利用者の操作でエントリを書き換えるサブコマンドのみを対象とする。定期
+    /// 的に実行されるsyncや、HOTPのカウンタを進めるtotpを記録すると、取り消
+    /// したい操作の記録が上限により破棄されたり、使用済みのカウンタに戻され
+    /// たりするため対象としない。
+    ///
+    fn is_journaled(&self) -> bool {
+        matches!(
+            self,
+            Self::Add(_) | Self::Edit(_) | Self::Remove(_) | Self::Revert(_) |
+            Self::Import(_) | Self::Gc(_)
+        )
+    }
 }
 
 ///
@@ -615,6 +906,27 @@ pub(crate) struct QueryOpts {
     )]
     match_mode: Option<MatchMode>,
 
+    /// 指定したプロパティの値を表示せずにクリップボードにコピー
+    #[arg(
+        short = 'c',
+        long = "copy",
+        value_name = "PROPERTY",
+        conflicts_with_all = ["full", "masked_mode"]
+    )]
+    copy: Option<String>,
+
+    /// コピーした値をクリップボードから消去するまでの秒数(0で消去しない)
+    #[arg(long = "clear-after", value_name = "SECS")]
+    clear_after: Option<u64>,
+
+    /// クリップボードのバックエンド
+    #[arg(long = "clipboard", value_enum, value_name = "BACKEND")]
+    clipboard: Option<ClipboardBackend>,
+
+    /// 内容を確認できないバックエンド(osc52)でも自動消去を行う
+    #[arg(long = "force-clear", requires = "copy")]
+    force_clear: bool,
+
     /// マスクモードのデフォルト値(config適用後に保持)
     #[arg(skip)]
     default_masked: Option<bool>,
@@ -662,6 +974,34 @@ impl QueryOpts {
         self.full
     }
 
+    ///
+    /// クリップボードにコピーするプロパティ名へのアクセサ
+    ///
+    pub(crate) fn copy(&self) -> Option<String> {
+        self.copy.clone()
+    }
+
+    ///
+    /// クリップボードを消去するまでの秒数へのアクセサ
+    ///
+    pub(crate) fn clear_after(&self) -> u64 {
+        self.clear_after.unwrap_or(DEFAULT_CLEAR_AFTER)
+    }
+
+    ///
+    /// クリップボードのバックエンドへのアクセサ
+    ///
+    pub(crate) fn clipboard(&self) -> ClipboardBackend {
+        self.clipboard.unwrap_or(ClipboardBackend::Auto)
+    }
+
+    ///
+    /// 内容を確認できないバックエンドでも自動消去を行うか否か
+    ///
+    pub(crate) fn is_force_clear(&self) -> bool {
+        self.force_clear
+    }
+
     ///
     /// テスト用のコンストラクタ
     ///
@@ -677,6 +1017,10 @@ impl QueryOpts {
             masked_mode: false,
             unmasked_mode: false,
             match_mode: Some(match_mode),
+            copy: None,
+            clear_after: None,
+            clipboard: None,
+            force_clear: false,
             default_masked: None,
             key: key.into(),
         }
@@ -700,6 +1044,10 @@ impl QueryOpts {
             masked_mode,
             unmasked_mode,
             match_mode: Some(match_mode),
+            copy: None,
+            clear_after: None,
+            clipboard: None,
+            force_clear: false,
             default_masked,
             key: key.into(),
         }
@@ -731,6 +1079,14 @@ impl ApplyConfig for QueryOpts {
         if self.default_masked.is_none() {
             self.default_masked = config.query_masked_mode();
         }
+
+        if self.clear_after.is_none() {
+            self.clear_after = config.query_clear_after();
+        }
+
+        if self.clipboard.is_none() {
+            self.clipboard = config.query_clipboard();
+        }
     }
 }
 
@@ -741,6 +1097,13 @@ impl ShowOptions for QueryOpts {
         println!("   key:   {}", self.key());
         println!("   mode:  {:?}", self.match_mode());
         println!("   mask:  {}", self.is_masked());
+
+        if let Some(property) = self.copy() {
+            println!("   copy:  {}", property);
+            println!("   clear: {}s", self.clear_after());
+            println!("   clipboard: {:?}", self.clipboard());
+            println!("   force clear: {}", self.is_force_clear());
+        }
     }
 }
 
@@ -1438,6 +1801,135 @@ pub(crate) struct SyncOpts {
         conflicts_with = "server_addr"
     )]
     client_addr: Option<String>,
+
+    /// 事前共有鍵を格納したファイルへのパス
+    #[arg(short = 'k', long = "psk-file", value_name = "FILE")]
+    psk_file: Option<PathBuf>,
+
+    /// 前回の同期位置を用いず全件を同期する
+    #[arg(short = 'F', long = "full")]
+    full: bool,
+
+    /// サーバモードで常駐し、複数のクライアントを順に受け付ける
+    #[arg(short = 'D', long = "daemon")]
+    daemon: bool,
+
+    /// サーバモードで同期を許可するノードID（複数指定可）
+    #[arg(short = 'a', long = "allow", value_name = "NODE-ID")]
+    allow: Vec<String>,
+
+    /// 競合時の解決方針
+    #[arg(long = "conflict", value_enum, value_name = "POLICY")]
+    conflict: Option<ConflictPolicy>,
+
+    /// 交換のみを行い、双方のデータベースに反映せずに結果を表示する
+    #[arg(long = "dry-run")]
+    dry_run: bool,
+
+    /// 探索で見つかった指定の相手(ノードIDもしくはラベル)にクライアントと
+    /// して接続する
+    #[arg(short = 'p', long = "peer", value_name = "PEER")]
+    peer: Option<String>,
+
+    /// ローカルネットワーク上で待ち受け中の同期相手を探索して一覧表示する
+    #[arg(long = "discover")]
+    discover: bool,
+
+    /// 探索に用いるUDPポート番号
+    #[arg(long = "discovery-port", value_name = "PORT")]
+    discovery_port: Option<u16>,
+
+    /// 共有ディレクトリ内の同期ファイルを介して同期する
+    #[arg(long = "folder", value_name = "DIR")]
+    folder: Option<PathBuf>,
+
+    /// SSHでリモートホストのpwmgrを起動し、その標準入出力を介して同期する
+    #[arg(long = "ssh", value_name = "[USER@]HOST")]
+    ssh: Option<String>,
+
+    /// SSH経由の同期でリモートホストで実行するコマンド
+    #[arg(long = "remote-command", value_name = "COMMAND")]
+    remote_command: Option<String>,
+
+    /// 標準入出力でサーバとして同期する(`--ssh`の接続先で用いる)
+    #[arg(long = "stdio")]
+    stdio: bool,
+
+    /// 実行する操作(省略時は同期)
+    #[command(subcommand)]
+    action: Option<SyncAction>,
+}
+
+///
+/// syncサブコマンドの操作
+///
+#[derive(Clone, Debug, Subcommand)]
+pub(crate) enum SyncAction {
+    /// 同期相手とのペアリング
+    Pair(SyncPairOpts),
+
+    /// ペアリング済み同期相手の管理
+    Peers {
+        #[command(subcommand)]
+        action: PeersAction,
+    },
+}
+
+///
+/// sync pairのオプション
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct SyncPairOpts {
+    /// サーバモードでの待ち受けアドレス（省略可）
+    #[arg(
+        short = 's',
+        long = "server",
+        value_name = "BIND-ADDR[:PORT]",
+        num_args = 0..=1,
+        default_missing_value = "0.0.0.0:2456",
+        conflicts_with = "client_addr"
+    )]
+    server_addr: Option<String>,
+
+    /// クライアントモードで接続するアドレス
+    #[arg(
+        short = 'c',
+        long = "client",
+        value_name = "CONNECT-ADDR[:PORT]",
+        conflicts_with = "server_addr"
+    )]
+    client_addr: Option<String>,
+
+    /// 相手に付けるラベル(`--peer`等でノードIDの代わりに指定できる)
+    #[arg(short = 'l', long = "label", value_name = "LABEL")]
+    label: Option<String>,
+}
+
+///
+/// sync peersの操作
+///
+#[derive(Clone, Debug, Subcommand)]
+pub(crate) enum PeersAction {
+    /// ペアリング済み同期相手の一覧
+    List,
+
+    /// ペアリング済み同期相手の削除
+    Remove {
+        /// 削除する相手のノードIDもしくはラベル
+        #[arg(value_name = "PEER")]
+        peer: String,
+    },
+
+    /// ペアリング済み同期相手のラベルの設定
+    Label {
+        /// 対象の相手のノードIDもしくはラベル
+        #[arg(value_name = "PEER")]
+        peer: String,
+
+        /// 設定するラベル(省略時はラベルを削除)
+        #[arg(value_name = "LABEL")]
+        label: Option<String>,
+    },
 }
 
 impl SyncOpts {
@@ -1445,6 +1937,15 @@ impl SyncOpts {
     /// アドレス文字列のバリデーション
     ///
     fn validate_addr(addr: &str) -> Result<()> {
+        // Unixドメインソケットはパスのみを確認する
+        if let Some(path) = addr.strip_prefix(sync::transport::UNIX_PREFIX) {
+            if path.is_empty() {
+                return Err(anyhow!("empty socket path: {}", addr));
+            }
+
+            return Ok(());
+        }
+
         static ADDR_RE: LazyLock<Regex> = LazyLock::new(|| {
             // ホスト部(英数字/ドット/ハイフン/アスタリスク) + 任意のポート
             Regex::new(r"^[A-Za-z0-9*](?:[A-Za-z0-9.-]*[A-Za-z0-9])?(?::\d{1,5})?$")
@@ -1474,44 +1975,90 @@ impl SyncOpts {
     /// 同期の動作モード
     ///
     pub(crate) fn mode(&self) -> Result<SyncMode> {
-        if let Some(addr) = &self.server_addr {
-            Ok(SyncMode::Server(addr.clone()))
-        } else if let Some(addr) = &self.client_addr {
-            Ok(SyncMode::Client(addr.clone()))
-        } else {
-            Err(anyhow!("either --server or --client must be specified"))
+        match &self.action {
+            None => {
+                if let Some(addr) = &self.server_addr {
+                    if self.daemon {
+                        Ok(SyncMode::Daemon(addr.clone()))
+                    } else {
+                        Ok(SyncMode::Server(addr.clone()))
+                    }
+                } else if let Some(addr) = &self.client_addr {
+                    Ok(SyncMode::Client(addr.clone()))
+                } else if let Some(peer) = &self.peer {
+                    Ok(SyncMode::ClientByNode(peer.clone()))
+                } else if let Some(dir) = &self.folder {
+                    Ok(SyncMode::Folder(dir.clone()))
+                } else if let Some(destination) = &self.ssh {
+                    Ok(SyncMode::Ssh(destination.clone()))
+                } else if self.stdio {
+                    Ok(SyncMode::Stdio)
+                } else if self.discover {
+                    Ok(SyncMode::Discover)
+                } else {
+                    Err(anyhow!(
+                        "either --server, --client, --peer, --folder, --ssh, \
+                         --stdio or --discover must be specified"
+                    ))
+                }
+            }
+
+            Some(SyncAction::Pair(opts)) => {
+                if let Some(addr) = &opts.server_addr {
+                    Ok(SyncMode::PairServer(addr.clone()))
+                } else if let Some(addr) = &opts.client_addr {
+                    Ok(SyncMode::PairClient(addr.clone()))
+                } else {
+                    Err(anyhow!("either --server or --client must be specified"))
+                }
+            }
+
+            Some(SyncAction::Peers { action: PeersAction::List }) => {
+                Ok(SyncMode::PeersList)
+            }
+
+            Some(SyncAction::Peers { action: PeersAction::Remove { peer } }) => {
+                Ok(SyncMode::PeersRemove(peer.clone()))
+            }
+
+            Some(SyncAction::Peers {
+                action: PeersAction::Label { peer, label }
+            }) => {
+                Ok(SyncMode::PeersLabel(peer.clone(), label.clone()))
+            }
         }
     }
-}
-
-///
-/// syncモードを表す列挙
-///
-#[derive(Clone, Debug)]
-pub(crate) enum SyncMode {
-    /// サーバとして待ち受け
-    Server(String),
 
-    /// クライアントとして接続
-    Client(String),
-}
+    ///
+    /// `--server`/`--client`以外の動作モードが指定されているか否か
+    ///
+    fn has_extra_mode(&self) -> bool {
+        self.peer.is_some()
+            || self.folder.is_some()
+            || self.ssh.is_some()
+            || self.stdio
+            || self.discover
+    }
 
-// ShowOptionsトレイトの実装
-impl ShowOptions for SyncOpts {
-    fn show_options(&self) {
-        println!("sync command options");
-        match self.mode() {
-            Ok(SyncMode::Server(addr)) => println!("   mode: server @ {}", addr),
-            Ok(SyncMode::Client(addr)) => println!("   mode: client -> {}", addr),
-            Err(err) => println!("   mode: invalid ({})", err),
+    ///
+    /// 同期相手のラベルのバリデーション
+    ///
+    fn validate_label(label: Option<&String>) -> Result<()> {
+        match label {
+            Some(label) if label.trim().is_empty() => {
+                Err(anyhow!("空のラベルは指定できません"))
+            }
+            _ => Ok(()),
         }
     }
-}
 
-// Validateトレイトの実装
-impl Validate for SyncOpts {
-    fn validate(&mut self) -> Result<()> {
-        match (self.server_addr.as_ref(), self.client_addr.as_ref()) {
+    ///
+    /// サーバ/クライアント指定のバリデーション
+    ///
+    fn validate_role(server_addr: Option<&String>, client_addr: Option<&String>)
+        -> Result<()>
+    {
+        match (server_addr, client_addr) {
             (Some(_), Some(_)) => Err(anyhow!("--server と --client は同時に指定できません")),
             (None, None) => Err(anyhow!("--server か --client のどちらかを指定してください")),
             (Some(addr), None) if addr.trim().is_empty() => {
@@ -1530,29 +2077,437 @@ impl Validate for SyncOpts {
             }
         }
     }
-}
 
-// Validateトレイトの実装
-impl Validate for ImportOpts {
-    fn validate(&mut self) -> Result<()> {
-        if self.dry_run && !self.merge {
-            return Err(anyhow!("--dry-run は --merge 指定時のみ指定できます"));
-        }
-        Ok(())
+    ///
+    /// 事前共有鍵ファイルへのパスへのアクセサ
+    ///
+    pub(crate) fn psk_file(&self) -> Option<PathBuf> {
+        self.psk_file.clone()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Cursor;
+    ///
+    /// 全件同期フラグへのアクセサ
+    ///
+    pub(crate) fn is_full(&self) -> bool {
+        self.full
+    }
 
-    fn config_from_toml(src: &str) -> Config {
-        toml::from_str(src).expect("toml parse failed")
+    ///
+    /// 同期を許可するノードIDのリストへのアクセサ
+    ///
+    /// # 戻り値
+    /// 制限しない場合は空のリストを返す。
+    ///
+    pub(crate) fn allowed_peers(&self) -> Vec<String> {
+        self.allow.clone()
     }
 
-    #[test]
-    fn search_apply_config_fill_defaults() {
+    ///
+    /// 競合時の解決方針へのアクセサ
+    ///
+    pub(crate) fn conflict_policy(&self) -> ConflictPolicy {
+        self.conflict.unwrap_or(ConflictPolicy::Prompt)
+    }
+
+    ///
+    /// ドライランフラグへのアクセサ
+    ///
+    pub(crate) fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    ///
+    /// 探索に用いるUDPポート番号へのアクセサ
+    ///
+    pub(crate) fn discovery_port(&self) -> u16 {
+        self.discovery_port.unwrap_or(sync::discovery::DEFAULT_DISCOVERY_PORT)
+    }
+
+    ///
+    /// SSH経由の同期でリモートホストで実行するコマンドへのアクセサ
+    ///
+    pub(crate) fn remote_command(&self) -> String {
+        self.remote_command
+            .clone()
+            .unwrap_or_else(|| sync::transport::DEFAULT_REMOTE_COMMAND.to_string())
+    }
+
+    ///
+    /// ペアリングした相手に付けるラベルへのアクセサ
+    ///
+    /// # 戻り値
+    /// ペアリング以外の場合やラベルが指定されていない場合は`None`を返す。
+    ///
+    pub(crate) fn pair_label(&self) -> Option<String> {
+        match &self.action {
+            Some(SyncAction::Pair(opts)) => opts.label.clone(),
+            _ => None,
+        }
+    }
+}
+
+// ApplyConfigトレイトの実装
+impl ApplyConfig for SyncOpts {
+    fn apply_config(&mut self, config: &Config) {
+        if self.psk_file.is_none() {
+            self.psk_file = config.sync_psk_file();
+        }
+
+        if self.allow.is_empty() {
+            self.allow = config.sync_allowed_peers().unwrap_or_default();
+        }
+
+        if self.conflict.is_none() {
+            self.conflict = config.sync_conflict();
+        }
+
+        if self.discovery_port.is_none() {
+            self.discovery_port = config.sync_discovery_port();
+        }
+
+        if self.remote_command.is_none() {
+            self.remote_command = config.sync_remote_command();
+        }
+    }
+}
+
+///
+/// サブコマンドagentのオプション
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct AgentOpts {
+    /// 無操作の状態が続いた場合に鍵を破棄するまでの秒数
+    #[arg(short = 't', long = "idle-timeout", value_name = "SECS")]
+    idle_timeout: Option<u64>,
+
+    /// デーモン化せずにフォアグラウンドで動作
+    #[arg(short = 'f', long = "foreground")]
+    foreground: bool,
+
+    /// 実行する操作(省略時はエージェントの起動)
+    #[command(subcommand)]
+    action: Option<AgentAction>,
+}
+
+impl AgentOpts {
+    /// アイドルタイムアウトのデフォルト値(秒)
+    const DEFAULT_IDLE_TIMEOUT: u64 = 900;
+
+    ///
+    /// アイドルタイムアウトへのアクセサ
+    ///
+    pub(crate) fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.idle_timeout.unwrap_or(Self::DEFAULT_IDLE_TIMEOUT)
+        )
+    }
+
+    ///
+    /// フォアグラウンド動作フラグへのアクセサ
+    ///
+    pub(crate) fn is_foreground(&self) -> bool {
+        self.foreground
+    }
+
+    ///
+    /// 実行する操作へのアクセサ
+    ///
+    pub(crate) fn action(&self) -> AgentAction {
+        self.action.clone().unwrap_or(AgentAction::Start)
+    }
+}
+
+// ShowOptionsトレイトの実装
+impl ShowOptions for AgentOpts {
+    fn show_options(&self) {
+        println!("agent command options");
+        println!("   action:       {:?}", self.action());
+        println!("   idle_timeout: {}s", self.idle_timeout().as_secs());
+        println!("   foreground:   {}", self.is_foreground());
+    }
+}
+
+///
+/// agentサブコマンドの操作
+///
+#[derive(Clone, Debug, Subcommand)]
+pub(crate) enum AgentAction {
+    /// エージェントの起動
+    Start,
+
+    /// キャッシュしている鍵の破棄
+    Lock,
+
+    /// エージェントの動作状況の表示
+    Status,
+
+    /// エージェントの停止
+    Stop,
+}
+
+///
+/// 同期で競合した際の解決方針を表す列挙子
+///
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, ValueEnum, PartialEq, Eq)]
+#[value(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ConflictPolicy {
+    /// 利用者に確認する
+    Prompt,
+
+    /// サーバ側の値を採用する
+    PreferServer,
+
+    /// クライアント側の値を採用する
+    PreferClient,
+
+    /// サーバ側の値を採用し、クライアント側の版を別エントリとして残す
+    KeepBoth,
+
+    /// 同期を中断する
+    Fail,
+}
+
+///
+/// クリップボードのバックエンドを表す列挙子
+///
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, ValueEnum, PartialEq, Eq)]
+#[value(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ClipboardBackend {
+    /// 環境に応じて自動的に選択する
+    Auto,
+
+    /// wl-copy/wl-paste(Wayland)
+    WlCopy,
+
+    /// xclip(X11)
+    Xclip,
+
+    /// xsel(X11)
+    Xsel,
+
+    /// 端末のエスケープシーケンス(OSC 52)
+    Osc52,
+}
+
+///
+/// syncモードを表す列挙
+///
+#[derive(Clone, Debug)]
+pub(crate) enum SyncMode {
+    /// サーバとして待ち受け
+    Server(String),
+
+    /// サーバとして常駐し、複数のクライアントを受け付け
+    Daemon(String),
+
+    /// クライアントとして接続
+    Client(String),
+
+    /// 探索で見つかった指定の相手(ノードIDもしくはラベル)にクライアントと
+    /// して接続
+    ClientByNode(String),
+
+    /// 待ち受け中の同期相手の探索
+    Discover,
+
+    /// 共有ディレクトリを介した同期
+    Folder(PathBuf),
+
+    /// SSHで起動したリモートホストのpwmgrとの同期
+    Ssh(String),
+
+    /// 標準入出力でのサーバとしての同期
+    Stdio,
+
+    /// ペアリングのために待ち受け
+    PairServer(String),
+
+    /// ペアリングのために接続
+    PairClient(String),
+
+    /// ペアリング済み同期相手の一覧表示
+    PeersList,
+
+    /// ペアリング済み同期相手の削除
+    PeersRemove(String),
+
+    /// ペアリング済み同期相手のラベルの設定(ラベルが`None`の場合は削除)
+    PeersLabel(String, Option<String>),
+}
+
+// ShowOptionsトレイトの実装
+impl ShowOptions for SyncOpts {
+    fn show_options(&self) {
+        println!("sync command options");
+        match self.mode() {
+            Ok(SyncMode::Server(addr)) => println!("   mode: server @ {}", addr),
+            Ok(SyncMode::Daemon(addr)) => {
+                println!("   mode: server daemon @ {}", addr)
+            }
+            Ok(SyncMode::Client(addr)) => println!("   mode: client -> {}", addr),
+            Ok(SyncMode::ClientByNode(peer)) => {
+                println!("   mode: client -> peer {}", peer)
+            }
+            Ok(SyncMode::Discover) => println!("   mode: discover"),
+            Ok(SyncMode::Folder(dir)) => {
+                println!("   mode: folder {}", dir.display())
+            }
+            Ok(SyncMode::Ssh(destination)) => {
+                println!("   mode: ssh -> {}", destination)
+            }
+            Ok(SyncMode::Stdio) => println!("   mode: stdio"),
+            Ok(SyncMode::PairServer(addr)) => {
+                println!("   mode: pair server @ {}", addr)
+            }
+            Ok(SyncMode::PairClient(addr)) => {
+                println!("   mode: pair client -> {}", addr)
+            }
+            Ok(SyncMode::PeersList) => println!("   mode: peers list"),
+            Ok(SyncMode::PeersRemove(peer)) => {
+                println!("   mode: peers remove {}", peer)
+            }
+            Ok(SyncMode::PeersLabel(peer, label)) => {
+                println!(
+                    "   mode: peers label {} {}",
+                    peer,
+                    label.as_deref().unwrap_or("(none)")
+                )
+            }
+            Err(err) => println!("   mode: invalid ({})", err),
+        }
+
+        if let Some(path) = &self.psk_file {
+            println!("   psk file: {}", path.display());
+        } else {
+            println!("   psk file: (none)");
+        }
+
+        println!("   full:     {}", self.is_full());
+
+        if self.allow.is_empty() {
+            println!("   allow:    (any paired peer)");
+        } else {
+            println!("   allow:    {}", self.allow.join(", "));
+        }
+
+        println!("   conflict: {:?}", self.conflict_policy());
+        println!("   dry-run:  {}", self.is_dry_run());
+        println!("   discovery port: {}", self.discovery_port());
+        println!("   remote command: {}", self.remote_command());
+    }
+}
+
+// Validateトレイトの実装
+impl Validate for SyncOpts {
+    fn validate(&mut self) -> Result<()> {
+        if self.action.is_some()
+            && (self.server_addr.is_some() || self.client_addr.is_some())
+        {
+            return Err(anyhow!(
+                "--server/--client は pair の後に指定してください"
+            ));
+        }
+
+        if self.action.is_some() && self.has_extra_mode() {
+            return Err(anyhow!(
+                "--peer/--folder/--ssh/--stdio/--discover は pair/peers と同時に\
+                 指定できません"
+            ));
+        }
+
+        if self.daemon && self.server_addr.is_none() {
+            return Err(anyhow!("--daemon は --server 指定時のみ指定できます"));
+        }
+
+        if self.dry_run
+            && self.client_addr.is_none()
+            && self.peer.is_none()
+            && self.folder.is_none()
+            && self.ssh.is_none()
+        {
+            return Err(anyhow!(
+                "--dry-run は --client/--peer/--folder/--ssh 指定時のみ指定でき\
+                 ます"
+            ));
+        }
+
+        match &self.action {
+            None if self.has_extra_mode() => {
+                let roles = [
+                    self.server_addr.is_some(),
+                    self.client_addr.is_some(),
+                    self.peer.is_some(),
+                    self.folder.is_some(),
+                    self.ssh.is_some(),
+                    self.stdio,
+                    self.discover,
+                ];
+
+                if roles.iter().filter(|role| **role).count() > 1 {
+                    return Err(anyhow!(
+                        "--server/--client/--peer/--folder/--ssh/--stdio/\
+                         --discover は同時に指定できません"
+                    ));
+                }
+
+                if let Some(destination) = &self.ssh {
+                    // sshのオプションとして解釈される指定は受け付けない
+                    if destination.trim().is_empty() || destination.starts_with('-') {
+                        return Err(anyhow!(
+                            "--ssh の接続先が不正です: {}", destination
+                        ));
+                    }
+                }
+
+                match &self.peer {
+                    Some(peer) if peer.trim().is_empty() => {
+                        Err(anyhow!("--peer で空の相手は指定できません"))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            None => Self::validate_role(
+                self.server_addr.as_ref(),
+                self.client_addr.as_ref()
+            ),
+            Some(SyncAction::Pair(opts)) => {
+                Self::validate_label(opts.label.as_ref())?;
+                Self::validate_role(
+                    opts.server_addr.as_ref(),
+                    opts.client_addr.as_ref()
+                )
+            }
+            Some(SyncAction::Peers {
+                action: PeersAction::Label { label, .. }
+            }) => Self::validate_label(label.as_ref()),
+            Some(SyncAction::Peers { .. }) => Ok(()),
+        }
+    }
+}
+
+// Validateトレイトの実装
+impl Validate for ImportOpts {
+    fn validate(&mut self) -> Result<()> {
+        if self.dry_run && !self.merge {
+            return Err(anyhow!("--dry-run は --merge 指定時のみ指定できます"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn config_from_toml(src: &str) -> Config {
+        toml::from_str(src).expect("toml parse failed")
+    }
+
+    #[test]
+    fn search_apply_config_fill_defaults() {
         let cfg = config_from_toml(
             r#"
 [search]
@@ -1659,55 +2614,730 @@ reverse_sort = true
     }
 
     #[test]
-    fn confirm_overwrite_yes_and_no() {
-        let mut output = Vec::new();
-        let mut yes = Cursor::new(b"y\n");
-        let mut no = Cursor::new(b"n\n");
-        let path = Path::new("dummy");
+    fn sync_daemon_mode_and_allowlist() {
+        let cfg = config_from_toml(
+            r#"
+[sync]
+allowed_peers = ["node-a", "node-b"]
+"#,
+        );
 
-        assert!(confirm_overwrite_with_io(path, &mut yes, &mut output).unwrap());
-        assert!(!confirm_overwrite_with_io(path, &mut no, &mut output).unwrap());
+        let mut opts = SyncOpts {
+            server_addr: Some("127.0.0.1:2456".to_string()),
+            client_addr: None,
+            psk_file: None,
+            full: false,
+            daemon: true,
+            allow: vec![],
+            conflict: None,
+            dry_run: false,
+            peer: None,
+            discover: false,
+            discovery_port: None,
+            folder: None,
+            ssh: None,
+            remote_command: None,
+            stdio: false,
+            action: None,
+        };
+
+        opts.apply_config(&cfg);
+        assert!(opts.validate().is_ok());
+        assert!(matches!(opts.mode().unwrap(), SyncMode::Daemon(_)));
+        assert_eq!(opts.allowed_peers(), vec!["node-a", "node-b"]);
+        assert_eq!(opts.conflict_policy(), ConflictPolicy::Prompt);
+
+        opts.server_addr = None;
+        opts.client_addr = Some("127.0.0.1:2456".to_string());
+        assert!(opts.validate().is_err());
     }
-}
 
-///
-/// サブコマンドremoveのオプション
-///
-#[derive(Clone, Args, Debug)]
-pub(crate) struct RemoveOpts {
-    /// ハードリムーブフラグ
-    #[arg(long = "hard")]
-    hard: bool,
+    #[test]
+    fn sync_conflict_policy_from_config() {
+        let cfg = config_from_toml(
+            r#"
+[sync]
+conflict = "keep-both"
+"#,
+        );
 
-    /// 削除対象のID
-    #[arg()]
-    id: String,
-}
+        let mut opts = SyncOpts {
+            server_addr: None,
+            client_addr: Some("127.0.0.1:2456".to_string()),
+            psk_file: None,
+            full: false,
+            daemon: false,
+            allow: vec![],
+            conflict: None,
+            dry_run: false,
+            peer: None,
+            discover: false,
+            discovery_port: None,
+            folder: None,
+            ssh: None,
+            remote_command: None,
+            stdio: false,
+            action: None,
+        };
 
-impl RemoveOpts {
-    ///
-    /// ハードリムーブか否かを表すフラグを返す
-    ///
-    pub(crate) fn is_hard(&self) -> bool {
-        self.hard
-    }
+        opts.apply_config(&cfg);
+        assert_eq!(opts.conflict_policy(), ConflictPolicy::KeepBoth);
 
-    ///
-    /// 削除対象IDへのアクセサ
-    ///
-    pub(crate) fn id(&self) -> String {
-        self.id.clone()
+        // コマンドラインでの指定が優先される
+        opts.conflict = Some(ConflictPolicy::Fail);
+        opts.apply_config(&cfg);
+        assert_eq!(opts.conflict_policy(), ConflictPolicy::Fail);
+
+        // ドライランはクライアントモードのみ
+        opts.dry_run = true;
+        assert!(opts.validate().is_ok());
+        opts.client_addr = None;
+        opts.server_addr = Some("127.0.0.1:2456".to_string());
+        assert!(opts.validate().is_err());
     }
 
-    ///
-    /// テスト用のコンストラクタ
-    ///
-    #[cfg(test)]
-    #[allow(dead_code)]
-    pub(crate) fn new_for_test(id: impl Into<String>, hard: bool) -> Self {
+    #[test]
+    fn sync_discover_and_peer_modes() {
+        let cfg = config_from_toml(
+            r#"
+[sync]
+discovery_port = 3457
+"#,
+        );
+
+        let mut opts = SyncOpts {
+            server_addr: None,
+            client_addr: None,
+            psk_file: None,
+            full: false,
+            daemon: false,
+            allow: vec![],
+            conflict: None,
+            dry_run: false,
+            peer: None,
+            discover: true,
+            discovery_port: None,
+            folder: None,
+            ssh: None,
+            remote_command: None,
+            stdio: false,
+            action: None,
+        };
+
+        opts.apply_config(&cfg);
+        assert!(opts.validate().is_ok());
+        assert!(matches!(opts.mode().unwrap(), SyncMode::Discover));
+        assert_eq!(opts.discovery_port(), 3457);
+
+        // --peerとの同時指定は不可
+        opts.peer = Some("node-a".to_string());
+        assert!(opts.validate().is_err());
+
+        opts.discover = false;
+        opts.dry_run = true;
+        assert!(opts.validate().is_ok());
+        assert!(matches!(
+            opts.mode().unwrap(),
+            SyncMode::ClientByNode(node_id) if node_id == "node-a"
+        ));
+    }
+
+    #[test]
+    fn sync_unix_socket_address() {
+        assert!(SyncOpts::validate_addr("unix:/run/pwmgr/sync.sock").is_ok());
+        assert!(SyncOpts::validate_addr("unix:").is_err());
+        assert!(SyncOpts::validate_addr("127.0.0.1:2456").is_ok());
+    }
+
+    #[test]
+    fn sync_folder_mode() {
+        let mut opts = SyncOpts {
+            server_addr: None,
+            client_addr: None,
+            psk_file: None,
+            full: false,
+            daemon: false,
+            allow: vec![],
+            conflict: None,
+            dry_run: true,
+            peer: None,
+            discover: false,
+            discovery_port: None,
+            folder: Some(PathBuf::from("/shared/pwmgr")),
+            ssh: None,
+            remote_command: None,
+            stdio: false,
+            action: None,
+        };
+
+        assert!(opts.validate().is_ok());
+        assert!(matches!(
+            opts.mode().unwrap(),
+            SyncMode::Folder(dir) if dir == Path::new("/shared/pwmgr")
+        ));
+
+        // 他の動作モードとの同時指定は不可
+        opts.client_addr = Some("127.0.0.1:2456".to_string());
+        assert!(opts.validate().is_err());
+    }
+
+    #[test]
+    fn sync_ssh_and_stdio_modes() {
+        let mut opts = SyncOpts {
+            server_addr: None,
+            client_addr: None,
+            psk_file: None,
+            full: false,
+            daemon: false,
+            allow: vec![],
+            conflict: None,
+            dry_run: true,
+            peer: None,
+            discover: false,
+            discovery_port: None,
+            folder: None,
+            ssh: Some("alice@example.com".to_string()),
+            remote_command: None,
+            stdio: false,
+            action: None,
+        };
+
+        assert!(opts.validate().is_ok());
+        assert!(matches!(
+            opts.mode().unwrap(),
+            SyncMode::Ssh(dest) if dest == "alice@example.com"
+        ));
+        assert_eq!(opts.remote_command(), "pwmgr sync --stdio");
+
+        // sshのオプションと解釈される接続先は不可
+        opts.ssh = Some("-oProxyCommand=sh".to_string());
+        assert!(opts.validate().is_err());
+
+        // サーバ側はドライラン不可
+        opts.ssh = None;
+        opts.stdio = true;
+        assert!(opts.validate().is_err());
+
+        opts.dry_run = false;
+        assert!(opts.validate().is_ok());
+        assert!(matches!(opts.mode().unwrap(), SyncMode::Stdio));
+    }
+
+    #[test]
+    fn confirm_overwrite_yes_and_no() {
+        let mut output = Vec::new();
+        let mut yes = Cursor::new(b"y\n");
+        let mut no = Cursor::new(b"n\n");
+        let path = Path::new("dummy");
+
+        assert!(confirm_overwrite_with_io(path, &mut yes, &mut output).unwrap());
+        assert!(!confirm_overwrite_with_io(path, &mut no, &mut output).unwrap());
+    }
+
+    #[test]
+    fn journaled_commands() {
+        let journaled = |args: &[&str]| {
+            Options::try_parse_from(args)
+                .unwrap()
+                .command
+                .unwrap()
+                .is_journaled()
+        };
+
+        assert!(journaled(&["pwmgr", "remove", "--hard", "ID"]));
+        assert!(journaled(&["pwmgr", "import", "backup.yml"]));
+        assert!(journaled(&["pwmgr", "gc"]));
+        assert!(!journaled(&["pwmgr", "sync", "--stdio"]));
+        assert!(!journaled(&["pwmgr", "totp", "example"]));
+        assert!(!journaled(&["pwmgr", "undo"]));
+    }
+}
+
+///
+/// サブコマンドremoveのオプション
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct RemoveOpts {
+    /// ハードリムーブフラグ
+    #[arg(long = "hard")]
+    hard: bool,
+
+    /// 削除対象のID
+    #[arg()]
+    id: String,
+}
+
+impl RemoveOpts {
+    ///
+    /// ハードリムーブか否かを表すフラグを返す
+    ///
+    pub(crate) fn is_hard(&self) -> bool {
+        self.hard
+    }
+
+    ///
+    /// 削除対象IDへのアクセサ
+    ///
+    pub(crate) fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    ///
+    /// テスト用のコンストラクタ
+    ///
+    #[cfg(test)]
+    #[allow(dead_code)]
+    pub(crate) fn new_for_test(id: impl Into<String>, hard: bool) -> Self {
         Self { id: id.into(), hard }
     }
 }
+
+///
+/// サブコマンドhistoryのオプション
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct HistoryOpts {
+    /// 対象のID
+    #[arg()]
+    id: String,
+}
+
+impl HistoryOpts {
+    ///
+    /// 対象IDへのアクセサ
+    ///
+    pub(crate) fn id(&self) -> String {
+        self.id.clone()
+    }
+}
+
+///
+/// サブコマンドrevertのオプション
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct RevertOpts {
+    /// 対象のID
+    #[arg()]
+    id: String,
+
+    /// 戻す版の番号(historyサブコマンドで表示される番号)
+    #[arg(value_name = "REV")]
+    revision: u64,
+}
+
+impl RevertOpts {
+    ///
+    /// 対象IDへのアクセサ
+    ///
+    pub(crate) fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    ///
+    /// 戻す版の番号へのアクセサ
+    ///
+    pub(crate) fn revision(&self) -> u64 {
+        self.revision
+    }
+}
+
+///
+/// サブコマンドdiffのオプション
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct DiffOpts {
+    /// 秘匿プロパティの値をマスクせずに表示する
+    #[arg(short = 'U', long = "unmasked-mode")]
+    unmasked_mode: bool,
+
+    /// 対象のID
+    #[arg()]
+    id: String,
+
+    /// 比較元の版の番号(省略時は現在の一つ前の版)
+    #[arg(value_name = "REV_A")]
+    rev_a: Option<u64>,
+
+    /// 比較先の版の番号(省略時は現在の版)
+    #[arg(value_name = "REV_B")]
+    rev_b: Option<u64>,
+}
+
+impl DiffOpts {
+    ///
+    /// 対象IDへのアクセサ
+    ///
+    pub(crate) fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    ///
+    /// 比較元の版の番号へのアクセサ
+    ///
+    pub(crate) fn rev_a(&self) -> Option<u64> {
+        self.rev_a
+    }
+
+    ///
+    /// 比較先の版の番号へのアクセサ
+    ///
+    pub(crate) fn rev_b(&self) -> Option<u64> {
+        self.rev_b
+    }
+
+    ///
+    /// 秘匿プロパティをマスクするか否か
+    ///
+    pub(crate) fn is_masked(&self) -> bool {
+        !self.unmasked_mode
+    }
+}
+
+///
+/// サブコマンドundoのオプション
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct UndoOpts {
+    /// 取り消し可能な操作の一覧を表示する
+    #[arg(short = 'l', long = "list")]
+    list: bool,
+}
+
+impl UndoOpts {
+    ///
+    /// 一覧表示か否か
+    ///
+    pub(crate) fn is_list(&self) -> bool {
+        self.list
+    }
+}
+
+///
+/// サブコマンドgenerateのオプション
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct GenerateOpts {
+    /// 生成するパスワードの文字数
+    #[arg(short = 'l', long = "length", value_name = "NUMBER")]
+    length: Option<usize>,
+
+    /// 使用する文字種(lower/upper/digit/sym/alpha/alnum/allを'+'で連結)
+    #[arg(short = 'c', long = "charset", value_name = "CHARSET")]
+    charset: Option<String>,
+
+    /// 見間違えやすい文字(0O1Il|)を除外する
+    #[arg(short = 'A', long = "no-ambiguous")]
+    no_ambiguous: bool,
+
+    /// 除外する文字
+    #[arg(short = 'x', long = "exclude", value_name = "CHARS")]
+    exclude: Option<String>,
+
+    /// 指定した単語数のパスフレーズを生成する
+    #[arg(short = 'w', long = "words", value_name = "NUMBER")]
+    words: Option<usize>,
+
+    /// パスフレーズの単語区切り
+    #[arg(short = 's', long = "separator", value_name = "SEPARATOR")]
+    separator: Option<String>,
+
+    /// パスフレーズに用いる単語リストのファイル(1行1単語)
+    #[arg(long = "wordlist", value_name = "FILE")]
+    wordlist: Option<PathBuf>,
+
+    /// 生成する個数
+    #[arg(short = 'n', long = "count", value_name = "NUMBER", default_value = "1")]
+    count: usize,
+}
+
+impl GenerateOpts {
+    ///
+    /// 生成方針の構築
+    ///
+    pub(crate) fn policy(&self) -> Result<generator::Policy> {
+        let mut policy = generator::Policy::default();
+
+        if let Some(length) = self.length {
+            policy.set_length(length);
+        }
+
+        if let Some(charset) = &self.charset {
+            policy.set_classes(CharClass::parse(charset)?);
+        }
+
+        policy.set_no_ambiguous(self.no_ambiguous);
+
+        if let Some(exclude) = &self.exclude {
+            policy.set_exclude(exclude.clone());
+        }
+
+        if let Some(words) = self.words {
+            policy.set_words(words);
+        }
+
+        if let Some(separator) = &self.separator {
+            policy.set_separator(separator.clone());
+        }
+
+        if let Some(path) = &self.wordlist {
+            policy.set_wordlist(path.clone());
+        }
+
+        Ok(policy)
+    }
+
+    ///
+    /// 生成する個数へのアクセサ
+    ///
+    pub(crate) fn count(&self) -> usize {
+        self.count
+    }
+}
+
+// Validateトレイトの実装
+impl Validate for GenerateOpts {
+    fn validate(&mut self) -> Result<()> {
+        if self.count == 0 {
+            return Err(anyhow!("--count には1以上を指定してください"));
+        }
+
+        if self.words.is_some() && (self.length.is_some() || self.charset.is_some()) {
+            return Err(anyhow!(
+                "--words と --length/--charset は同時に指定できません"
+            ));
+        }
+
+        self.policy().map(|_| ())
+    }
+}
+
+///
+/// サブコマンドtotpのオプション
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct TotpOpts {
+    /// マッチモード
+    #[arg(
+        short = 'm',
+        long = "match-mode",
+        value_enum,
+        value_name = "MODE",
+        help = "マッチモード\n"
+    )]
+    match_mode: Option<MatchMode>,
+
+    /// 共有鍵を格納したプロパティ名(省略時はtotp!/otp!/totp/otpの順に探す)
+    #[arg(short = 'p', long = "property", value_name = "NAME")]
+    property: Option<String>,
+
+    /// 検索のためのキー(サービス名/過去名/ID)
+    #[arg()]
+    key: String,
+}
+
+impl TotpOpts {
+    ///
+    /// 検索キーへのアクセサ
+    ///
+    pub(crate) fn key(&self) -> String {
+        self.key.clone()
+    }
+
+    ///
+    /// マッチモードへのアクセサ
+    ///
+    pub(crate) fn match_mode(&self) -> MatchMode {
+        self.match_mode.unwrap_or(MatchMode::Contains)
+    }
+
+    ///
+    /// 共有鍵を格納したプロパティ名へのアクセサ
+    ///
+    pub(crate) fn property(&self) -> Option<String> {
+        self.property.clone()
+    }
+
+    ///
+    /// テスト用のコンストラクタ
+    ///
+    #[cfg(test)]
+    pub(crate) fn new_for_test(
+        match_mode: MatchMode,
+        key: impl Into<String>,
+        property: Option<String>,
+    ) -> Self {
+        Self {
+            match_mode: Some(match_mode),
+            property,
+            key: key.into(),
+        }
+    }
+}
+
+// ApplyConfigトレイトの実装
+impl ApplyConfig for TotpOpts {
+    fn apply_config(&mut self, config: &Config) {
+        // queryサブコマンドと同じマッチモードを用いる
+        if self.match_mode.is_none() {
+            self.match_mode = config.query_match_mode();
+        }
+    }
+}
+
+// ShowOptionsトレイトの実装
+impl ShowOptions for TotpOpts {
+    fn show_options(&self) {
+        println!("totp command options");
+        println!("   key:      {}", self.key());
+        println!("   mode:     {:?}", self.match_mode());
+        println!("   property: {}", self.property().unwrap_or("(auto)".into()));
+    }
+}
+
+///
+/// サブコマンドclipboard-clear(内部用)のオプション
+///
+/// # 注記
+/// `query --copy`が自動消去のために自身を起動し直す際に用いる。消去の対象
+/// とする値のハッシュ値は標準入力で受け取る。
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct ClipboardClearOpts {
+    /// 消去するまでの秒数
+    #[arg(long = "after", value_name = "SECS")]
+    after: u64,
+
+    /// クリップボードのバックエンド
+    #[arg(long = "backend", value_enum, value_name = "BACKEND")]
+    backend: ClipboardBackend,
+
+    /// 内容を確認できない場合も消去する
+    #[arg(long = "force")]
+    force: bool,
+}
+
+impl ClipboardClearOpts {
+    ///
+    /// 消去するまでの秒数へのアクセサ
+    ///
+    pub(crate) fn after(&self) -> u64 {
+        self.after
+    }
+
+    ///
+    /// クリップボードのバックエンドへのアクセサ
+    ///
+    pub(crate) fn backend(&self) -> ClipboardBackend {
+        self.backend
+    }
+
+    ///
+    /// 内容を確認できない場合も消去するか否か
+    ///
+    pub(crate) fn is_force(&self) -> bool {
+        self.force
+    }
+}
+
+///
+/// サブコマンドgcのオプション
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct GcOpts {
+    /// 墓標を保持する期間(日数)
+    #[arg(short = 'r', long = "retention", value_name = "DAYS")]
+    retention_days: Option<u32>,
+
+    /// 回収の対象を表示するのみで削除しない
+    #[arg(short = 'n', long = "dry-run")]
+    dry_run: bool,
+}
+
+impl GcOpts {
+    ///
+    /// 墓標を保持する期間(日数)へのアクセサ
+    ///
+    pub(crate) fn retention_days(&self) -> u32 {
+        self.retention_days.unwrap_or(gc::DEFAULT_RETENTION_DAYS)
+    }
+
+    ///
+    /// ドライランか否かを返す
+    ///
+    pub(crate) fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+}
+
+// ApplyConfigトレイトの実装
+impl ApplyConfig for GcOpts {
+    fn apply_config(&mut self, config: &Config) {
+        if self.retention_days.is_none() {
+            self.retention_days = config.gc_retention_days();
+        }
+    }
+}
+
+// ShowOptionsトレイトの実装
+impl ShowOptions for GcOpts {
+    fn show_options(&self) {
+        println!("gc command options");
+        println!("   retention days: {}", self.retention_days());
+        println!("   dry run:        {}", self.dry_run);
+    }
+}
+
+///
+/// サブコマンドauditのオプション
+///
+#[derive(Clone, Args, Debug)]
+pub(crate) struct AuditOpts {
+    /// 古いとみなす最終更新からの経過日数
+    #[arg(short = 'a', long = "max-age", value_name = "DAYS")]
+    max_age_days: Option<u32>,
+
+    /// 弱いとみなすエントロピー推定値(ビット)の閾値
+    #[arg(short = 'e', long = "min-entropy", value_name = "BITS")]
+    min_entropy: Option<u32>,
+}
+
+impl AuditOpts {
+    ///
+    /// 古いとみなす最終更新からの経過日数へのアクセサ
+    ///
+    pub(crate) fn max_age_days(&self) -> u32 {
+        self.max_age_days.unwrap_or(audit::DEFAULT_MAX_AGE_DAYS)
+    }
+
+    ///
+    /// 弱いとみなすエントロピー推定値の閾値へのアクセサ
+    ///
+    pub(crate) fn min_entropy(&self) -> u32 {
+        self.min_entropy.unwrap_or(audit::DEFAULT_MIN_ENTROPY)
+    }
+}
+
+// ApplyConfigトレイトの実装
+impl ApplyConfig for AuditOpts {
+    fn apply_config(&mut self, config: &Config) {
+        if self.max_age_days.is_none() {
+            self.max_age_days = config.audit_max_age_days();
+        }
+
+        if self.min_entropy.is_none() {
+            self.min_entropy = config.audit_min_entropy();
+        }
+    }
+}
+
+// ShowOptionsトレイトの実装
+impl ShowOptions for AuditOpts {
+    fn show_options(&self) {
+        println!("audit command options");
+        println!("   max age days: {}", self.max_age_days());
+        println!("   min entropy:  {}", self.min_entropy());
+    }
+}
 ///
 /// コマンドライン引数のパース処理
 ///
@@ -22,6 +22,7 @@ use crate::database::{
 };
 use super::{
     editor::{default_editor_launcher, rewrite_id_line, EditorLauncher},
+    generator::{check_policy, expand_placeholders},
     prompt::{Prompter, StdPrompter},
     util::is_blank,
     CommandContext,
@@ -172,15 +173,43 @@ impl CommandContext for AddCommandContext {
                 }
             }
 
+            // パスワード生成のプレースホルダを展開
+            let policy = entry.policy();
+            let properties = match expand_placeholders(
+                entry.properties(),
+                policy.as_ref()
+            ) {
+                Ok(properties) => properties,
+                Err(err) => {
+                    if self.prompter.ask_retry(
+                        &format!("パスワードの生成に失敗しました: {err:#}")
+                    )? {
+                        continue;
+                    } else {
+                        return Err(err);
+                    }
+                }
+            };
+
+            // パスワードポリシーへの適合を確認
+            if let Err(err) = check_policy(&properties, policy.as_ref()) {
+                if self.prompter.ask_retry(&format!("{err:#}"))? {
+                    continue;
+                } else {
+                    return Err(err);
+                }
+            }
+
             let entry = Entry::new(
                 id.clone(),
                 entry.service(),
                 entry.aliases(),
                 entry.tags(),
-                entry.properties(),
+                properties,
             );
-            // 更新日時をセット
+            // ポリシーと更新日時をセット
             let mut entry = entry;
+            entry.set_policy(policy);
             entry.set_last_update_now();
 
             self.manager.borrow_mut().put(&entry)?;
@@ -249,7 +278,7 @@ mod tests {
 
     fn build_manager() -> EntryManager {
         let path = temp_db_path();
-        EntryManager::open(path).unwrap()
+        EntryManager::open_for_test(path).unwrap()
     }
 
     /// 正常系: 編集内容が正しく登録され、別名/タグがソート+重複除去されること
@@ -354,6 +383,45 @@ mod tests {
         assert_eq!(ids.len(), 1);
     }
 
+    /// パスワード生成のプレースホルダが展開されて登録されること
+    #[test]
+    fn exec_expands_generate_placeholder() {
+        let mgr = build_manager();
+
+        let editor = Arc::new(|path: &Path| -> Result<()> {
+            let id = read_id_from_template(path);
+            let yaml = format!(
+                concat!(
+                    "id: \"{id}\"\n",
+                    "service: \"svc\"\n",
+                    "aliases: []\n",
+                    "tags: []\n",
+                    "properties:\n",
+                    "  password!: \"{{{{GEN:length=24,charset=digit}}}}\"\n",
+                ),
+                id = id
+            );
+            fs::write(path, yaml)?;
+            Ok(())
+        });
+
+        let ctx = AddCommandContext::with_deps(
+            mgr,
+            Arc::new(QueuePrompter::new(vec![])),
+            editor,
+            None,
+        );
+
+        ctx.exec().unwrap();
+
+        let mut mgr = ctx.manager.borrow_mut();
+        let ids = mgr.all_service().unwrap();
+        let entry = mgr.get(&ids[0]).unwrap().unwrap();
+        let password = entry.properties()["password!"].clone();
+        assert_eq!(password.len(), 24);
+        assert!(password.chars().all(|ch| ch.is_ascii_digit()));
+    }
+
     #[test]
     /// YAML解釈エラー時にリトライを拒否するとエラーで終了すること
     fn exec_fails_on_yaml_error_without_retry() {
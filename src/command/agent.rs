@@ -0,0 +1,742 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! agentサブコマンドの実装
+//!
+//! 導出済みの暗号鍵をUnixドメインソケット経由で他のプロセスへ受け渡すための
+//! 常駐プロセス(エージェント)と、そのクライアント処理を提供する。
+//!
+
+use std::collections::HashMap;
+use std::fs::{self, DirBuilder, Permissions};
+use std::io::{Read, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+use crate::cmd_args::{AgentAction, AgentOpts, Options};
+use crate::database::crypto::{VaultKey, KEY_LEN};
+use super::CommandContext;
+
+/// エージェントのソケットパスを受け渡すための環境変数名
+pub(crate) const AGENT_SOCK_ENV: &str = "PWMGR_AGENT_SOCK";
+
+/// メッセージ長の上限(バイト)
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// クライアント側の送受信タイムアウト
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// アイドルタイムアウトの監視間隔
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+///
+/// エージェントへの要求
+///
+#[derive(Debug, Serialize, Deserialize)]
+enum AgentRequest {
+    ///
+    /// データベースに対応する鍵の取得
+    ///
+    GetKey { db_path: String },
+
+    ///
+    /// データベースに対応する鍵の登録
+    ///
+    PutKey { db_path: String, key: Vec<u8> },
+
+    ///
+    /// 保持している全ての鍵の破棄
+    ///
+    Lock,
+
+    ///
+    /// 動作状況の問い合わせ
+    ///
+    Status,
+
+    ///
+    /// エージェントの停止
+    ///
+    Stop,
+}
+
+///
+/// エージェントからの応答
+///
+#[derive(Debug, Serialize, Deserialize)]
+enum AgentResponse {
+    ///
+    /// 鍵(保持していない場合はNone)
+    ///
+    Key(Option<Vec<u8>>),
+
+    ///
+    /// 要求の受理
+    ///
+    Ok,
+
+    ///
+    /// 動作状況
+    ///
+    Status(AgentStatus),
+
+    ///
+    /// 要求の処理失敗
+    ///
+    Error(String),
+}
+
+///
+/// エージェントの動作状況
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct AgentStatus {
+    /// エージェントのプロセスID
+    pid: u32,
+
+    /// 鍵を保持しているデータベースの数
+    cached_keys: usize,
+
+    /// アイドルタイムアウト(秒)
+    idle_timeout: u64,
+
+    /// 最後に鍵へアクセスされてからの経過時間(秒)
+    idle: u64,
+}
+
+///
+/// メッセージ送信(長さプレフィックス + MessagePack)
+///
+fn send_message<T>(stream: &mut UnixStream, msg: &T) -> Result<()>
+where
+    T: Serialize,
+{
+    let buf = rmp_serde::to_vec_named(msg).context("serialize message")?;
+    let len = buf.len() as u32;
+    stream.write_all(&len.to_be_bytes()).context("write length")?;
+    stream.write_all(&buf).context("write message")?;
+    stream.flush().ok();
+    Ok(())
+}
+
+///
+/// メッセージ受信(長さプレフィックス + MessagePack)
+///
+fn recv_message<T>(stream: &mut UnixStream) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let mut len_buf = [0u8; 4];
+    stream.read_exact(&mut len_buf).context("read length")?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+
+    if len > MAX_MESSAGE_LEN {
+        return Err(anyhow!("message too large: {} bytes", len));
+    }
+
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf).context("read message")?;
+
+    let msg = rmp_serde::from_slice(&buf).context("deserialize message");
+    buf.zeroize();
+
+    msg
+}
+
+///
+/// エージェントへの要求送信
+///
+/// # 引数
+/// * `sock` - エージェントのソケットパス
+/// * `req` - 要求
+///
+/// # 戻り値
+/// エージェントからの応答を`Ok()`でラップして返す。エージェントがエラーを
+/// 応答した場合はエラー情報を`Err()`でラップして返す。
+///
+fn request(sock: &Path, req: &AgentRequest) -> Result<AgentResponse> {
+    let mut stream = UnixStream::connect(sock)
+        .with_context(|| format!("connect to agent {}", sock.display()))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    send_message(&mut stream, req)?;
+
+    match recv_message(&mut stream)? {
+        AgentResponse::Error(msg) => Err(anyhow!("agent error: {}", msg)),
+        resp => Ok(resp),
+    }
+}
+
+///
+/// エージェントからの鍵の取得
+///
+/// # 引数
+/// * `sock` - エージェントのソケットパス
+/// * `db_path` - データベースを識別するパス文字列
+///
+/// # 戻り値
+/// エージェントが鍵を保持している場合は鍵を`Ok(Some())`でラップして返す。
+///
+pub(crate) fn fetch_key(sock: &Path, db_path: &str)
+    -> Result<Option<VaultKey>>
+{
+    let req = AgentRequest::GetKey { db_path: db_path.to_string() };
+
+    match request(sock, &req)? {
+        AgentResponse::Key(Some(mut bytes)) => {
+            let key = VaultKey::from_bytes(&bytes);
+            bytes.zeroize();
+            Ok(Some(key?))
+        }
+        AgentResponse::Key(None) => Ok(None),
+        resp => Err(anyhow!("unexpected agent response: {:?}", resp)),
+    }
+}
+
+///
+/// エージェントへの鍵の登録
+///
+/// # 引数
+/// * `sock` - エージェントのソケットパス
+/// * `db_path` - データベースを識別するパス文字列
+/// * `key` - 登録する鍵
+///
+pub(crate) fn store_key(sock: &Path, db_path: &str, key: &VaultKey)
+    -> Result<()>
+{
+    let mut req = AgentRequest::PutKey {
+        db_path: db_path.to_string(),
+        key: key.as_bytes().to_vec(),
+    };
+
+    let resp = request(sock, &req);
+
+    if let AgentRequest::PutKey { key, .. } = &mut req {
+        key.zeroize();
+    }
+
+    match resp? {
+        AgentResponse::Ok => Ok(()),
+        resp => Err(anyhow!("unexpected agent response: {:?}", resp)),
+    }
+}
+
+///
+/// エージェントが動作中か否かの確認
+///
+fn is_running(sock: &Path) -> bool {
+    matches!(request(sock, &AgentRequest::Status), Ok(AgentResponse::Status(_)))
+}
+
+///
+/// ページアウトされないようにロックしたメモリ上に保持する鍵
+///
+struct LockedKey(Box<[u8; KEY_LEN]>);
+
+impl LockedKey {
+    ///
+    /// オブジェクトの生成
+    ///
+    /// # 引数
+    /// * `bytes` - 鍵のバイト列
+    ///
+    /// # 注記
+    /// メモリのロックに失敗した場合(RLIMIT_MEMLOCKの制限等)は警告を記録した上
+    /// でそのまま保持する。
+    ///
+    fn new(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != KEY_LEN {
+            return Err(anyhow!("invalid key length: {}", bytes.len()));
+        }
+
+        let mut key = Box::new([0u8; KEY_LEN]);
+
+        // SAFETY: ヒープ上に確保した固定長領域の先頭と長さを渡しているだけ
+        if unsafe { libc::mlock(key.as_ptr().cast(), KEY_LEN) } != 0 {
+            warn!("mlock failed: {}", std::io::Error::last_os_error());
+        }
+
+        key.copy_from_slice(bytes);
+
+        Ok(Self(key))
+    }
+
+    ///
+    /// 鍵のバイト列へのアクセサ
+    ///
+    fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+// Dropトレイトの実装
+impl Drop for LockedKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+
+        // SAFETY: new()でロックした領域をそのまま解放している
+        unsafe {
+            libc::munlock(self.0.as_ptr().cast(), KEY_LEN);
+        }
+    }
+}
+
+///
+/// エージェントの内部状態
+///
+struct AgentState {
+    /// データベース毎の鍵
+    keys: HashMap<String, LockedKey>,
+
+    /// 最後に鍵へアクセスされた時刻
+    last_access: Instant,
+
+    /// アイドルタイムアウト
+    idle_timeout: Duration,
+
+    /// 停止要求を受けたか否か
+    stopped: bool,
+}
+
+impl AgentState {
+    ///
+    /// 要求の処理
+    ///
+    fn handle(&mut self, req: AgentRequest) -> AgentResponse {
+        match req {
+            AgentRequest::GetKey { db_path } => {
+                self.expire();
+
+                match self.keys.get(&db_path) {
+                    Some(key) => {
+                        self.last_access = Instant::now();
+                        AgentResponse::Key(Some(key.as_bytes().to_vec()))
+                    }
+                    None => AgentResponse::Key(None),
+                }
+            }
+
+            AgentRequest::PutKey { db_path, mut key } => {
+                let locked = LockedKey::new(&key);
+                key.zeroize();
+
+                match locked {
+                    Ok(locked) => {
+                        self.keys.insert(db_path, locked);
+                        self.last_access = Instant::now();
+                        AgentResponse::Ok
+                    }
+                    Err(err) => AgentResponse::Error(err.to_string()),
+                }
+            }
+
+            AgentRequest::Lock => {
+                self.keys.clear();
+                info!("agent: locked by request");
+                AgentResponse::Ok
+            }
+
+            AgentRequest::Status => {
+                self.expire();
+
+                AgentResponse::Status(AgentStatus {
+                    pid: process::id(),
+                    cached_keys: self.keys.len(),
+                    idle_timeout: self.idle_timeout.as_secs(),
+                    idle: self.last_access.elapsed().as_secs(),
+                })
+            }
+
+            AgentRequest::Stop => {
+                self.keys.clear();
+                self.stopped = true;
+                AgentResponse::Ok
+            }
+        }
+    }
+
+    ///
+    /// アイドルタイムアウトを超過していれば鍵を破棄する
+    ///
+    fn expire(&mut self) {
+        if !self.keys.is_empty() && self.last_access.elapsed() >= self.idle_timeout {
+            self.keys.clear();
+            info!("agent: locked by idle timeout");
+        }
+    }
+}
+
+///
+/// エージェントのサーバ処理
+///
+pub(crate) struct AgentServer {
+    /// 待ち受けソケット
+    listener: UnixListener,
+
+    /// ソケットのパス
+    path: PathBuf,
+
+    /// 内部状態
+    state: Arc<Mutex<AgentState>>,
+}
+
+impl AgentServer {
+    ///
+    /// ソケットの作成
+    ///
+    /// # 引数
+    /// * `path` - ソケットのパス
+    /// * `idle_timeout` - 鍵を破棄するまでの無操作時間
+    ///
+    /// # 戻り値
+    /// 待ち受けの準備ができたサーバオブジェクトを`Ok()`でラップして返す。
+    ///
+    /// # 注記
+    /// ソケットは所有者のみが読み書きできるパーミッション(0600)で作成する。
+    ///
+    pub(crate) fn bind(path: &Path, idle_timeout: Duration) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .with_context(|| format!("create {}", dir.display()))?;
+        }
+
+        // 前回異常終了した際のソケットファイルが残っている場合は削除する
+        if path.exists() {
+            if is_running(path) {
+                return Err(anyhow!("agent is already running"));
+            }
+
+            fs::remove_file(path)
+                .with_context(|| format!("remove {}", path.display()))?;
+        }
+
+        // パーミッションを設定するまでの間に他ユーザから接続されないよう、
+        // umaskで作成時点のパーミッションを絞っておく
+        // SAFETY: プロセスのumaskを一時的に変更するのみ
+        let mask = unsafe { libc::umask(0o177) };
+        let listener = UnixListener::bind(path);
+        unsafe { libc::umask(mask) };
+
+        let listener = listener
+            .with_context(|| format!("bind {}", path.display()))?;
+        fs::set_permissions(path, Permissions::from_mode(0o600))?;
+
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+            state: Arc::new(Mutex::new(AgentState {
+                keys: HashMap::new(),
+                last_access: Instant::now(),
+                idle_timeout,
+                stopped: false,
+            })),
+        })
+    }
+
+    ///
+    /// 要求の待ち受け
+    ///
+    /// # 戻り値
+    /// 停止要求を受けた場合は`Ok(())`を返す。
+    ///
+    pub(crate) fn serve(self) -> Result<()> {
+        info!("agent: listening on {}", self.path.display());
+
+        /*
+         * アイドルタイムアウトの監視
+         */
+        let state = Arc::clone(&self.state);
+        thread::spawn(move || loop {
+            thread::sleep(WATCH_INTERVAL);
+
+            let mut state = state.lock().unwrap();
+            if state.stopped {
+                break;
+            }
+
+            state.expire();
+        });
+
+        /*
+         * 要求の処理
+         */
+        for stream in self.listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("agent: accept failed: {}", err);
+                    continue;
+                }
+            };
+
+            stream.set_read_timeout(Some(IO_TIMEOUT))?;
+            stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+            let req = match recv_message::<AgentRequest>(&mut stream) {
+                Ok(req) => req,
+                Err(err) => {
+                    warn!("agent: invalid request: {:#}", err);
+                    continue;
+                }
+            };
+
+            let (resp, stopped) = {
+                let mut state = self.state.lock().unwrap();
+                (state.handle(req), state.stopped)
+            };
+
+            if let Err(err) = send_message(&mut stream, &resp) {
+                warn!("agent: send response failed: {:#}", err);
+            }
+
+            if stopped {
+                info!("agent: stopped by request");
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Dropトレイトの実装
+impl Drop for AgentServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+///
+/// agentサブコマンドのコンテキスト情報をパックした構造体
+///
+struct AgentCommandContext {
+    /// ソケットのパス
+    sock: PathBuf,
+
+    /// 実行する操作
+    action: AgentAction,
+
+    /// アイドルタイムアウト
+    idle_timeout: Duration,
+
+    /// フォアグラウンドで動作するか否か
+    foreground: bool,
+
+    /// JSON出力フラグ
+    json_output: bool,
+}
+
+impl AgentCommandContext {
+    ///
+    /// オブジェクトの生成
+    ///
+    fn new(opts: &Options, sub_opts: &AgentOpts) -> Result<Self> {
+        Ok(Self {
+            sock: opts.agent_sock_path(),
+            action: sub_opts.action(),
+            idle_timeout: sub_opts.idle_timeout(),
+            foreground: sub_opts.is_foreground(),
+            json_output: opts.json(),
+        })
+    }
+
+    ///
+    /// エージェントの起動
+    ///
+    /// # 注記
+    /// フォアグラウンド指定が無い場合は自身をフォアグラウンドモードで起動し直
+    /// し、ソケットの準備ができた時点でシェルに評価させるための環境変数設定を
+    /// 出力して終了する。
+    ///
+    fn start(&self) -> Result<()> {
+        if self.foreground {
+            return AgentServer::bind(&self.sock, self.idle_timeout)?.serve();
+        }
+
+        if is_running(&self.sock) {
+            return Err(anyhow!("agent is already running"));
+        }
+
+        let child = process::Command::new(std::env::current_exe()?)
+            .arg("agent")
+            .arg("--foreground")
+            .arg("--idle-timeout")
+            .arg(self.idle_timeout.as_secs().to_string())
+            .env(AGENT_SOCK_ENV, &self.sock)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0)
+            .spawn()
+            .context("spawn agent")?;
+
+        let deadline = Instant::now() + IO_TIMEOUT;
+        while !is_running(&self.sock) {
+            if Instant::now() >= deadline {
+                return Err(anyhow!("agent did not start"));
+            }
+
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        debug!("agent started: pid={}", child.id());
+        println!(
+            "{}={}; export {};",
+            AGENT_SOCK_ENV, self.sock.display(), AGENT_SOCK_ENV
+        );
+
+        Ok(())
+    }
+
+    ///
+    /// 動作状況の表示
+    ///
+    fn status(&self) -> Result<()> {
+        let status = match request(&self.sock, &AgentRequest::Status) {
+            Ok(AgentResponse::Status(status)) => Some(status),
+            Ok(resp) => {
+                return Err(anyhow!("unexpected agent response: {:?}", resp));
+            }
+            Err(err) => {
+                debug!("agent status: {:#}", err);
+                None
+            }
+        };
+
+        if self.json_output {
+            println!("{}", serde_json::to_string_pretty(&status)?);
+            return Ok(());
+        }
+
+        match status {
+            Some(status) => {
+                println!("agent status");
+                println!("   socket:       {}", self.sock.display());
+                println!("   pid:          {}", status.pid);
+                println!("   cached keys:  {}", status.cached_keys);
+                println!("   idle timeout: {}s", status.idle_timeout);
+                println!("   idle:         {}s", status.idle);
+            }
+            None => println!("agent is not running"),
+        }
+
+        Ok(())
+    }
+}
+
+// CommandContextトレイトの実装
+impl CommandContext for AgentCommandContext {
+    fn exec(&self) -> Result<()> {
+        match self.action {
+            AgentAction::Start => self.start(),
+
+            AgentAction::Lock => {
+                request(&self.sock, &AgentRequest::Lock)?;
+                println!("agent locked");
+                Ok(())
+            }
+
+            AgentAction::Status => self.status(),
+
+            AgentAction::Stop => {
+                request(&self.sock, &AgentRequest::Stop)?;
+                println!("agent stopped");
+                Ok(())
+            }
+        }
+    }
+}
+
+///
+/// コマンドコンテキストの生成
+///
+pub(crate) fn build_context(opts: &Options, sub_opts: &AgentOpts)
+    -> Result<Box<dyn CommandContext>>
+{
+    Ok(Box::new(AgentCommandContext::new(opts, sub_opts)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ulid::Ulid;
+
+    fn temp_sock_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("pwmgr-agent-test-{}", Ulid::new()))
+            .join("agent.sock")
+    }
+
+    fn spawn_agent(path: &Path, idle_timeout: Duration)
+        -> thread::JoinHandle<Result<()>>
+    {
+        let server = AgentServer::bind(path, idle_timeout).unwrap();
+        thread::spawn(move || server.serve())
+    }
+
+    ///
+    /// 鍵の登録→取得→ロック→停止の一連の動作を確認
+    ///
+    #[test]
+    fn agent_store_fetch_lock_stop() {
+        let path = temp_sock_path();
+        let handle = spawn_agent(&path, Duration::from_secs(60));
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let key = VaultKey::from_bytes(&[7u8; KEY_LEN]).unwrap();
+        assert!(fetch_key(&path, "db").unwrap().is_none());
+
+        store_key(&path, "db", &key).unwrap();
+        let got = fetch_key(&path, "db").unwrap().unwrap();
+        assert_eq!(got.as_bytes(), key.as_bytes());
+        assert!(fetch_key(&path, "other").unwrap().is_none());
+
+        request(&path, &AgentRequest::Lock).unwrap();
+        assert!(fetch_key(&path, "db").unwrap().is_none());
+
+        request(&path, &AgentRequest::Stop).unwrap();
+        handle.join().unwrap().unwrap();
+        assert!(!path.exists());
+    }
+
+    ///
+    /// アイドルタイムアウト経過後は鍵が破棄されることを確認
+    ///
+    #[test]
+    fn agent_idle_timeout_drops_keys() {
+        let path = temp_sock_path();
+        let handle = spawn_agent(&path, Duration::from_millis(200));
+
+        let key = VaultKey::from_bytes(&[9u8; KEY_LEN]).unwrap();
+        store_key(&path, "db", &key).unwrap();
+        assert!(fetch_key(&path, "db").unwrap().is_some());
+
+        thread::sleep(Duration::from_millis(400));
+        assert!(fetch_key(&path, "db").unwrap().is_none());
+
+        request(&path, &AgentRequest::Stop).unwrap();
+        handle.join().unwrap().unwrap();
+    }
+}
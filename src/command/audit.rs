@@ -0,0 +1,664 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//! auditサブコマンドの実装
+
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeDelta};
+use log::info;
+use serde::Serialize;
+
+use crate::cmd_args::{AuditOpts, Options};
+use crate::database::{EntryManager, TransactionReadable, TransactionReader};
+use super::generator::BUILTIN_WORDLIST;
+use super::CommandContext;
+
+/// 古いとみなす最終更新からの経過日数のデフォルト値
+pub(crate) const DEFAULT_MAX_AGE_DAYS: u32 = 365;
+
+/// 弱いとみなすエントロピー推定値(ビット)のデフォルトの閾値
+pub(crate) const DEFAULT_MIN_ENTROPY: u32 = 60;
+
+/// よく使われるパスワードのリスト
+const COMMON_PASSWORDS: &str = include_str!("templates/common_passwords.txt");
+
+/// 辞書照合の対象とする単語の最小文字数
+const MIN_WORD_LENGTH: usize = 4;
+
+/// 短すぎるとみなす文字数
+const SHORT_LENGTH: usize = 8;
+
+/// 繰り返し・連続とみなす最小文字数
+const MIN_PATTERN_LENGTH: usize = 3;
+
+/// 連続した文字の判定に用いるキーボードの並び
+const KEYBOARD_ROWS: &[&str] = &[
+    "1234567890",
+    "qwertyuiop",
+    "asdfghjkl",
+    "zxcvbnm",
+];
+
+///
+/// 脆弱さの要因
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Issue {
+    /// 文字数が少ない
+    Short,
+
+    /// 1種類の文字種のみで構成されている
+    SingleClass,
+
+    /// よく使われるパスワードと一致する
+    CommonPassword,
+
+    /// 辞書の単語を含む
+    DictionaryWord,
+
+    /// 同じ文字の繰り返しを含む
+    RepeatedChars,
+
+    /// 連続した文字(`abc`, `321`, `qwerty`等)を含む
+    Sequence,
+}
+
+///
+/// エントロピー推定値に基づく強度の評価
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Rating {
+    VeryWeak,
+    Weak,
+    Fair,
+    Strong,
+    VeryStrong,
+}
+
+impl Rating {
+    ///
+    /// エントロピー推定値からの評価の決定
+    ///
+    fn from_bits(bits: f64) -> Self {
+        match bits {
+            b if b < 28.0 => Self::VeryWeak,
+            b if b < 36.0 => Self::Weak,
+            b if b < 60.0 => Self::Fair,
+            b if b < 80.0 => Self::Strong,
+            _ => Self::VeryStrong,
+        }
+    }
+}
+
+///
+/// パスワードの強度の推定結果
+///
+#[derive(Debug)]
+struct Strength {
+    /// エントロピーの推定値(ビット)
+    bits: f64,
+
+    /// 検出された脆弱さの要因
+    issues: BTreeSet<Issue>,
+}
+
+///
+/// 強度の推定に用いる辞書
+///
+struct Dictionary {
+    /// よく使われるパスワード(小文字化済み)
+    common: HashSet<String>,
+
+    /// 単語(小文字化済み)
+    words: HashSet<String>,
+
+    /// 最長の単語の文字数
+    max_length: usize,
+}
+
+impl Dictionary {
+    ///
+    /// 組み込みのリストからの辞書の生成
+    ///
+    /// # 注記
+    /// 単語にはパスワード生成用の単語リストに加え、よく使われるパスワード
+    /// のうち英字のみで構成されるものを含める。
+    ///
+    fn builtin() -> Self {
+        let common = COMMON_PASSWORDS.lines()
+            .map(|line| line.trim().to_lowercase())
+            .filter(|line| !line.is_empty())
+            .collect::<HashSet<_>>();
+
+        let words = BUILTIN_WORDLIST.lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .map(|word| word.to_lowercase())
+            .chain(common.iter()
+                .filter(|word| word.chars().all(|c| c.is_alphabetic()))
+                .cloned())
+            .filter(|word| word.chars().count() >= MIN_WORD_LENGTH)
+            .collect::<HashSet<_>>();
+
+        let max_length = words.iter()
+            .map(|word| word.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        Self {common, words, max_length}
+    }
+
+    ///
+    /// 先頭から始まる最長の単語の文字数
+    ///
+    fn longest_match(&self, chars: &[char]) -> Option<usize> {
+        let limit = self.max_length.min(chars.len());
+
+        (MIN_WORD_LENGTH..=limit).rev()
+            .find(|len| self.words.contains(&chars[..*len].iter().collect::<String>()))
+    }
+
+    ///
+    /// 単語1つあたりのエントロピー(ビット)
+    ///
+    fn word_bits(&self) -> f64 {
+        (self.words.len().max(1) as f64).log2()
+    }
+
+    ///
+    /// よく使われるパスワード1つあたりのエントロピー(ビット)
+    ///
+    fn common_bits(&self) -> f64 {
+        (self.common.len().max(1) as f64).log2()
+    }
+}
+
+///
+/// パスワードに含まれる文字種から推定する文字集合の大きさ
+///
+/// # 戻り値
+/// 文字集合の大きさと含まれる文字種の数の組を返す。
+///
+fn pool_size(chars: &[char]) -> (usize, usize) {
+    let classes = [
+        (chars.iter().any(char::is_ascii_lowercase), 26),
+        (chars.iter().any(char::is_ascii_uppercase), 26),
+        (chars.iter().any(char::is_ascii_digit), 10),
+        (chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' '), 33),
+        (chars.iter().any(|c| !c.is_ascii()), 100),
+    ];
+
+    classes.iter()
+        .filter(|(found, _)| *found)
+        .fold((0, 0), |(size, count), (_, n)| (size + n, count + 1))
+}
+
+///
+/// 先頭から続く同じ文字の数
+///
+fn repeat_length(chars: &[char]) -> usize {
+    match chars.first() {
+        Some(head) => chars.iter().take_while(|c| *c == head).count(),
+        None => 0,
+    }
+}
+
+///
+/// 隣接する2文字の並びの向き(連続していない場合は`None`)
+///
+fn sequence_step(a: char, b: char) -> Option<i32> {
+    for row in KEYBOARD_ROWS {
+        if let (Some(x), Some(y)) = (row.find(a), row.find(b)) {
+            let step = y as i32 - x as i32;
+            if step.abs() == 1 {
+                return Some(step);
+            }
+        }
+    }
+
+    if a.is_ascii_alphanumeric() && b.is_ascii_alphanumeric() {
+        let step = b as i32 - a as i32;
+        if step.abs() == 1 {
+            return Some(step);
+        }
+    }
+
+    None
+}
+
+///
+/// 先頭から続く連続した文字(アルファベット順・数字順・キーボード順)の数
+///
+fn sequence_length(chars: &[char]) -> usize {
+    if chars.len() < 2 {
+        return chars.len();
+    }
+
+    let Some(step) = sequence_step(chars[0], chars[1]) else {
+        return 1;
+    };
+
+    let mut len = 2;
+    while len < chars.len() && sequence_step(chars[len - 1], chars[len]) == Some(step) {
+        len += 1;
+    }
+
+    len
+}
+
+///
+/// パスワードの強度の推定
+///
+/// # 引数
+/// * `password` - 評価対象のパスワード
+/// * `dict` - 照合に用いる辞書
+///
+/// # 戻り値
+/// エントロピーの推定値と検出された脆弱さの要因を返す。
+///
+/// # 注記
+/// 文字種から求めた文字集合の大きさを1文字あたりのエントロピーとし、先頭
+/// から順に辞書の単語・同じ文字の繰り返し・連続した文字を検出した区間はそ
+/// れぞれを1つの要素とみなして低く見積もる。
+///
+fn estimate(password: &str, dict: &Dictionary) -> Strength {
+    let chars = password.chars().collect::<Vec<_>>();
+    let lower = chars.iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect::<Vec<_>>();
+    let (pool, classes) = pool_size(&chars);
+    let char_bits = (pool.max(1) as f64).log2();
+    let mut issues = BTreeSet::new();
+
+    if chars.len() < SHORT_LENGTH {
+        issues.insert(Issue::Short);
+    }
+
+    if classes == 1 {
+        issues.insert(Issue::SingleClass);
+    }
+
+    if dict.common.contains(&lower.iter().collect::<String>()) {
+        issues.insert(Issue::CommonPassword);
+        return Strength {bits: dict.common_bits(), issues};
+    }
+
+    let mut bits = 0.0;
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        if let Some(len) = dict.longest_match(&lower[pos..]) {
+            // 大文字・小文字の変化は1ビット分のみ加算する
+            let mixed = chars[pos..pos + len].iter().any(|c| c.is_uppercase());
+            bits += dict.word_bits() + if mixed { 1.0 } else { 0.0 };
+            issues.insert(Issue::DictionaryWord);
+            pos += len;
+            continue;
+        }
+
+        let len = repeat_length(&chars[pos..]);
+        if len >= MIN_PATTERN_LENGTH {
+            bits += char_bits + (len as f64).log2();
+            issues.insert(Issue::RepeatedChars);
+            pos += len;
+            continue;
+        }
+
+        let len = sequence_length(&lower[pos..]);
+        if len >= MIN_PATTERN_LENGTH {
+            bits += char_bits + (len as f64).log2();
+            issues.insert(Issue::Sequence);
+            pos += len;
+            continue;
+        }
+
+        bits += char_bits;
+        pos += 1;
+    }
+
+    Strength {bits, issues}
+}
+
+///
+/// 秘匿情報を格納したプロパティの所在
+///
+#[derive(Clone, Debug, Serialize)]
+struct SecretRef {
+    /// サービスID
+    id: String,
+
+    /// サービス名
+    service: String,
+
+    /// プロパティ名
+    property: String,
+}
+
+///
+/// 強度が不足している秘匿情報
+///
+#[derive(Debug, Serialize)]
+struct WeakSecret {
+    /// 秘匿情報の所在
+    #[serde(flatten)]
+    secret: SecretRef,
+
+    /// エントロピーの推定値(ビット)
+    bits: u32,
+
+    /// 強度の評価
+    rating: Rating,
+
+    /// 検出された脆弱さの要因
+    issues: Vec<Issue>,
+}
+
+///
+/// 最終更新から期間が経過したエントリ
+///
+#[derive(Debug, Serialize)]
+struct StaleEntry {
+    /// サービスID
+    id: String,
+
+    /// サービス名
+    service: String,
+
+    /// 最終更新日時(記録されていない場合は`None`)
+    last_update: Option<DateTime<Local>>,
+
+    /// 最終更新からの経過日数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    days: Option<i64>,
+}
+
+///
+/// 監査結果
+///
+#[derive(Debug, Default, Serialize)]
+struct AuditReport {
+    /// 強度が不足している秘匿情報
+    weak: Vec<WeakSecret>,
+
+    /// 複数のエントリで使い回されている秘匿情報の所在のグループ
+    reused: Vec<Vec<SecretRef>>,
+
+    /// 最終更新から期間が経過したエントリ
+    stale: Vec<StaleEntry>,
+}
+
+///
+/// 監査の実行
+///
+/// # 引数
+/// * `reader` - 読み込みトランザクション
+/// * `now` - 基準となる現在時刻
+/// * `max_age_days` - 古いとみなす最終更新からの経過日数
+/// * `min_entropy` - 弱いとみなすエントロピー推定値の閾値
+///
+/// # 戻り値
+/// 監査結果を`Ok()`でラップして返す。
+///
+/// # 注記
+/// 使い回しの検出では値そのものは結果に含めず、所在のみを報告する。同一
+/// エントリ内での重複は使い回しとはみなさない。
+///
+fn audit(
+    reader: &TransactionReader,
+    now: DateTime<Local>,
+    max_age_days: u32,
+    min_entropy: u32,
+) -> Result<AuditReport> {
+    let dict = Dictionary::builtin();
+    let threshold = now - TimeDelta::days(max_age_days as i64);
+    let mut report = AuditReport::default();
+    let mut groups: Vec<Vec<SecretRef>> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for id in reader.all_service()? {
+        let entry = match reader.get(&id)? {
+            Some(entry) if !entry.is_removed() => entry,
+            _ => continue,
+        };
+
+        for (key, value) in entry.properties() {
+            if !key.ends_with('!') || value.is_empty() {
+                continue;
+            }
+
+            let secret = SecretRef {
+                id: entry.id().to_string(),
+                service: entry.service(),
+                property: key.clone(),
+            };
+
+            let strength = estimate(&value, &dict);
+            if strength.bits < min_entropy as f64
+                || strength.issues.contains(&Issue::CommonPassword)
+            {
+                report.weak.push(WeakSecret {
+                    secret: secret.clone(),
+                    bits: strength.bits.round() as u32,
+                    rating: Rating::from_bits(strength.bits),
+                    issues: strength.issues.into_iter().collect(),
+                });
+            }
+
+            let pos = *index.entry(value).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[pos].push(secret);
+        }
+
+        match entry.last_update() {
+            Some(ts) if ts >= threshold => {}
+            last_update => report.stale.push(StaleEntry {
+                id: entry.id().to_string(),
+                service: entry.service(),
+                last_update,
+                days: last_update.map(|ts| (now - ts).num_days()),
+            }),
+        }
+    }
+
+    report.reused = groups.into_iter()
+        .filter(|group| {
+            group.iter()
+                .map(|secret| &secret.id)
+                .collect::<HashSet<_>>()
+                .len() >= 2
+        })
+        .collect();
+
+    Ok(report)
+}
+
+///
+/// auditサブコマンドのコンテキスト情報をパックした構造体
+///
+struct AuditCommandContext {
+    /// データベースオブジェクト
+    manager: RefCell<EntryManager>,
+
+    /// 古いとみなす最終更新からの経過日数
+    max_age_days: u32,
+
+    /// 弱いとみなすエントロピー推定値の閾値
+    min_entropy: u32,
+
+    /// JSONで出力するか否か
+    json_output: bool,
+}
+
+impl AuditCommandContext {
+    ///
+    /// オブジェクトの生成
+    ///
+    fn new(opts: &Options, sub_opts: &AuditOpts) -> Result<Self> {
+        Ok(Self {
+            manager: RefCell::new(opts.open()?),
+            max_age_days: sub_opts.max_age_days(),
+            min_entropy: sub_opts.min_entropy(),
+            json_output: opts.json(),
+        })
+    }
+
+    ///
+    /// 監査結果の生成
+    ///
+    fn report(&self) -> Result<AuditReport> {
+        self.manager.borrow().with_read_transaction(|reader| {
+            audit(reader, Local::now(), self.max_age_days, self.min_entropy)
+        })
+    }
+}
+
+// CommandContextトレイトの実装
+impl CommandContext for AuditCommandContext {
+    fn exec(&self) -> Result<()> {
+        let report = self.report()?;
+
+        info!(
+            "audit: weak={}, reused={}, stale={}",
+            report.weak.len(),
+            report.reused.len(),
+            report.stale.len()
+        );
+
+        if self.json_output {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print!("{}", serde_yaml_ng::to_string(&report)?);
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// コマンドコンテキストの生成
+///
+pub(crate) fn build_context(opts: &Options, sub_opts: &AuditOpts)
+    -> Result<Box<dyn CommandContext>>
+{
+    Ok(Box::new(AuditCommandContext::new(opts, sub_opts)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use ulid::Ulid;
+
+    use crate::database::types::{Entry, ServiceId};
+
+    fn temp_db_path() -> PathBuf {
+        std::env::temp_dir().join(format!("pwmgr-test-{}.redb", Ulid::new()))
+    }
+
+    fn make_entry(service: &str, properties: &[(&str, &str)]) -> Entry {
+        Entry::new(
+            ServiceId::new(),
+            service.to_string(),
+            vec![],
+            vec![],
+            properties.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+        )
+    }
+
+    ///
+    /// 強度の推定が典型的なパターンを低く見積もることを確認
+    ///
+    #[test]
+    fn estimate_detects_patterns() {
+        let dict = Dictionary::builtin();
+
+        let weak = estimate("Password", &dict);
+        assert!(weak.issues.contains(&Issue::CommonPassword));
+        assert!(weak.bits < 28.0);
+
+        let weak = estimate("aaaaaa123456", &dict);
+        assert!(weak.issues.contains(&Issue::RepeatedChars));
+        assert!(weak.issues.contains(&Issue::Sequence));
+        assert!(weak.bits < 36.0);
+
+        let weak = estimate("qwertyzyx", &dict);
+        assert!(weak.issues.contains(&Issue::Sequence));
+        assert!(weak.issues.contains(&Issue::SingleClass));
+
+        let weak = estimate("Dragon2024", &dict);
+        assert!(weak.issues.contains(&Issue::DictionaryWord));
+
+        let strong = estimate("vT7#qL9!xW2$kP4m", &dict);
+        assert!(strong.issues.is_empty());
+        assert!(strong.bits >= DEFAULT_MIN_ENTROPY as f64);
+    }
+
+    ///
+    /// 弱い秘匿情報・使い回し・古いエントリが検出されることを確認
+    ///
+    #[test]
+    fn audit_reports_weak_reused_and_stale() {
+        let mut mgr = EntryManager::open_for_test(temp_db_path()).unwrap();
+        let now = Local::now();
+        let shared = "vT7#qL9!xW2$kP4m";
+
+        let alpha = make_entry("Alpha", &[
+            ("password!", shared),
+            ("pin!", "hunter2"),
+            ("user", "password"),
+        ]);
+
+        let mut beta = make_entry("Beta", &[("password!", shared)]);
+        beta.set_last_update(now - TimeDelta::days(400));
+
+        // 同一エントリ内の重複は使い回しとはみなさない
+        let gamma = make_entry("Gamma", &[
+            ("password!", "Zq8%rN3^mB6&yH1*"),
+            ("backup!", "Zq8%rN3^mB6&yH1*"),
+        ]);
+
+        // 削除済みのエントリは対象外
+        let mut removed = make_entry("Removed", &[("password!", shared)]);
+        removed.set_removed(true);
+
+        for entry in [&alpha, &beta, &gamma, &removed] {
+            mgr.put(entry).unwrap();
+        }
+
+        let report = mgr.with_read_transaction(|reader| {
+            audit(reader, now, DEFAULT_MAX_AGE_DAYS, DEFAULT_MIN_ENTROPY)
+        }).unwrap();
+
+        assert_eq!(report.weak.len(), 1);
+        assert_eq!(report.weak[0].secret.service, "Alpha");
+        assert_eq!(report.weak[0].secret.property, "pin!");
+
+        assert_eq!(report.reused.len(), 1);
+        let mut services = report.reused[0].iter()
+            .map(|secret| secret.service.as_str())
+            .collect::<Vec<_>>();
+        services.sort();
+        assert_eq!(services, ["Alpha", "Beta"]);
+
+        assert_eq!(report.stale.len(), 1);
+        assert_eq!(report.stale[0].service, "Beta");
+        assert_eq!(report.stale[0].days, Some(400));
+
+        // 出力に秘匿情報の値が含まれないこと
+        let yaml = serde_yaml_ng::to_string(&report).unwrap();
+        assert!(!yaml.contains(shared));
+        assert!(!yaml.contains("hunter2"));
+    }
+}
@@ -0,0 +1,501 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! クリップボードの操作と自動消去
+//!
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process::{self, Stdio};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+
+use anyhow::{anyhow, Context, Result};
+use blake2::{Blake2s256, Digest};
+use clap::ValueEnum;
+use log::{debug, info};
+
+use crate::cmd_args::{ClipboardBackend, ClipboardClearOpts, Options};
+use super::CommandContext;
+
+/// クリップボードを消去するまでの秒数のデフォルト値
+pub(crate) const DEFAULT_CLEAR_AFTER: u64 = 45;
+
+///
+/// クリップボードのバックエンドが実装するトレイト
+///
+pub(crate) trait Clipboard {
+    ///
+    /// バックエンドの種別
+    ///
+    fn backend(&self) -> ClipboardBackend;
+
+    ///
+    /// クリップボードへの書き込み
+    ///
+    fn copy(&self, text: &str) -> Result<()>;
+
+    ///
+    /// クリップボードの内容の読み出し
+    ///
+    /// # 戻り値
+    /// 内容を`Ok()`でラップして返す。読み出しに対応していない場合はエラーを
+    /// 返す。
+    ///
+    fn paste(&self) -> Result<String>;
+
+    ///
+    /// 内容の読み出しに対応しているか否か
+    ///
+    fn can_paste(&self) -> bool {
+        true
+    }
+}
+
+///
+/// 外部コマンドを用いるバックエンド
+///
+struct CommandClipboard {
+    /// バックエンドの種別
+    backend: ClipboardBackend,
+
+    /// 書き込みに用いるコマンドライン(標準入力で内容を渡す)
+    copy_cmd: &'static [&'static str],
+
+    /// 読み出しに用いるコマンドライン(標準出力で内容を受け取る)
+    paste_cmd: &'static [&'static str],
+}
+
+impl CommandClipboard {
+    ///
+    /// wl-clipboard(Wayland)
+    ///
+    const WL_COPY: Self = Self {
+        backend: ClipboardBackend::WlCopy,
+        copy_cmd: &["wl-copy"],
+        paste_cmd: &["wl-paste", "--no-newline"],
+    };
+
+    ///
+    /// xclip(X11)
+    ///
+    const XCLIP: Self = Self {
+        backend: ClipboardBackend::Xclip,
+        copy_cmd: &["xclip", "-selection", "clipboard", "-in"],
+        paste_cmd: &["xclip", "-selection", "clipboard", "-out"],
+    };
+
+    ///
+    /// xsel(X11)
+    ///
+    const XSEL: Self = Self {
+        backend: ClipboardBackend::Xsel,
+        copy_cmd: &["xsel", "--clipboard", "--input"],
+        paste_cmd: &["xsel", "--clipboard", "--output"],
+    };
+}
+
+impl Clipboard for CommandClipboard {
+    fn backend(&self) -> ClipboardBackend {
+        self.backend
+    }
+
+    fn copy(&self, text: &str) -> Result<()> {
+        // xclip等はクリップボードを保持するため常駐するので、標準出力は
+        // 引き継がせない
+        let mut child = process::Command::new(self.copy_cmd[0])
+            .args(&self.copy_cmd[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| {
+                format!("{}の起動に失敗しました", self.copy_cmd[0])
+            })?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(anyhow!(
+                "{}が異常終了しました: {}", self.copy_cmd[0], status
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn paste(&self) -> Result<String> {
+        let output = process::Command::new(self.paste_cmd[0])
+            .args(&self.paste_cmd[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .with_context(|| {
+                format!("{}の起動に失敗しました", self.paste_cmd[0])
+            })?;
+
+        // 空のクリップボードを異常終了として報告するコマンドもあるため、終了
+        // 状態は確認しない
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+///
+/// 端末のエスケープシーケンス(OSC 52)を用いるバックエンド
+///
+/// # 注記
+/// SSH越しでも手元の端末のクリップボードに書き込めるが、内容の読み出しには
+/// 対応しない。空の内容の書き込み(`ESC]52;c;BEL`)で消去する。
+///
+struct Osc52Clipboard;
+
+impl Clipboard for Osc52Clipboard {
+    fn backend(&self) -> ClipboardBackend {
+        ClipboardBackend::Osc52
+    }
+
+    fn copy(&self, text: &str) -> Result<()> {
+        let seq = format!("\x1b]52;c;{}\x07", encode_base64(text.as_bytes()));
+
+        // 標準出力がリダイレクトされていても端末に届くよう制御端末に書く
+        match OpenOptions::new().write(true).open("/dev/tty") {
+            Ok(mut tty) => tty.write_all(seq.as_bytes())?,
+            Err(_) => io::stdout().write_all(seq.as_bytes())?,
+        }
+
+        Ok(())
+    }
+
+    fn paste(&self) -> Result<String> {
+        Err(anyhow!("OSC 52ではクリップボードを読み出せません"))
+    }
+
+    fn can_paste(&self) -> bool {
+        false
+    }
+}
+
+///
+/// バックエンドの生成
+///
+/// # 引数
+/// * `backend` - 使用するバックエンド
+///
+/// # 戻り値
+/// バックエンドのオブジェクトを返す。
+///
+/// # 注記
+/// `Auto`の場合は、Waylandの環境ではwl-copyを、X11の環境ではxclip、xselの
+/// 順にコマンドが見つかったものを用い、いずれも使用できない場合はOSC 52を
+/// 用いる。
+///
+pub(crate) fn open(backend: ClipboardBackend) -> Box<dyn Clipboard> {
+    match backend {
+        ClipboardBackend::WlCopy => Box::new(CommandClipboard::WL_COPY),
+        ClipboardBackend::Xclip => Box::new(CommandClipboard::XCLIP),
+        ClipboardBackend::Xsel => Box::new(CommandClipboard::XSEL),
+        ClipboardBackend::Osc52 => Box::new(Osc52Clipboard),
+        ClipboardBackend::Auto => {
+            let wayland = env::var_os("WAYLAND_DISPLAY").is_some();
+            let x11 = env::var_os("DISPLAY").is_some();
+
+            let candidates = [
+                (wayland, CommandClipboard::WL_COPY),
+                (x11, CommandClipboard::XCLIP),
+                (x11, CommandClipboard::XSEL),
+            ];
+
+            for (available, clipboard) in candidates {
+                if available && find_in_path(clipboard.copy_cmd[0]) {
+                    return Box::new(clipboard);
+                }
+            }
+
+            Box::new(Osc52Clipboard)
+        }
+    }
+}
+
+///
+/// 値をクリップボードにコピーし、自動消去のプロセスを起動する
+///
+/// # 引数
+/// * `clipboard` - 使用するバックエンド
+/// * `value` - コピーする値
+/// * `clear_after` - 消去するまでの秒数(0の場合は消去しない)
+/// * `force` - 読み出しに対応しないバックエンドでも消去するか否か
+///
+/// # 戻り値
+/// 自動消去のプロセスを起動した場合は`Ok(true)`を、消去を行わない場合は
+/// `Ok(false)`を返す。
+///
+/// # 注記
+/// 消去は自身を`clipboard-clear`サブコマンドで起動し直して行う。値そのもの
+/// はプロセス引数に残さないよう、値のハッシュ値のみを標準入力で渡す。読み
+/// 出しに対応しないバックエンド(OSC 52)では利用者が後からコピーした内容を
+/// 消してしまう恐れがあるため、`force`が指定された場合のみ消去のプロセスを
+/// 起動する。
+///
+pub(crate) fn copy_with_clear(
+    clipboard: &dyn Clipboard,
+    value: &str,
+    clear_after: u64,
+    force: bool,
+) -> Result<bool> {
+    clipboard.copy(value)?;
+
+    if clear_after == 0 || !(clipboard.can_paste() || force) {
+        return Ok(false);
+    }
+
+    let backend = clipboard.backend()
+        .to_possible_value()
+        .ok_or_else(|| anyhow!("unknown clipboard backend"))?;
+
+    let mut command = process::Command::new(env::current_exe()?);
+    command
+        .arg("clipboard-clear")
+        .arg("--after")
+        .arg(clear_after.to_string())
+        .arg("--backend")
+        .arg(backend.get_name())
+        .args(force.then_some("--force"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    // 端末を閉じても消去されるよう、プロセスグループを分離する
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let mut child = command.spawn().context("spawn clipboard-clear")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "{}", digest(value))?;
+    }
+
+    debug!("clipboard-clear started: pid={}", child.id());
+    Ok(true)
+}
+
+///
+/// クリップボードの内容が変わっていない場合のみの消去
+///
+/// # 引数
+/// * `clipboard` - 使用するバックエンド
+/// * `expected` - コピーした値のハッシュ値
+/// * `force` - 読み出しに対応しないバックエンドでも消去するか否か
+///
+/// # 戻り値
+/// 消去した場合は`Ok(true)`を、他の内容に置き換わっていた場合や内容を確認
+/// できなかった場合は`Ok(false)`を返す。
+///
+/// # 注記
+/// 読み出しに対応しないバックエンドでは内容を確認できないため、`force`が
+/// 指定された場合のみ無条件に消去する。
+///
+fn clear_if_unchanged(clipboard: &dyn Clipboard, expected: &str, force: bool)
+    -> Result<bool>
+{
+    let unchanged = if clipboard.can_paste() {
+        digest(&clipboard.paste()?) == expected
+    } else {
+        force
+    };
+
+    if !unchanged {
+        return Ok(false);
+    }
+
+    clipboard.copy("")?;
+    Ok(true)
+}
+
+///
+/// 値のハッシュ値(16進表記)の計算
+///
+fn digest(value: &str) -> String {
+    Blake2s256::digest(value.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+///
+/// コマンドがPATH上に存在するか否か
+///
+fn find_in_path(command: &str) -> bool {
+    env::var_os("PATH")
+        .map(|paths| {
+            env::split_paths(&paths)
+                .any(|dir| Path::new(&dir).join(command).is_file())
+        })
+        .unwrap_or(false)
+}
+
+///
+/// Base64(RFC 4648)のエンコード
+///
+fn encode_base64(data: &[u8]) -> String {
+    const TABLE: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let byte = |i: usize| chunk.get(i).copied().unwrap_or(0);
+        let bits = u32::from_be_bytes([0, byte(0), byte(1), byte(2)]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (bits >> (18 - 6 * i)) & 0x3f;
+                encoded.push(TABLE[index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+///
+/// clipboard-clearサブコマンドのコンテキスト情報をパックした構造体
+///
+struct ClipboardClearCommandContext {
+    /// 使用するバックエンド
+    clipboard: Box<dyn Clipboard>,
+
+    /// 消去するまでの時間
+    after: Duration,
+
+    /// 読み出しに対応しないバックエンドでも消去するか否か
+    force: bool,
+}
+
+// CommandContextトレイトの実装
+impl CommandContext for ClipboardClearCommandContext {
+    fn exec(&self) -> Result<()> {
+        let mut expected = String::new();
+        io::stdin().lock().read_line(&mut expected)?;
+
+        thread::sleep(self.after);
+
+        let cleared = clear_if_unchanged(
+            self.clipboard.as_ref(),
+            expected.trim(),
+            self.force
+        )?;
+        info!("clipboard-clear: cleared={}", cleared);
+
+        Ok(())
+    }
+}
+
+///
+/// コマンドコンテキストの生成
+///
+pub(crate) fn build_context(_opts: &Options, sub_opts: &ClipboardClearOpts)
+    -> Result<Box<dyn CommandContext>>
+{
+    Ok(Box::new(ClipboardClearCommandContext {
+        clipboard: open(sub_opts.backend()),
+        after: Duration::from_secs(sub_opts.after()),
+        force: sub_opts.is_force(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    ///
+    /// 試験用のメモリ上のクリップボード
+    ///
+    struct MemoryClipboard(RefCell<String>, bool);
+
+    impl Clipboard for MemoryClipboard {
+        fn backend(&self) -> ClipboardBackend {
+            ClipboardBackend::Auto
+        }
+
+        fn copy(&self, text: &str) -> Result<()> {
+            *self.0.borrow_mut() = text.to_string();
+            Ok(())
+        }
+
+        fn paste(&self) -> Result<String> {
+            Ok(self.0.borrow().clone())
+        }
+
+        fn can_paste(&self) -> bool {
+            self.1
+        }
+    }
+
+    ///
+    /// クリップボードの内容がコピーした値のままの場合のみ消去されることを
+    /// 確認
+    ///
+    #[test]
+    fn clear_only_when_unchanged() {
+        let clipboard = MemoryClipboard(RefCell::new(String::new()), true);
+        let expected = digest("secret");
+
+        clipboard.copy("secret").unwrap();
+        assert!(clear_if_unchanged(&clipboard, &expected, false).unwrap());
+        assert_eq!(clipboard.paste().unwrap(), "");
+
+        clipboard.copy("something else").unwrap();
+        assert!(!clear_if_unchanged(&clipboard, &expected, true).unwrap());
+        assert_eq!(clipboard.paste().unwrap(), "something else");
+    }
+
+    ///
+    /// 読み出せないバックエンドでは明示的に指定された場合のみ消去されること
+    /// を確認
+    ///
+    #[test]
+    fn clear_unreadable_only_when_forced() {
+        let clipboard = MemoryClipboard(RefCell::new(String::new()), false);
+        let expected = digest("secret");
+
+        clipboard.copy("something else").unwrap();
+        assert!(!clear_if_unchanged(&clipboard, &expected, false).unwrap());
+        assert_eq!(clipboard.0.borrow().as_str(), "something else");
+
+        // 自動消去のプロセスも起動しない
+        assert!(!copy_with_clear(&clipboard, "secret", 30, false).unwrap());
+        assert_eq!(clipboard.0.borrow().as_str(), "secret");
+
+        assert!(clear_if_unchanged(&clipboard, &expected, true).unwrap());
+        assert_eq!(clipboard.0.borrow().as_str(), "");
+    }
+
+    ///
+    /// Base64のエンコード結果を確認(RFC 4648 10章の試験値)
+    ///
+    #[test]
+    fn encode_base64_vectors() {
+        let vectors = [
+            ("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy"),
+        ];
+
+        for (plain, encoded) in vectors {
+            assert_eq!(encode_base64(plain.as_bytes()), encoded);
+        }
+    }
+}
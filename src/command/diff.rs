@@ -0,0 +1,413 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! diffサブコマンドの実装
+//!
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::cmd_args::{DiffOpts, Options};
+use crate::database::types::{Entry, ServiceId};
+use crate::database::{EntryManager, TransactionReadable};
+use super::CommandContext;
+
+/// マスク時に秘匿プロパティの値の代わりに表示する文字列
+const MASKED_VALUE: &str = "<< SECRET >>";
+
+///
+/// 単一の値の変更
+///
+#[derive(Debug, PartialEq, Serialize)]
+struct ValueChange<T> {
+    /// 比較元の値
+    old: T,
+
+    /// 比較先の値
+    new: T,
+}
+
+///
+/// 集合(別名・タグ)の変更
+///
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct SetChange {
+    /// 追加された要素
+    added: Vec<String>,
+
+    /// 削除された要素
+    removed: Vec<String>,
+}
+
+impl SetChange {
+    ///
+    /// 2つの集合の比較
+    ///
+    fn new(old: &[String], new: &[String]) -> Self {
+        let old: BTreeSet<&String> = old.iter().collect();
+        let new: BTreeSet<&String> = new.iter().collect();
+
+        Self {
+            added: new.difference(&old).map(|s| s.to_string()).collect(),
+            removed: old.difference(&new).map(|s| s.to_string()).collect(),
+        }
+    }
+
+    ///
+    /// 変更が無いか否か
+    ///
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+///
+/// プロパティの変更
+///
+/// # 注記
+/// 追加されたキーは`old`が、削除されたキーは`new`が`None`となる。
+///
+#[derive(Debug, PartialEq, Serialize)]
+struct PropertyChange {
+    /// キー名
+    key: String,
+
+    /// 比較元の値
+    #[serde(skip_serializing_if = "Option::is_none")]
+    old: Option<String>,
+
+    /// 比較先の値
+    #[serde(skip_serializing_if = "Option::is_none")]
+    new: Option<String>,
+}
+
+///
+/// 2つの版の間の差分
+///
+#[derive(Debug, Serialize)]
+struct EntryDiff {
+    /// サービスID
+    id: String,
+
+    /// 比較元の版の番号
+    from: u64,
+
+    /// 比較先の版の番号
+    to: u64,
+
+    /// サービス名の変更
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service: Option<ValueChange<String>>,
+
+    /// 削除フラグの変更
+    #[serde(skip_serializing_if = "Option::is_none")]
+    removed: Option<ValueChange<bool>>,
+
+    /// 別名の変更
+    #[serde(skip_serializing_if = "SetChange::is_empty")]
+    aliases: SetChange,
+
+    /// タグの変更
+    #[serde(skip_serializing_if = "SetChange::is_empty")]
+    tags: SetChange,
+
+    /// プロパティの変更(キー順)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    properties: Vec<PropertyChange>,
+}
+
+impl EntryDiff {
+    ///
+    /// 2つの版の比較
+    ///
+    /// # 引数
+    /// * `from` - 比較元の版の番号とエントリ
+    /// * `to` - 比較先の版の番号とエントリ
+    ///
+    /// # 戻り値
+    /// 差分を返す。
+    ///
+    /// # 注記
+    /// 秘匿プロパティも復号された値で比較するため、双方のエントリは解錠済み
+    /// のデータベースから読み出しておくこと。
+    ///
+    fn new(from: (u64, &Entry), to: (u64, &Entry)) -> Self {
+        let (from_rev, old) = from;
+        let (to_rev, new) = to;
+
+        let service = Some(ValueChange {old: old.service(), new: new.service()})
+            .filter(|change| change.old != change.new);
+        let removed = Some(ValueChange {
+            old: old.is_removed(),
+            new: new.is_removed(),
+        }).filter(|change| change.old != change.new);
+
+        let old_props = old.properties();
+        let new_props = new.properties();
+        let keys: BTreeSet<&String> = old_props.keys()
+            .chain(new_props.keys())
+            .collect();
+
+        let properties = keys.into_iter()
+            .map(|key| PropertyChange {
+                key: key.clone(),
+                old: old_props.get(key).cloned(),
+                new: new_props.get(key).cloned(),
+            })
+            .filter(|change| change.old != change.new)
+            .collect();
+
+        Self {
+            id: new.id().to_string(),
+            from: from_rev,
+            to: to_rev,
+            service,
+            removed,
+            aliases: SetChange::new(&old.aliases(), &new.aliases()),
+            tags: SetChange::new(&old.tags(), &new.tags()),
+            properties,
+        }
+    }
+
+    ///
+    /// 秘匿プロパティの値のマスク
+    ///
+    fn mask_secret_properties(&mut self) {
+        for change in self.properties.iter_mut() {
+            if !change.key.ends_with('!') {
+                continue;
+            }
+
+            let values = [&mut change.old, &mut change.new];
+            for value in values.into_iter().flatten() {
+                *value = MASKED_VALUE.to_string();
+            }
+        }
+    }
+
+    ///
+    /// テキスト形式での出力
+    ///
+    fn print(&self) {
+        println!("--- {} (revision {})", self.id, self.from);
+        println!("+++ {} (revision {})", self.id, self.to);
+
+        if let Some(change) = &self.service {
+            println!("~ service: {} -> {}", change.old, change.new);
+        }
+
+        if let Some(change) = &self.removed {
+            println!("~ removed: {} -> {}", change.old, change.new);
+        }
+
+        for (label, set) in [("alias", &self.aliases), ("tag", &self.tags)] {
+            for item in &set.added {
+                println!("+ {}: {}", label, item);
+            }
+
+            for item in &set.removed {
+                println!("- {}: {}", label, item);
+            }
+        }
+
+        for change in &self.properties {
+            match (&change.old, &change.new) {
+                (None, Some(new)) => println!("+ {}: {}", change.key, new),
+                (Some(old), None) => println!("- {}: {}", change.key, old),
+                (Some(old), Some(new)) => {
+                    println!("~ {}: {} -> {}", change.key, old, new)
+                }
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+///
+/// diffサブコマンドのコンテキスト情報をパックした構造体
+///
+struct DiffCommandContext {
+    /// データベースオブジェクト
+    manager: RefCell<EntryManager>,
+
+    /// サブコマンドオプション
+    opts: DiffOpts,
+
+    /// JSONで出力するか否か
+    json_output: bool,
+}
+
+impl DiffCommandContext {
+    ///
+    /// オブジェクトの生成
+    ///
+    /// # 注記
+    /// 秘匿プロパティの変更の有無を判定するため、マスクする場合も解錠する。
+    ///
+    fn new(opts: &Options, sub_opts: &DiffOpts) -> Result<Self> {
+        Ok(Self {
+            manager: RefCell::new(opts.open()?),
+            opts: sub_opts.clone(),
+            json_output: opts.json(),
+        })
+    }
+}
+
+// CommandContextトレイトの実装
+impl CommandContext for DiffCommandContext {
+    fn exec(&self) -> Result<()> {
+        let id_str = self.opts.id();
+        let id = ServiceId::from_string(&id_str)
+            .map_err(|_| anyhow!("IDの形式が不正です: {}", id_str))?;
+
+        let mut diff = self.manager.borrow().with_read_transaction(|reader| {
+            let current = reader.get(&id)?
+                .ok_or_else(|| {
+                    anyhow!("指定されたIDのエントリが見つかりません: {}", id)
+                })?;
+
+            // 現在のエントリは最後の版の次の番号とする
+            let mut history = reader.revisions(&id)?;
+            let next = history.last().map_or(1, |(rev, _)| rev + 1);
+            history.push((next, current));
+
+            diff_revisions(&history, self.opts.rev_a(), self.opts.rev_b())
+        })?;
+
+        if self.opts.is_masked() {
+            diff.mask_secret_properties();
+        }
+
+        if self.json_output {
+            println!("{}", serde_json::to_string_pretty(&diff)?);
+        } else {
+            diff.print();
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// 版の一覧から指定された2つの版の差分を求める
+///
+/// # 引数
+/// * `history` - 版の番号とエントリの組のリスト(末尾が現在のエントリ)
+/// * `rev_a` - 比較元の版の番号(`None`の場合は比較先の一つ前の版)
+/// * `rev_b` - 比較先の版の番号(`None`の場合は現在の版)
+///
+/// # 戻り値
+/// 差分を`Ok()`でラップして返す。
+///
+fn diff_revisions(
+    history: &[(u64, Entry)],
+    rev_a: Option<u64>,
+    rev_b: Option<u64>,
+) -> Result<EntryDiff> {
+    let find = |rev: u64| {
+        history.iter()
+            .find(|(r, _)| *r == rev)
+            .map(|(_, entry)| (rev, entry))
+            .ok_or_else(|| anyhow!("指定された版が見つかりません: {}", rev))
+    };
+
+    let to = match rev_b {
+        Some(rev) => find(rev)?,
+        None => history.last()
+            .map(|(rev, entry)| (*rev, entry))
+            .ok_or_else(|| anyhow!("エントリの版がありません"))?,
+    };
+
+    let from = match rev_a {
+        Some(rev) => find(rev)?,
+        None if to.0 > 1 => find(to.0 - 1)?,
+        None => return Err(anyhow!("比較する過去の版がありません")),
+    };
+
+    Ok(EntryDiff::new(from, to))
+}
+
+///
+/// コマンドコンテキストの生成
+///
+pub(crate) fn build_context(opts: &Options, sub_opts: &DiffOpts)
+    -> Result<Box<dyn CommandContext>>
+{
+    Ok(Box::new(DiffCommandContext::new(opts, sub_opts)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn make_entry(
+        id: &ServiceId,
+        aliases: &[&str],
+        props: &[(&str, &str)],
+    ) -> Entry {
+        Entry::new(
+            id.clone(),
+            "svc".to_string(),
+            aliases.iter().map(|s| s.to_string()).collect(),
+            vec![],
+            props.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+        )
+    }
+
+    ///
+    /// 既定では直前の版と現在の版を比較し、秘匿プロパティの値がマスクされる
+    /// ことを確認
+    ///
+    #[test]
+    fn diff_reports_changes_and_masks_secrets() {
+        let id = ServiceId::new();
+        let history = vec![
+            (1, make_entry(&id, &["a"], &[("user", "alice")])),
+            (2, make_entry(&id, &["a"], &[("user", "alice"), ("pass!", "p1")])),
+            (3, make_entry(&id, &["b"], &[("pass!", "p2"), ("url", "x")])),
+        ];
+
+        let mut diff = diff_revisions(&history, None, None).unwrap();
+        assert_eq!((diff.from, diff.to), (2, 3));
+        assert!(diff.service.is_none());
+        assert_eq!(diff.aliases.added, vec!["b".to_string()]);
+        assert_eq!(diff.aliases.removed, vec!["a".to_string()]);
+
+        diff.mask_secret_properties();
+        assert_eq!(diff.properties, vec![
+            PropertyChange {
+                key: "pass!".to_string(),
+                old: Some(MASKED_VALUE.to_string()),
+                new: Some(MASKED_VALUE.to_string()),
+            },
+            PropertyChange {
+                key: "url".to_string(),
+                old: None,
+                new: Some("x".to_string()),
+            },
+            PropertyChange {
+                key: "user".to_string(),
+                old: Some("alice".to_string()),
+                new: None,
+            },
+        ]);
+
+        // 版を指定した場合
+        let diff = diff_revisions(&history, Some(1), Some(2)).unwrap();
+        assert_eq!(diff.properties.len(), 1);
+        assert_eq!(diff.properties[0].new, Some("p1".to_string()));
+
+        assert!(diff_revisions(&history, Some(9), None).is_err());
+        assert!(diff_revisions(&history[..1], None, None).is_err());
+    }
+}
@@ -22,6 +22,7 @@ use serde_yaml_ng;
 use crate::cmd_args::{EditOpts, Options};
 use crate::command::prompt::{Prompter, StdPrompter};
 use crate::command::editor::{default_editor_launcher, rewrite_id_line};
+use crate::command::generator::{check_policy, expand_placeholders};
 use crate::database::EntryManager;
 use crate::database::types::{Entry, ServiceId};
 use super::CommandContext;
@@ -171,6 +172,37 @@ impl CommandContext for EditCommandContext {
                 }
             }
 
+            /*
+             * パスワード生成のプレースホルダの展開
+             */
+            let policy = entry_new.policy();
+            let properties = match expand_placeholders(
+                entry_new.properties(),
+                policy.as_ref()
+            ) {
+                Ok(properties) => properties,
+                Err(err) => {
+                    if self.prompter.ask_retry(
+                        &format!("パスワードの生成に失敗しました: {err:#}")
+                    )? {
+                        continue;
+                    } else {
+                        return Err(err);
+                    }
+                }
+            };
+
+            /*
+             * パスワードポリシーへの適合の確認
+             */
+            if let Err(err) = check_policy(&properties, policy.as_ref()) {
+                if self.prompter.ask_retry(&format!("{err:#}"))? {
+                    continue;
+                } else {
+                    return Err(err);
+                }
+            }
+
             /*
              * 正規化して保存
              */
@@ -179,10 +211,11 @@ impl CommandContext for EditCommandContext {
                 entry_new.service(),
                 entry_new.aliases(),
                 entry_new.tags(),
-                entry_new.properties(),
+                properties,
             );
             let mut entry_norm = entry_norm;
             entry_norm.set_removed(entry_new.is_removed());
+            entry_norm.set_policy(policy);
             entry_norm.set_last_update_now();
 
             self.manager.borrow_mut().put(&entry_norm)?;
@@ -198,6 +231,7 @@ impl CommandContext for EditCommandContext {
 mod tests {
     use super::*;
     use crate::command::prompt::test::QueuePrompter;
+    use crate::database::types::CharClass;
     use crate::database::EntryManager;
     use std::collections::BTreeMap;
     use std::sync::atomic::{AtomicUsize, Ordering};
@@ -210,7 +244,7 @@ mod tests {
 
     fn build_mgr_with_entry() -> (EntryManager, ServiceId) {
         let path = temp_db_path();
-        let mut mgr = EntryManager::open(path).unwrap();
+        let mut mgr = EntryManager::open_for_test(path).unwrap();
         let id = ServiceId::new();
 
         let entry = Entry::new(
@@ -322,6 +356,64 @@ mod tests {
         ctx.exec().unwrap();
     }
 
+    ///
+    /// パスワードポリシーに違反した場合に再編集を促し、生成されるパスワード
+    /// がポリシーを満たすこと
+    ///
+    #[test]
+    fn edit_retry_on_policy_violation() {
+        let (mgr, id) = build_mgr_with_entry();
+        let target_id = id.to_string();
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let editor_attempts = attempts.clone();
+        let editor_id = id.clone();
+
+        let editor = Arc::new(move |path: &Path| -> Result<()> {
+            let n = editor_attempts.fetch_add(1, Ordering::SeqCst);
+            let password = if n == 0 { "short" } else { "\"{{GEN}}\"" };
+
+            let content = format!(
+                concat!(
+                    "id: \"{id}\"\n",
+                    "service: \"Alpha\"\n",
+                    "aliases: []\n",
+                    "tags: []\n",
+                    "properties:\n",
+                    "  password!: {password}\n",
+                    "policy:\n",
+                    "  min_length: 30\n",
+                    "  require: [digit, sym]\n",
+                    "  forbid: \"#$%\"\n",
+                ),
+                id = editor_id,
+                password = password,
+            );
+            fs::write(path, content)?;
+            Ok(())
+        });
+
+        let ctx = EditCommandContext::with_deps(
+            mgr,
+            Arc::new(QueuePrompter::new(vec![true])),
+            editor,
+            target_id,
+        );
+
+        ctx.exec().unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        let mut mgr = ctx.manager.borrow_mut();
+        let entry = mgr.get(&id).unwrap().unwrap();
+        let policy = entry.policy().unwrap();
+        let password = entry.properties().get("password!").unwrap().clone();
+
+        assert_eq!(password.chars().count(), 30);
+        assert!(policy.violations(&password).is_empty());
+        assert!(password.chars().all(|ch| ch.is_ascii_digit()
+            || CharClass::SYMBOLS.contains(ch)));
+    }
+
     ///
     /// YAML解釈エラーで再編集を拒否するとエラーになること
     ///
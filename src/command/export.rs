@@ -116,7 +116,7 @@ mod tests {
 
     fn build_mgr_with_entries() -> EntryManager {
         let path = temp_db_path();
-        let mut mgr = EntryManager::open(path).unwrap();
+        let mut mgr = EntryManager::open_for_test(path).unwrap();
 
         let e1 = Entry::new(
             ServiceId::new(),
@@ -174,7 +174,7 @@ mod tests {
     #[test]
     fn export_empty_outputs_empty_array() {
         let path = temp_db_path();
-        let mgr = EntryManager::open(path).unwrap();
+        let mgr = EntryManager::open_for_test(path).unwrap();
 
         let opts = ExportOpts::new_for_test(None);
 
@@ -0,0 +1,283 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//! gcサブコマンドの実装
+
+use std::cell::RefCell;
+
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeDelta};
+use log::{debug, info};
+use serde::Serialize;
+
+use crate::cmd_args::{GcOpts, Options};
+use crate::database::types::TrustedPeer;
+use crate::database::{EntryManager, TransactionReadable, TransactionWriter};
+use super::CommandContext;
+
+/// 墓標を保持する期間(日数)のデフォルト値
+pub(crate) const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+///
+/// 回収の対象となった墓標
+///
+#[derive(Debug, Serialize)]
+struct Tombstone {
+    /// サービスID
+    id: String,
+
+    /// サービス名
+    service: String,
+
+    /// 受信を確認できていない同期相手のノードID
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    waiting_for: Vec<String>,
+}
+
+///
+/// gcの結果のレポート
+///
+#[derive(Debug, Default, Serialize)]
+struct GcReport {
+    /// 取り除いた墓標
+    purged: Vec<Tombstone>,
+
+    /// 保持期間を過ぎたが同期相手の受信を確認できていない墓標
+    pending: Vec<Tombstone>,
+}
+
+///
+/// gcサブコマンドのコンテキスト情報をパックした構造体
+///
+struct GcCommandContext {
+    /// データベースオブジェクト
+    manager: RefCell<EntryManager>,
+
+    /// 墓標を保持する期間(日数)
+    retention_days: u32,
+
+    /// ドライランか否か
+    dry_run: bool,
+
+    /// JSONで出力するか否か
+    json_output: bool,
+}
+
+impl GcCommandContext {
+    ///
+    /// オブジェクトの生成
+    ///
+    fn new(opts: &Options, sub_opts: &GcOpts) -> Result<Self> {
+        Ok(Self {
+            manager: RefCell::new(opts.open_locked()?),
+            retention_days: sub_opts.retention_days(),
+            dry_run: sub_opts.is_dry_run(),
+            json_output: opts.json(),
+        })
+    }
+}
+
+impl CommandContext for GcCommandContext {
+    fn exec(&self) -> Result<()> {
+        let cutoff = Local::now() - TimeDelta::days(self.retention_days as i64);
+
+        let report = self.manager.borrow().with_write_transaction(|writer| {
+            // ドライランの場合は通常通り処理した上で書き込みを全て破棄する
+            if self.dry_run {
+                writer.discard();
+            }
+
+            collect(writer, cutoff)
+        })?;
+
+        info!(
+            "gc: purged={}, pending={}, dry_run={}",
+            report.purged.len(),
+            report.pending.len(),
+            self.dry_run
+        );
+
+        if self.json_output {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print!("{}", serde_yaml_ng::to_string(&report)?);
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// 墓標の回収
+///
+/// # 引数
+/// * `writer` - 書き込みトランザクション
+/// * `cutoff` - この日時より前に削除された墓標を回収の対象とする
+///
+/// # 戻り値
+/// 回収の結果を`Ok()`でラップして返す。
+///
+/// # 注記
+/// 同期相手が残っている墓標を持たないまま取り除くと、相手から古い版が再び
+/// 送られた際にエントリが復活してしまう。このため保持期間を過ぎた墓標のう
+/// ち、削除の変更を全ての同期相手が受信済みであることを確認できたもののみ
+/// を取り除く。
+///
+fn collect(writer: &mut TransactionWriter, cutoff: DateTime<Local>)
+    -> Result<GcReport>
+{
+    let peers = writer.all_peers()?;
+    let mut report = GcReport::default();
+
+    for id in writer.all_service()? {
+        let entry = match writer.get(&id)? {
+            Some(entry) if entry.is_removed() => entry,
+            _ => continue,
+        };
+
+        // 更新日時の無いエントリは保持期間を過ぎたものとして扱う
+        if entry.last_update().is_some_and(|ts| ts >= cutoff) {
+            continue;
+        }
+
+        let seq = writer.entry_seq(&id)?.unwrap_or_default();
+        let tombstone = Tombstone {
+            id: id.to_string(),
+            service: entry.service(),
+            waiting_for: unacked_peers(&peers, seq),
+        };
+
+        if tombstone.waiting_for.is_empty() {
+            debug!("gc: purge id={}", id);
+            writer.remove(&id)?;
+            report.purged.push(tombstone);
+
+        } else {
+            debug!("gc: pending id={} ({:?})", id, tombstone.waiting_for);
+            report.pending.push(tombstone);
+        }
+    }
+
+    Ok(report)
+}
+
+///
+/// 変更の受信を確認できていない同期相手の抽出
+///
+/// # 引数
+/// * `peers` - ペアリング済みの同期相手
+/// * `seq` - 対象の変更の変更シーケンス番号
+///
+/// # 戻り値
+/// 受信を確認できていない同期相手のノードIDのリストを返す。
+///
+fn unacked_peers(peers: &[TrustedPeer], seq: u64) -> Vec<String> {
+    peers.iter()
+        .filter(|peer| peer.acked_seq().is_none_or(|acked| acked < seq))
+        .map(|peer| peer.node_id())
+        .collect()
+}
+
+///
+/// コマンドコンテキストの生成
+///
+pub(crate) fn build_context(opts: &Options, sub_opts: &GcOpts)
+    -> Result<Box<dyn CommandContext>>
+{
+    Ok(Box::new(GcCommandContext::new(opts, sub_opts)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use ulid::Ulid;
+
+    use crate::database::types::{Entry, ServiceId};
+
+    fn temp_db_path() -> PathBuf {
+        std::env::temp_dir().join(format!("pwmgr-test-{}.redb", Ulid::new()))
+    }
+
+    fn make_entry(id: &ServiceId, service: &str, removed_at: Option<DateTime<Local>>)
+        -> Entry
+    {
+        let mut entry = Entry::new(
+            id.clone(),
+            service.to_string(),
+            vec![],
+            vec![],
+            BTreeMap::new(),
+        );
+
+        if let Some(ts) = removed_at {
+            entry.set_removed(true);
+            entry.set_last_update(ts);
+        }
+
+        entry
+    }
+
+    ///
+    /// 保持期間を過ぎ、全ての同期相手が受信済みの墓標のみが回収されることを
+    /// 確認
+    ///
+    #[test]
+    fn collect_waits_for_retention_and_acks() {
+        let mgr = EntryManager::open_for_test(temp_db_path()).unwrap();
+        let old = Local::now() - TimeDelta::days(40);
+        let cutoff = Local::now() - TimeDelta::days(30);
+
+        let live = ServiceId::new();
+        let fresh = ServiceId::new();
+        let stale = ServiceId::new();
+
+        mgr.with_write_transaction(|writer| {
+            writer.put(&make_entry(&live, "live", None))?;
+            writer.put(&make_entry(&fresh, "fresh", Some(Local::now())))?;
+            writer.put(&make_entry(&stale, "stale", Some(old)))?;
+
+            let mut peer = TrustedPeer::new(
+                "peer-a".to_string(),
+                vec![0; 32],
+                String::new(),
+            );
+            peer.set_acked_seq(writer.entry_seq(&stale)?.unwrap() - 1);
+            writer.put_peer(&peer)
+        }).unwrap();
+
+        /*
+         * 同期相手が削除を受信していない間は回収しない
+         */
+        let report = mgr.with_write_transaction(|writer| collect(writer, cutoff))
+            .unwrap();
+        assert!(report.purged.is_empty());
+        assert_eq!(report.pending.len(), 1);
+        assert_eq!(report.pending[0].id, stale.to_string());
+        assert_eq!(report.pending[0].waiting_for, vec!["peer-a".to_string()]);
+
+        /*
+         * 受信を確認できた墓標のみを回収する
+         */
+        let report = mgr.with_write_transaction(|writer| {
+            let mut peer = writer.get_peer("peer-a")?.unwrap();
+            peer.set_acked_seq(writer.change_seq()?);
+            writer.put_peer(&peer)?;
+
+            collect(writer, cutoff)
+        }).unwrap();
+        assert_eq!(report.purged.len(), 1);
+        assert!(report.pending.is_empty());
+
+        mgr.with_read_transaction(|reader| {
+            assert!(reader.get(&stale)?.is_none());
+            assert!(reader.get(&fresh)?.is_some());
+            assert!(reader.get(&live)?.is_some());
+            Ok(())
+        }).unwrap();
+    }
+}
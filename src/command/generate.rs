@@ -0,0 +1,73 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! generateサブコマンドの実装
+//!
+
+use anyhow::Result;
+
+use crate::cmd_args::{GenerateOpts, Options};
+use super::generator::Policy;
+use super::CommandContext;
+
+///
+/// generateサブコマンドのコンテキスト情報をパックした構造体
+///
+struct GenerateCommandContext {
+    /// 生成方針
+    policy: Policy,
+
+    /// 生成する個数
+    count: usize,
+
+    /// JSONで出力するか否か
+    json_output: bool,
+}
+
+impl GenerateCommandContext {
+    ///
+    /// オブジェクトの生成
+    ///
+    /// # 注記
+    /// データベースは使用しないため開かない。
+    ///
+    fn new(opts: &Options, sub_opts: &GenerateOpts) -> Result<Self> {
+        Ok(Self {
+            policy: sub_opts.policy()?,
+            count: sub_opts.count(),
+            json_output: opts.json(),
+        })
+    }
+}
+
+// CommandContextトレイトの実装
+impl CommandContext for GenerateCommandContext {
+    fn exec(&self) -> Result<()> {
+        let passwords = (0..self.count)
+            .map(|_| self.policy.generate())
+            .collect::<Result<Vec<_>>>()?;
+
+        if self.json_output {
+            println!("{}", serde_json::to_string_pretty(&passwords)?);
+        } else {
+            for password in passwords {
+                println!("{}", password);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// コマンドコンテキストの生成
+///
+pub(crate) fn build_context(opts: &Options, sub_opts: &GenerateOpts)
+    -> Result<Box<dyn CommandContext>>
+{
+    Ok(Box::new(GenerateCommandContext::new(opts, sub_opts)?))
+}
@@ -0,0 +1,629 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! パスワードの生成とパスワードポリシーの確認
+//!
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use regex::{Captures, Regex};
+
+use crate::database::types::{CharClass, PasswordPolicy};
+
+/// 生成するパスワードの長さのデフォルト値
+pub(crate) const DEFAULT_LENGTH: usize = 20;
+
+/// パスフレーズの単語区切りのデフォルト値
+pub(crate) const DEFAULT_SEPARATOR: &str = "-";
+
+/// 使用する文字種のデフォルト値
+pub(crate) const DEFAULT_CHARSET: &str = "alnum+sym";
+
+/// 組み込みの単語リスト(BIP39の英単語リスト)
+pub(crate) const BUILTIN_WORDLIST: &str = include_str!("templates/wordlist.txt");
+
+/// 見間違えやすい文字
+const AMBIGUOUS: &str = "0O1Il|";
+
+/// プレースホルダのパターン
+const PLACEHOLDER_PATTERN: &str = r"\{\{GEN(?::([^}]*))?\}\}";
+
+///
+/// パスワードの生成方針
+///
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Policy {
+    /// 文字数
+    length: usize,
+
+    /// 使用する文字種(各文字種から最低1文字を含める)
+    classes: Vec<CharClass>,
+
+    /// 見間違えやすい文字を除外するか否か
+    no_ambiguous: bool,
+
+    /// 除外する文字
+    exclude: String,
+
+    /// パスフレーズとして生成する場合の単語数
+    words: Option<usize>,
+
+    /// パスフレーズの単語区切り
+    separator: String,
+
+    /// パスフレーズに用いる単語リストのファイル(`None`の場合は組み込み)
+    wordlist: Option<PathBuf>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            length: DEFAULT_LENGTH,
+            classes: CharClass::parse(DEFAULT_CHARSET)
+                .expect("invalid default charset"),
+            no_ambiguous: false,
+            exclude: String::new(),
+            words: None,
+            separator: DEFAULT_SEPARATOR.to_string(),
+            wordlist: None,
+        }
+    }
+}
+
+impl TryFrom<&PasswordPolicy> for Policy {
+    type Error = anyhow::Error;
+
+    ///
+    /// パスワードポリシーを満たす生成方針の構築
+    ///
+    /// # 注記
+    /// 文字数はデフォルト値をポリシーの範囲に収め、必須の文字種が指定されて
+    /// いる場合はその文字種のみを用いる。必須の文字種が無い場合はデフォルト
+    /// の文字種のうち、全ての文字が使用できない文字種を除いたものを用いる。
+    /// 必須の文字種の全ての文字が使用できない等、満たすことのできないポリシ
+    /// ーの場合はエラーを返す。
+    ///
+    fn try_from(policy: &PasswordPolicy) -> Result<Self> {
+        policy.validate()?;
+
+        let forbid = policy.forbid();
+        let classes = if policy.require().is_empty() {
+            Self::default().classes
+                .into_iter()
+                .filter(|class| {
+                    !class.chars().iter().all(|ch| forbid.contains(*ch))
+                })
+                .collect()
+        } else {
+            policy.require()
+        };
+
+        let mut generator = Self {
+            classes,
+            ..Self::default()
+        };
+        generator.constrain(policy)?;
+
+        Ok(generator)
+    }
+}
+
+impl Policy {
+    ///
+    /// プレースホルダ等で用いる方針の記述の反映
+    ///
+    /// # 引数
+    /// * `spec` - `key=value`をカンマで区切った記述
+    ///
+    /// # 戻り値
+    /// 反映に成功した場合は`Ok(())`を返す。
+    ///
+    /// # 注記
+    /// 指定できるキーは`length`、`charset`、`no-ambiguous`(値は省略可能)、
+    /// `exclude`、`words`、`separator`、`wordlist`。指定されなかった項目は
+    /// 元の値のままとなる。
+    ///
+    pub(crate) fn apply_spec(&mut self, spec: &str) -> Result<()> {
+        let policy = self;
+
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = match item.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (item, None),
+            };
+
+            let require = || {
+                value.ok_or_else(|| anyhow!("{}の値が指定されていません", key))
+            };
+
+            match key {
+                "length" => policy.set_length(parse_count(key, require()?)?),
+                "charset" => policy.set_classes(CharClass::parse(require()?)?),
+                "no-ambiguous" => policy.set_no_ambiguous(match value {
+                    None | Some("true") => true,
+                    Some("false") => false,
+                    Some(val) => return Err(anyhow!(
+                        "no-ambiguousの値が不正です: {}", val
+                    )),
+                }),
+                "exclude" => policy.set_exclude(require()?.to_string()),
+                "words" => policy.set_words(parse_count(key, require()?)?),
+                "separator" => policy.set_separator(require()?.to_string()),
+                "wordlist" => policy.set_wordlist(PathBuf::from(require()?)),
+                _ => return Err(anyhow!("不明な指定です: {}", key)),
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// パスワードポリシーによる制約の反映
+    ///
+    /// # 引数
+    /// * `policy` - エントリのパスワードポリシー
+    ///
+    /// # 戻り値
+    /// 反映に成功した場合は`Ok(())`を返す。
+    ///
+    /// # 注記
+    /// 文字数はポリシーの範囲に収め、使用できない文字は除外する文字に追加す
+    /// る。パスワードを生成する場合に、使用する文字種が必須の文字種を含まな
+    /// いときはエラーを返す。`apply_spec()`の後に呼び出すことで、記述による
+    /// 指定がポリシーを緩めることを防ぐ。
+    ///
+    pub(crate) fn constrain(&mut self, policy: &PasswordPolicy) -> Result<()> {
+        if let Some(min) = policy.min_length() {
+            self.length = self.length.max(min);
+        }
+        if let Some(max) = policy.max_length() {
+            self.length = self.length.min(max);
+        }
+
+        if self.words.is_none() {
+            let missing = policy.require()
+                .into_iter()
+                .filter(|class| !self.classes.contains(class))
+                .map(|class| class.to_string())
+                .collect::<Vec<_>>();
+
+            if !missing.is_empty() {
+                return Err(anyhow!(
+                    "文字種に必須の文字種が含まれていません: {}",
+                    missing.join(", ")
+                ));
+            }
+        }
+
+        for ch in policy.forbid().chars() {
+            if !self.exclude.contains(ch) {
+                self.exclude.push(ch);
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// 文字数の設定
+    ///
+    pub(crate) fn set_length(&mut self, length: usize) {
+        self.length = length;
+    }
+
+    ///
+    /// 使用する文字種の設定
+    ///
+    pub(crate) fn set_classes(&mut self, classes: Vec<CharClass>) {
+        self.classes = classes;
+    }
+
+    ///
+    /// 見間違えやすい文字の除外の設定
+    ///
+    pub(crate) fn set_no_ambiguous(&mut self, no_ambiguous: bool) {
+        self.no_ambiguous = no_ambiguous;
+    }
+
+    ///
+    /// 除外する文字の設定
+    ///
+    pub(crate) fn set_exclude(&mut self, exclude: String) {
+        self.exclude = exclude;
+    }
+
+    ///
+    /// パスフレーズの単語数の設定(設定した場合はパスフレーズを生成する)
+    ///
+    pub(crate) fn set_words(&mut self, words: usize) {
+        self.words = Some(words);
+    }
+
+    ///
+    /// パスフレーズの単語区切りの設定
+    ///
+    pub(crate) fn set_separator(&mut self, separator: String) {
+        self.separator = separator;
+    }
+
+    ///
+    /// パスフレーズに用いる単語リストのファイルの設定
+    ///
+    pub(crate) fn set_wordlist(&mut self, path: PathBuf) {
+        self.wordlist = Some(path);
+    }
+
+    ///
+    /// パスワードの生成
+    ///
+    /// # 戻り値
+    /// 生成したパスワード(パスフレーズ)を`Ok()`でラップして返す。
+    ///
+    pub(crate) fn generate(&self) -> Result<String> {
+        match self.words {
+            Some(words) => self.generate_passphrase(words),
+            None => self.generate_password(),
+        }
+    }
+
+    ///
+    /// 文字種を指定したパスワードの生成
+    ///
+    fn generate_password(&self) -> Result<String> {
+        if self.classes.is_empty() {
+            return Err(anyhow!("文字種が指定されていません"));
+        }
+
+        let pools = self.classes.iter()
+            .map(|class| {
+                let pool = class.chars()
+                    .into_iter()
+                    .filter(|ch| !(self.no_ambiguous && AMBIGUOUS.contains(*ch)))
+                    .filter(|ch| !self.exclude.contains(*ch))
+                    .collect::<Vec<_>>();
+
+                if pool.is_empty() {
+                    Err(anyhow!("除外により使用できる文字が無い文字種があります"))
+                } else {
+                    Ok(pool)
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if self.length < pools.len() {
+            return Err(anyhow!(
+                "文字数は文字種の数({})以上を指定してください", pools.len()
+            ));
+        }
+
+        // 各文字種から1文字ずつ選んだ上で、残りを全文字種から選んで混ぜる
+        let all = pools.concat();
+        let mut chars = pools.iter()
+            .map(|pool| pool[random_below(pool.len())])
+            .collect::<Vec<_>>();
+
+        while chars.len() < self.length {
+            chars.push(all[random_below(all.len())]);
+        }
+
+        for i in (1..chars.len()).rev() {
+            chars.swap(i, random_below(i + 1));
+        }
+
+        Ok(chars.into_iter().collect())
+    }
+
+    ///
+    /// 単語リストを用いたパスフレーズの生成
+    ///
+    /// # 注記
+    /// 除外する文字を含む単語は用いない。単語区切りに除外する文字が含まれる
+    /// 場合はエラーを返す。
+    ///
+    fn generate_passphrase(&self, words: usize) -> Result<String> {
+        if words == 0 {
+            return Err(anyhow!("単語数は1以上を指定してください"));
+        }
+
+        if self.separator.chars().any(|ch| self.exclude.contains(ch)) {
+            return Err(anyhow!(
+                "単語区切りに除外する文字が含まれています: {}", self.separator
+            ));
+        }
+
+        let content = match &self.wordlist {
+            Some(path) => fs::read_to_string(path).with_context(|| {
+                format!("単語リストの読み込みに失敗しました: {}", path.display())
+            })?,
+            None => BUILTIN_WORDLIST.to_string(),
+        };
+
+        // ダイスの目が付与された形式(`11111 word`)では末尾の単語のみを使う
+        let list = content.lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .filter(|word| !word.chars().any(|ch| self.exclude.contains(ch)))
+            .collect::<Vec<_>>();
+
+        if list.len() < 2 {
+            return Err(anyhow!("単語リストの単語が不足しています"));
+        }
+
+        Ok((0..words)
+            .map(|_| list[random_below(list.len())])
+            .collect::<Vec<_>>()
+            .join(&self.separator))
+    }
+}
+
+///
+/// 個数を表す値の解釈
+///
+fn parse_count(key: &str, value: &str) -> Result<usize> {
+    value.parse::<usize>()
+        .map_err(|_| anyhow!("{}の値が不正です: {}", key, value))
+}
+
+///
+/// 0以上`n`未満の一様な乱数の生成
+///
+/// # 注記
+/// 剰余による偏りが生じないよう、`n`の倍数に収まらない値は棄却する。
+///
+fn random_below(n: usize) -> usize {
+    let n = n as u64;
+    let zone = u64::MAX - (u64::MAX % n);
+
+    loop {
+        let val = OsRng.next_u64();
+        if val < zone {
+            return (val % n) as usize;
+        }
+    }
+}
+
+///
+/// プロパティ中のパスワード生成のプレースホルダの展開
+///
+/// # 引数
+/// * `properties` - エディタで編集されたプロパティ
+/// * `policy` - エントリのパスワードポリシー
+///
+/// # 戻り値
+/// 値に含まれる`{{GEN}}`もしくは`{{GEN:方針}}`を生成したパスワードに置き換
+/// えたプロパティを`Ok()`でラップして返す。
+///
+/// # 注記
+/// パスワードポリシーが指定されている場合はそれを満たす方針を基に、プレー
+/// スホルダに記述された方針を反映して生成する。記述された方針はポリシーの
+/// 範囲内に制限され、必須の文字種を欠く文字種の指定はエラーとなる。方針の
+/// 記述の形式は`Policy::apply_spec()`を参照。
+///
+pub(crate) fn expand_placeholders(
+    properties: BTreeMap<String, String>,
+    policy: Option<&PasswordPolicy>,
+) -> Result<BTreeMap<String, String>> {
+    let base = policy.map(Policy::try_from)
+        .transpose()?
+        .unwrap_or_default();
+    let re = Regex::new(PLACEHOLDER_PATTERN)?;
+    let mut expanded = BTreeMap::new();
+
+    for (key, value) in properties {
+        if !re.is_match(&value) {
+            expanded.insert(key, value);
+            continue;
+        }
+
+        let mut error = None;
+        let value = re.replace_all(&value, |caps: &Captures| {
+            let spec = caps.get(1).map_or("", |m| m.as_str());
+            let mut generator = base.clone();
+            let result = generator.apply_spec(spec)
+                .and_then(|_| match policy {
+                    Some(policy) => generator.constrain(policy),
+                    None => Ok(()),
+                })
+                .and_then(|_| generator.generate());
+
+            match result {
+                Ok(password) => password,
+                Err(err) => {
+                    error.get_or_insert(err);
+                    String::new()
+                }
+            }
+        }).into_owned();
+
+        if let Some(err) = error {
+            return Err(err.context(format!("プロパティ \"{}\"", key)));
+        }
+
+        expanded.insert(key, value);
+    }
+
+    Ok(expanded)
+}
+
+///
+/// 秘匿プロパティのパスワードポリシーへの適合の確認
+///
+/// # 引数
+/// * `properties` - 確認するプロパティ
+/// * `policy` - エントリのパスワードポリシー
+///
+/// # 戻り値
+/// 全ての秘匿プロパティ(キー名が`!`で終わるもの)がポリシーを満たす場合(ポ
+/// リシーが無い場合を含む)は`Ok(())`を、違反がある場合は違反内容を列挙した
+/// エラーを返す。
+///
+pub(crate) fn check_policy(
+    properties: &BTreeMap<String, String>,
+    policy: Option<&PasswordPolicy>,
+) -> Result<()> {
+    let Some(policy) = policy else {
+        return Ok(());
+    };
+
+    policy.validate()?;
+
+    let violations = properties.iter()
+        .filter(|(key, _)| key.ends_with('!'))
+        .filter_map(|(key, value)| {
+            let violations = policy.violations(value);
+            (!violations.is_empty())
+                .then(|| format!("  {}: {}", key, violations.join(", ")))
+        })
+        .collect::<Vec<_>>();
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "パスワードポリシーに違反しています\n{}", violations.join("\n")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(spec: &str) -> Result<Policy> {
+        let mut policy = Policy::default();
+        policy.apply_spec(spec)?;
+        Ok(policy)
+    }
+
+    ///
+    /// 方針に従った文字種・文字数で生成されることを確認
+    ///
+    #[test]
+    fn generate_follows_policy() {
+        let policy = parse(
+            "length=32, charset=digit+sym, no-ambiguous, exclude=#$"
+        ).unwrap();
+
+        for _ in 0..20 {
+            let password = policy.generate().unwrap();
+            assert_eq!(password.chars().count(), 32);
+            assert!(password.chars().any(|ch| ch.is_ascii_digit()));
+            assert!(password.chars().any(|ch| CharClass::SYMBOLS.contains(ch)));
+            assert!(!password.chars().any(|ch| "01|#$".contains(ch)));
+            assert!(!password.chars().any(|ch| ch.is_ascii_alphabetic()));
+        }
+
+        assert!(parse("length=1,charset=alnum").unwrap().generate().is_err());
+        assert!(parse("charset=emoji").is_err());
+        assert!(parse("length").is_err());
+        assert!(parse("color=red").is_err());
+    }
+
+    ///
+    /// パスフレーズが組み込みの単語リストから生成されることを確認
+    ///
+    #[test]
+    fn generate_passphrase_from_wordlist() {
+        let policy = parse("words=5,separator=.").unwrap();
+        let passphrase = policy.generate().unwrap();
+        let words = passphrase.split('.').collect::<Vec<_>>();
+
+        assert_eq!(words.len(), 5);
+        assert!(words.iter().all(|word| BUILTIN_WORDLIST.lines().any(|w| w == *word)));
+    }
+
+    ///
+    /// プレースホルダのみが展開されることを確認
+    ///
+    #[test]
+    fn expand_placeholders_in_properties() {
+        let properties = BTreeMap::from([
+            ("user".to_string(), "alice".to_string()),
+            ("password!".to_string(), "{{GEN:length=24,charset=alnum}}".to_string()),
+            ("pin!".to_string(), "pin-{{GEN:length=4,charset=digit}}".to_string()),
+        ]);
+
+        let expanded = expand_placeholders(properties, None).unwrap();
+        assert_eq!(expanded["user"], "alice");
+
+        let password = &expanded["password!"];
+        assert_eq!(password.len(), 24);
+        assert!(password.chars().all(|ch| ch.is_ascii_alphanumeric()));
+
+        let pin = expanded["pin!"].strip_prefix("pin-").unwrap();
+        assert!(pin.len() == 4 && pin.chars().all(|ch| ch.is_ascii_digit()));
+
+        let invalid = BTreeMap::from([
+            ("password!".to_string(), "{{GEN:length=x}}".to_string()),
+        ]);
+        assert!(expand_placeholders(invalid, None).is_err());
+    }
+
+    ///
+    /// プレースホルダの方針がパスワードポリシーの範囲に制限されることを確認
+    ///
+    #[test]
+    fn expand_placeholders_within_policy() {
+        let policy: PasswordPolicy = serde_yaml_ng::from_str(
+            "min_length: 30\nmax_length: 40\nrequire: [upper, digit]\n\
+             forbid: \"-AB01a\"\n"
+        ).unwrap();
+        let expand = |value: &str| {
+            expand_placeholders(
+                BTreeMap::from([("password!".to_string(), value.to_string())]),
+                Some(&policy)
+            ).map(|mut props| props.remove("password!").unwrap())
+        };
+
+        // 文字数は範囲に収められ、除外の指定はポリシーの禁止文字に追加される
+        for spec in ["{{GEN}}", "{{GEN:length=8,exclude=C}}", "{{GEN:length=64}}"] {
+            let password = expand(spec).unwrap();
+            assert!((30..=40).contains(&password.chars().count()));
+            assert!(!password.chars().any(|ch| "-AB01a".contains(ch)));
+            assert!(policy.violations(&password).is_empty());
+        }
+        assert!(!expand("{{GEN:exclude=C}}").unwrap().contains('C'));
+
+        // 必須の文字種を欠く文字種の指定はエラー
+        assert!(expand("{{GEN:charset=lower+digit}}").is_err());
+        assert!(expand("{{GEN:charset=upper+digit+sym}}").is_ok());
+
+        // パスフレーズも禁止文字(デフォルトの区切りを含む)を用いない
+        assert!(expand("{{GEN:words=4}}").is_err());
+        let passphrase = expand("{{GEN:words=4,separator=.}}").unwrap();
+        assert_eq!(passphrase.split('.').count(), 4);
+        assert!(!passphrase.chars().any(|ch| "-AB01a".contains(ch)));
+    }
+
+    ///
+    /// 必須の文字種の全ての文字が使用できないポリシーがエラーとなることを
+    /// 確認
+    ///
+    #[test]
+    fn policy_rejects_unsatisfiable_required_class() {
+        let policy: PasswordPolicy = serde_yaml_ng::from_str(
+            "require: [lower, digit]\nforbid: \"0123456789\"\n"
+        ).unwrap();
+        assert!(Policy::try_from(&policy).is_err());
+
+        let properties = BTreeMap::from([
+            ("password!".to_string(), "{{GEN}}".to_string()),
+        ]);
+        assert!(expand_placeholders(properties, Some(&policy)).is_err());
+
+        // 必須でない文字種は全ての文字が使用できなくても除外して生成する
+        let policy: PasswordPolicy = serde_yaml_ng::from_str(
+            &format!("forbid: \"{}\"\n", CharClass::SYMBOLS)
+        ).unwrap();
+        let password = Policy::try_from(&policy).unwrap().generate().unwrap();
+        assert!(password.chars().all(|ch| ch.is_ascii_alphanumeric()));
+    }
+}
@@ -0,0 +1,138 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! historyサブコマンドの実装
+//!
+
+use std::cell::RefCell;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::cmd_args::{HistoryOpts, Options};
+use crate::database::types::{Entry, ServiceId};
+use crate::database::{EntryManager, TransactionReadable};
+use super::CommandContext;
+
+///
+/// 版の一覧の要素
+///
+#[derive(Debug, Serialize)]
+struct RevisionInfo {
+    /// 版番号
+    revision: u64,
+
+    /// 版の更新日時
+    last_update: Option<DateTime<Local>>,
+
+    /// サービス名
+    service: String,
+
+    /// 削除済みか否か
+    removed: bool,
+
+    /// 現在のエントリか否か
+    current: bool,
+}
+
+impl RevisionInfo {
+    ///
+    /// オブジェクトの生成
+    ///
+    fn new(revision: u64, entry: &Entry, current: bool) -> Self {
+        Self {
+            revision,
+            last_update: entry.last_update(),
+            service: entry.service(),
+            removed: entry.is_removed(),
+            current,
+        }
+    }
+}
+
+///
+/// historyサブコマンドのコンテキスト情報をパックした構造体
+///
+struct HistoryCommandContext {
+    /// データベースオブジェクト
+    manager: RefCell<EntryManager>,
+
+    /// 対象ID
+    id: String,
+
+    /// JSONで出力するか否か
+    json_output: bool,
+}
+
+impl HistoryCommandContext {
+    ///
+    /// オブジェクトの生成
+    ///
+    fn new(opts: &Options, sub_opts: &HistoryOpts) -> Result<Self> {
+        Ok(Self {
+            manager: RefCell::new(opts.open_locked()?),
+            id: sub_opts.id(),
+            json_output: opts.json(),
+        })
+    }
+}
+
+// CommandContextトレイトの実装
+impl CommandContext for HistoryCommandContext {
+    fn exec(&self) -> Result<()> {
+        let id = ServiceId::from_string(&self.id)
+            .map_err(|_| anyhow!("IDの形式が不正です: {}", self.id))?;
+
+        let history = self.manager.borrow().with_read_transaction(|reader| {
+            let current = reader.get(&id)?
+                .ok_or_else(|| {
+                    anyhow!("指定されたIDのエントリが見つかりません: {}", id)
+                })?;
+
+            let mut history = reader.revisions(&id)?
+                .iter()
+                .map(|(rev, entry)| RevisionInfo::new(*rev, entry, false))
+                .collect::<Vec<_>>();
+
+            // 現在のエントリは最後の版の次の番号とする
+            let next = history.last().map_or(1, |info| info.revision + 1);
+            history.push(RevisionInfo::new(next, &current, true));
+
+            Ok(history)
+        })?;
+
+        if self.json_output {
+            println!("{}", serde_json::to_string_pretty(&history)?);
+            return Ok(());
+        }
+
+        for info in history {
+            let stamp = info.last_update
+                .map(|dt| dt.to_rfc3339())
+                .unwrap_or_else(|| "-".to_string());
+            let prefix = if info.removed { "-" } else { "" };
+            let suffix = if info.current { "\t(current)" } else { "" };
+
+            println!(
+                "{}{}\t{}\t{}{}",
+                prefix, info.revision, stamp, info.service, suffix
+            );
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// コマンドコンテキストの生成
+///
+pub(crate) fn build_context(opts: &Options, sub_opts: &HistoryOpts)
+    -> Result<Box<dyn CommandContext>>
+{
+    Ok(Box::new(HistoryCommandContext::new(opts, sub_opts)?))
+}
@@ -65,6 +65,11 @@ impl ImportCommandContext {
             entry.set_removed(true);
         }
 
+        entry.set_policy(entry_raw.policy());
+
+        // バックアップ時点の版を引き継ぐ(書き込み時に自ノードの版を進める)
+        entry.set_version(entry_raw.version());
+
         entry
     }
 
@@ -77,9 +82,10 @@ impl ImportCommandContext {
         let merge = self.opts.is_merge();
         let overwrite = self.opts.is_overwrite();
         let dry_run = self.opts.is_dry_run();
+        let replace = !merge && !dry_run;
 
         // 置換モードでの削除対象リストを事前取得（読み取り）
-        let existing_ids = if !merge && !dry_run {
+        let existing_ids = if replace {
             self.manager.borrow().all_service()?
         } else {
             Vec::new()
@@ -89,9 +95,17 @@ impl ImportCommandContext {
 
         self.manager.borrow().with_write_transaction(|writer| {
             // 置換モード: 先に全削除
-            if !merge && !dry_run {
+            // 同期相手が居る場合は、相手の持つ版が次回の同期で復活しないよう
+            // 墓標として残す(remove --hardと同様)
+            if replace {
+                let has_peers = !writer.all_peers()?.is_empty();
+
                 for id in existing_ids.iter() {
-                    writer.remove(id)?;
+                    if has_peers {
+                        writer.bury(id)?;
+                    } else {
+                        writer.remove(id)?;
+                    }
                 }
             }
 
@@ -100,7 +114,10 @@ impl ImportCommandContext {
                 let entry = Self::normalize_entry(entry_raw);
                 let id = entry.id();
 
-                if let Some(existing) = writer.get(&id)? {
+                // 置換モードでは既存のエントリは全て削除済み(墓標)として扱う
+                let existing = writer.get(&id)?.filter(|_| !replace);
+
+                if let Some(existing) = existing {
                     if !overwrite {
                         return Err(anyhow!("既に存在するIDです: {}", id));
                     }
@@ -183,7 +200,7 @@ mod tests {
 
     use crate::cmd_args::ImportOpts;
     use crate::database::EntryManager;
-    use crate::database::types::ServiceId;
+    use crate::database::types::{ServiceId, TrustedPeer};
     use crate::command::prompt::test::QueuePrompter;
     use super::*;
 
@@ -203,7 +220,7 @@ mod tests {
     #[test]
     fn import_multi_docs() {
         let path = temp_db_path();
-        let mgr = EntryManager::open(path).unwrap();
+        let mgr = EntryManager::open_for_test(path).unwrap();
 
         let yaml = r#"---
 id: "01J1M8Z6Y1Y1Y1Y1Y1Y1Y1Y1Y1"
@@ -241,7 +258,7 @@ properties:
     #[test]
     fn import_duplicate_id_errors_without_overwrite() {
         let path = temp_db_path();
-        let mut mgr = EntryManager::open(path).unwrap();
+        let mut mgr = EntryManager::open_for_test(path).unwrap();
 
         let yaml = r#"---
 id: "01J1M8Z6Y1Y1Y1Y1Y1Y1Y1Y1Y1"
@@ -277,7 +294,7 @@ properties: {}
     #[test]
     fn import_dry_run_does_not_write() {
         let path = temp_db_path();
-        let mgr = EntryManager::open(path).unwrap();
+        let mgr = EntryManager::open_for_test(path).unwrap();
 
         let yaml = r#"---
 id: "01J1M8Z6Y1Y1Y1Y1Y1Y1Y1Y1Y1"
@@ -301,4 +318,70 @@ properties: {}
             Err(_) => {}
         }
     }
+
+    ///
+    /// 同期相手が居る場合、置換モードで削除したエントリが墓標として残ること
+    ///
+    #[test]
+    fn import_replace_buries_with_peers() {
+        let path = temp_db_path();
+        let mut mgr = EntryManager::open_for_test(path).unwrap();
+        let old_id = ServiceId::new();
+
+        mgr.put(&Entry::new(
+            old_id.clone(),
+            "Old".to_string(),
+            vec![],
+            vec![],
+            BTreeMap::from([("user".to_string(), "carol".to_string())]),
+        )).unwrap();
+
+        mgr.put(&Entry::new(
+            ServiceId::from_string("01J1M8Z6Y1Y1Y1Y1Y1Y1Y1Y1Y1").unwrap(),
+            "Alpha".to_string(),
+            vec![],
+            vec![],
+            BTreeMap::new(),
+        )).unwrap();
+
+        mgr.with_write_transaction(|writer| {
+            writer.put_peer(&TrustedPeer::new(
+                "peer-a".to_string(),
+                vec![0; 32],
+                String::new(),
+            ))
+        }).unwrap();
+
+        let yaml = r#"---
+id: "01J1M8Z6Y1Y1Y1Y1Y1Y1Y1Y1Y1"
+service: "Alpha"
+aliases: []
+tags: []
+properties:
+  user: alice
+"#;
+
+        let ctx = ImportCommandContext {
+            manager: RefCell::new(mgr),
+            opts: ImportOpts::new_for_test(None, false, false, false),
+            prompter: Box::new(QueuePrompter::new(vec![true])),
+        };
+
+        let imported = ctx.import_entries(Cursor::new(yaml)).unwrap();
+        assert_eq!(imported, 1);
+
+        let mut mgr = ctx.manager.borrow_mut();
+
+        // 取り込まなかったエントリは内容を破棄した墓標となる
+        let tombstone = mgr.get(&old_id).unwrap().unwrap();
+        assert!(tombstone.is_removed());
+        assert!(tombstone.properties().is_empty());
+
+        // 取り込んだエントリは墓標の版を引き継いで復活する
+        let alpha = mgr.get(
+            &ServiceId::from_string("01J1M8Z6Y1Y1Y1Y1Y1Y1Y1Y1Y1").unwrap()
+        ).unwrap().unwrap();
+        assert!(!alpha.is_removed());
+        assert_eq!(alpha.properties().get("user"), Some(&"alice".to_string()));
+    }
 }
@@ -47,7 +47,7 @@ impl ListCommandContext {
     ///
     fn new(opts: &Options, sub_opts: &ListOpts) -> Result<Self> {
         Ok(Self {
-            manager: RefCell::new(opts.open()?),
+            manager: RefCell::new(opts.open_locked()?),
             target_tags: sub_opts.target_tags(),
             tag_and: sub_opts.is_tag_and(),
             sort_mode: sub_opts.sort_mode(),
@@ -239,7 +239,7 @@ mod tests {
 
     fn build_mgr() -> EntryManager {
         let path = temp_db_path();
-        let mut mgr = EntryManager::open(path).unwrap();
+        let mut mgr = EntryManager::open_for_test(path).unwrap();
 
         let mut e1 = Entry::new(
             ServiceId::new(),
@@ -9,18 +9,32 @@
 //!
 
 pub(crate) mod add;
+#[cfg(unix)]
+pub(crate) mod agent;
+pub(crate) mod audit;
+pub(crate) mod clipboard;
+pub(crate) mod diff;
 pub(crate) mod edit;
 pub(crate) mod editor;
 pub(crate) mod export;
+pub(crate) mod generate;
+pub(crate) mod generator;
+pub(crate) mod gc;
+pub(crate) mod history;
 pub(crate) mod import;
 pub(crate) mod list;
 pub(crate) mod tags;
+pub(crate) mod totp;
+pub(crate) mod undo;
 pub(crate) mod search;
 pub(crate) mod query;
 pub(crate) mod matcher;
+pub(crate) mod otp;
 pub(crate) mod prompt;
 pub(crate) mod util;
 pub(crate) mod remove;
+pub(crate) mod rekey;
+pub(crate) mod revert;
 pub(crate) mod sync;
 
 use anyhow::Result;
@@ -6,7 +6,14 @@
 
 use std::io::{self, Write};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use zeroize::Zeroizing;
+
+/// マスタパスフレーズを受け渡すための環境変数名
+pub(crate) const PASSPHRASE_ENV: &str = "PWMGR_PASSPHRASE";
+
+/// 新しいマスタパスフレーズを受け渡すための環境変数名(rekeyで使用)
+pub(crate) const NEW_PASSPHRASE_ENV: &str = "PWMGR_NEW_PASSPHRASE";
 
 ///
 /// 対話的な問い合わせを扱うためのトレイト
@@ -65,6 +72,76 @@ impl Prompter for StdPrompter {
     }
 }
 
+///
+/// パスフレーズの入力（エコーバック無し）
+///
+/// # 引数
+/// * `msg` - プロンプトとして表示するメッセージ
+///
+/// # 戻り値
+/// 入力されたパスフレーズを`Ok()`でラップして返す。
+///
+pub(crate) fn read_passphrase(msg: &str) -> Result<Zeroizing<String>> {
+    Ok(Zeroizing::new(rpassword::prompt_password(msg)?))
+}
+
+///
+/// 新規パスフレーズの入力（確認のため2回入力させる）
+///
+/// # 引数
+/// * `msg` - プロンプトとして表示するメッセージ
+///
+/// # 戻り値
+/// 2回の入力が一致した場合はパスフレーズを`Ok()`でラップして返す。
+///
+pub(crate) fn read_new_passphrase(msg: &str) -> Result<Zeroizing<String>> {
+    let pass = read_passphrase(msg)?;
+    check_new_passphrase(&pass)?;
+
+    let confirm = read_passphrase("確認のためもう一度入力してください: ")?;
+    if pass != confirm {
+        return Err(anyhow!("パスフレーズが一致しません"));
+    }
+
+    Ok(pass)
+}
+
+///
+/// 新規パスフレーズの取得
+///
+/// # 引数
+/// * `env` - パスフレーズを受け渡す環境変数名
+/// * `msg` - 環境変数が未設定の場合に表示するプロンプト
+///
+/// # 戻り値
+/// 環境変数が設定されている場合はその値を、未設定の場合は端末から入力させた
+/// ものを`Ok()`でラップして返す。
+///
+/// # 注記
+/// 環境変数の値にも端末からの入力と同じ確認(空でないこと)を行う。
+///
+pub(crate) fn new_passphrase(env: &str, msg: &str) -> Result<Zeroizing<String>> {
+    match std::env::var(env) {
+        Ok(pass) => {
+            let pass = Zeroizing::new(pass);
+            check_new_passphrase(&pass)?;
+            Ok(pass)
+        }
+        Err(_) => read_new_passphrase(msg),
+    }
+}
+
+///
+/// 新規に設定するパスフレーズの確認
+///
+fn check_new_passphrase(pass: &str) -> Result<()> {
+    if pass.is_empty() {
+        return Err(anyhow!("空のパスフレーズは設定できません"));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 pub(crate) mod test {
     use std::sync::Mutex;
@@ -15,10 +15,10 @@ use std::convert::From;
 use anyhow::{anyhow, Result};
 use serde::Serialize;
 
-use crate::cmd_args::{QueryOpts, Options};
+use crate::cmd_args::{ClipboardBackend, QueryOpts, Options};
 use crate::database::types::{Entry, ServiceId};
 use crate::database::EntryManager;
-use super::{matcher::Matcher, CommandContext};
+use super::{clipboard, matcher::Matcher, CommandContext};
 
 ///
 /// 表示用の簡略化エントリ
@@ -71,8 +71,16 @@ impl QueryCommandContext {
     /// オブジェクトの生成
     ///
     fn new(opts: &Options, sub_opts: &QueryOpts) -> Result<Self> {
+        // 秘匿項目をマスクする場合は復号の必要がないため解錠しない(コピーす
+        // る場合はマスクしない)
+        let manager = if sub_opts.is_masked() && sub_opts.copy().is_none() {
+            opts.open_locked()?
+        } else {
+            opts.open()?
+        };
+
         Ok(Self {
-            manager: RefCell::new(opts.open()?),
+            manager: RefCell::new(manager),
             opts: sub_opts.clone(),
             json_output: opts.json(),
         })
@@ -129,6 +137,74 @@ impl QueryCommandContext {
         Ok(())
     }
 
+    ///
+    /// プロパティの値のクリップボードへのコピー
+    ///
+    /// # 引数
+    /// * `hits` - 検索でヒットしたエントリ
+    /// * `property` - コピーするプロパティ名
+    ///
+    /// # 注記
+    /// 値は端末に表示しない。コピーするエントリが1件に定まらない場合はエラー
+    /// とする。
+    ///
+    fn copy_property(&self, hits: &[Entry], property: &str) -> Result<()> {
+        let entry = match hits {
+            [entry] => entry,
+            _ => return Err(anyhow!(
+                "複数のエントリが該当したためコピーできません:\n{}",
+                hits.iter()
+                    .map(|entry| format!("  {} {}", entry.id(), entry.service()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )),
+        };
+
+        let value = entry.properties()
+            .get(property)
+            .cloned()
+            .ok_or_else(|| anyhow!("プロパティが見つかりません: {}", property))?;
+
+        let clipboard = clipboard::open(self.opts.clipboard());
+        let clear_after = self.opts.clear_after();
+        let cleared = clipboard::copy_with_clear(
+            clipboard.as_ref(),
+            &value,
+            clear_after,
+            self.opts.is_force_clear()
+        )?;
+
+        let copied = CopiedProperty {
+            id: entry.id().to_string(),
+            service: entry.service(),
+            property: property.to_string(),
+            backend: clipboard.backend(),
+            clear_after: cleared.then_some(clear_after),
+        };
+
+        if self.json_output {
+            println!("{}", serde_json::to_string_pretty(&copied)?);
+        } else if let Some(secs) = copied.clear_after {
+            println!(
+                "copied {} of {} (clears in {}s)",
+                copied.property, copied.service, secs
+            );
+        } else if clear_after > 0 && !clipboard.can_paste() {
+            println!(
+                "copied {} of {} (not cleared automatically: clipboard \
+                 cannot be verified; use --force-clear to clear anyway)",
+                copied.property, copied.service
+            );
+        } else {
+            println!(
+                "copied {} of {} (not cleared automatically)",
+                copied.property, copied.service
+            );
+        }
+
+        Ok(())
+    }
+
     ///
     /// JSON出力用のエントリ表現を構築する
     ///
@@ -169,6 +245,10 @@ impl CommandContext for QueryCommandContext {
             return Err(anyhow!("該当するエントリが見つかりませんでした"));
         }
 
+        if let Some(property) = self.opts.copy() {
+            return self.copy_property(&hits, &property);
+        }
+
         if self.opts.is_masked() {
             for entry in hits.iter_mut() {
                 entry.mask_secret_properties();
@@ -212,6 +292,27 @@ pub(crate) fn build_context(opts: &Options, sub_opts: &QueryOpts)
     Ok(Box::new(QueryCommandContext::new(opts, sub_opts)?))
 }
 
+///
+/// クリップボードへのコピー結果
+///
+#[derive(Serialize)]
+struct CopiedProperty {
+    /// サービスID
+    id: String,
+
+    /// サービス名
+    service: String,
+
+    /// コピーしたプロパティ名
+    property: String,
+
+    /// 使用したバックエンド
+    backend: ClipboardBackend,
+
+    /// 消去するまでの秒数(自動消去しない場合は`None`)
+    clear_after: Option<u64>,
+}
+
 #[derive(Serialize)]
 struct DisplayEntry {
     id: String,
@@ -236,7 +337,7 @@ mod tests {
 
     fn build_mgr_with_entries() -> EntryManager {
         let path = temp_db_path();
-        let mut mgr = EntryManager::open(path).unwrap();
+        let mut mgr = EntryManager::open_for_test(path).unwrap();
 
         // service: "Alpha", aliases: ["alp"]
         let entry1 = Entry::new(
@@ -0,0 +1,86 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! rekeyサブコマンドの実装
+//!
+
+use std::cell::RefCell;
+
+use anyhow::Result;
+use log::info;
+use zeroize::Zeroizing;
+
+use crate::cmd_args::Options;
+use crate::command::prompt::{new_passphrase, NEW_PASSPHRASE_ENV};
+use crate::database::types::KdfParams;
+use crate::database::EntryManager;
+use super::CommandContext;
+
+///
+/// rekeyサブコマンドのコンテキスト情報をパックした構造体
+///
+struct RekeyCommandContext {
+    /// データベースオブジェクト
+    manager: RefCell<EntryManager>,
+
+    /// 新しいマスタパスフレーズ
+    passphrase: Zeroizing<String>,
+
+    /// グローバルオプション(エージェントへの鍵の登録に用いる)
+    #[cfg_attr(not(unix), allow(dead_code))]
+    opts: Options,
+}
+
+impl RekeyCommandContext {
+    ///
+    /// オブジェクトの生成
+    ///
+    /// # 注記
+    /// 現在のパスフレーズで解錠できた場合にのみ新しいパスフレーズを問い合わ
+    /// せる。エージェントにキャッシュされた鍵では解錠せず、常に現在のパスフ
+    /// レーズを確認する。新しいパスフレーズは環境変数PWMGR_NEW_PASSPHRASEが設
+    /// 定されていればその値を用いる。
+    ///
+    fn new(opts: &Options) -> Result<Self> {
+        let manager = opts.open_by_passphrase()?;
+
+        let passphrase = new_passphrase(NEW_PASSPHRASE_ENV, "新しいパスフレーズ: ")?;
+
+        Ok(Self {
+            manager: RefCell::new(manager),
+            passphrase,
+            opts: opts.clone(),
+        })
+    }
+}
+
+// CommandContextトレイトの実装
+impl CommandContext for RekeyCommandContext {
+    fn exec(&self) -> Result<()> {
+        let mut mgr = self.manager.borrow_mut();
+        mgr.rekey(&self.passphrase, KdfParams::default())?;
+
+        // エージェントが変更前の鍵を保持したままにならないよう、新しい鍵で
+        // 置き換える
+        #[cfg(unix)]
+        self.opts.cache_key_to_agent(&mgr);
+
+        println!("パスフレーズを変更しました");
+        info!("rekey: passphrase changed");
+
+        Ok(())
+    }
+}
+
+///
+/// コマンドコンテキストの生成
+///
+pub(crate) fn build_context(opts: &Options)
+    -> Result<Box<dyn CommandContext>>
+{
+    Ok(Box::new(RekeyCommandContext::new(opts)?))
+}
@@ -36,7 +36,7 @@ impl RemoveCommandContext {
     ///
     fn new(opts: &Options, sub_opts: &RemoveOpts) -> Result<Self> {
         Ok(Self {
-            manager: RefCell::new(opts.open()?),
+            manager: RefCell::new(opts.open_locked()?),
             id: sub_opts.id(),
             hard: sub_opts.is_hard(),
         })
@@ -49,9 +49,19 @@ impl CommandContext for RemoveCommandContext {
             .map_err(|_| anyhow!("IDの形式が不正です: {}", self.id))?;
 
         if self.hard {
-            self.manager.borrow_mut().remove(&id)?;
+            let mut mgr = self.manager.borrow_mut();
+
+            if mgr.all_peers()?.is_empty() {
+                mgr.remove(&id)?;
+                info!("remove (hard): id={}", id);
+            } else {
+                // 同期相手から古い版が送られて復活しないよう、削除を伝播させ
+                // る墓標として残す(gcサブコマンドで取り除く)
+                mgr.with_write_transaction(|writer| writer.bury(&id))?;
+                info!("remove (hard): id={}, kept as tombstone", id);
+            }
+
             println!("removed (hard): {}", id);
-            info!("remove (hard): id={}", id);
         } else {
             let mut mgr = self.manager.borrow_mut();
             if let Some(mut entry) = mgr.get(&id)? {
@@ -0,0 +1,215 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! revertサブコマンドの実装
+//!
+
+use std::cell::RefCell;
+
+use anyhow::{anyhow, Result};
+use log::info;
+
+use crate::cmd_args::{Options, RevertOpts};
+use crate::database::types::{Entry, ServiceId};
+use crate::database::{EntryManager, TransactionReadable, TransactionWriter};
+use super::CommandContext;
+
+///
+/// revertサブコマンドのコンテキスト情報をパックした構造体
+///
+struct RevertCommandContext {
+    /// データベースオブジェクト
+    manager: RefCell<EntryManager>,
+
+    /// 対象ID
+    id: String,
+
+    /// 戻す版の番号
+    revision: u64,
+}
+
+impl RevertCommandContext {
+    ///
+    /// オブジェクトの生成
+    ///
+    fn new(opts: &Options, sub_opts: &RevertOpts) -> Result<Self> {
+        Ok(Self {
+            manager: RefCell::new(opts.open_locked()?),
+            id: sub_opts.id(),
+            revision: sub_opts.revision(),
+        })
+    }
+}
+
+// CommandContextトレイトの実装
+impl CommandContext for RevertCommandContext {
+    fn exec(&self) -> Result<()> {
+        let id = ServiceId::from_string(&self.id)
+            .map_err(|_| anyhow!("IDの形式が不正です: {}", self.id))?;
+
+        let restored = self.manager.borrow().with_write_transaction(|writer| {
+            revert_entry(writer, &id, self.revision)
+        })?;
+
+        println!("reverted: {} (revision {})", id, self.revision);
+        info!(
+            "revert: id={}, revision={}, service={}",
+            id,
+            self.revision,
+            restored.service()
+        );
+
+        Ok(())
+    }
+}
+
+///
+/// エントリを過去の版に戻す
+///
+/// # 引数
+/// * `writer` - 書き込みトランザクション
+/// * `id` - 対象のサービスID
+/// * `revision` - 戻す版の番号
+///
+/// # 戻り値
+/// 書き込んだエントリを`Ok()`でラップして返す。
+///
+/// # 注記
+/// 過去の版の内容をローカルでの変更として書き込むため、戻す前のエントリも
+/// 新たな版として記録され、同期相手にも変更として伝播する。秘匿プロパティは
+/// 暗号化されたまま書き戻すため、解錠は不要。
+///
+fn revert_entry(writer: &mut TransactionWriter, id: &ServiceId, revision: u64)
+    -> Result<Entry>
+{
+    if writer.get(id)?.is_none() {
+        return Err(anyhow!("指定されたIDのエントリが見つかりません: {}", id));
+    }
+
+    let revisions = writer.revisions(id)?;
+    let current = revisions.last().map_or(1, |(rev, _)| rev + 1);
+    if revision == current {
+        return Err(anyhow!("指定された版は現在のエントリです: {}", revision));
+    }
+
+    let mut restored = revisions.into_iter()
+        .find(|(rev, _)| *rev == revision)
+        .map(|(_, entry)| entry)
+        .ok_or_else(|| anyhow!("指定された版が見つかりません: {}", revision))?;
+
+    restored.set_last_update_now();
+    writer.put(&restored)?;
+
+    Ok(restored)
+}
+
+///
+/// コマンドコンテキストの生成
+///
+pub(crate) fn build_context(opts: &Options, sub_opts: &RevertOpts)
+    -> Result<Box<dyn CommandContext>>
+{
+    Ok(Box::new(RevertCommandContext::new(opts, sub_opts)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use ulid::Ulid;
+
+    fn temp_db_path() -> PathBuf {
+        std::env::temp_dir().join(format!("pwmgr-test-{}.redb", Ulid::new()))
+    }
+
+    fn make_entry(id: &ServiceId, password: &str) -> Entry {
+        Entry::new(
+            id.clone(),
+            "svc".to_string(),
+            vec![],
+            vec![],
+            BTreeMap::from([
+                ("password!".to_string(), password.to_string()),
+            ]),
+        )
+    }
+
+    ///
+    /// 上書きした秘匿プロパティを過去の版から復元できることを確認
+    ///
+    #[test]
+    fn revert_restores_overwritten_secret() {
+        let mgr = EntryManager::open_for_test(temp_db_path()).unwrap();
+        let id = ServiceId::new();
+
+        mgr.with_write_transaction(|writer| {
+            writer.put(&make_entry(&id, "first"))?;
+            writer.put(&make_entry(&id, "second"))
+        }).unwrap();
+
+        mgr.with_write_transaction(|writer| {
+            revert_entry(writer, &id, 1)?;
+
+            let entry = writer.get(&id)?.unwrap();
+            assert_eq!(
+                entry.properties().get("password!"),
+                Some(&"first".to_string())
+            );
+
+            // 戻す前の版も記録され、さらに戻すことができる
+            assert_eq!(writer.revisions(&id)?.len(), 2);
+            revert_entry(writer, &id, 2)?;
+            assert_eq!(
+                writer.get(&id)?.unwrap().properties().get("password!"),
+                Some(&"second".to_string())
+            );
+
+            assert!(revert_entry(writer, &id, 4).is_err());
+            assert!(revert_entry(writer, &id, 9).is_err());
+            assert!(revert_entry(writer, &ServiceId::new(), 1).is_err());
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// 同期で相手側の版に置き換えられたエントリを元の版に戻せることを確認
+    ///
+    #[test]
+    fn revert_restores_entry_replaced_by_sync() {
+        let mgr = EntryManager::open_for_test(temp_db_path()).unwrap();
+        let id = ServiceId::new();
+
+        mgr.with_write_transaction(|writer| {
+            writer.put(&make_entry(&id, "local"))?;
+
+            let mut remote = make_entry(&id, "remote");
+            let mut version = writer.get(&id)?.unwrap().version();
+            version.increment("other");
+            remote.set_version(version);
+            writer.put_synced(&remote)?;
+
+            // 同一の版の再受信では記録しない
+            writer.put_synced(&remote)
+        }).unwrap();
+
+        mgr.with_write_transaction(|writer| {
+            assert_eq!(writer.revisions(&id)?.len(), 1);
+            revert_entry(writer, &id, 1)?;
+
+            let entry = writer.get(&id)?.unwrap();
+            assert_eq!(
+                entry.properties().get("password!"),
+                Some(&"local".to_string())
+            );
+
+            // 戻した版は相手側の版より新しい変更となる
+            assert_eq!(entry.version().get("other"), 1);
+            Ok(())
+        }).unwrap();
+    }
+}
@@ -35,7 +35,7 @@ impl SearchCommandContext {
     ///
     fn new(opts: &Options, sub_opts: &SearchOpts) -> Result<Self> {
         Ok(Self {
-            manager: RefCell::new(opts.open()?),
+            manager: RefCell::new(opts.open_locked()?),
             opts: sub_opts.clone(),
         })
     }
@@ -221,7 +221,7 @@ mod tests {
     ///
     fn build_mgr_with_entries() -> EntryManager {
         let path = temp_db_path();
-        let mut mgr = EntryManager::open(path).unwrap();
+        let mut mgr = EntryManager::open_for_test(path).unwrap();
 
         let e1 = Entry::new(
             ServiceId::new(),
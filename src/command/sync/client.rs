@@ -7,55 +7,71 @@
 //! クライアント側の同期処理
 
 use std::collections::HashSet;
-use std::net::TcpStream;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
 use chrono::Local;
 use log::{debug, error, info};
-use ulid::Ulid;
 
+use crate::cmd_args::ConflictPolicy;
 use crate::command::prompt::Prompter;
+use crate::command::sync::merge;
+use crate::command::sync::secure::{
+    Established, Initiator, LocalIdentity, SecureChannel, SyncPsk,
+};
+use crate::command::sync::transport::{self, Transport};
 use crate::command::sync::{
-    recv_packet, send_packet, NodeRole, SyncPacket, PROTOCOL_VERSION,
+    hello_context, recv_packet, recv_plain_packet, send_packet,
+    send_plain_packet, NodeRole, SyncPacket, SyncReport, SyncRequest,
+    Watermark, PROTOCOL_VERSION,
 };
 use crate::database::{TransactionReadable, TransactionWriter};
-use crate::database::types::{Entry, ServiceId};
+use crate::database::types::{Causality, Entry, ServiceId, SyncWatermark};
+
+/// 競合で複製したエントリに付与するタグ
+const CONFLICT_TAG: &str = "conflict";
 
 /*
- * クライアントモードのエントリーポイント
+ * サーバとのハンドシェイク（ペアリングと共用）
  */
-pub(super) fn run(
-    addr: &str,
-    writer: &mut TransactionWriter,
-    prompter: &dyn Prompter,
-) -> Result<()> {
-    /*
-     * サーバへ接続
-     */
-    info!("client: connect to {}", addr);
-    let mut stream = TcpStream::connect(addr)
-        .with_context(|| format!("connect {}", addr))?;
+pub(super) fn handshake(
+    mut stream: Box<dyn Transport>,
+    identity: &LocalIdentity,
+    psk: Option<&SyncPsk>,
+    pairing: bool,
+    request: SyncRequest,
+) -> Result<(Box<dyn Transport>, Established)> {
+    info!("client: handshake with {}", stream.peer_label());
 
-    let node_id = Ulid::new().to_string();
+    let node_id = identity.node_id();
 
     /*
-     * Helloの送信
+     * Helloの送信（ハンドシェイクの開始を兼ねる）
      */
-    send_packet(&mut stream, SyncPacket::hello(
+    let (initiator, handshake) = Initiator::start(
+        identity,
+        psk,
+        PROTOCOL_VERSION,
+        &hello_context(pairing, &request)?,
+    )?;
+
+    debug!(
+        "client: send Hello proto={}, node={}, pairing={}, request={:?}",
+        PROTOCOL_VERSION, node_id, pairing, request
+    );
+    send_plain_packet(stream.as_mut(), SyncPacket::hello(
         PROTOCOL_VERSION,
-        node_id.clone(),
+        node_id,
         NodeRole::Client,
         Local::now().timestamp_millis() as u64,
+        pairing,
+        request,
+        handshake,
     ))?;
-    debug!(
-        "client: sent Hello proto={}, node={}",
-        PROTOCOL_VERSION, node_id
-    );
 
     /*
      * HelloAckの受信と確認
      */
-    let ack = match recv_packet(&mut stream)? {
+    let ack = match recv_plain_packet(stream.as_mut())? {
         SyncPacket::HelloAck(ack) => ack,
         pkt => return Err(anyhow!("unexpected packet: {:?}", pkt)),
     };
@@ -70,8 +86,135 @@ pub(super) fn run(
             ack.reason.unwrap_or_else(|| "unknown".into())
         ));
     }
+
+    /*
+     * ハンドシェイクの完了
+     */
+    let established = match ack.handshake {
+        Some(message) => initiator.finish(&message)?,
+        None => return Err(anyhow!("server did not complete handshake")),
+    };
     info!("client: HelloAck accepted");
 
+    Ok((stream, established))
+}
+
+///
+/// クライアントモードの動作設定
+///
+pub(super) struct ClientSettings {
+    /// 前回の同期位置を用いず全件を同期するか否か
+    pub(super) full: bool,
+
+    /// 競合時の解決方針
+    pub(super) conflict: ConflictPolicy,
+
+    /// 交換のみを行い、双方のデータベースに反映しないか否か
+    pub(super) dry_run: bool,
+}
+
+/*
+ * クライアントモードのエントリーポイント
+ */
+pub(super) fn run(
+    addr: &str,
+    identity: &LocalIdentity,
+    psk: Option<&SyncPsk>,
+    settings: &ClientSettings,
+    writer: &mut TransactionWriter,
+    prompter: &dyn Prompter,
+) -> Result<SyncReport> {
+    info!("client: connect to {}", addr);
+    let stream = transport::connect(addr)?;
+
+    session(stream, addr, identity, psk, settings, writer, prompter)
+}
+
+/*
+ * 接続済みの通信路上での同期処理
+ *
+ * `addr`は前回の同期位置の検索と、同期完了時の相手のアドレスの記録に用い
+ * る。
+ */
+pub(super) fn session(
+    stream: Box<dyn Transport>,
+    addr: &str,
+    identity: &LocalIdentity,
+    psk: Option<&SyncPsk>,
+    settings: &ClientSettings,
+    writer: &mut TransactionWriter,
+    prompter: &dyn Prompter,
+) -> Result<SyncReport> {
+    // ドライランの場合は通常通り処理した上で書き込みを全て破棄する
+    if settings.dry_run {
+        writer.discard();
+    }
+
+    let mut report = SyncReport::default();
+
+    /*
+     * 前回の同期位置の取得
+     *
+     * 接続先のノードIDはハンドシェイクが完了するまで確定しないため、前回
+     * 同じアドレスで同期した相手の同期位置を提示する。別のノードだった場合
+     * はサーバ側でノードIDの不一致により無視される。
+     */
+    let last_mark = if settings.full {
+        None
+    } else {
+        writer.all_peers()?
+            .into_iter()
+            .find(|peer| peer.address() == addr)
+            .and_then(|peer| {
+                peer.watermark().map(|mark| (peer.node_id(), mark))
+            })
+    };
+
+    let watermark = last_mark.as_ref().map(|(node_id, mark)| Watermark {
+        node_id: node_id.clone(),
+        seq: mark.remote_seq(),
+    });
+
+    /*
+     * 前回の同期以降に自身で変更したエントリ（差分同期時の送信候補）
+     */
+    let local_changes: HashSet<String> = match &last_mark {
+        Some((_, mark)) => writer.changed_since(mark.local_seq())?
+            .into_iter()
+            .map(|id| id.to_string())
+            .collect(),
+        None => HashSet::new(),
+    };
+
+    /*
+     * サーバとのハンドシェイク
+     */
+    let request = SyncRequest {watermark, dry_run: settings.dry_run};
+    let (stream, established) = handshake(stream, identity, psk, false, request)?;
+
+    /*
+     * ペアリング済みのサーバか否かの確認
+     */
+    let trusted = writer.all_peers()?
+        .into_iter()
+        .find(|peer| peer.public_key() == established.remote_static());
+
+    // 以降のパケットは暗号化通信路で送受信する
+    let mut channel = established.into_channel(stream);
+
+    let mut trusted = match trusted {
+        Some(trusted) => {
+            info!("client: server authenticated as {}", trusted.node_id());
+            trusted
+        }
+
+        None => {
+            send_packet(&mut channel, SyncPacket::abort("unknown peer"))?;
+            error!("client: server is not paired");
+            return Err(anyhow!("server is not paired"));
+        }
+    };
+
     /*
      * サーバからの全件受信フェーズ
      */
@@ -82,50 +225,122 @@ pub(super) fn run(
         .into_iter()
         .map(|id| id.to_string())
         .collect();
+    let mut adopted: HashSet<String> = HashSet::new();
+    let mut conflicted: HashSet<String> = HashSet::new();
     let mut received = 0u64;
+    let incremental;
 
     loop {
-        match recv_packet(&mut stream)? {
+        match recv_packet(&mut channel)? {
             SyncPacket::ServerEntry(entry) => {
                 let entry_id = entry.id().to_string();
                 remaining_local.remove(&entry_id);
 
-                let decision = decide_entry(writer, &entry, prompter)?;
+                // ドライランでは確認を行わず、競合として報告するのみとする
+                let policy = if settings.dry_run {
+                    ConflictPolicy::Fail
+                } else {
+                    settings.conflict
+                };
+
+                let decision = decide_entry(writer, &entry, policy, prompter)?;
                 match decision {
                     EntryDecision::AdoptRemote => {
-                        writer.put(&entry)?;
-                        send_ack(&mut stream, &entry_id, true, None)?;
+                        if is_deletion(writer, &entry)? {
+                            report.deleted(&entry);
+                        } else {
+                            report.adopted(&entry);
+                        }
+
+                        writer.put_synced(&entry)?;
+                        writer.put_ancestor(&entry)?;
+                        adopted.insert(entry_id.clone());
+                        send_ack(&mut channel, &entry_id, true, None)?;
                         debug!(
                             "client: adopt remote entry id={}, service={}",
                             entry.id(),
                             entry.service()
                         );
                     }
+                    EntryDecision::Unchanged => {
+                        writer.put_ancestor(&entry)?;
+                        send_ack(&mut channel, &entry_id, true, None)?;
+                        debug!(
+                            "client: unchanged entry id={}, service={}",
+                            entry.id(),
+                            entry.service()
+                        );
+                    }
+                    EntryDecision::Resolved(resolved) => {
+                        // ローカル側の内容が変わる場合のみ採用として報告する
+                        let local = writer.get(&entry.id())?;
+                        if local.is_none_or(|local| !is_same_entry(&resolved, &local)) {
+                            report.adopted(&resolved);
+                        }
+
+                        // 併合した版を双方に反映する
+                        writer.put_synced(&resolved)?;
+                        send_candidates.insert(entry_id.clone());
+                        send_ack(&mut channel, &entry_id, true, None)?;
+                        debug!(
+                            "client: resolve concurrent entry id={}, service={}",
+                            entry.id(),
+                            entry.service()
+                        );
+                    }
+                    EntryDecision::KeepBoth(resolved, loser) => {
+                        // 採用した版を双方に反映し、ローカル側の版は別エント
+                        // リとして残す
+                        writer.put_synced(&resolved)?;
+                        send_candidates.insert(entry_id.clone());
+
+                        let copy = conflict_copy(&loser);
+                        writer.put(&copy)?;
+                        send_candidates.insert(copy.id().to_string());
+
+                        send_ack(&mut channel, &entry_id, true, None)?;
+                        info!(
+                            "client: keep both versions id={}, copy={}",
+                            entry.id(),
+                            copy.id()
+                        );
+                    }
                     EntryDecision::KeepLocal => {
                         send_candidates.insert(entry_id.clone());
-                        send_ack(&mut stream, &entry_id, true, None)?;
+                        send_ack(&mut channel, &entry_id, true, None)?;
                         debug!(
                             "client: keep local entry id={}, service={}",
                             entry.id(),
                             entry.service()
                         );
                     }
+                    EntryDecision::Abort(_) if settings.dry_run => {
+                        report.conflicted(&entry);
+                        conflicted.insert(entry_id.clone());
+                        send_ack(&mut channel, &entry_id, true, None)?;
+                        debug!(
+                            "client: conflict id={}, service={} (dry-run)",
+                            entry.id(),
+                            entry.service()
+                        );
+                    }
                     EntryDecision::Abort(msg) => {
-                        send_ack(&mut stream, &entry_id, false, Some(msg.clone()))?;
-                        send_packet(&mut stream, SyncPacket::abort(msg),)?;
+                        send_ack(&mut channel, &entry_id, false, Some(msg.clone()))?;
+                        send_packet(&mut channel, SyncPacket::abort(msg.clone()))?;
                         error!(
                             "client: abort on conflict id={}, service={}",
                             entry.id(),
                             entry.service()
                         );
-                        return Err(anyhow!("aborted by user"));
+                        return Err(anyhow!("sync aborted: {}", msg));
                     }
                 }
 
                 received += 1;
             }
 
-            SyncPacket::ServerEntriesEnd(_end) => {
+            SyncPacket::ServerEntriesEnd(end) => {
+                incremental = end.incremental;
                 break;
             }
 
@@ -136,14 +351,28 @@ pub(super) fn run(
             other => return Err(anyhow!("unexpected packet: {:?}", other)),
         }
     }
-    info!("client: receive phase end ({} entries)", received);
+    info!(
+        "client: receive phase end ({} entries, {})",
+        received,
+        if incremental { "incremental" } else { "full" }
+    );
 
     /*
      * クライアント側の差分送信フェーズ
      */
-    // サーバから届かなかったローカル専用エントリも送信対象にする
-    for id in remaining_local {
-        send_candidates.insert(id);
+    if incremental && last_mark.is_some() {
+        // 前回の同期以降に変更したエントリを送信対象にする（サーバ側の変
+        // 更を採用したものは除く）
+        for id in local_changes {
+            if !adopted.contains(&id) && !conflicted.contains(&id) {
+                send_candidates.insert(id);
+            }
+        }
+    } else {
+        // サーバから届かなかったローカル専用エントリも送信対象にする
+        for id in remaining_local {
+            send_candidates.insert(id);
+        }
     }
 
     let mut sent = 0u64;
@@ -155,17 +384,25 @@ pub(super) fn run(
                 .ok_or_else(|| anyhow!("missing local entry {}", id_str))?
         };
 
-        send_packet(&mut stream, SyncPacket::client_entry(entry))?;
+        if entry.is_removed() {
+            report.deleted(&entry);
+        } else {
+            report.pushed(&entry);
+        }
+
+        send_packet(&mut channel, SyncPacket::client_entry(entry.clone()))?;
         sent += 1;
 
-        match recv_packet(&mut stream)? {
+        match recv_packet(&mut channel)? {
             SyncPacket::EntryAck(ack) => {
                 if !ack.accepted {
                     let reason = ack.reason.unwrap_or_else(|| "rejected".into());
-                    send_packet(&mut stream, SyncPacket::abort(reason.clone()))?;
+                    send_packet(&mut channel, SyncPacket::abort(reason.clone()))?;
                     error!("client: server rejected entry id={}", ack.entry_id);
                     return Err(anyhow!("server rejected entry: {}", reason));
                 }
+                // サーバに受理された版を次回のマージの基準にする
+                writer.put_ancestor(&entry)?;
                 debug!("client: entry ack id={}", ack.entry_id);
             }
 
@@ -178,16 +415,27 @@ pub(super) fn run(
         }
     }
 
-    send_packet(&mut stream, SyncPacket::client_entries_end(sent))?;
+    send_packet(&mut channel, SyncPacket::client_entries_end(sent))?;
     info!("client: send phase end ({} entries)", sent);
 
     /*
      * 終了待ち
      */
-    match recv_packet(&mut stream)? {
-        SyncPacket::Finished => {
-            info!("client: sync finished");
-            Ok(())
+    match recv_packet(&mut channel)? {
+        SyncPacket::Finished(finished) => {
+            // 次回の差分同期の起点を記録する
+            let local_seq = writer.change_seq()?;
+            trusted.set_address(addr.to_string());
+            trusted.set_watermark(SyncWatermark::new(
+                finished.watermark,
+                local_seq,
+            ));
+            // 自身の変更は全てサーバに受理されている
+            trusted.set_acked_seq(local_seq);
+            writer.put_peer(&trusted)?;
+
+            info!("client: sync finished (server seq {})", finished.watermark);
+            Ok(report)
         }
         SyncPacket::Abort(abort) => {
             error!("client: server aborted: {}", abort.reason);
@@ -198,21 +446,29 @@ pub(super) fn run(
 }
 
 /// エントリ比較の結果
-enum EntryDecision {
+pub(super) enum EntryDecision {
     /// 受信エントリを採用
     AdoptRemote,
+    /// ローカルと同一のため何もしない
+    Unchanged,
     /// ローカルの方が新しいので保持（送信候補にする）
     KeepLocal,
-    /// 同時刻差分でユーザが拒否したため中断
+    /// 並行した変更を解決したエントリを保存し、送信候補にする
+    Resolved(Entry),
+    /// 解決したエントリ(第1要素)を保存し、ローカル側の版(第2要素)を別エン
+    /// トリとして複製する
+    KeepBoth(Entry, Box<Entry>),
+    /// 競合をユーザが拒否した、または解決方針により中断
     Abort(String),
 }
 
 /*
  * 受信エントリをどう扱うか判定する
  */
-fn decide_entry(
+pub(super) fn decide_entry(
     writer: &TransactionWriter,
     incoming: &Entry,
+    conflict: ConflictPolicy,
     prompter: &dyn Prompter,
 ) -> Result<EntryDecision> {
     let id = incoming.id();
@@ -224,29 +480,165 @@ fn decide_entry(
 
     let local_entry = local_entry.unwrap();
 
-    let incoming_ts = incoming.last_update();
-    let local_ts = local_entry.last_update();
+    /*
+     * バージョンベクタによる前後関係の判定
+     */
+    match local_entry.version().compare(&incoming.version()) {
+        // ローカルの版が受信した版の祖先
+        Causality::Before => Ok(EntryDecision::AdoptRemote),
 
-    // 同一時刻の扱い
-    if incoming_ts == local_ts {
-        if is_same_entry(&local_entry, incoming) {
-            return Ok(EntryDecision::KeepLocal);
+        // 受信した版がローカルの版の祖先
+        Causality::After => Ok(EntryDecision::KeepLocal),
+
+        // 同一の版（バージョンベクタ導入前のエントリを含む）
+        Causality::Equal => {
+            if is_same_entry(&local_entry, incoming) {
+                Ok(EntryDecision::Unchanged)
+            } else {
+                decide_by_timestamp(&local_entry, incoming, conflict, prompter)
+            }
         }
 
-        // サーバ優先だがユーザ確認を挟む
-        let ok = prompter.confirm(
-            "同一時刻の更新が競合しました。サーバ側を採用しますか？",
-            false,
-            Some("競合"),
-        )?;
-        if ok {
-            return Ok(EntryDecision::AdoptRemote);
-        } else {
-            return Ok(EntryDecision::Abort(
-                "user rejected conflict resolution".into(),
-            ));
+        // 双方で並行して変更された
+        Causality::Concurrent => {
+            let mut version = local_entry.version();
+            version.merge(&incoming.version());
+
+            let with_version = |entry: &Entry| {
+                let mut resolved = entry.clone();
+                resolved.set_version(version.clone());
+                resolved
+            };
+
+            if is_same_entry(&local_entry, incoming) {
+                return Ok(EntryDecision::Resolved(with_version(&local_entry)));
+            }
+
+            /*
+             * 共通祖先が無い場合はエントリ単位で解決する
+             */
+            let base = match writer.get_ancestor(&id)? {
+                Some(base) => base,
+                None => {
+                    let msg = format!(
+                        "エントリ {} ({}) が双方で並行して更新されました。\
+                         サーバ側を採用しますか？",
+                        id,
+                        incoming.service(),
+                    );
+
+                    return Ok(match conflict {
+                        ConflictPolicy::Prompt => {
+                            if prompter.confirm(&msg, false, Some("競合"))? {
+                                EntryDecision::Resolved(with_version(incoming))
+                            } else {
+                                EntryDecision::Abort(
+                                    "user rejected conflict resolution".into(),
+                                )
+                            }
+                        }
+                        ConflictPolicy::PreferServer => {
+                            EntryDecision::Resolved(with_version(incoming))
+                        }
+                        ConflictPolicy::PreferClient => {
+                            EntryDecision::Resolved(with_version(&local_entry))
+                        }
+                        ConflictPolicy::KeepBoth => EntryDecision::KeepBoth(
+                            with_version(incoming),
+                            Box::new(local_entry),
+                        ),
+                        ConflictPolicy::Fail => conflict_abort(&local_entry),
+                    });
+                }
+            };
+
+            /*
+             * 共通祖先を基準に項目単位でマージし、双方で変更された項目のみ
+             * 解決方針に従って解決する
+             */
+            let merged = merge::three_way(&base, &local_entry, incoming);
+            debug!(
+                "client: three-way merge id={}, {} conflict(s)",
+                id,
+                merged.conflicts().len()
+            );
+
+            if merged.conflicts().is_empty() {
+                let resolved = merged.resolve(|_| Ok(false))?;
+                return Ok(EntryDecision::Resolved(with_version(&resolved)));
+            }
+
+            let resolved = match conflict {
+                ConflictPolicy::Prompt => merged.resolve(|conflict| {
+                    prompter.confirm(
+                        &format!(
+                            "エントリ {} ({}) の{}\nサーバ側の値を採用しますか？",
+                            id,
+                            local_entry.service(),
+                            conflict.describe(),
+                        ),
+                        false,
+                        Some("競合"),
+                    )
+                })?,
+                ConflictPolicy::PreferServer | ConflictPolicy::KeepBoth => {
+                    merged.resolve(|_| Ok(true))?
+                }
+                ConflictPolicy::PreferClient => merged.resolve(|_| Ok(false))?,
+                ConflictPolicy::Fail => return Ok(conflict_abort(&local_entry)),
+            };
+
+            if conflict == ConflictPolicy::KeepBoth {
+                Ok(EntryDecision::KeepBoth(
+                    with_version(&resolved),
+                    Box::new(local_entry),
+                ))
+            } else {
+                Ok(EntryDecision::Resolved(with_version(&resolved)))
+            }
         }
     }
+}
+
+/*
+ * 更新日時による判定（バージョンベクタで前後関係を判定できない場合）
+ */
+fn decide_by_timestamp(
+    local_entry: &Entry,
+    incoming: &Entry,
+    conflict: ConflictPolicy,
+    prompter: &dyn Prompter,
+) -> Result<EntryDecision> {
+    let incoming_ts = incoming.last_update();
+    let local_ts = local_entry.last_update();
+
+    // 同一時刻の扱い（解決方針に従う）
+    if incoming_ts == local_ts {
+        return match conflict {
+            ConflictPolicy::Prompt => {
+                // サーバ優先だがユーザ確認を挟む
+                let ok = prompter.confirm(
+                    "同一時刻の更新が競合しました。サーバ側を採用しますか？",
+                    false,
+                    Some("競合"),
+                )?;
+                if ok {
+                    Ok(EntryDecision::AdoptRemote)
+                } else {
+                    Ok(EntryDecision::Abort(
+                        "user rejected conflict resolution".into(),
+                    ))
+                }
+            }
+            ConflictPolicy::PreferServer => Ok(EntryDecision::AdoptRemote),
+            ConflictPolicy::PreferClient => Ok(EntryDecision::KeepLocal),
+            ConflictPolicy::KeepBoth => Ok(EntryDecision::KeepBoth(
+                incoming.clone(),
+                Box::new(local_entry.clone()),
+            )),
+            ConflictPolicy::Fail => Ok(conflict_abort(local_entry)),
+        };
+    }
 
     // タイムスタンプ比較（Noneは常に古い扱い）
     match (incoming_ts, local_ts) {
@@ -258,15 +650,58 @@ fn decide_entry(
     }
 }
 
+/*
+ * 解決方針`fail`による中断
+ */
+fn conflict_abort(local_entry: &Entry) -> EntryDecision {
+    EntryDecision::Abort(format!(
+        "conflict on entry {} ({})",
+        local_entry.id(),
+        local_entry.service()
+    ))
+}
+
+/*
+ * 競合で採用されなかった版の複製（新しいサービスIDに`conflict`タグを付与）
+ */
+pub(super) fn conflict_copy(loser: &Entry) -> Entry {
+    let mut tags = loser.tags();
+    tags.push(CONFLICT_TAG.to_string());
+
+    let mut copy = Entry::new(
+        ServiceId::new(),
+        loser.service(),
+        loser.aliases(),
+        tags,
+        loser.properties(),
+    );
+    copy.set_removed(loser.is_removed());
+    copy.set_policy(loser.policy());
+
+    copy
+}
+
+/*
+ * 受信エントリの採用が削除の反映となるか否か
+ */
+pub(super) fn is_deletion(writer: &TransactionWriter, incoming: &Entry) -> Result<bool> {
+    if !incoming.is_removed() {
+        return Ok(false);
+    }
+
+    Ok(writer.get(&incoming.id())?.is_some_and(|local| !local.is_removed()))
+}
+
 /*
  * エントリ内容が同一かどうか比較する（timestamp除く）
  */
-fn is_same_entry(a: &Entry, b: &Entry) -> bool {
+pub(super) fn is_same_entry(a: &Entry, b: &Entry) -> bool {
     a.id() == b.id()
         && a.service() == b.service()
         && a.aliases() == b.aliases()
         && a.tags() == b.tags()
         && a.properties() == b.properties()
+        && a.policy() == b.policy()
         && a.is_removed() == b.is_removed()
 }
 
@@ -274,10 +709,346 @@ fn is_same_entry(a: &Entry, b: &Entry) -> bool {
  * ACK送信ヘルパ
  */
 fn send_ack(
-    stream: &mut TcpStream,
+    channel: &mut SecureChannel,
     entry_id: &str,
     accepted: bool,
     reason: Option<String>,
 ) -> Result<()> {
-    send_packet(stream, SyncPacket::entry_ack(entry_id, accepted, reason))
+    send_packet(channel, SyncPacket::entry_ack(entry_id, accepted, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use ulid::Ulid;
+
+    use crate::command::prompt::test::QueuePrompter;
+    use crate::command::sync::transport::MemoryPipe;
+    use crate::command::sync::{load_identity, server};
+    use crate::database::types::{TrustedPeer, VersionVector};
+    use crate::database::EntryManager;
+
+    fn temp_db_path() -> PathBuf {
+        std::env::temp_dir().join(format!("pwmgr-test-{}.redb", Ulid::new()))
+    }
+
+    fn make_entry(id: &ServiceId, service: &str) -> Entry {
+        Entry::new(
+            id.clone(),
+            service.to_string(),
+            vec![],
+            vec![],
+            BTreeMap::new(),
+        )
+    }
+
+    ///
+    /// 前後関係のある変更は確認無しで判定され、並行した変更のみ競合となる
+    /// ことを確認
+    ///
+    #[test]
+    fn decide_entry_by_causality() {
+        let path = temp_db_path();
+        let mgr = EntryManager::open_for_test(&path).unwrap();
+        let id = ServiceId::new();
+
+        mgr.with_write_transaction(|writer| {
+            writer.put(&make_entry(&id, "local"))?;
+            let local = writer.get(&id)?.unwrap();
+
+            // ローカルの版を引き継いだ相手側の変更は採用（時刻は問わない）
+            let mut successor = make_entry(&id, "remote");
+            let mut version = local.version();
+            version.increment("remote-node");
+            successor.set_version(version);
+            let prompter = QueuePrompter::new(vec![]);
+            assert!(matches!(
+                decide_entry(writer, &successor, ConflictPolicy::Prompt, &prompter)?,
+                EntryDecision::AdoptRemote
+            ));
+
+            // ローカルの版の祖先は保持
+            let mut ancestor = make_entry(&id, "old");
+            ancestor.set_version(VersionVector::default());
+            assert!(matches!(
+                decide_entry(writer, &ancestor, ConflictPolicy::Prompt, &prompter)?,
+                EntryDecision::KeepLocal
+            ));
+
+            // 並行した変更は確認の上で併合した版として解決
+            let mut concurrent = make_entry(&id, "concurrent");
+            let mut version = VersionVector::default();
+            version.increment("remote-node");
+            concurrent.set_version(version);
+            let prompter = QueuePrompter::new(vec![true]);
+            match decide_entry(writer, &concurrent, ConflictPolicy::Prompt, &prompter)? {
+                EntryDecision::Resolved(resolved) => {
+                    assert_eq!(resolved.service(), "concurrent");
+                    assert_eq!(
+                        resolved.version().compare(&local.version()),
+                        Causality::After
+                    );
+                }
+                _ => panic!("concurrent update must be resolved"),
+            }
+
+            let prompter = QueuePrompter::new(vec![false]);
+            assert!(matches!(
+                decide_entry(writer, &concurrent, ConflictPolicy::Prompt, &prompter)?,
+                EntryDecision::Abort(_)
+            ));
+
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// パスワードポリシーのみが異なるエントリが同一と判定されないことを確認
+    ///
+    #[test]
+    fn decide_entry_detects_policy_change() {
+        let path = temp_db_path();
+        let mgr = EntryManager::open_for_test(&path).unwrap();
+        let id = ServiceId::new();
+
+        mgr.with_write_transaction(|writer| {
+            writer.put(&make_entry(&id, "svc"))?;
+            let local = writer.get(&id)?.unwrap();
+
+            let mut remote = local.clone();
+            remote.set_policy(Some(serde_yaml_ng::from_str("min_length: 16\n")?));
+            remote.set_last_update_now();
+
+            assert!(is_same_entry(&local, &local.clone()));
+            assert!(!is_same_entry(&local, &remote));
+
+            // 版が同一でもポリシーの変更は未変更として扱わない
+            let prompter = QueuePrompter::new(vec![]);
+            assert!(matches!(
+                decide_entry(writer, &remote, ConflictPolicy::PreferServer, &prompter)?,
+                EntryDecision::AdoptRemote
+            ));
+
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// 共通祖先がある場合は項目単位でマージされ、双方で変更された項目のみ
+    /// 確認されることを確認
+    ///
+    #[test]
+    fn decide_entry_merges_from_ancestor() {
+        let path = temp_db_path();
+        let mgr = EntryManager::open_for_test(&path).unwrap();
+        let id = ServiceId::new();
+
+        let with_props = |props: &[(&str, &str)]| {
+            Entry::new(
+                id.clone(),
+                "svc".to_string(),
+                vec![],
+                vec![],
+                props.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+        };
+
+        mgr.with_write_transaction(|writer| {
+            let base = with_props(&[("user", "alice"), ("url", "x")]);
+            writer.put_ancestor(&base)?;
+            writer.put(&with_props(&[("user", "bob"), ("url", "x")]))?;
+
+            let mut remote = with_props(&[("user", "alice"), ("url", "y")]);
+            let mut version = VersionVector::default();
+            version.increment("remote-node");
+            remote.set_version(version);
+
+            // 別々のキーの変更は確認無しで双方とも反映される
+            let prompter = QueuePrompter::new(vec![]);
+            match decide_entry(writer, &remote, ConflictPolicy::Prompt, &prompter)? {
+                EntryDecision::Resolved(resolved) => {
+                    assert_eq!(
+                        resolved.properties(),
+                        BTreeMap::from([
+                            ("user".to_string(), "bob".to_string()),
+                            ("url".to_string(), "y".to_string()),
+                        ])
+                    );
+                }
+                _ => panic!("concurrent update must be merged"),
+            }
+
+            // 同じキーの変更は確認の結果に従う
+            let mut remote = with_props(&[("user", "carol"), ("url", "x")]);
+            let mut version = VersionVector::default();
+            version.increment("remote-node");
+            remote.set_version(version);
+
+            let prompter = QueuePrompter::new(vec![true]);
+            match decide_entry(writer, &remote, ConflictPolicy::Prompt, &prompter)? {
+                EntryDecision::Resolved(resolved) => {
+                    assert_eq!(
+                        resolved.properties().get("user"),
+                        Some(&"carol".to_string())
+                    );
+                }
+                _ => panic!("concurrent update must be merged"),
+            }
+
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// 解決方針を指定した場合は確認無しで競合が解決されることを確認
+    ///
+    #[test]
+    fn decide_entry_follows_conflict_policy() {
+        let path = temp_db_path();
+        let mgr = EntryManager::open_for_test(&path).unwrap();
+        let id = ServiceId::new();
+
+        mgr.with_write_transaction(|writer| {
+            writer.put(&make_entry(&id, "local"))?;
+
+            let mut remote = make_entry(&id, "remote");
+            let mut version = VersionVector::default();
+            version.increment("remote-node");
+            remote.set_version(version);
+
+            // 確認が行われた場合は既定値(No)により中断となる
+            let prompter = QueuePrompter::new(vec![]);
+
+            match decide_entry(
+                writer, &remote, ConflictPolicy::PreferServer, &prompter
+            )? {
+                EntryDecision::Resolved(resolved) => {
+                    assert_eq!(resolved.service(), "remote");
+                }
+                _ => panic!("server side must be adopted"),
+            }
+
+            match decide_entry(
+                writer, &remote, ConflictPolicy::PreferClient, &prompter
+            )? {
+                EntryDecision::Resolved(resolved) => {
+                    assert_eq!(resolved.service(), "local");
+                }
+                _ => panic!("client side must be adopted"),
+            }
+
+            match decide_entry(
+                writer, &remote, ConflictPolicy::KeepBoth, &prompter
+            )? {
+                EntryDecision::KeepBoth(resolved, loser) => {
+                    assert_eq!(resolved.service(), "remote");
+
+                    let copy = conflict_copy(&loser);
+                    assert_ne!(copy.id(), id);
+                    assert_eq!(copy.service(), "local");
+                    assert!(copy.tags().contains(&CONFLICT_TAG.to_string()));
+                }
+                _ => panic!("both versions must be kept"),
+            }
+
+            assert!(matches!(
+                decide_entry(writer, &remote, ConflictPolicy::Fail, &prompter)?,
+                EntryDecision::Abort(_)
+            ));
+
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// メモリ上の通信路でサーバとクライアントの同期が完了することを確認
+    ///
+    #[test]
+    fn sync_over_memory_pipe() {
+        let client_mgr = EntryManager::open_for_test(temp_db_path()).unwrap();
+        let server_mgr = EntryManager::open_for_test(temp_db_path()).unwrap();
+        let client_id = ServiceId::new();
+        let server_id = ServiceId::new();
+
+        /*
+         * 双方のノードを互いに同期相手として登録する
+         */
+        let client_identity = client_mgr.with_write_transaction(|writer| {
+            writer.put(&make_entry(&client_id, "client-entry"))?;
+            load_identity(writer)
+        }).unwrap();
+        let server_identity = server_mgr.with_write_transaction(|writer| {
+            writer.put(&make_entry(&server_id, "server-entry"))?;
+            load_identity(writer)
+        }).unwrap();
+
+        client_mgr.with_write_transaction(|writer| {
+            writer.put_peer(&TrustedPeer::new(
+                server_identity.node_id(),
+                server_identity.public_key().to_vec(),
+                "memory".to_string(),
+            ))
+        }).unwrap();
+        server_mgr.with_write_transaction(|writer| {
+            writer.put_peer(&TrustedPeer::new(
+                client_identity.node_id(),
+                client_identity.public_key().to_vec(),
+                "memory".to_string(),
+            ))
+        }).unwrap();
+
+        /*
+         * 同期
+         */
+        let (client_pipe, server_pipe) = MemoryPipe::pair();
+
+        let server = std::thread::spawn(move || {
+            server_mgr.with_write_transaction(|writer| {
+                server::session(
+                    Box::new(server_pipe),
+                    "memory",
+                    &server_identity,
+                    None,
+                    &[],
+                    writer,
+                ).map(|_| ())
+            }).unwrap();
+            server_mgr
+        });
+
+        let settings = ClientSettings {
+            full: false,
+            conflict: ConflictPolicy::Fail,
+            dry_run: false,
+        };
+        let prompter = QueuePrompter::new(vec![]);
+        client_mgr.with_write_transaction(|writer| {
+            session(
+                Box::new(client_pipe),
+                "memory",
+                &client_identity,
+                None,
+                &settings,
+                writer,
+                &prompter,
+            )
+        }).unwrap();
+
+        let server_mgr = server.join().unwrap();
+
+        /*
+         * 双方が全てのエントリを持っていることを確認
+         */
+        for mgr in [&client_mgr, &server_mgr] {
+            mgr.with_write_transaction(|writer| {
+                assert!(writer.get(&client_id)?.is_some());
+                assert!(writer.get(&server_id)?.is_some());
+                Ok(())
+            }).unwrap();
+        }
+    }
 }
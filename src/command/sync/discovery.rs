@@ -0,0 +1,268 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//! ローカルネットワーク上の同期相手の探索
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::command::sync::PROTOCOL_VERSION;
+
+/// 探索に用いるUDPポートのデフォルト値
+pub(crate) const DEFAULT_DISCOVERY_PORT: u16 = 2457;
+
+/// 告知パケットであることを示す識別子
+const MAGIC: &str = "pwmgr-sync";
+
+/// 探索で告知を待ち受ける時間
+pub(super) const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 告知の送信間隔
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 告知停止の確認間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 告知パケットの最大長
+const MAX_ANNOUNCE_LEN: usize = 512;
+
+///
+/// サーバが待ち受け中に送信する告知パケット
+///
+/// # 注記
+/// 告知内容は認証されないため、接続先の決定にのみ用いる。相手の正当性は
+/// 接続後のハンドシェイクで確認される。
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct Announcement {
+    /// 告知パケットであることを示す識別子
+    magic: String,
+
+    /// プロトコルバージョン
+    protocol_version: u16,
+
+    /// サーバのノードID
+    node_id: String,
+
+    /// 同期を待ち受けているアドレス(全アドレスで待ち受けている場合は`None`)
+    host: Option<IpAddr>,
+
+    /// 同期を待ち受けているポート番号
+    port: u16,
+}
+
+///
+/// 探索で見つかった同期相手
+///
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct DiscoveredPeer {
+    /// ノードID
+    pub(super) node_id: String,
+
+    /// 接続先アドレス(待ち受けアドレスが告知されていない場合は告知の送信元
+    /// アドレスを用いる)
+    pub(super) address: String,
+}
+
+///
+/// 待ち受け中であることの告知を行うスレッドの管理
+///
+/// # 注記
+/// 破棄時に告知を停止する。
+///
+pub(super) struct Announcer {
+    /// 停止要求フラグ
+    stop: Arc<AtomicBool>,
+
+    /// 告知スレッドのハンドル
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Announcer {
+    ///
+    /// 告知の開始
+    ///
+    /// # 引数
+    /// * `node_id` - 自ノードのID
+    /// * `listen_addr` - 同期を待ち受けているアドレス
+    /// * `discovery_port` - 告知の送信先のUDPポート番号
+    ///
+    /// # 戻り値
+    /// 告知スレッドの管理オブジェクトを`Ok()`でラップして返す。
+    ///
+    /// # 注記
+    /// ブロードキャストアドレスに加え、同一ホスト上のクライアント向けにルー
+    /// プバックアドレスにも送信する。
+    ///
+    pub(super) fn start(
+        node_id: String,
+        listen_addr: SocketAddr,
+        discovery_port: u16,
+    ) -> Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .context("bind announce socket")?;
+        socket.set_broadcast(true)?;
+
+        let packet = rmp_serde::to_vec(&Announcement {
+            magic: MAGIC.to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            node_id,
+            host: Some(listen_addr.ip()).filter(|ip| !ip.is_unspecified()),
+            port: listen_addr.port(),
+        })?;
+
+        let targets = [
+            SocketAddr::from((Ipv4Addr::BROADCAST, discovery_port)),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, discovery_port)),
+        ];
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = stop.clone();
+
+            thread::spawn(move || {
+                let mut last_sent: Option<Instant> = None;
+
+                while !stop.load(Ordering::SeqCst) {
+                    if last_sent.is_none_or(|at| at.elapsed() >= ANNOUNCE_INTERVAL) {
+                        for target in &targets {
+                            // 経路が無い等の送信エラーは告知を諦めるのみとする
+                            if let Err(err) = socket.send_to(&packet, target) {
+                                debug!("announce to {} failed: {}", target, err);
+                            }
+                        }
+                        last_sent = Some(Instant::now());
+                    }
+
+                    thread::sleep(POLL_INTERVAL);
+                }
+            })
+        };
+
+        info!("announce {} on udp/{}", listen_addr, discovery_port);
+
+        Ok(Self {stop, handle: Some(handle)})
+    }
+}
+
+// Dropトレイトの実装
+impl Drop for Announcer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+///
+/// 同期相手の探索
+///
+/// # 引数
+/// * `discovery_port` - 告知を受信するUDPポート番号
+/// * `timeout` - 告知を待ち受ける時間
+/// * `target` - 探しているノードID(見つかった時点で探索を打ち切る)
+///
+/// # 戻り値
+/// 見つかった同期相手のリスト(ノードID順)を`Ok()`でラップして返す。
+///
+pub(super) fn discover(
+    discovery_port: u16,
+    timeout: Duration,
+    target: Option<&str>,
+) -> Result<Vec<DiscoveredPeer>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, discovery_port))
+        .with_context(|| format!("bind discovery port {}", discovery_port))?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+
+    info!("discover peers on udp/{} ({:?})", discovery_port, timeout);
+
+    let deadline = Instant::now() + timeout;
+    let mut found = BTreeMap::new();
+    let mut buf = [0u8; MAX_ANNOUNCE_LEN];
+
+    while Instant::now() < deadline {
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(err) if matches!(
+                err.kind(),
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+            ) => continue,
+            Err(err) => return Err(err).context("receive announcement"),
+        };
+
+        let announce: Announcement = match rmp_serde::from_slice(&buf[..len]) {
+            Ok(announce) => announce,
+            Err(err) => {
+                debug!("ignore invalid announcement from {}: {}", from, err);
+                continue;
+            }
+        };
+
+        if announce.magic != MAGIC || announce.protocol_version != PROTOCOL_VERSION {
+            debug!("ignore incompatible announcement from {}", from);
+            continue;
+        }
+
+        let host = announce.host.unwrap_or(from.ip());
+        let address = SocketAddr::new(host, announce.port).to_string();
+        debug!("found peer {} at {}", announce.node_id, address);
+
+        let matched = target == Some(announce.node_id.as_str());
+        found.insert(announce.node_id.clone(), DiscoveredPeer {
+            node_id: announce.node_id,
+            address,
+        });
+
+        if matched {
+            break;
+        }
+    }
+
+    Ok(found.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///
+    /// ループバック上で告知したサーバが探索で見つかることを確認
+    ///
+    #[test]
+    fn discover_announcer_on_loopback() {
+        // 他のテストと衝突しにくい空きポートを選ぶ
+        let port = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let _announcer = Announcer::start(
+            "node-a".to_string(),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 7000)),
+            port,
+        ).unwrap();
+
+        let peers = discover(port, Duration::from_secs(5), Some("node-a"))
+            .unwrap();
+
+        // 待ち受けアドレスが告知されるため送信元によらず同じアドレスとなる
+        assert_eq!(peers, vec![DiscoveredPeer {
+            node_id: "node-a".to_string(),
+            address: "127.0.0.1:7000".to_string(),
+        }]);
+    }
+}
@@ -0,0 +1,637 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! 共有ディレクトリを介した同期処理
+//!
+//! 各ノードはペアリング済みの同期相手毎に、自身の全エントリを宛先のノード
+//! のみが復号できる同期ファイルとして共有ディレクトリに書き出す。相手が書き
+//! 出した同期ファイルは、クライアントモードと同じ規則でマージする。
+//!
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::cmd_args::ConflictPolicy;
+use crate::command::prompt::Prompter;
+use crate::command::sync::client::{
+    conflict_copy, decide_entry, is_deletion, is_same_entry, EntryDecision,
+};
+use crate::command::sync::secure::{self, LocalIdentity, SyncPsk};
+use crate::command::sync::{
+    recv_packet, send_packet, SyncPacket, SyncReport, MAX_PLAIN_PACKET_LEN,
+    PROTOCOL_VERSION,
+};
+use crate::database::{TransactionReadable, TransactionWriter};
+use crate::database::types::{Entry, TrustedPeer};
+
+/// 同期ファイルであることを示す識別子
+const MAGIC: &str = "pwmgr-sync-bundle";
+
+/// 同期ファイルの拡張子
+const BUNDLE_EXT: &str = "pwsync";
+
+///
+/// 同期ファイルのヘッダ(平文)
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleHeader {
+    /// 同期ファイルであることを示す識別子
+    magic: String,
+
+    /// プロトコルバージョン
+    protocol_version: u16,
+
+    /// 送信元のノードID
+    from: String,
+
+    /// 宛先のノードID
+    to: String,
+
+    /// 書き出し日時(エポックミリ秒)
+    created_epoch_ms: u64,
+
+    /// 書き出し時点の送信元の変更シーケンス番号
+    #[serde(default)]
+    seq: u64,
+
+    /// 送信元が取り込み済みの、宛先が書き出した同期ファイルの変更シーケンス
+    /// 番号
+    #[serde(default)]
+    acked_seq: Option<u64>,
+
+    /// Noiseハンドシェイクのメッセージ
+    handshake: Vec<u8>,
+}
+
+impl BundleHeader {
+    ///
+    /// ハンドシェイクに束縛する付帯情報
+    ///
+    /// # 注記
+    /// 受信の確認は墓標のgcの判定に用いるため、改竄できないよう変更シーケン
+    /// ス番号も束縛する。
+    ///
+    fn context(&self) -> Result<Vec<u8>> {
+        Ok(rmp_serde::to_vec(&(
+            &self.magic,
+            &self.to,
+            self.created_epoch_ms,
+            self.seq,
+            self.acked_seq,
+        ))?)
+    }
+}
+
+///
+/// 共有ディレクトリを介した同期の処理状態
+///
+struct FolderSync<'a> {
+    /// 共有ディレクトリ
+    dir: &'a Path,
+
+    /// 自ノードの鍵情報
+    identity: &'a LocalIdentity,
+
+    /// 事前共有鍵
+    psk: Option<&'a SyncPsk>,
+
+    /// 競合時の解決方針
+    conflict: ConflictPolicy,
+
+    /// ドライランか否か
+    dry_run: bool,
+
+    /// プロンプター
+    prompter: &'a dyn Prompter,
+
+    /// 同期結果のレポート
+    report: SyncReport,
+
+    /// 競合したエントリのID(ドライラン時のみ)
+    conflicted: HashSet<String>,
+
+    /// 取り込んだ同期ファイルの変更シーケンス番号(送信元のノードIDをキーと
+    /// する)
+    imported: HashMap<String, u64>,
+}
+
+impl FolderSync<'_> {
+    ///
+    /// 相手が書き出した同期ファイルの取り込み
+    ///
+    /// # 引数
+    /// * `writer` - 書き込みトランザクション
+    /// * `peer` - 同期相手
+    ///
+    /// # 戻り値
+    /// 同期ファイルに含まれていたエントリ(IDをキーとする)を`Ok()`でラップし
+    /// て返す。同期ファイルが無い場合は空のマップを返す。
+    ///
+    fn import(&mut self, writer: &mut TransactionWriter, peer: &TrustedPeer)
+        -> Result<HashMap<String, Entry>>
+    {
+        let node_id = self.identity.node_id();
+        let path = bundle_path(self.dir, &peer.node_id(), &node_id);
+        let mut incoming = HashMap::new();
+
+        if !path.exists() {
+            debug!("folder: no bundle from {}", peer.node_id());
+            return Ok(incoming);
+        }
+
+        info!("folder: import {}", path.display());
+
+        /*
+         * ヘッダの読み込みと送信元の認証
+         */
+        let mut reader = BufReader::new(
+            File::open(&path)
+                .with_context(|| format!("open {}", path.display()))?
+        );
+        let header = read_header(&mut reader)?;
+
+        if header.magic != MAGIC {
+            return Err(anyhow!("not a sync bundle: {}", path.display()));
+        }
+
+        if header.protocol_version != PROTOCOL_VERSION {
+            error!(
+                "folder: protocol version mismatch: peer={}",
+                header.protocol_version
+            );
+            return Err(anyhow!("protocol version mismatch"));
+        }
+
+        if header.from != peer.node_id() || header.to != node_id {
+            return Err(anyhow!("bundle address mismatch: {}", path.display()));
+        }
+
+        let established = secure::open_bundle(
+            self.identity,
+            &header.from,
+            peer.public_key(),
+            self.psk,
+            header.protocol_version,
+            &header.context()?,
+            &header.handshake,
+        )?;
+        let mut channel = established.into_channel(reader);
+
+        /*
+         * エントリの受信とマージ
+         */
+        let policy = if self.dry_run {
+            ConflictPolicy::Fail
+        } else {
+            self.conflict
+        };
+
+        loop {
+            match recv_packet(&mut channel)? {
+                SyncPacket::ServerEntry(entry) => {
+                    self.apply(writer, &entry, policy)?;
+                    incoming.insert(entry.id().to_string(), entry);
+                }
+
+                SyncPacket::ServerEntriesEnd(end) => {
+                    if end.total_sent != incoming.len() as u64 {
+                        return Err(anyhow!(
+                            "bundle is truncated: {}", path.display()
+                        ));
+                    }
+                    break;
+                }
+
+                other => return Err(anyhow!("unexpected packet: {:?}", other)),
+            }
+        }
+
+        info!(
+            "folder: {} entries from {}",
+            incoming.len(),
+            peer.node_id()
+        );
+
+        /*
+         * 受信の確認の記録
+         */
+        self.imported.insert(peer.node_id(), header.seq);
+
+        if let Some(seq) = header.acked_seq {
+            // 相手は自身が書き出した同期ファイルをこの位置まで取り込み済み
+            let mut peer = peer.clone();
+            peer.set_acked_seq(seq);
+            writer.put_peer(&peer)?;
+        }
+
+        Ok(incoming)
+    }
+
+    ///
+    /// 受信したエントリの適用
+    ///
+    /// # 引数
+    /// * `writer` - 書き込みトランザクション
+    /// * `entry` - 同期ファイルから読み込んだエントリ
+    /// * `policy` - 競合時の解決方針
+    ///
+    fn apply(
+        &mut self,
+        writer: &mut TransactionWriter,
+        entry: &Entry,
+        policy: ConflictPolicy,
+    ) -> Result<()> {
+        match decide_entry(writer, entry, policy, self.prompter)? {
+            EntryDecision::AdoptRemote => {
+                if is_deletion(writer, entry)? {
+                    self.report.deleted(entry);
+                } else {
+                    self.report.adopted(entry);
+                }
+
+                writer.put_synced(entry)?;
+                writer.put_ancestor(entry)?;
+                debug!("folder: adopt remote entry id={}", entry.id());
+            }
+
+            EntryDecision::Unchanged => {
+                writer.put_ancestor(entry)?;
+            }
+
+            EntryDecision::Resolved(resolved) => {
+                let local = writer.get(&entry.id())?;
+                if local.is_none_or(|local| !is_same_entry(&resolved, &local)) {
+                    self.report.adopted(&resolved);
+                }
+
+                writer.put_synced(&resolved)?;
+                debug!("folder: resolve concurrent entry id={}", entry.id());
+            }
+
+            EntryDecision::KeepBoth(resolved, loser) => {
+                writer.put_synced(&resolved)?;
+
+                let copy = conflict_copy(&loser);
+                writer.put(&copy)?;
+                info!(
+                    "folder: keep both versions id={}, copy={}",
+                    entry.id(),
+                    copy.id()
+                );
+            }
+
+            EntryDecision::KeepLocal => {
+                debug!("folder: keep local entry id={}", entry.id());
+            }
+
+            EntryDecision::Abort(_) if self.dry_run => {
+                self.report.conflicted(entry);
+                self.conflicted.insert(entry.id().to_string());
+            }
+
+            EntryDecision::Abort(msg) => {
+                error!("folder: abort on conflict id={}", entry.id());
+                return Err(anyhow!("sync aborted: {}", msg));
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// 同期相手宛ての同期ファイルの書き出し
+    ///
+    /// # 引数
+    /// * `peer` - 同期相手
+    /// * `entries` - 書き出すエントリ
+    /// * `seq` - 書き出すエントリに対応する自身の変更シーケンス番号
+    ///
+    /// # 注記
+    /// 共有ディレクトリの同期ツールが書き込み途中のファイルを転送しないよう、
+    /// 一時ファイルに書き出してから置き換える。
+    ///
+    fn export(&self, peer: &TrustedPeer, entries: &[Entry], seq: u64)
+        -> Result<()>
+    {
+        let node_id = self.identity.node_id();
+        let path = bundle_path(self.dir, &node_id, &peer.node_id());
+        let tmp_path = path.with_extension(format!("{}.tmp", BUNDLE_EXT));
+
+        let mut header = BundleHeader {
+            magic: MAGIC.to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            from: node_id,
+            to: peer.node_id(),
+            created_epoch_ms: Local::now().timestamp_millis() as u64,
+            seq,
+            acked_seq: self.imported.get(&peer.node_id()).copied(),
+            handshake: vec![],
+        };
+
+        let (handshake, established) = secure::seal_bundle(
+            self.identity,
+            peer.public_key(),
+            self.psk,
+            header.protocol_version,
+            &header.context()?,
+        )?;
+        header.handshake = handshake;
+
+        let mut writer = BufWriter::new(
+            File::create(&tmp_path)
+                .with_context(|| format!("create {}", tmp_path.display()))?
+        );
+        write_header(&mut writer, &header)?;
+
+        let mut channel = established.into_channel(writer);
+        for entry in entries {
+            send_packet(&mut channel, SyncPacket::server_entry(entry.clone()))?;
+        }
+        send_packet(
+            &mut channel,
+            SyncPacket::server_entries_end(entries.len() as u64, false),
+        )?;
+
+        let file = channel.finish()?
+            .into_inner()
+            .map_err(|err| anyhow!("flush {}: {}", tmp_path.display(), err))?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("rename {}", path.display()))?;
+
+        info!("folder: export {} entries to {}", entries.len(), path.display());
+        Ok(())
+    }
+}
+
+///
+/// 共有ディレクトリを介した同期のエントリーポイント
+///
+/// # 引数
+/// * `dir` - 共有ディレクトリ
+/// * `identity` - 自ノードの鍵情報
+/// * `psk` - 事前共有鍵
+/// * `conflict` - 競合時の解決方針
+/// * `dry_run` - 取り込みのみを行い、反映と書き出しを行わないか否か
+/// * `writer` - 書き込みトランザクション
+/// * `prompter` - プロンプター
+///
+/// # 戻り値
+/// 同期結果のレポートを`Ok()`でラップして返す。
+///
+/// # 注記
+/// ペアリング済みの全ての同期相手について、相手が書き出した同期ファイルを
+/// 取り込んだ後、マージ後の全エントリを相手宛ての同期ファイルとして書き出
+/// す。同期ファイルは常に全件を含むため、相手が前回の同期ファイルを取り込む
+/// 前に置き換えられても変更は失われない。
+///
+pub(super) fn run(
+    dir: &Path,
+    identity: &LocalIdentity,
+    psk: Option<&SyncPsk>,
+    conflict: ConflictPolicy,
+    dry_run: bool,
+    writer: &mut TransactionWriter,
+    prompter: &dyn Prompter,
+) -> Result<SyncReport> {
+    // ドライランの場合は通常通り処理した上で書き込みを全て破棄する
+    if dry_run {
+        writer.discard();
+    }
+
+    if !dir.is_dir() {
+        return Err(anyhow!("not a directory: {}", dir.display()));
+    }
+
+    let mut sync = FolderSync {
+        dir,
+        identity,
+        psk,
+        conflict,
+        dry_run,
+        prompter,
+        report: SyncReport::default(),
+        conflicted: HashSet::new(),
+        imported: HashMap::new(),
+    };
+
+    let peers = writer.all_peers()?;
+    if peers.is_empty() {
+        return Err(anyhow!("no paired peer"));
+    }
+
+    /*
+     * 相手の同期ファイルの取り込み
+     */
+    let mut received = Vec::new();
+    for peer in &peers {
+        received.push(sync.import(writer, peer)?);
+    }
+
+    /*
+     * マージ後のエントリの書き出し
+     */
+    let mut entries = Vec::new();
+    for id in writer.all_service()? {
+        if let Some(entry) = writer.get(&id)? {
+            entries.push(entry);
+        }
+    }
+
+    // 相手の同期ファイルと内容が異なるエントリを送信分として報告する
+    let mut pushed = HashSet::new();
+    for incoming in &received {
+        for entry in &entries {
+            let id = entry.id().to_string();
+            let same = incoming.get(&id)
+                .is_some_and(|remote| is_same_entry(remote, entry));
+
+            if !same && !sync.conflicted.contains(&id) && pushed.insert(id) {
+                if entry.is_removed() {
+                    sync.report.deleted(entry);
+                } else {
+                    sync.report.pushed(entry);
+                }
+            }
+        }
+    }
+
+    if !dry_run {
+        let seq = writer.change_seq()?;
+        for peer in &peers {
+            sync.export(peer, &entries, seq)?;
+        }
+    }
+
+    Ok(sync.report)
+}
+
+///
+/// 同期ファイルのパス
+///
+/// # 引数
+/// * `dir` - 共有ディレクトリ
+/// * `from` - 送信元のノードID
+/// * `to` - 宛先のノードID
+///
+fn bundle_path(dir: &Path, from: &str, to: &str) -> PathBuf {
+    dir.join(format!("{}.{}.{}", from, to, BUNDLE_EXT))
+}
+
+///
+/// ヘッダの書き込み（長さプレフィックス + MessagePack）
+///
+fn write_header<W: Write>(writer: &mut W, header: &BundleHeader) -> Result<()> {
+    let buf = rmp_serde::to_vec_named(header).context("serialize header")?;
+    writer.write_all(&(buf.len() as u32).to_be_bytes())
+        .context("write length")?;
+    writer.write_all(&buf).context("write header")?;
+    Ok(())
+}
+
+///
+/// ヘッダの読み込み（長さプレフィックス + MessagePack）
+///
+fn read_header<R: Read>(reader: &mut R) -> Result<BundleHeader> {
+    let mut len_buf = [0u8; 4];
+    reader.read_exact(&mut len_buf).context("read length")?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+
+    if len > MAX_PLAIN_PACKET_LEN {
+        return Err(anyhow!("header too large: {} bytes", len));
+    }
+
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf).context("read header")?;
+
+    rmp_serde::from_slice(&buf).context("deserialize header")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use ulid::Ulid;
+
+    use crate::command::prompt::test::QueuePrompter;
+    use crate::command::sync::load_identity;
+    use crate::database::types::ServiceId;
+    use crate::database::EntryManager;
+
+    fn temp_path(ext: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pwmgr-test-{}.{}", Ulid::new(), ext))
+    }
+
+    fn make_entry(id: &ServiceId, service: &str) -> Entry {
+        Entry::new(
+            id.clone(),
+            service.to_string(),
+            vec![],
+            vec![],
+            BTreeMap::new(),
+        )
+    }
+
+    ///
+    /// 共有ディレクトリを介して双方の変更が反映されることを確認
+    ///
+    #[test]
+    fn exchange_through_folder() {
+        let dir = temp_path("d");
+        fs::create_dir(&dir).unwrap();
+
+        let a = EntryManager::open_for_test(temp_path("redb")).unwrap();
+        let b = EntryManager::open_for_test(temp_path("redb")).unwrap();
+        let id = ServiceId::new();
+        let prompter = QueuePrompter::new(vec![]);
+
+        // 双方のノードを互いに同期相手として登録する
+        let identity_a = a.with_write_transaction(load_identity).unwrap();
+        let identity_b = b.with_write_transaction(load_identity).unwrap();
+        a.with_write_transaction(|writer| {
+            writer.put_peer(&TrustedPeer::new(
+                identity_b.node_id(),
+                identity_b.public_key().to_vec(),
+                String::new(),
+            ))
+        }).unwrap();
+        b.with_write_transaction(|writer| {
+            writer.put_peer(&TrustedPeer::new(
+                identity_a.node_id(),
+                identity_a.public_key().to_vec(),
+                String::new(),
+            ))
+        }).unwrap();
+
+        let sync = |mgr: &EntryManager, identity: &LocalIdentity, dry_run| {
+            mgr.with_write_transaction(|writer| {
+                run(
+                    &dir,
+                    identity,
+                    None,
+                    ConflictPolicy::Fail,
+                    dry_run,
+                    writer,
+                    &prompter,
+                )
+            }).unwrap()
+        };
+
+        /*
+         * Aで追加したエントリがBに取り込まれる
+         */
+        a.with_write_transaction(|writer| writer.put(&make_entry(&id, "alpha")))
+            .unwrap();
+        let report = sync(&a, &identity_a, false);
+        assert_eq!(report.pushed.len(), 1);
+        let exported_seq = a.with_read_transaction(|reader| reader.change_seq())
+            .unwrap();
+
+        // ドライランでは反映されない
+        let report = sync(&b, &identity_b, true);
+        assert_eq!(report.adopted.len(), 1);
+        assert!(b.with_write_transaction(|writer| writer.get(&id)).unwrap().is_none());
+
+        let report = sync(&b, &identity_b, false);
+        assert_eq!(report.adopted.len(), 1);
+        assert!(report.pushed.is_empty());
+
+        /*
+         * Bでの変更がAに取り込まれる
+         */
+        b.with_write_transaction(|writer| writer.put(&make_entry(&id, "beta")))
+            .unwrap();
+        sync(&b, &identity_b, false);
+
+        let report = sync(&a, &identity_a, false);
+        assert_eq!(report.adopted.len(), 1);
+        let entry = a.with_write_transaction(|writer| writer.get(&id))
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.service(), "beta");
+
+        // Bが取り込んだ同期ファイルの位置までが受信済みとして記録される
+        let acked = a.with_write_transaction(|writer| {
+            Ok(writer.get_peer(&identity_b.node_id())?.unwrap().acked_seq())
+        }).unwrap();
+        assert_eq!(acked, Some(exported_seq));
+
+        // 宛先でない同期ファイルは取り込まない
+        let stray = bundle_path(&dir, "unknown-node", &identity_a.node_id());
+        fs::write(&stray, b"garbage").unwrap();
+        assert!(sync(&a, &identity_a, false).adopted.is_empty());
+    }
+}
@@ -0,0 +1,395 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//! 並行して更新されたエントリの3方向マージ
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::database::types::Entry;
+
+///
+/// 双方で異なる値に変更された項目
+///
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Field {
+    /// サービス名
+    Service,
+
+    /// プロパティ(キー名)
+    Property(String),
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Service => write!(f, "サービス名"),
+            Self::Property(key) => write!(f, "プロパティ \"{}\"", key),
+        }
+    }
+}
+
+///
+/// 3方向マージで自動的に解決できなかった競合
+///
+#[derive(Debug, Clone)]
+pub(super) struct Conflict {
+    /// 競合した項目
+    pub(super) field: Field,
+
+    /// ローカル側の値(プロパティが削除された場合は`None`)
+    pub(super) local: Option<String>,
+
+    /// サーバ側の値(プロパティが削除された場合は`None`)
+    pub(super) remote: Option<String>,
+}
+
+impl Conflict {
+    ///
+    /// 利用者への提示用に双方の値を整形する
+    ///
+    /// # 戻り値
+    /// 項目名とサーバ側・ローカル側の値を並べた文字列を返す。
+    ///
+    pub(super) fn describe(&self) -> String {
+        fn value(val: &Option<String>) -> &str {
+            val.as_deref().unwrap_or("(削除)")
+        }
+
+        format!(
+            "{}が双方で変更されました。\n  サーバ側: {}\n  ローカル: {}",
+            self.field,
+            value(&self.remote),
+            value(&self.local),
+        )
+    }
+}
+
+///
+/// 3方向マージの結果
+///
+pub(super) struct Merged {
+    /// マージ済みのエントリ(競合した項目はローカル側の値を保持)
+    entry: Entry,
+
+    /// 自動的に解決できなかった競合
+    conflicts: Vec<Conflict>,
+}
+
+impl Merged {
+    ///
+    /// 競合のリストへのアクセサ
+    ///
+    pub(super) fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    ///
+    /// 競合を解決してマージ済みのエントリを確定する
+    ///
+    /// # 引数
+    /// * `take_remote` - 競合ごとに呼び出され、サーバ側の値を採用する場合に
+    ///   `true`を返すクロージャ
+    ///
+    /// # 戻り値
+    /// 確定したエントリを`Ok()`でラップして返す。
+    ///
+    pub(super) fn resolve<F>(self, mut take_remote: F) -> Result<Entry>
+    where
+        F: FnMut(&Conflict) -> Result<bool>,
+    {
+        let mut entry = self.entry;
+
+        for conflict in &self.conflicts {
+            if !take_remote(conflict)? {
+                continue;
+            }
+
+            match &conflict.field {
+                Field::Service => {
+                    entry = rebuild(
+                        &entry,
+                        conflict.remote.clone().unwrap_or_default(),
+                        entry.properties(),
+                    );
+                }
+
+                Field::Property(key) => {
+                    let mut properties = entry.properties();
+                    match &conflict.remote {
+                        Some(val) => properties.insert(key.clone(), val.clone()),
+                        None => properties.remove(key),
+                    };
+                    entry = rebuild(&entry, entry.service(), properties);
+                }
+            }
+        }
+
+        Ok(entry)
+    }
+}
+
+///
+/// 共通祖先を基準とした3方向マージ
+///
+/// # 引数
+/// * `base` - 前回の同期で双方が合意した版
+/// * `local` - ローカル側の版
+/// * `remote` - サーバ側の版
+///
+/// # 戻り値
+/// マージ結果を返す。
+///
+/// # 注記
+/// 別名とタグは集合として、プロパティはキー単位でマージする。片側のみで変
+/// 更された項目はその変更を採用し、双方で異なる値に変更された項目のみを競
+/// 合として報告する。バージョンベクタは呼び出し側で設定すること。
+///
+pub(super) fn three_way(base: &Entry, local: &Entry, remote: &Entry) -> Merged {
+    let mut conflicts = Vec::new();
+
+    /*
+     * サービス名
+     */
+    let service = match merge_value(
+        Some(base.service()),
+        Some(local.service()),
+        Some(remote.service()),
+    ) {
+        Ok(service) => service.unwrap_or_default(),
+        Err((local, remote)) => {
+            let service = local.clone().unwrap_or_default();
+            conflicts.push(Conflict {field: Field::Service, local, remote});
+            service
+        }
+    };
+
+    /*
+     * 別名とタグ
+     */
+    let aliases = merge_set(&base.aliases(), &local.aliases(), &remote.aliases());
+    let tags = merge_set(&base.tags(), &local.tags(), &remote.tags());
+
+    /*
+     * プロパティ
+     */
+    let base_props = base.properties();
+    let local_props = local.properties();
+    let remote_props = remote.properties();
+
+    let keys: BTreeSet<&String> = base_props.keys()
+        .chain(local_props.keys())
+        .chain(remote_props.keys())
+        .collect();
+
+    let mut properties = BTreeMap::new();
+    for key in keys {
+        let merged = merge_value(
+            base_props.get(key).cloned(),
+            local_props.get(key).cloned(),
+            remote_props.get(key).cloned(),
+        );
+
+        match merged {
+            Ok(Some(val)) => {
+                properties.insert(key.clone(), val);
+            }
+
+            Ok(None) => {}
+
+            Err((local, remote)) => {
+                if let Some(val) = &local {
+                    properties.insert(key.clone(), val.clone());
+                }
+                conflicts.push(Conflict {
+                    field: Field::Property(key.clone()),
+                    local,
+                    remote,
+                });
+            }
+        }
+    }
+
+    /*
+     * 削除フラグ（二値のため双方の変更が食い違うことは無い）
+     */
+    let removed = merge_value(
+        Some(base.is_removed()),
+        Some(local.is_removed()),
+        Some(remote.is_removed()),
+    ).unwrap_or_else(|(local, _)| local).unwrap_or_default();
+
+    /*
+     * パスワードポリシー（双方で異なる変更をした場合はローカル側を採用）
+     */
+    let policy = merge_value(
+        base.policy(),
+        local.policy(),
+        remote.policy(),
+    ).unwrap_or_else(|(local, _)| local);
+
+    let mut entry = Entry::new(local.id(), service, aliases, tags, properties);
+    entry.set_removed(removed);
+    entry.set_policy(policy);
+
+    // 更新日時は新しい方を引き継ぐ
+    if let Some(ts) = local.last_update().max(remote.last_update()) {
+        entry.set_last_update(ts);
+    }
+
+    Merged {entry, conflicts}
+}
+
+///
+/// 単一の値の3方向マージ
+///
+/// # 戻り値
+/// 自動的に決定できた場合はその値を`Ok()`で、双方で異なる値に変更されてい
+/// た場合はローカル側とサーバ側の値の組を`Err()`で返す。
+///
+fn merge_value<T>(base: Option<T>, local: Option<T>, remote: Option<T>)
+    -> std::result::Result<Option<T>, (Option<T>, Option<T>)>
+where
+    T: PartialEq,
+{
+    if local == remote || remote == base {
+        Ok(local)
+    } else if local == base {
+        Ok(remote)
+    } else {
+        Err((local, remote))
+    }
+}
+
+///
+/// 集合の3方向マージ
+///
+/// # 注記
+/// 双方に存在する要素と、片側で追加された要素を残す。片側で削除された要素
+/// は、もう一方で削除されていなくても取り除く。
+///
+fn merge_set(base: &[String], local: &[String], remote: &[String])
+    -> Vec<String>
+{
+    local.iter()
+        .chain(remote.iter())
+        .filter(|item| {
+            let in_local = local.contains(item);
+            let in_remote = remote.contains(item);
+            (in_local && in_remote) || !base.contains(item)
+        })
+        .cloned()
+        .collect()
+}
+
+///
+/// サービス名とプロパティを差し替えたエントリの再構築
+///
+fn rebuild(
+    entry: &Entry,
+    service: String,
+    properties: BTreeMap<String, String>,
+) -> Entry {
+    let mut rebuilt = Entry::new(
+        entry.id(),
+        service,
+        entry.aliases(),
+        entry.tags(),
+        properties,
+    );
+    rebuilt.set_removed(entry.is_removed());
+    rebuilt.set_policy(entry.policy());
+    if let Some(ts) = entry.last_update() {
+        rebuilt.set_last_update(ts);
+    }
+
+    rebuilt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::types::ServiceId;
+
+    fn make_entry(
+        id: &ServiceId,
+        service: &str,
+        tags: &[&str],
+        props: &[(&str, &str)],
+    ) -> Entry {
+        Entry::new(
+            id.clone(),
+            service.to_string(),
+            vec![],
+            tags.iter().map(|s| s.to_string()).collect(),
+            props.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    ///
+    /// 片側のみの変更は自動的にマージされることを確認
+    ///
+    #[test]
+    fn merges_one_sided_changes() {
+        let id = ServiceId::new();
+        let base = make_entry(
+            &id, "svc", &["a", "b"], &[("user", "alice"), ("url", "x")]
+        );
+        let local = make_entry(
+            &id, "svc", &["a", "b", "c"], &[("user", "bob"), ("url", "x")]
+        );
+        let remote = make_entry(
+            &id, "renamed", &["b"], &[("user", "alice"), ("note", "n")]
+        );
+
+        let merged = three_way(&base, &local, &remote);
+        assert!(merged.conflicts().is_empty());
+
+        let entry = merged.resolve(|_| panic!("no conflict expected")).unwrap();
+        assert_eq!(entry.service(), "renamed");
+        assert_eq!(entry.tags(), vec!["b".to_string(), "c".to_string()]);
+        assert_eq!(
+            entry.properties(),
+            BTreeMap::from([
+                ("user".to_string(), "bob".to_string()),
+                ("note".to_string(), "n".to_string()),
+            ])
+        );
+    }
+
+    ///
+    /// 双方で変更されたキーのみが競合となり、選択に従って解決されることを
+    /// 確認
+    ///
+    #[test]
+    fn reports_conflicting_keys_only() {
+        let id = ServiceId::new();
+        let base = make_entry(&id, "svc", &[], &[("user", "alice"), ("pin", "1")]);
+        let local = make_entry(&id, "svc", &[], &[("user", "bob"), ("pin", "2")]);
+        let remote = make_entry(&id, "svc", &[], &[("user", "carol"), ("pin", "1")]);
+
+        let merged = three_way(&base, &local, &remote);
+        assert_eq!(merged.conflicts().len(), 1);
+
+        let conflict = &merged.conflicts()[0];
+        assert_eq!(conflict.field, Field::Property("user".to_string()));
+        assert!(conflict.describe().contains("carol"));
+        assert!(conflict.describe().contains("bob"));
+
+        let entry = merged.resolve(|_| Ok(true)).unwrap();
+        assert_eq!(entry.properties().get("user"), Some(&"carol".to_string()));
+        assert_eq!(entry.properties().get("pin"), Some(&"2".to_string()));
+
+        // ローカル側を選んだ場合
+        let merged = three_way(&base, &local, &remote);
+        let entry = merged.resolve(|_| Ok(false)).unwrap();
+        assert_eq!(entry.properties().get("user"), Some(&"bob".to_string()));
+    }
+}
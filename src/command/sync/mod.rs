@@ -9,25 +9,40 @@
 //!
 
 pub(crate) mod client;
+pub(crate) mod discovery;
+pub(crate) mod folder;
+pub(crate) mod merge;
+pub(crate) mod pair;
+pub(crate) mod secure;
 pub(crate) mod server;
+pub(crate) mod transport;
 
 use std::cell::RefCell;
 use std::io::{Read, Write};
-use std::net::TcpStream;
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
-use log::info;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
 
-use crate::cmd_args::{Options, SyncMode, SyncOpts};
+use crate::cmd_args::{ConflictPolicy, Options, SyncMode, SyncOpts};
 use crate::command::prompt::{Prompter, StdPrompter};
 use crate::command::CommandContext;
-use crate::database::types::Entry;
-use crate::database::EntryManager;
+use crate::database::types::{Entry, TrustedPeer};
+use crate::database::{EntryManager, TransactionReadable, TransactionWriter};
+use secure::{LocalIdentity, SecureChannel, SyncPsk};
+use transport::{SshTransport, StdioTransport, Transport};
 
 /// プロトコルバージョン
-const PROTOCOL_VERSION: u16 = 1;
+const PROTOCOL_VERSION: u16 = 3;
+
+/// 事前共有鍵を渡す環境変数の名前
+pub(crate) const SYNC_PSK_ENV: &str = "PWMGR_SYNC_PSK";
+
+/// 認証前に受け付ける平文パケットの最大長
+const MAX_PLAIN_PACKET_LEN: usize = 64 * 1024;
 
 ///
 /// プロトコルで用いるパケット
@@ -72,12 +87,32 @@ enum SyncPacket {
     ///
     /// 双方の同期完了を示す
     ///
-    Finished,
+    Finished(Finished),
 
     ///
     /// エラーやユーザ拒否による中断を示す
     ///
     Abort(Abort),
+
+    ///
+    /// ペアリング: クライアントのノンスに対するコミットメント
+    ///
+    PairCommit(PairCommit),
+
+    ///
+    /// ペアリング: サーバのノードIDとノンス
+    ///
+    PairNonce(PairNonce),
+
+    ///
+    /// ペアリング: クライアントのノンスの開示
+    ///
+    PairReveal(PairReveal),
+
+    ///
+    /// ペアリング: 検証コードの照合結果
+    ///
+    PairConfirm(PairConfirm),
 }
 
 impl SyncPacket {
@@ -89,12 +124,18 @@ impl SyncPacket {
         node_id: String,
         role: NodeRole,
         now_epoch_ms: u64,
+        pairing: bool,
+        request: SyncRequest,
+        handshake: Vec<u8>,
     ) -> Self {
         Self::Hello(Hello {
             protocol_version,
             node_id,
             role,
             now_epoch_ms,
+            pairing,
+            request,
+            handshake,
         })
     }
 
@@ -105,11 +146,13 @@ impl SyncPacket {
         protocol_version: u16,
         accepted: bool,
         reason: Option<String>,
+        handshake: Option<Vec<u8>>,
     ) -> Self {
         Self::HelloAck(HelloAck {
             protocol_version,
             accepted,
             reason,
+            handshake,
         })
     }
 
@@ -123,8 +166,8 @@ impl SyncPacket {
     ///
     /// サーバ送信終端パケットの生成
     ///
-    fn server_entries_end(total_sent: u64) -> Self {
-        Self::ServerEntriesEnd(ServerEntriesEnd { total_sent })
+    fn server_entries_end(total_sent: u64, incremental: bool) -> Self {
+        Self::ServerEntriesEnd(ServerEntriesEnd { total_sent, incremental })
     }
 
     ///
@@ -159,8 +202,8 @@ impl SyncPacket {
     ///
     /// Finishedパケットの生成
     ///
-    fn finished() -> Self {
-        Self::Finished
+    fn finished(watermark: u64) -> Self {
+        Self::Finished(Finished { watermark })
     }
 
     ///
@@ -171,6 +214,34 @@ impl SyncPacket {
             reason: reason.into(),
         })
     }
+
+    ///
+    /// PairCommitパケットの生成
+    ///
+    fn pair_commit(commitment: Vec<u8>) -> Self {
+        Self::PairCommit(PairCommit { commitment })
+    }
+
+    ///
+    /// PairNonceパケットの生成
+    ///
+    fn pair_nonce(node_id: String, nonce: Vec<u8>) -> Self {
+        Self::PairNonce(PairNonce { node_id, nonce })
+    }
+
+    ///
+    /// PairRevealパケットの生成
+    ///
+    fn pair_reveal(nonce: Vec<u8>) -> Self {
+        Self::PairReveal(PairReveal { nonce })
+    }
+
+    ///
+    /// PairConfirmパケットの生成
+    ///
+    fn pair_confirm(accepted: bool) -> Self {
+        Self::PairConfirm(PairConfirm { accepted })
+    }
 }
 
 ///
@@ -197,6 +268,62 @@ struct Hello {
     /// 相手との時計ずれ確認用の現在時刻（エポックミリ秒）
     ///
     now_epoch_ms: u64,
+
+    ///
+    /// ペアリング要求か否か
+    ///
+    pairing: bool,
+
+    ///
+    /// 同期の要求内容（ペアリング時は既定値）
+    ///
+    request: SyncRequest,
+
+    ///
+    /// Noiseハンドシェイクの最初のメッセージ
+    ///
+    handshake: Vec<u8>,
+}
+
+impl Hello {
+    ///
+    /// ハンドシェイクに束縛する付帯情報
+    ///
+    fn context(&self) -> Result<Vec<u8>> {
+        hello_context(self.pairing, &self.request)
+    }
+}
+
+///
+/// クライアントからの同期の要求内容
+///
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct SyncRequest {
+    ///
+    /// 前回の同期位置（差分同期を要求する場合のみ）
+    ///
+    watermark: Option<Watermark>,
+
+    ///
+    /// 交換のみを行い、双方のデータベースに反映しないか否か
+    ///
+    dry_run: bool,
+}
+
+///
+/// クライアントが前回の同期で受信済みのサーバ側の同期位置
+///
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Watermark {
+    ///
+    /// 同期位置を発行したサーバのノードID
+    ///
+    node_id: String,
+
+    ///
+    /// サーバの変更シーケンス番号
+    ///
+    seq: u64,
 }
 
 ///
@@ -218,6 +345,11 @@ struct HelloAck {
     /// 非受理時の理由
     ///
     reason: Option<String>,
+
+    ///
+    /// Noiseハンドシェイクの応答メッセージ（受理時のみ）
+    ///
+    handshake: Option<Vec<u8>>,
 }
 
 ///
@@ -245,6 +377,11 @@ struct ServerEntriesEnd {
     /// サーバが送信したエントリ件数
     ///
     total_sent: u64,
+
+    ///
+    /// 差分のみを送信したか否か（falseの場合は全件送信）
+    ///
+    incremental: bool,
 }
 
 ///
@@ -279,6 +416,17 @@ struct EntryAck {
     reason: Option<String>,
 }
 
+///
+/// 同期完了パケット
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct Finished {
+    ///
+    /// 同期完了時点のサーバの変更シーケンス番号（次回の差分同期の起点）
+    ///
+    watermark: u64,
+}
+
 ///
 /// セッション中断パケット
 ///
@@ -291,9 +439,61 @@ struct Abort {
 }
 
 ///
-/// パケット送信（長さプレフィックス + MessagePack）
+/// ペアリングのコミットメントパケット
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct PairCommit {
+    ///
+    /// クライアントのノンスのハッシュ
+    ///
+    commitment: Vec<u8>,
+}
+
+///
+/// ペアリングのノンスパケット
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct PairNonce {
+    ///
+    /// サーバのノードID
+    ///
+    node_id: String,
+
+    ///
+    /// サーバのノンス
+    ///
+    nonce: Vec<u8>,
+}
+
+///
+/// ペアリングのノンス開示パケット
 ///
-fn send_packet(stream: &mut TcpStream, packet: SyncPacket)
+#[derive(Debug, Serialize, Deserialize)]
+struct PairReveal {
+    ///
+    /// クライアントのノンス
+    ///
+    nonce: Vec<u8>,
+}
+
+///
+/// ペアリングの照合結果パケット
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct PairConfirm {
+    ///
+    /// 利用者が検証コードの一致を確認したか否か
+    ///
+    accepted: bool,
+}
+
+///
+/// 平文でのパケット送信（長さプレフィックス + MessagePack）
+///
+/// # 注記
+/// 認証前のHello/HelloAckの送受信にのみ用いる。
+///
+fn send_plain_packet(stream: &mut dyn Transport, packet: SyncPacket)
     -> Result<()>
 {
     let buf = rmp_serde::to_vec_named(&packet)
@@ -306,19 +506,273 @@ fn send_packet(stream: &mut TcpStream, packet: SyncPacket)
 }
 
 ///
-/// パケット受信（長さプレフィックス + MessagePack）
+/// 平文でのパケット受信（長さプレフィックス + MessagePack）
 ///
-fn recv_packet(stream: &mut TcpStream) -> Result<SyncPacket> {
+fn recv_plain_packet(stream: &mut dyn Transport) -> Result<SyncPacket> {
     let mut len_buf = [0u8; 4];
     stream.read_exact(&mut len_buf).context("read length")?;
     let len = u32::from_be_bytes(len_buf) as usize;
 
+    if len > MAX_PLAIN_PACKET_LEN {
+        return Err(anyhow!("packet too large: {} bytes", len));
+    }
+
     let mut buf = vec![0u8; len];
     stream.read_exact(&mut buf).context("read packet")?;
 
     rmp_serde::from_slice(&buf).context("deserialize packet")
 }
 
+///
+/// ハンドシェイクに束縛するHelloの付帯情報の生成
+///
+/// # 引数
+/// * `pairing` - ペアリング要求か否か
+/// * `request` - 同期の要求内容
+///
+fn hello_context(pairing: bool, request: &SyncRequest) -> Result<Vec<u8>> {
+    Ok(rmp_serde::to_vec(&(pairing, request))?)
+}
+
+///
+/// HelloAckによる接続の拒否
+///
+/// # 引数
+/// * `stream` - 接続中の通信路
+/// * `reason` - 拒否理由
+///
+fn reject_hello(stream: &mut dyn Transport, reason: &str) -> Result<()> {
+    send_plain_packet(stream, SyncPacket::hello_ack(
+        PROTOCOL_VERSION,
+        false,
+        Some(reason.into()),
+        None,
+    ))
+}
+
+///
+/// Helloの受信と検証
+///
+/// # 引数
+/// * `stream` - 接続中の通信路
+///
+/// # 戻り値
+/// プロトコルバージョンと役割が妥当な場合は受信したHelloを`Ok()`でラップし
+/// て返す。妥当でない場合は接続を拒否した上でエラー情報を返す。
+///
+fn recv_hello(stream: &mut dyn Transport) -> Result<Hello> {
+    let hello = match recv_plain_packet(stream)? {
+        SyncPacket::Hello(h) => h,
+        pkt => return Err(anyhow!("unexpected packet: {:?}", pkt)),
+    };
+    debug!(
+        "recv Hello: proto={}, role={:?}, node={}, pairing={}",
+        hello.protocol_version, hello.role, hello.node_id, hello.pairing
+    );
+
+    if hello.protocol_version != PROTOCOL_VERSION {
+        reject_hello(stream, "protocol version mismatch")?;
+
+        error!("protocol version mismatch: peer={}", hello.protocol_version);
+        return Err(anyhow!("protocol version mismatch"));
+    }
+
+    if hello.role != NodeRole::Client {
+        reject_hello(stream, "role mismatch")?;
+
+        error!("unexpected role from peer: {:?}", hello.role);
+        return Err(anyhow!("unexpected role from peer"));
+    }
+
+    Ok(hello)
+}
+
+///
+/// 暗号化通信路でのパケット送信
+///
+fn send_packet<S: Write>(channel: &mut SecureChannel<S>, packet: SyncPacket)
+    -> Result<()>
+{
+    let buf = Zeroizing::new(
+        rmp_serde::to_vec_named(&packet).context("serialize packet")?
+    );
+    channel.send(&buf)
+}
+
+///
+/// 暗号化通信路でのパケット受信
+///
+fn recv_packet<S: Read>(channel: &mut SecureChannel<S>) -> Result<SyncPacket> {
+    let buf = channel.recv()?;
+    rmp_serde::from_slice(&buf).context("deserialize packet")
+}
+
+///
+/// 事前共有鍵の読み込み
+///
+/// # 引数
+/// * `sub_opts` - syncサブコマンドのオプション
+///
+/// # 戻り値
+/// 事前共有鍵が指定されている場合は`Some()`でラップして返す。
+///
+/// # 注記
+/// `--psk-file`(またはコンフィギュレーションの`sync.psk_file`)で指定された
+/// ファイルの内容を優先し、指定がない場合は環境変数`PWMGR_SYNC_PSK`の値を用
+/// いる。事前共有鍵を用いる場合は双方のノードで指定する必要がある。
+///
+fn load_psk(sub_opts: &SyncOpts) -> Result<Option<SyncPsk>> {
+    let secret = if let Some(path) = sub_opts.psk_file() {
+        Zeroizing::new(
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("read {}", path.display()))?
+        )
+    } else if let Ok(secret) = std::env::var(SYNC_PSK_ENV) {
+        Zeroizing::new(secret)
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(SyncPsk::derive(secret.trim())?))
+}
+
+///
+/// 自ノードの鍵情報の読み込み
+///
+/// # 注記
+/// 識別情報が未生成の場合は、ノードIDと静的鍵ペアを生成して登録する。
+///
+fn load_identity(writer: &mut TransactionWriter) -> Result<LocalIdentity> {
+    let identity = match writer.node_identity()? {
+        Some(identity) => identity,
+        None => {
+            let (public_key, private_key) = secure::generate_keypair()?;
+            let node_id = writer.local_node_id()?;
+            let identity = writer.create_node_identity(
+                node_id,
+                public_key,
+                &private_key,
+            )?;
+            info!("node identity created: {}", identity.node_id());
+            identity
+        }
+    };
+
+    Ok(LocalIdentity::new(
+        identity.node_id(),
+        identity.public_key().to_vec(),
+        writer.node_private_key(&identity)?,
+    ))
+}
+
+///
+/// ペアリング済み同期相手の表示用情報
+///
+#[derive(Debug, Serialize)]
+struct PeerInfo {
+    /// ノードID
+    node_id: String,
+
+    /// ラベル
+    label: Option<String>,
+
+    /// 公開鍵のフィンガープリント
+    fingerprint: String,
+
+    /// ペアリング時のアドレス
+    address: String,
+
+    /// ペアリング日時
+    paired_at: DateTime<Local>,
+
+    /// 最後に同期が完了した日時
+    last_sync: Option<DateTime<Local>>,
+}
+
+///
+/// 探索で見つかった同期相手の表示用情報
+///
+#[derive(Debug, Serialize)]
+struct DiscoveredInfo {
+    /// ノードID
+    node_id: String,
+
+    /// 接続先アドレス
+    address: String,
+
+    /// ペアリング済みか否か
+    paired: bool,
+}
+
+///
+/// 同期結果のレポート(ドライラン時に表示)
+///
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct SyncReport {
+    /// サーバ側の版を採用するエントリ
+    adopted: Vec<ReportEntry>,
+
+    /// サーバへ送信するエントリ
+    pushed: Vec<ReportEntry>,
+
+    /// 競合したエントリ
+    conflicted: Vec<ReportEntry>,
+
+    /// 削除が反映されるエントリ
+    deleted: Vec<ReportEntry>,
+}
+
+impl SyncReport {
+    ///
+    /// サーバ側の版を採用するエントリの記録
+    ///
+    fn adopted(&mut self, entry: &Entry) {
+        self.adopted.push(ReportEntry::from(entry));
+    }
+
+    ///
+    /// サーバへ送信するエントリの記録
+    ///
+    fn pushed(&mut self, entry: &Entry) {
+        self.pushed.push(ReportEntry::from(entry));
+    }
+
+    ///
+    /// 競合したエントリの記録
+    ///
+    fn conflicted(&mut self, entry: &Entry) {
+        self.conflicted.push(ReportEntry::from(entry));
+    }
+
+    ///
+    /// 削除が反映されるエントリの記録
+    ///
+    fn deleted(&mut self, entry: &Entry) {
+        self.deleted.push(ReportEntry::from(entry));
+    }
+}
+
+///
+/// レポート中のエントリの情報
+///
+#[derive(Debug, Serialize)]
+struct ReportEntry {
+    /// サービスID
+    id: String,
+
+    /// サービス名
+    service: String,
+}
+
+impl From<&Entry> for ReportEntry {
+    fn from(entry: &Entry) -> Self {
+        Self {
+            id: entry.id().to_string(),
+            service: entry.service(),
+        }
+    }
+}
+
 ///
 /// syncコマンドコンテキスト
 ///
@@ -331,6 +785,33 @@ pub(crate) struct SyncCommandContext {
 
     /// プロンプターコンテキスト
     prompter: Arc<dyn Prompter>,
+
+    /// 事前共有鍵
+    psk: Option<SyncPsk>,
+
+    /// サーバモードで同期を許可するノードID
+    allow: Vec<String>,
+
+    /// 差分同期を行わず全件を同期するか否か
+    full: bool,
+
+    /// 競合時の解決方針
+    conflict: ConflictPolicy,
+
+    /// ドライランか否か
+    dry_run: bool,
+
+    /// 探索に用いるUDPポート番号
+    discovery_port: u16,
+
+    /// SSH経由の同期でリモートホストで実行するコマンド
+    remote_command: String,
+
+    /// ペアリングした相手に付けるラベル
+    pair_label: Option<String>,
+
+    /// JSONで出力するか否か
+    json_output: bool,
 }
 
 impl SyncCommandContext {
@@ -338,12 +819,218 @@ impl SyncCommandContext {
     /// オブジェクトの生成
     ///
     pub(crate) fn new(opts: &Options, sub_opts: &SyncOpts) -> Result<Self> {
+        let mode = sub_opts.mode()?;
+
+        // 同期相手の管理は秘匿情報を扱わないため解錠不要
+        let manager = match mode {
+            SyncMode::PeersList
+            | SyncMode::PeersRemove(_)
+            | SyncMode::PeersLabel(..)
+            | SyncMode::Discover => opts.open_locked()?,
+            _ => opts.open()?,
+        };
+
         Ok(Self {
-            mode: sub_opts.mode()?,
-            manager: RefCell::new(opts.open()?),
+            mode,
+            psk: load_psk(sub_opts)?,
+            allow: sub_opts.allowed_peers(),
+            full: sub_opts.is_full(),
+            conflict: sub_opts.conflict_policy(),
+            dry_run: sub_opts.is_dry_run(),
+            discovery_port: sub_opts.discovery_port(),
+            remote_command: sub_opts.remote_command(),
+            pair_label: sub_opts.pair_label(),
+            manager: RefCell::new(manager),
             prompter: Arc::new(StdPrompter),
+            json_output: opts.json(),
         })
     }
+
+    ///
+    /// ペアリング済み同期相手の一覧表示
+    ///
+    fn list_peers(&self) -> Result<()> {
+        let peers = self.manager.borrow().all_peers()?
+            .into_iter()
+            .map(|peer| PeerInfo {
+                node_id: peer.node_id(),
+                label: peer.label(),
+                fingerprint: secure::fingerprint(peer.public_key()),
+                address: peer.address(),
+                paired_at: peer.paired_at(),
+                last_sync: peer.watermark().map(|mark| mark.synced_at()),
+            })
+            .collect::<Vec<_>>();
+
+        if self.json_output {
+            println!("{}", serde_json::to_string_pretty(&peers)?);
+            return Ok(());
+        }
+
+        for peer in peers {
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                peer.node_id,
+                peer.label.as_deref().unwrap_or("-"),
+                peer.fingerprint,
+                peer.address,
+                peer.paired_at.format("%Y-%m-%d %H:%M:%S"),
+                peer.last_sync
+                    .map(|at| at.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            );
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// ノードIDもしくはラベルによるペアリング済み同期相手の取得
+    ///
+    /// # 引数
+    /// * `key` - 相手のノードIDもしくはラベル
+    ///
+    /// # 戻り値
+    /// 見つかった相手の情報を`Ok()`でラップして返す。
+    ///
+    fn find_peer(&self, key: &str) -> Result<TrustedPeer> {
+        pair::find_peer(self.manager.borrow().all_peers()?, key)
+            .ok_or_else(|| anyhow!("peer not found: {}", key))
+    }
+
+    ///
+    /// ペアリング済み同期相手の削除
+    ///
+    fn remove_peer(&self, key: &str) -> Result<()> {
+        let node_id = self.find_peer(key)?.node_id();
+        self.manager.borrow_mut().remove_peer(&node_id)?;
+
+        info!("peer removed: {}", node_id);
+        Ok(())
+    }
+
+    ///
+    /// ペアリング済み同期相手のラベルの設定
+    ///
+    /// # 引数
+    /// * `key` - 相手のノードIDもしくはラベル
+    /// * `label` - 設定するラベル(`None`の場合は削除)
+    ///
+    fn label_peer(&self, key: &str, label: Option<&str>) -> Result<()> {
+        let mut peer = self.find_peer(key)?;
+
+        self.manager.borrow_mut().with_write_transaction(|writer| {
+            if let Some(label) = label {
+                pair::check_label(&writer.all_peers()?, &peer.node_id(), label)?;
+            }
+
+            peer.set_label(label.map(str::to_string));
+            writer.put_peer(&peer)
+        })?;
+
+        info!("peer labeled: {} {:?}", peer.node_id(), label);
+        Ok(())
+    }
+
+    ///
+    /// 待ち受け中の同期相手の探索と一覧表示
+    ///
+    fn list_discovered(&self) -> Result<()> {
+        let paired = self.manager.borrow().all_peers()?
+            .into_iter()
+            .map(|peer| peer.node_id())
+            .collect::<Vec<_>>();
+
+        let peers = discovery::discover(
+            self.discovery_port,
+            discovery::DISCOVERY_TIMEOUT,
+            None,
+        )?
+            .into_iter()
+            .map(|peer| DiscoveredInfo {
+                paired: paired.contains(&peer.node_id),
+                node_id: peer.node_id,
+                address: peer.address,
+            })
+            .collect::<Vec<_>>();
+
+        if self.json_output {
+            println!("{}", serde_json::to_string_pretty(&peers)?);
+            return Ok(());
+        }
+
+        for peer in peers {
+            println!(
+                "{}\t{}\t{}",
+                peer.node_id,
+                peer.address,
+                if peer.paired { "paired" } else { "unpaired" },
+            );
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// 探索による接続先アドレスの解決
+    ///
+    /// # 引数
+    /// * `key` - 接続先のノードIDもしくはペアリング済みの相手のラベル
+    ///
+    /// # 戻り値
+    /// 見つかった相手のアドレスを`Ok()`でラップして返す。
+    ///
+    /// # 注記
+    /// ペアリング済みの相手に一致しない場合はノードIDとして探索する。
+    ///
+    fn resolve_peer(&self, key: &str) -> Result<String> {
+        let node_id = pair::find_peer(self.manager.borrow().all_peers()?, key)
+            .map_or_else(|| key.to_string(), |peer| peer.node_id());
+
+        discovery::discover(
+            self.discovery_port,
+            discovery::DISCOVERY_TIMEOUT,
+            Some(&node_id),
+        )?
+            .into_iter()
+            .find(|peer| peer.node_id == node_id)
+            .map(|peer| peer.address)
+            .ok_or_else(|| anyhow!("peer not found on the network: {}", node_id))
+    }
+
+    ///
+    /// クライアントとしての同期の設定の生成
+    ///
+    fn client_settings(&self) -> client::ClientSettings {
+        client::ClientSettings {
+            full: self.full,
+            conflict: self.conflict,
+            dry_run: self.dry_run,
+        }
+    }
+
+    ///
+    /// ログ出力用の動作モードの説明
+    ///
+    fn describe_mode(&self) -> String {
+        match &self.mode {
+            SyncMode::Server(addr) => format!("server mode @ {}", addr),
+            SyncMode::Daemon(addr) => format!("server daemon @ {}", addr),
+            SyncMode::Client(addr) => format!("client mode -> {}", addr),
+            SyncMode::ClientByNode(peer) => {
+                format!("client mode -> peer {}", peer)
+            }
+            SyncMode::Discover => "discover".to_string(),
+            SyncMode::Folder(dir) => format!("folder {}", dir.display()),
+            SyncMode::Ssh(destination) => format!("ssh -> {}", destination),
+            SyncMode::Stdio => "stdio".to_string(),
+            SyncMode::PairServer(addr) => format!("pair server @ {}", addr),
+            SyncMode::PairClient(addr) => format!("pair client -> {}", addr),
+            SyncMode::PeersList => "peers list".to_string(),
+            SyncMode::PeersRemove(peer) => format!("peers remove {}", peer),
+            SyncMode::PeersLabel(peer, _) => format!("peers label {}", peer),
+        }
+    }
 }
 
 impl CommandContext for SyncCommandContext {
@@ -352,34 +1039,155 @@ impl CommandContext for SyncCommandContext {
     ///
     fn exec(&self) -> Result<()> {
         match &self.mode {
-            SyncMode::Server(addr) => {
-                info!("sync start: server mode @ {}", addr);
+            SyncMode::PeersList => return self.list_peers(),
+            SyncMode::PeersRemove(peer) => return self.remove_peer(peer),
+            SyncMode::PeersLabel(peer, label) => {
+                return self.label_peer(peer, label.as_deref());
             }
+            SyncMode::Discover => return self.list_discovered(),
+            _ => {}
+        }
 
-            SyncMode::Client(addr) => {
-                info!("sync start: client mode -> {}", addr);
-            }
+        info!("sync start: {}", self.describe_mode());
+
+        // 常駐時はセッション毎にトランザクションを切り替える
+        if let SyncMode::Daemon(addr) = &self.mode {
+            server::daemon(
+                addr,
+                self.psk.as_ref(),
+                &self.allow,
+                self.discovery_port,
+                &self.manager.borrow(),
+            )?;
+
+            info!("sync finished: {}", self.describe_mode());
+            return Ok(());
         }
 
-        self.manager.borrow_mut().with_write_transaction(|writer| {
-            match &self.mode {
+        // ノードID指定の場合は探索で接続先を決定する
+        let mode = match &self.mode {
+            SyncMode::ClientByNode(peer) => {
+                let addr = self.resolve_peer(peer)?;
+                info!("peer {} found at {}", peer, addr);
+                SyncMode::Client(addr)
+            }
+            mode => mode.clone(),
+        };
+
+        let report = self.manager.borrow_mut().with_write_transaction(|writer| {
+            let identity = load_identity(writer)?;
+            let psk = self.psk.as_ref();
+            let prompter = self.prompter.as_ref();
+
+            match &mode {
                 SyncMode::Server(addr) => {
-                    server::run(addr, writer)
+                    server::run(
+                        addr,
+                        &identity,
+                        psk,
+                        &self.allow,
+                        self.discovery_port,
+                        writer,
+                    )?;
+                    Ok(None)
                 }
 
                 SyncMode::Client(addr) => {
-                    client::run(addr, writer, self.prompter.as_ref())
+                    client::run(
+                        addr,
+                        &identity,
+                        psk,
+                        &self.client_settings(),
+                        writer,
+                        prompter,
+                    ).map(Some)
+                }
+
+                SyncMode::Ssh(destination) => {
+                    let stream = SshTransport::spawn(
+                        destination,
+                        &self.remote_command,
+                    )?;
+                    let label = stream.peer_label();
+
+                    client::session(
+                        Box::new(stream),
+                        &label,
+                        &identity,
+                        psk,
+                        &self.client_settings(),
+                        writer,
+                        prompter,
+                    ).map(Some)
+                }
+
+                SyncMode::Stdio => {
+                    server::session(
+                        Box::new(StdioTransport::new()),
+                        "stdio",
+                        &identity,
+                        psk,
+                        &self.allow,
+                        writer,
+                    )?;
+                    Ok(None)
                 }
+
+                SyncMode::Folder(dir) => {
+                    folder::run(
+                        dir,
+                        &identity,
+                        psk,
+                        self.conflict,
+                        self.dry_run,
+                        writer,
+                        prompter,
+                    ).map(Some)
+                }
+
+                SyncMode::PairServer(addr) => {
+                    pair::serve(
+                        addr,
+                        &identity,
+                        psk,
+                        self.pair_label.as_deref(),
+                        writer,
+                        prompter,
+                    )?;
+                    Ok(None)
+                }
+
+                SyncMode::PairClient(addr) => {
+                    pair::connect(
+                        addr,
+                        &identity,
+                        psk,
+                        self.pair_label.as_deref(),
+                        writer,
+                        prompter,
+                    )?;
+                    Ok(None)
+                }
+
+                SyncMode::Daemon(_)
+                | SyncMode::ClientByNode(_)
+                | SyncMode::Discover
+                | SyncMode::PeersList
+                | SyncMode::PeersRemove(_)
+                | SyncMode::PeersLabel(..) => unreachable!(),
             }
         })?;
 
-        match &self.mode {
-            SyncMode::Server(addr) => {
-                info!("sync finished: server mode @ {}", addr);
-            }
+        info!("sync finished: {}", self.describe_mode());
 
-            SyncMode::Client(addr) => {
-                info!("sync finished: client mode -> {}", addr);
+        /*
+         * ドライランの結果の表示
+         */
+        if let Some(report) = report.filter(|_| self.dry_run) {
+            if self.json_output {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print!("{}", serde_yaml_ng::to_string(&report)?);
             }
         }
 
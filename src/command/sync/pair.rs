@@ -0,0 +1,404 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//! 同期相手とのペアリング処理
+
+use anyhow::{anyhow, Context, Result};
+use log::{error, info};
+
+use crate::command::prompt::Prompter;
+use crate::command::sync::client;
+use crate::command::sync::transport::{self, Listener};
+use crate::command::sync::secure::{
+    self, LocalIdentity, Responder, SecureChannel, SyncPsk, PAIR_NONCE_LEN,
+};
+use crate::command::sync::{
+    recv_hello, recv_packet, reject_hello, send_packet, send_plain_packet,
+    SyncPacket, SyncRequest, PROTOCOL_VERSION,
+};
+use crate::database::types::TrustedPeer;
+use crate::database::{TransactionReadable, TransactionWriter};
+
+/*
+ * ペアリング(待ち受け側)のエントリーポイント
+ */
+pub(super) fn serve(
+    addr: &str,
+    identity: &LocalIdentity,
+    psk: Option<&SyncPsk>,
+    label: Option<&str>,
+    writer: &mut TransactionWriter,
+    prompter: &dyn Prompter,
+) -> Result<()> {
+    /*
+     * クライアントの接続待ち受け
+     */
+    let listener = Listener::bind(addr)?;
+    let (mut stream, peer) = listener.accept().context("accept")?;
+    info!("pairing client connected: {}", peer);
+
+    /*
+     * Helloの受信と検証
+     */
+    let hello = recv_hello(stream.as_mut())?;
+
+    if !hello.pairing {
+        reject_hello(stream.as_mut(), "server is waiting for pairing")?;
+
+        error!("sync request from {} during pairing", peer);
+        return Err(anyhow!("unexpected sync request"));
+    }
+
+    if hello.node_id == identity.node_id() {
+        reject_hello(stream.as_mut(), "cannot pair with itself")?;
+        return Err(anyhow!("cannot pair with itself"));
+    }
+
+    let checked = match label {
+        Some(label) => check_label(&writer.all_peers()?, &hello.node_id, label),
+        None => Ok(()),
+    };
+
+    if let Err(err) = checked {
+        reject_hello(stream.as_mut(), "pairing cancelled")?;
+        return Err(err);
+    }
+
+    /*
+     * ハンドシェイク
+     */
+    let responder = match Responder::accept(
+        identity,
+        psk,
+        hello.protocol_version,
+        &hello.node_id,
+        &hello.context()?,
+        &hello.handshake,
+    ) {
+        Ok(responder) => responder,
+        Err(err) => {
+            reject_hello(stream.as_mut(), "authentication failed")?;
+
+            error!("handshake failed: peer={}, node={}", peer, hello.node_id);
+            return Err(err);
+        }
+    };
+
+    let (reply, established) = responder.reply()?;
+    send_plain_packet(stream.as_mut(), SyncPacket::hello_ack(
+        PROTOCOL_VERSION,
+        true,
+        None,
+        Some(reply),
+    ))?;
+
+    let peer_public = established.remote_static().to_vec();
+    let handshake_hash = established.handshake_hash().to_vec();
+    let mut channel = established.into_channel(stream);
+
+    /*
+     * 検証コード算出用のノンスの交換
+     * (クライアントのコミットメントを受け取ってからノンスを送る)
+     */
+    let commit = match recv_packet(&mut channel)? {
+        SyncPacket::PairCommit(commit) => commit,
+        pkt => return Err(anyhow!("unexpected packet: {:?}", pkt)),
+    };
+
+    let server_nonce = secure::generate_nonce();
+    send_packet(&mut channel, SyncPacket::pair_nonce(
+        identity.node_id(),
+        server_nonce.clone(),
+    ))?;
+
+    let client_nonce = match recv_packet(&mut channel)? {
+        SyncPacket::PairReveal(reveal) => reveal.nonce,
+        pkt => return Err(anyhow!("unexpected packet: {:?}", pkt)),
+    };
+
+    if secure::commitment(&client_nonce) != commit.commitment {
+        send_packet(&mut channel, SyncPacket::abort("commitment mismatch"))?;
+
+        error!("pairing commitment mismatch: node={}", hello.node_id);
+        return Err(anyhow!("pairing commitment mismatch"));
+    }
+
+    /*
+     * 検証コードの照合
+     */
+    let code = secure::verification_code(
+        &handshake_hash,
+        &client_nonce,
+        &server_nonce,
+    );
+
+    confirm_pairing(
+        &mut channel,
+        prompter,
+        identity,
+        &hello.node_id,
+        &peer_public,
+        &code,
+    )?;
+
+    /*
+     * 相手の登録
+     */
+    register_peer(writer, &hello.node_id, peer_public, peer.clone(), label)?;
+    info!("paired with {} ({})", hello.node_id, peer);
+    println!("paired with {}", hello.node_id);
+
+    Ok(())
+}
+
+/*
+ * ペアリング(接続側)のエントリーポイント
+ */
+pub(super) fn connect(
+    addr: &str,
+    identity: &LocalIdentity,
+    psk: Option<&SyncPsk>,
+    label: Option<&str>,
+    writer: &mut TransactionWriter,
+    prompter: &dyn Prompter,
+) -> Result<()> {
+    /*
+     * サーバへ接続してハンドシェイク
+     */
+    let (stream, established) = client::handshake(
+        transport::connect(addr)?,
+        identity,
+        psk,
+        true,
+        SyncRequest::default(),
+    )?;
+
+    let peer_public = established.remote_static().to_vec();
+    let handshake_hash = established.handshake_hash().to_vec();
+    let mut channel = established.into_channel(stream);
+
+    /*
+     * 検証コード算出用のノンスの交換
+     * (サーバのノンスを受け取る前に自身のノンスをコミットする)
+     */
+    let client_nonce = secure::generate_nonce();
+    send_packet(
+        &mut channel,
+        SyncPacket::pair_commit(secure::commitment(&client_nonce)),
+    )?;
+
+    let (peer_id, server_nonce) = match recv_packet(&mut channel)? {
+        SyncPacket::PairNonce(nonce) => (nonce.node_id, nonce.nonce),
+        SyncPacket::Abort(abort) => {
+            return Err(anyhow!("server aborted: {}", abort.reason));
+        }
+        pkt => return Err(anyhow!("unexpected packet: {:?}", pkt)),
+    };
+
+    if server_nonce.len() != PAIR_NONCE_LEN {
+        return Err(anyhow!("invalid pairing nonce"));
+    }
+
+    let checked = match label {
+        Some(label) => check_label(&writer.all_peers()?, &peer_id, label),
+        None => Ok(()),
+    };
+
+    if let Err(err) = checked {
+        send_packet(&mut channel, SyncPacket::abort("pairing cancelled"))?;
+        return Err(err);
+    }
+
+    send_packet(&mut channel, SyncPacket::pair_reveal(client_nonce.clone()))?;
+
+    /*
+     * 検証コードの照合
+     */
+    let code = secure::verification_code(
+        &handshake_hash,
+        &client_nonce,
+        &server_nonce,
+    );
+
+    confirm_pairing(
+        &mut channel,
+        prompter,
+        identity,
+        &peer_id,
+        &peer_public,
+        &code,
+    )?;
+
+    /*
+     * 相手の登録
+     */
+    register_peer(writer, &peer_id, peer_public, addr.to_string(), label)?;
+    info!("paired with {} ({})", peer_id, addr);
+    println!("paired with {}", peer_id);
+
+    Ok(())
+}
+
+/*
+ * 検証コードを表示して利用者に照合させ、双方の結果を交換する
+ */
+fn confirm_pairing(
+    channel: &mut SecureChannel,
+    prompter: &dyn Prompter,
+    identity: &LocalIdentity,
+    peer_id: &str,
+    peer_public: &[u8],
+    code: &str,
+) -> Result<()> {
+    let msg = format!(
+        "this node: {} ({})\npeer node: {} ({})\n\n    検証コード: {}\n",
+        identity.node_id(),
+        secure::fingerprint(identity.public_key()),
+        peer_id,
+        secure::fingerprint(peer_public),
+        code,
+    );
+
+    let accepted = prompter.confirm(
+        &msg,
+        false,
+        Some("相手側に表示されている検証コードと一致しますか？"),
+    )?;
+
+    send_packet(channel, SyncPacket::pair_confirm(accepted))?;
+
+    let peer_accepted = match recv_packet(channel)? {
+        SyncPacket::PairConfirm(confirm) => confirm.accepted,
+        SyncPacket::Abort(abort) => {
+            return Err(anyhow!("peer aborted: {}", abort.reason));
+        }
+        pkt => return Err(anyhow!("unexpected packet: {:?}", pkt)),
+    };
+
+    if !accepted {
+        return Err(anyhow!("pairing rejected"));
+    }
+
+    if !peer_accepted {
+        return Err(anyhow!("pairing rejected by peer"));
+    }
+
+    Ok(())
+}
+
+/*
+ * ペアリングした相手の登録
+ *
+ * ラベルが指定されなかった場合は、登録済みの相手のラベルを引き継ぐ。
+ */
+fn register_peer(
+    writer: &mut TransactionWriter,
+    node_id: &str,
+    public_key: Vec<u8>,
+    address: String,
+    label: Option<&str>,
+) -> Result<()> {
+    let label = match label {
+        Some(label) => Some(label.to_string()),
+        None => writer.get_peer(node_id)?.and_then(|peer| peer.label()),
+    };
+
+    let mut peer = TrustedPeer::new(node_id.to_string(), public_key, address);
+    peer.set_label(label);
+    writer.put_peer(&peer)
+}
+
+/*
+ * ラベルが他の相手の指定と紛らわしくないことの確認
+ */
+pub(super) fn check_label(peers: &[TrustedPeer], node_id: &str, label: &str)
+    -> Result<()>
+{
+    if label.trim().is_empty() {
+        return Err(anyhow!("empty peer label"));
+    }
+
+    let conflict = peers.iter()
+        .filter(|peer| peer.node_id() != node_id)
+        .any(|peer| {
+            peer.node_id() == label || peer.label().as_deref() == Some(label)
+        });
+
+    if conflict {
+        return Err(anyhow!("peer label already in use: {}", label));
+    }
+
+    Ok(())
+}
+
+/*
+ * ノードIDもしくはラベルによるペアリング済み同期相手の検索
+ *
+ * ノードIDの一致を優先し、一致するものが無い場合はラベルで検索する。
+ */
+pub(super) fn find_peer(peers: Vec<TrustedPeer>, key: &str)
+    -> Option<TrustedPeer>
+{
+    let (by_id, others): (Vec<_>, Vec<_>) = peers.into_iter()
+        .partition(|peer| peer.node_id() == key);
+
+    by_id.into_iter()
+        .next()
+        .or_else(|| {
+            others.into_iter().find(|peer| peer.label().as_deref() == Some(key))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_peer(node_id: &str, label: Option<&str>) -> TrustedPeer {
+        let mut peer = TrustedPeer::new(
+            node_id.to_string(),
+            vec![0; 32],
+            "127.0.0.1:2456".to_string(),
+        );
+        peer.set_label(label.map(str::to_string));
+        peer
+    }
+
+    ///
+    /// ノードIDとラベルのどちらでも相手を検索できることを確認
+    ///
+    #[test]
+    fn find_peer_by_id_or_label() {
+        let peers = vec![
+            make_peer("node-a", Some("laptop")),
+            make_peer("node-b", Some("node-a")),
+            make_peer("node-c", None),
+        ];
+
+        let find = |key: &str| find_peer(peers.clone(), key).map(|p| p.node_id());
+
+        assert_eq!(find("laptop").as_deref(), Some("node-a"));
+        assert_eq!(find("node-c").as_deref(), Some("node-c"));
+        assert_eq!(find("node-a").as_deref(), Some("node-a"));
+        assert_eq!(find("desktop"), None);
+    }
+
+    ///
+    /// 他の相手のノードIDやラベルと重なるラベルが拒否されることを確認
+    ///
+    #[test]
+    fn check_label_rejects_ambiguous_label() {
+        let peers = vec![
+            make_peer("node-a", Some("laptop")),
+            make_peer("node-b", None),
+        ];
+
+        assert!(check_label(&peers, "node-c", "desktop").is_ok());
+        assert!(check_label(&peers, "node-a", "laptop").is_ok());
+        assert!(check_label(&peers, "node-c", "laptop").is_err());
+        assert!(check_label(&peers, "node-c", "node-b").is_err());
+        assert!(check_label(&peers, "node-c", " ").is_err());
+    }
+}
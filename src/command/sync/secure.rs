@@ -0,0 +1,819 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! 同期通信路の認証と暗号化
+//!
+//! Noiseプロトコル(IX)によるハンドシェイクで各ノードの静的公開鍵を交換し、
+//! 以降のパケットを暗号化して送受信する。事前共有鍵(PSK)が指定されている場
+//! 合はIXpsk2を用い、PSKを知っていることも相互に確認する。
+//!
+//! 共有ディレクトリ経由の同期では、互いの静的公開鍵を既知とする一方向のパ
+//! ターン(K、PSK併用時はKpsk0)で宛先のノードのみが復号できるように暗号化す
+//! る。
+//!
+
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use blake2::{Blake2s256, Digest};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use snow::{Builder, HandshakeState, TransportState};
+use zeroize::Zeroizing;
+
+use crate::command::sync::transport::Transport;
+
+/// 使用するNoiseプロトコルのパターン
+const NOISE_PATTERN: &str = "Noise_IX_25519_ChaChaPoly_BLAKE2s";
+
+/// 事前共有鍵を併用する場合のNoiseプロトコルのパターン
+const NOISE_PATTERN_PSK: &str = "Noise_IXpsk2_25519_ChaChaPoly_BLAKE2s";
+
+/// 共有ディレクトリ経由の同期で用いるNoiseプロトコルのパターン
+const NOISE_PATTERN_BUNDLE: &str = "Noise_K_25519_ChaChaPoly_BLAKE2s";
+
+/// 共有ディレクトリ経由の同期で事前共有鍵を併用する場合のパターン
+const NOISE_PATTERN_BUNDLE_PSK: &str = "Noise_Kpsk0_25519_ChaChaPoly_BLAKE2s";
+
+/// プロローグの接頭辞(ハンドシェイクを本プロトコルに束縛する)
+const PROLOGUE_PREFIX: &[u8] = b"pwmgr-sync\0";
+
+/// 事前共有鍵の長さ(バイト)
+pub(super) const PSK_LEN: usize = 32;
+
+/// 事前共有鍵の導出に用いるソルト
+const PSK_SALT: &[u8] = b"pwmgr-sync-psk";
+
+/// 検証コード算出用ノンスの長さ(バイト)
+pub(super) const PAIR_NONCE_LEN: usize = 32;
+
+/// Noiseメッセージの最大長
+const NOISE_MAX_LEN: usize = 65535;
+
+/// 認証タグの長さ
+const TAG_LEN: usize = 16;
+
+/// 1チャンクあたりの平文の最大長
+const CHUNK_LEN: usize = NOISE_MAX_LEN - TAG_LEN;
+
+/// 1パケットあたりの平文の最大長
+const MAX_PACKET_LEN: usize = 16 * 1024 * 1024;
+
+///
+/// 同期用の事前共有鍵
+///
+pub(crate) struct SyncPsk(Zeroizing<[u8; PSK_LEN]>);
+
+impl SyncPsk {
+    ///
+    /// 合言葉からの事前共有鍵の導出
+    ///
+    /// # 引数
+    /// * `secret` - 双方のノードで共有している合言葉
+    ///
+    /// # 戻り値
+    /// 導出した事前共有鍵を`Ok()`でラップして返す。
+    ///
+    /// # 注記
+    /// ハンドシェイクの盗聴による総当たりを困難にするため、Argon2idで導出す
+    /// る。
+    ///
+    pub(crate) fn derive(secret: &str) -> Result<Self> {
+        if secret.is_empty() {
+            return Err(anyhow!("empty pre-shared key"));
+        }
+
+        let params = Params::new(19 * 1024, 2, 1, Some(PSK_LEN))
+            .map_err(|err| anyhow!("invalid kdf parameters: {}", err))?;
+
+        let mut psk = Zeroizing::new([0u8; PSK_LEN]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(secret.as_bytes(), PSK_SALT, psk.as_mut())
+            .map_err(|err| anyhow!("psk derivation failed: {}", err))?;
+
+        Ok(Self(psk))
+    }
+}
+
+///
+/// ハンドシェイクに用いる自ノードの鍵情報
+///
+pub(super) struct LocalIdentity {
+    /// ノードID
+    node_id: String,
+
+    /// 静的公開鍵
+    public_key: Vec<u8>,
+
+    /// 静的秘密鍵
+    private_key: Zeroizing<Vec<u8>>,
+}
+
+impl LocalIdentity {
+    ///
+    /// オブジェクトの生成
+    ///
+    pub(super) fn new(
+        node_id: String,
+        public_key: Vec<u8>,
+        private_key: Zeroizing<Vec<u8>>,
+    ) -> Self {
+        Self { node_id, public_key, private_key }
+    }
+
+    ///
+    /// ノードIDへのアクセサ
+    ///
+    pub(super) fn node_id(&self) -> String {
+        self.node_id.clone()
+    }
+
+    ///
+    /// 静的公開鍵へのアクセサ
+    ///
+    pub(super) fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+}
+
+///
+/// 静的鍵ペアの生成
+///
+/// # 戻り値
+/// 公開鍵と秘密鍵の組を`Ok()`でラップして返す。
+///
+pub(super) fn generate_keypair() -> Result<(Vec<u8>, Zeroizing<Vec<u8>>)> {
+    let keypair = Builder::new(NOISE_PATTERN.parse()?).generate_keypair()?;
+    Ok((keypair.public, Zeroizing::new(keypair.private)))
+}
+
+///
+/// 公開鍵のフィンガープリントの生成
+///
+/// # 注記
+/// 一覧表示等で公開鍵を人間が照合するために用いる。
+///
+pub(crate) fn fingerprint(public_key: &[u8]) -> String {
+    Blake2s256::digest(public_key)[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+///
+/// ハンドシェイクのプロローグの生成
+///
+/// # 引数
+/// * `protocol_version` - プロトコルバージョン
+/// * `node_id` - クライアントのノードID
+/// * `context` - Helloのその他の付帯情報をシリアライズしたもの
+///
+/// # 注記
+/// 平文で送受信するHelloの内容をハンドシェイクに束縛し、改竄された場合は
+/// ハンドシェイクが失敗するようにする。
+///
+fn prologue(protocol_version: u16, node_id: &str, context: &[u8]) -> Vec<u8> {
+    let mut prologue = PROLOGUE_PREFIX.to_vec();
+    prologue.extend_from_slice(&protocol_version.to_be_bytes());
+    prologue.extend_from_slice(&(node_id.len() as u32).to_be_bytes());
+    prologue.extend_from_slice(node_id.as_bytes());
+    prologue.extend_from_slice(context);
+    prologue
+}
+
+///
+/// ハンドシェイク状態の生成
+///
+fn build_state(
+    identity: &LocalIdentity,
+    psk: Option<&SyncPsk>,
+    prologue: &[u8],
+    initiator: bool,
+) -> Result<HandshakeState> {
+    let pattern = if psk.is_some() { NOISE_PATTERN_PSK } else { NOISE_PATTERN };
+    let mut builder = Builder::new(pattern.parse()?)
+        .local_private_key(&identity.private_key)
+        .prologue(prologue);
+
+    if let Some(psk) = psk {
+        builder = builder.psk(2, psk.0.as_ref());
+    }
+
+    Ok(if initiator {
+        builder.build_initiator()?
+    } else {
+        builder.build_responder()?
+    })
+}
+
+///
+/// ハンドシェイクを完了した状態
+///
+pub(super) struct Established {
+    /// Noiseの暗号化状態
+    transport: TransportState,
+
+    /// 相手の静的公開鍵
+    remote_static: Vec<u8>,
+
+    /// ハンドシェイクハッシュ(検証コードの算出に用いる)
+    handshake_hash: Vec<u8>,
+}
+
+impl Established {
+    ///
+    /// ハンドシェイク状態からの変換
+    ///
+    fn from_state(state: HandshakeState) -> Result<Self> {
+        let remote_static = state.get_remote_static()
+            .ok_or_else(|| anyhow!("peer static key is missing"))?
+            .to_vec();
+        let handshake_hash = state.get_handshake_hash().to_vec();
+
+        Ok(Self {
+            transport: state.into_transport_mode()?,
+            remote_static,
+            handshake_hash,
+        })
+    }
+
+    ///
+    /// 相手の静的公開鍵へのアクセサ
+    ///
+    pub(super) fn remote_static(&self) -> &[u8] {
+        &self.remote_static
+    }
+
+    ///
+    /// ハンドシェイクハッシュへのアクセサ
+    ///
+    pub(super) fn handshake_hash(&self) -> &[u8] {
+        &self.handshake_hash
+    }
+
+    ///
+    /// 暗号化通信路への変換
+    ///
+    pub(super) fn into_channel<S>(self, stream: S) -> SecureChannel<S> {
+        SecureChannel {
+            stream,
+            transport: self.transport,
+        }
+    }
+}
+
+///
+/// クライアント側のハンドシェイク
+///
+pub(super) struct Initiator {
+    state: HandshakeState,
+}
+
+impl Initiator {
+    ///
+    /// ハンドシェイクの開始
+    ///
+    /// # 引数
+    /// * `identity` - 自ノードの鍵情報
+    /// * `psk` - 事前共有鍵(使用しない場合は`None`)
+    /// * `protocol_version` - Helloで送るプロトコルバージョン
+    /// * `context` - Helloの付帯情報(ハンドシェイクに束縛する)
+    ///
+    /// # 戻り値
+    /// ハンドシェイク状態とHelloに載せる最初のメッセージを`Ok()`でラップして
+    /// 返す。
+    ///
+    pub(super) fn start(
+        identity: &LocalIdentity,
+        psk: Option<&SyncPsk>,
+        protocol_version: u16,
+        context: &[u8],
+    ) -> Result<(Self, Vec<u8>)> {
+        let prologue = prologue(protocol_version, &identity.node_id, context);
+        let mut state = build_state(identity, psk, &prologue, true)?;
+        let mut buf = vec![0u8; NOISE_MAX_LEN];
+        let len = state.write_message(&[], &mut buf)?;
+        buf.truncate(len);
+
+        Ok((Self { state }, buf))
+    }
+
+    ///
+    /// ハンドシェイクの完了
+    ///
+    /// # 引数
+    /// * `message` - HelloAckで受信したメッセージ
+    ///
+    /// # 戻り値
+    /// ハンドシェイクが成立した場合は完了状態を`Ok()`でラップして返す。
+    ///
+    /// # 注記
+    /// サーバがペアリング済みの相手か否かは、呼び出し側で完了状態の
+    /// `remote_static()`を照合して確認すること。
+    ///
+    pub(super) fn finish(mut self, message: &[u8]) -> Result<Established> {
+        let mut buf = vec![0u8; NOISE_MAX_LEN];
+        self.state
+            .read_message(message, &mut buf)
+            .map_err(|_| anyhow!("server authentication failed"))?;
+
+        Established::from_state(self.state)
+    }
+}
+
+///
+/// サーバ側のハンドシェイク
+///
+pub(super) struct Responder {
+    state: HandshakeState,
+}
+
+impl Responder {
+    ///
+    /// 最初のメッセージの受理
+    ///
+    /// # 引数
+    /// * `identity` - 自ノードの鍵情報
+    /// * `psk` - 事前共有鍵(使用しない場合は`None`)
+    /// * `protocol_version` - Helloで受信したプロトコルバージョン
+    /// * `node_id` - Helloで受信したノードID
+    /// * `context` - Helloで受信した付帯情報
+    /// * `message` - Helloで受信したメッセージ
+    ///
+    /// # 注記
+    /// 応答を返す前に`remote_static()`でクライアントの静的公開鍵を照合するこ
+    /// と。最初のメッセージの時点では公開鍵に対応する秘密鍵やPSKの保持は証明
+    /// されていないが、以降の通信路の鍵はそれらに依存するため、成りすました
+    /// 相手は暗号化されたパケットを復号できない。
+    ///
+    pub(super) fn accept(
+        identity: &LocalIdentity,
+        psk: Option<&SyncPsk>,
+        protocol_version: u16,
+        node_id: &str,
+        context: &[u8],
+        message: &[u8],
+    ) -> Result<Self> {
+        let prologue = prologue(protocol_version, node_id, context);
+        let mut state = build_state(identity, psk, &prologue, false)?;
+        let mut buf = vec![0u8; NOISE_MAX_LEN];
+        state
+            .read_message(message, &mut buf)
+            .map_err(|_| anyhow!("client authentication failed"))?;
+
+        Ok(Self { state })
+    }
+
+    ///
+    /// クライアントの静的公開鍵へのアクセサ
+    ///
+    pub(super) fn remote_static(&self) -> &[u8] {
+        self.state.get_remote_static().unwrap_or_default()
+    }
+
+    ///
+    /// 応答メッセージの生成とハンドシェイクの完了
+    ///
+    /// # 戻り値
+    /// HelloAckに載せる応答メッセージと完了状態を`Ok()`でラップして返す。
+    ///
+    pub(super) fn reply(mut self) -> Result<(Vec<u8>, Established)> {
+        let mut buf = vec![0u8; NOISE_MAX_LEN];
+        let len = self.state.write_message(&[], &mut buf)?;
+        buf.truncate(len);
+
+        Ok((buf, Established::from_state(self.state)?))
+    }
+}
+
+///
+/// 共有ディレクトリ経由の同期用のハンドシェイク状態の生成
+///
+/// # 引数
+/// * `identity` - 自ノードの鍵情報
+/// * `remote` - 相手の静的公開鍵
+/// * `psk` - 事前共有鍵(使用しない場合は`None`)
+/// * `prologue` - プロローグ
+/// * `initiator` - 送信側か否か
+///
+fn build_bundle_state(
+    identity: &LocalIdentity,
+    remote: &[u8],
+    psk: Option<&SyncPsk>,
+    prologue: &[u8],
+    initiator: bool,
+) -> Result<HandshakeState> {
+    let pattern = if psk.is_some() {
+        NOISE_PATTERN_BUNDLE_PSK
+    } else {
+        NOISE_PATTERN_BUNDLE
+    };
+    let mut builder = Builder::new(pattern.parse()?)
+        .local_private_key(&identity.private_key)
+        .remote_public_key(remote)
+        .prologue(prologue);
+
+    if let Some(psk) = psk {
+        builder = builder.psk(0, psk.0.as_ref());
+    }
+
+    Ok(if initiator {
+        builder.build_initiator()?
+    } else {
+        builder.build_responder()?
+    })
+}
+
+///
+/// 同期ファイルの暗号化の開始
+///
+/// # 引数
+/// * `identity` - 自ノードの鍵情報
+/// * `recipient` - 宛先のノードの静的公開鍵
+/// * `psk` - 事前共有鍵(使用しない場合は`None`)
+/// * `protocol_version` - ヘッダに記録するプロトコルバージョン
+/// * `context` - ヘッダのその他の情報(ハンドシェイクに束縛する)
+///
+/// # 戻り値
+/// ヘッダに載せるハンドシェイクメッセージと、送信専用の完了状態を`Ok()`で
+/// ラップして返す。
+///
+pub(super) fn seal_bundle(
+    identity: &LocalIdentity,
+    recipient: &[u8],
+    psk: Option<&SyncPsk>,
+    protocol_version: u16,
+    context: &[u8],
+) -> Result<(Vec<u8>, Established)> {
+    let prologue = prologue(protocol_version, &identity.node_id, context);
+    let mut state = build_bundle_state(identity, recipient, psk, &prologue, true)?;
+    let mut buf = vec![0u8; NOISE_MAX_LEN];
+    let len = state.write_message(&[], &mut buf)?;
+    buf.truncate(len);
+
+    Ok((buf, Established::from_state(state)?))
+}
+
+///
+/// 同期ファイルの復号の開始
+///
+/// # 引数
+/// * `identity` - 自ノードの鍵情報
+/// * `sender_id` - ヘッダに記録された送信元のノードID
+/// * `sender` - 送信元のノードの静的公開鍵(ペアリング時に登録したもの)
+/// * `psk` - 事前共有鍵(使用しない場合は`None`)
+/// * `protocol_version` - ヘッダに記録されたプロトコルバージョン
+/// * `context` - ヘッダのその他の情報
+/// * `message` - ヘッダに記録されたハンドシェイクメッセージ
+///
+/// # 戻り値
+/// 送信元の認証に成功した場合は受信専用の完了状態を`Ok()`でラップして返す。
+///
+pub(super) fn open_bundle(
+    identity: &LocalIdentity,
+    sender_id: &str,
+    sender: &[u8],
+    psk: Option<&SyncPsk>,
+    protocol_version: u16,
+    context: &[u8],
+    message: &[u8],
+) -> Result<Established> {
+    let prologue = prologue(protocol_version, sender_id, context);
+    let mut state = build_bundle_state(identity, sender, psk, &prologue, false)?;
+    let mut buf = vec![0u8; NOISE_MAX_LEN];
+    state
+        .read_message(message, &mut buf)
+        .map_err(|_| anyhow!("bundle authentication failed"))?;
+
+    Established::from_state(state)
+}
+
+///
+/// 検証コードの算出
+///
+/// # 引数
+/// * `handshake_hash` - ハンドシェイクハッシュ
+/// * `client_nonce` - クライアントがコミットしたノンス
+/// * `server_nonce` - サーバが送ったノンス
+///
+/// # 戻り値
+/// 双方で表示して照合する6桁の検証コードを返す。
+///
+/// # 注記
+/// 中間者が双方の検証コードを一致させる鍵を探索できないよう、クライアントの
+/// ノンスはサーバのノンスを受け取る前にコミットしておくこと。
+///
+pub(super) fn verification_code(
+    handshake_hash: &[u8],
+    client_nonce: &[u8],
+    server_nonce: &[u8],
+) -> String {
+    let digest = Blake2s256::new()
+        .chain_update(b"pwmgr-pairing-code\0")
+        .chain_update(handshake_hash)
+        .chain_update(client_nonce)
+        .chain_update(server_nonce)
+        .finalize();
+
+    let code = u32::from_be_bytes([
+        digest[0], digest[1], digest[2], digest[3]
+    ]) % 1_000_000;
+
+    format!("{:03} {:03}", code / 1000, code % 1000)
+}
+
+///
+/// 検証コード算出用ノンスの生成
+///
+pub(super) fn generate_nonce() -> Vec<u8> {
+    let mut nonce = vec![0u8; PAIR_NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+///
+/// ノンスに対するコミットメントの生成
+///
+pub(super) fn commitment(nonce: &[u8]) -> Vec<u8> {
+    Blake2s256::new()
+        .chain_update(b"pwmgr-pairing-commit\0")
+        .chain_update(nonce)
+        .finalize()
+        .to_vec()
+}
+
+///
+/// 暗号化された通信路
+///
+pub(super) struct SecureChannel<S = Box<dyn Transport>> {
+    /// 下位の通信路
+    stream: S,
+
+    /// Noiseの暗号化状態
+    transport: TransportState,
+}
+
+impl<S: Write> SecureChannel<S> {
+    ///
+    /// データの送信
+    ///
+    /// # 注記
+    /// 平文長(u32)を先頭に送り、平文をNoiseメッセージの上限に収まるチャンク
+    /// に分割してそれぞれ暗号化して送る。
+    ///
+    pub(super) fn send(&mut self, plain: &[u8]) -> Result<()> {
+        if plain.len() > MAX_PACKET_LEN {
+            return Err(anyhow!("packet too large: {} bytes", plain.len()));
+        }
+
+        let mut buf = vec![0u8; NOISE_MAX_LEN];
+        let len = self.transport
+            .write_message(&(plain.len() as u32).to_be_bytes(), &mut buf)?;
+        self.stream.write_all(&buf[..len]).context("write length")?;
+
+        for chunk in plain.chunks(CHUNK_LEN) {
+            let len = self.transport.write_message(chunk, &mut buf)?;
+            self.stream.write_all(&buf[..len]).context("write packet")?;
+        }
+
+        self.stream.flush().ok();
+        Ok(())
+    }
+
+    ///
+    /// 下位のストリームへの書き出しの完了
+    ///
+    /// # 戻り値
+    /// 下位のストリームを`Ok()`でラップして返す。
+    ///
+    pub(super) fn finish(mut self) -> Result<S> {
+        self.stream.flush().context("flush stream")?;
+        Ok(self.stream)
+    }
+}
+
+impl<S: Read> SecureChannel<S> {
+    ///
+    /// データの受信
+    ///
+    pub(super) fn recv(&mut self) -> Result<Zeroizing<Vec<u8>>> {
+        let mut buf = vec![0u8; NOISE_MAX_LEN];
+
+        let mut len_buf = [0u8; 4];
+        self.read_chunk(&mut buf, 4)?;
+        len_buf.copy_from_slice(&buf[..4]);
+        let total = u32::from_be_bytes(len_buf) as usize;
+
+        if total > MAX_PACKET_LEN {
+            return Err(anyhow!("packet too large: {} bytes", total));
+        }
+
+        let mut plain = Zeroizing::new(Vec::with_capacity(total));
+        while plain.len() < total {
+            let size = (total - plain.len()).min(CHUNK_LEN);
+            self.read_chunk(&mut buf, size)?;
+            plain.extend_from_slice(&buf[..size]);
+        }
+
+        Ok(plain)
+    }
+
+    ///
+    /// 1チャンクの受信と復号
+    ///
+    /// # 引数
+    /// * `buf` - 復号結果の格納先
+    /// * `size` - チャンクの平文長
+    ///
+    fn read_chunk(&mut self, buf: &mut [u8], size: usize) -> Result<()> {
+        let mut cipher = vec![0u8; size + TAG_LEN];
+        self.stream.read_exact(&mut cipher).context("read packet")?;
+
+        self.transport
+            .read_message(&cipher, buf)
+            .map_err(|_| anyhow!("packet decryption failed"))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use super::*;
+
+    fn test_identity(node_id: &str) -> (LocalIdentity, Vec<u8>) {
+        let (public, private) = generate_keypair().unwrap();
+        (
+            LocalIdentity::new(node_id.to_string(), public.clone(), private),
+            public
+        )
+    }
+
+    fn handshake(
+        client: &LocalIdentity,
+        client_psk: Option<&SyncPsk>,
+        server: &LocalIdentity,
+        server_psk: Option<&SyncPsk>,
+    ) -> Result<(Established, Established)> {
+        handshake_as(client, client_psk, server, server_psk, &client.node_id())
+    }
+
+    fn handshake_as(
+        client: &LocalIdentity,
+        client_psk: Option<&SyncPsk>,
+        server: &LocalIdentity,
+        server_psk: Option<&SyncPsk>,
+        claimed_id: &str,
+    ) -> Result<(Established, Established)> {
+        handshake_with(client, client_psk, server, server_psk, claimed_id, &[])
+    }
+
+    fn handshake_with(
+        client: &LocalIdentity,
+        client_psk: Option<&SyncPsk>,
+        server: &LocalIdentity,
+        server_psk: Option<&SyncPsk>,
+        claimed_id: &str,
+        claimed_context: &[u8],
+    ) -> Result<(Established, Established)> {
+        let (initiator, msg) = Initiator::start(client, client_psk, 1, &[])?;
+        let responder = Responder::accept(
+            server, server_psk, 1, claimed_id, claimed_context, &msg
+        )?;
+        let (reply, server_side) = responder.reply()?;
+        let client_side = initiator.finish(&reply)?;
+
+        Ok((client_side, server_side))
+    }
+
+    ///
+    /// ハンドシェイクで静的公開鍵が交換され、暗号化通信ができることを確認
+    ///
+    #[test]
+    fn handshake_and_transfer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (client, client_public) = test_identity("client");
+        let (server, server_public) = test_identity("server");
+        let (client_side, server_side) =
+            handshake(&client, None, &server, None).unwrap();
+
+        assert_eq!(client_side.remote_static(), server_public.as_slice());
+        assert_eq!(server_side.remote_static(), client_public.as_slice());
+
+        let peer = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut channel = server_side.into_channel(stream);
+            let data = channel.recv().unwrap();
+            channel.send(&data).unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut channel = client_side.into_channel(stream);
+
+        // チャンク分割が必要な大きさのデータで往復させる
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        channel.send(&data).unwrap();
+        assert_eq!(*channel.recv().unwrap(), data);
+
+        peer.join().unwrap();
+    }
+
+    ///
+    /// PSKやHelloの内容が異なる場合はハンドシェイクが失敗することを確認
+    ///
+    /// IXでは最初のメッセージに認証が無いため、不一致はクライアントが応答を
+    /// 検証した時点で検出される。
+    ///
+    #[test]
+    fn handshake_rejects_mismatch() {
+        let (client, _) = test_identity("client");
+        let (server, _) = test_identity("server");
+        let psk = SyncPsk::derive("secret").unwrap();
+        let other = SyncPsk::derive("other").unwrap();
+
+        assert!(handshake(&client, Some(&psk), &server, Some(&psk)).is_ok());
+        assert!(handshake(&client, Some(&psk), &server, Some(&other)).is_err());
+        assert!(handshake(&client, Some(&psk), &server, None).is_err());
+
+        // Helloのノードが改竄された場合はクライアント側で検出される
+        assert!(
+            handshake_as(&client, None, &server, None, "spoofed").is_err()
+        );
+
+        // Helloの付帯情報が改竄された場合も同様
+        assert!(
+            handshake_with(&client, None, &server, None, "client", &[1])
+                .is_err()
+        );
+    }
+
+    ///
+    /// 同期ファイル用の暗号化が宛先のノードでのみ復号できることを確認
+    ///
+    #[test]
+    fn bundle_round_trip() {
+        let (sender, sender_public) = test_identity("sender");
+        let (recipient, recipient_public) = test_identity("recipient");
+        let (other, _) = test_identity("other");
+        let psk = SyncPsk::derive("secret").unwrap();
+
+        let (message, established) = seal_bundle(
+            &sender, &recipient_public, Some(&psk), 1, b"ctx"
+        ).unwrap();
+        let mut channel = established.into_channel(Vec::new());
+        channel.send(b"payload").unwrap();
+        let sealed = channel.finish().unwrap();
+
+        let open = |identity: &LocalIdentity, psk: Option<&SyncPsk>, ctx: &[u8]| {
+            open_bundle(identity, "sender", &sender_public, psk, 1, ctx, &message)
+        };
+
+        let established = open(&recipient, Some(&psk), b"ctx").unwrap();
+        assert_eq!(established.remote_static(), sender_public.as_slice());
+        let mut channel = established.into_channel(sealed.as_slice());
+        assert_eq!(channel.recv().unwrap().as_slice(), b"payload");
+
+        // 宛先以外のノード、PSKやヘッダの不一致では復号できない
+        assert!(open(&other, Some(&psk), b"ctx").is_err());
+        assert!(open(&recipient, None, b"ctx").is_err());
+        assert!(open(&recipient, Some(&psk), b"tampered").is_err());
+    }
+
+    ///
+    /// 検証コードが双方で一致し、ノンスが異なれば変化することを確認
+    ///
+    #[test]
+    fn verification_code_matches() {
+        let (client, _) = test_identity("client");
+        let (server, _) = test_identity("server");
+        let (client_side, server_side) =
+            handshake(&client, None, &server, None).unwrap();
+
+        let client_nonce = generate_nonce();
+        let server_nonce = generate_nonce();
+        let code = verification_code(
+            client_side.handshake_hash(), &client_nonce, &server_nonce
+        );
+
+        assert_eq!(code.len(), 7);
+        assert_eq!(client_side.handshake_hash(), server_side.handshake_hash());
+        assert_eq!(
+            code,
+            verification_code(
+                server_side.handshake_hash(), &client_nonce, &server_nonce
+            )
+        );
+        assert_eq!(commitment(&client_nonce), commitment(&client_nonce));
+    }
+}
@@ -6,78 +6,328 @@
 
 //! サーバ側の同期処理
 
-use std::net::TcpListener;
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Result};
 use log::{debug, error, info, warn};
 
+use crate::command::sync::discovery::Announcer;
+use crate::command::sync::secure::{LocalIdentity, Responder, SyncPsk};
+use crate::command::sync::transport::{Listener, Transport};
 use crate::command::sync::{
-    recv_packet, send_packet, NodeRole, SyncPacket, PROTOCOL_VERSION,
+    load_identity, recv_hello, recv_packet, reject_hello, send_packet,
+    send_plain_packet, SyncPacket, PROTOCOL_VERSION,
 };
-use crate::database::{TransactionReadable, TransactionWriter};
+use crate::database::{EntryManager, TransactionReadable, TransactionWriter};
+
+/// デーモンモードでのソケット送受信のタイムアウト
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// デーモンモードで停止要求を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 停止要求のシグナルを受信したか否か
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+///
+/// 1セッション分の同期結果
+///
+pub(super) struct SessionSummary {
+    /// 相手のノードID
+    node_id: String,
+
+    /// 送信したエントリ数
+    sent: u64,
+
+    /// 受信したエントリ数
+    received: u64,
+
+    /// ドライランか否か
+    dry_run: bool,
+}
 
 /*
  * サーバモードのエントリーポイント
  */
-pub(super) fn run(addr: &str, writer: &mut TransactionWriter) -> Result<()> {
+pub(super) fn run(
+    addr: &str,
+    identity: &LocalIdentity,
+    psk: Option<&SyncPsk>,
+    allow: &[String],
+    discovery_port: u16,
+    writer: &mut TransactionWriter,
+) -> Result<()> {
     /*
-     * クライアントの接続待ち受け
+     * クライアントの接続待ち受け（待ち受け中は探索用の告知を行う）
      */
-    let listener = TcpListener::bind(addr)
-        .with_context(|| format!("bind {}", addr))?;
+    let listener = Listener::bind(addr)?;
+    let _announcer = start_announcer(&listener, identity, discovery_port)?;
 
-    let (mut stream, peer) = listener.accept().context("accept")?;
+    let (stream, peer) = listener.accept().context("accept")?;
     info!("client connected: {}", peer);
 
+    session(stream, &peer, identity, psk, allow, writer)?;
+
+    Ok(())
+}
+
+/*
+ * サーバモード(常駐)のエントリーポイント
+ *
+ * クライアントを1件ずつ順に受け付け、セッション毎に書き込みトランザクション
+ * を切り替える。SIGTERM/SIGINTを受けた場合は処理中のセッションを完了させて
+ * から終了する。
+ */
+pub(super) fn daemon(
+    addr: &str,
+    psk: Option<&SyncPsk>,
+    allow: &[String],
+    discovery_port: u16,
+    manager: &EntryManager,
+) -> Result<()> {
+    let identity = manager.with_write_transaction(load_identity)?;
+
+    /*
+     * 待ち受けの開始
+     */
+    let listener = Listener::bind(addr)?;
+
+    // 停止要求を確認できるよう、待ち受けはノンブロッキングで行う
+    listener.set_nonblocking(true)?;
+    install_signal_handler()?;
+
+    let _announcer = start_announcer(&listener, &identity, discovery_port)?;
+
+    info!("server daemon: listening on {} as {}", addr, identity.node_id());
+
+    /*
+     * セッションの処理
+     */
+    let mut sessions = 0u64;
+
+    while !SHUTDOWN_REQUESTED.load(Ordering::SeqCst) {
+        let (stream, peer) = match listener.accept() {
+            Ok(accepted) => accepted,
+
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+
+            Err(err) => {
+                warn!("server daemon: accept failed: {}", err);
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+
+        sessions += 1;
+        info!("session #{} start: peer={}", sessions, peer);
+
+        let started = Instant::now();
+        // 応答しないクライアントで待ち受けが停止しないようにする
+        let result = stream.set_io_timeout(Some(IO_TIMEOUT)).and_then(|_| {
+            manager.with_write_transaction(|writer| {
+                session(stream, &peer, &identity, psk, allow, writer)
+            })
+        });
+
+        match result {
+            Ok(summary) => info!(
+                "session #{} finished: peer={}, node={}, sent={}, received={}, \
+                 dry_run={}, elapsed={}ms",
+                sessions,
+                peer,
+                summary.node_id,
+                summary.sent,
+                summary.received,
+                summary.dry_run,
+                started.elapsed().as_millis(),
+            ),
+
+            Err(err) => error!(
+                "session #{} failed: peer={}, elapsed={}ms: {:#}",
+                sessions,
+                peer,
+                started.elapsed().as_millis(),
+                err,
+            ),
+        }
+    }
+
+    info!("server daemon: shutdown ({} sessions)", sessions);
+
+    Ok(())
+}
+
+/*
+ * 停止要求のシグナルハンドラ
+ */
+extern "C" fn on_shutdown_signal(_signum: libc::c_int) {
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/*
+ * SIGTERM/SIGINTのシグナルハンドラの登録
+ */
+fn install_signal_handler() -> Result<()> {
+    let handler = on_shutdown_signal as extern "C" fn(libc::c_int);
+
+    for signum in [libc::SIGTERM, libc::SIGINT] {
+        // SAFETY: ハンドラはアトミック変数への書き込みのみを行う
+        let prev = unsafe {
+            libc::signal(signum, handler as libc::sighandler_t)
+        };
+
+        if prev == libc::SIG_ERR {
+            return Err(anyhow!("failed to install signal handler"));
+        }
+    }
+
+    Ok(())
+}
+
+/*
+ * 探索用の告知の開始（TCPで待ち受けている場合のみ）
+ */
+fn start_announcer(
+    listener: &Listener,
+    identity: &LocalIdentity,
+    discovery_port: u16,
+) -> Result<Option<Announcer>> {
+    match listener.tcp_addr()? {
+        Some(addr) => Ok(Some(
+            Announcer::start(identity.node_id(), addr, discovery_port)?
+        )),
+        None => Ok(None),
+    }
+}
+
+/*
+ * 1クライアント分の同期処理
+ */
+pub(super) fn session(
+    mut stream: Box<dyn Transport>,
+    peer: &str,
+    identity: &LocalIdentity,
+    psk: Option<&SyncPsk>,
+    allow: &[String],
+    writer: &mut TransactionWriter,
+) -> Result<SessionSummary> {
     /*
      * Helloの受信と検証
      */
-    let hello = match recv_packet(&mut stream)? {
-        SyncPacket::Hello(h) => h,
-        pkt => return Err(anyhow!("unexpected packet: {:?}", pkt)),
-    };
-    debug!(
-        "recv Hello: proto={}, role={:?}, node={}",
-        hello.protocol_version, hello.role, hello.node_id
-    );
+    let hello = recv_hello(stream.as_mut())?;
 
-    if hello.protocol_version != PROTOCOL_VERSION {
-        send_packet(&mut stream, SyncPacket::hello_ack(
-            PROTOCOL_VERSION,
-            false,
-            Some("protocol version mismatch".into()),
-        ))?;
+    if hello.pairing {
+        reject_hello(stream.as_mut(), "pairing is not accepted")?;
 
-        error!("protocol version mismatch: peer={}", hello.protocol_version);
-        return Err(anyhow!("protocol version mismatch"));
+        error!("pairing request from {} is not accepted", peer);
+        return Err(anyhow!("pairing request is not accepted"));
     }
 
-    if hello.role != NodeRole::Client {
-        send_packet(&mut stream, SyncPacket::hello_ack(
-            PROTOCOL_VERSION,
-            false,
-            Some("role mismatch".into()),
-        ))?;
+    if !allow.is_empty() && !allow.contains(&hello.node_id) {
+        reject_hello(stream.as_mut(), "peer not allowed")?;
 
-        error!("unexpected role from peer: {:?}", hello.role);
-        return Err(anyhow!("unexpected role from peer"));
+        error!("node not in allowlist: peer={}, node={}", peer, hello.node_id);
+        return Err(anyhow!("node not allowed: {}", hello.node_id));
     }
 
+    /*
+     * ペアリング済みの相手か否かの確認
+     */
+    let mut trusted = match writer.get_peer(&hello.node_id)? {
+        Some(trusted) => trusted,
+        None => {
+            reject_hello(stream.as_mut(), "unknown peer")?;
+
+            error!("unpaired node: peer={}, node={}", peer, hello.node_id);
+            return Err(anyhow!("unpaired node: {}", hello.node_id));
+        }
+    };
+
+    /*
+     * ハンドシェイク（ペアリング時に登録した公開鍵でクライアントを認証）
+     */
+    let responder = match Responder::accept(
+        identity,
+        psk,
+        hello.protocol_version,
+        &hello.node_id,
+        &hello.context()?,
+        &hello.handshake,
+    ) {
+        Ok(responder) if responder.remote_static() == trusted.public_key() => {
+            responder
+        }
+
+        _ => {
+            reject_hello(stream.as_mut(), "authentication failed")?;
+
+            error!("handshake failed: peer={}, node={}", peer, hello.node_id);
+            return Err(anyhow!("authentication failed"));
+        }
+    };
+
+    let (reply, established) = responder.reply()?;
+
     /*
      * HelloAckの送信
      */
-    send_packet(&mut stream, SyncPacket::hello_ack(
+    send_plain_packet(stream.as_mut(), SyncPacket::hello_ack(
         PROTOCOL_VERSION,
         true,
-        None
+        None,
+        Some(reply),
     ))?;
     info!("sent HelloAck: accept");
 
+    // 以降のパケットは暗号化通信路で送受信する
+    let mut channel = established.into_channel(stream);
+
+    // ドライランの場合は交換のみ行い、書き込みは全て破棄する
+    if hello.request.dry_run {
+        info!("dry-run requested by {}", hello.node_id);
+        writer.discard();
+    }
+
+    /*
+     * 送信対象の決定（有効な同期位置が示された場合は差分のみ）
+     */
+    let current_seq = writer.change_seq()?;
+    let since = match &hello.request.watermark {
+        Some(mark) if mark.node_id == identity.node_id()
+            && mark.seq <= current_seq => Some(mark.seq),
+
+        Some(mark) => {
+            warn!(
+                "ignore watermark: node={}, seq={} (current {})",
+                mark.node_id, mark.seq, current_seq
+            );
+            None
+        }
+
+        None => None,
+    };
+
+    let ids = match since {
+        Some(seq) => writer.changed_since(seq)?,
+        None => writer.all_service()?,
+    };
+
     /*
-     * エントリ送信フェーズ（全件送信し、エントリごとにACKを受信）
+     * エントリ送信フェーズ（エントリごとにACKを受信）
      */
-    let ids = writer.all_service()?;
-    info!("server send phase start: {} entries", ids.len());
+    info!(
+        "server send phase start: {} entries ({})",
+        ids.len(),
+        if since.is_some() { "incremental" } else { "full" }
+    );
     let mut sent = 0u64;
     for id in ids {
         let entry = writer.get(&id)?
@@ -88,18 +338,22 @@ pub(super) fn run(addr: &str, writer: &mut TransactionWriter) -> Result<()> {
             entry.service()
         );
 
-        send_packet(&mut stream, SyncPacket::server_entry(entry))?;
+        send_packet(&mut channel, SyncPacket::server_entry(entry.clone()))?;
         sent += 1;
 
-        match recv_packet(&mut stream)? {
+        match recv_packet(&mut channel)? {
             SyncPacket::EntryAck(ack) => {
                 if !ack.accepted {
                     let reason = ack.reason.unwrap_or_else(|| "rejected".into());
-                    send_packet(&mut stream, SyncPacket::abort(reason.clone()))?;
+                    send_packet(&mut channel, SyncPacket::abort(reason.clone()))?;
 
                     error!("client rejected entry id={}: {}", ack.entry_id, reason);
                     return Err(anyhow!("client rejected entry: {}", reason));
                 }
+
+                // クライアントが受理した版を次回のマージの基準にする（クライ
+                // アント側の版を保持した場合は受信フェーズで上書きされる）
+                writer.put_ancestor(&entry)?;
             }
 
             SyncPacket::Abort(abort) => {
@@ -112,8 +366,8 @@ pub(super) fn run(addr: &str, writer: &mut TransactionWriter) -> Result<()> {
     }
 
     send_packet(
-        &mut stream,
-        SyncPacket::server_entries_end(sent),
+        &mut channel,
+        SyncPacket::server_entries_end(sent, since.is_some()),
     )?;
     info!("server send phase end: {} entries sent", sent);
 
@@ -123,16 +377,19 @@ pub(super) fn run(addr: &str, writer: &mut TransactionWriter) -> Result<()> {
     info!("server receive phase start");
     let mut received = 0u64;
     loop {
-        match recv_packet(&mut stream)? {
+        match recv_packet(&mut channel)? {
             SyncPacket::ClientEntry(entry) => {
                 debug!(
                     "recv entry from client: id={}, service={}",
                     entry.id(),
                     entry.service()
                 );
-                match writer.put(&entry) {
+                let applied = writer.put_synced(&entry)
+                    .and_then(|_| writer.put_ancestor(&entry));
+
+                match applied {
                     Ok(_) => {
-                        send_packet(&mut stream,  SyncPacket::entry_ack(
+                        send_packet(&mut channel,  SyncPacket::entry_ack(
                             entry.id(),
                             true,
                             None
@@ -145,14 +402,14 @@ pub(super) fn run(addr: &str, writer: &mut TransactionWriter) -> Result<()> {
                     }
 
                     Err(err) => {
-                        send_packet(&mut stream, SyncPacket::entry_ack(
+                        send_packet(&mut channel, SyncPacket::entry_ack(
                             entry.id(),
                             false,
                             Some(err.to_string()),
                         ))?;
 
                         send_packet(
-                            &mut stream,
+                            &mut channel,
                             SyncPacket::abort("failed to apply client entry"),
                         )?;
                         error!(
@@ -189,12 +446,24 @@ pub(super) fn run(addr: &str, writer: &mut TransactionWriter) -> Result<()> {
     /*
      * 正常終了通知
      */
-    send_packet(&mut stream, SyncPacket::finished())?;
+    // クライアントから受け取った分も含めた位置を次回の起点とする
+    let seq = writer.change_seq()?;
+
+    // この位置までの変更はクライアントが受信済み(墓標のgcの判定に用いる)
+    trusted.set_acked_seq(seq);
+    writer.put_peer(&trusted)?;
+
+    send_packet(&mut channel, SyncPacket::finished(seq))?;
     info!("server receive phase end: {} entries received", received);
     info!("server finished sync");
 
     /*
      * 終了
      */
-    Ok(())
+    Ok(SessionSummary {
+        node_id: hello.node_id,
+        sent,
+        received,
+        dry_run: hello.request.dry_run,
+    })
 }
@@ -0,0 +1,454 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! 同期プロトコルを流す下位の通信路
+//!
+//! 同期の状態遷移(`client`/`server`/`pair`)は本モジュールの`Transport`のみ
+//! に依存し、TCP・Unixドメインソケット・標準入出力・SSH等の通信路の違いを意
+//! 識しない。
+//!
+
+use std::io::{self, Read, Stdin, Stdout, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+
+/// Unixドメインソケットを指定するアドレスの接頭辞
+pub(crate) const UNIX_PREFIX: &str = "unix:";
+
+/// SSH経由の通信路の表記の接頭辞
+const SSH_PREFIX: &str = "ssh:";
+
+/// SSHのクライアントプログラム
+const SSH_PROGRAM: &str = "ssh";
+
+/// SSH経由の同期でリモートホストで実行するコマンドのデフォルト値
+pub(crate) const DEFAULT_REMOTE_COMMAND: &str = "pwmgr sync --stdio";
+
+///
+/// 同期プロトコルを流す双方向のバイトストリーム
+///
+pub(crate) trait Transport: Read + Write + Send {
+    ///
+    /// ログ出力等に用いる相手の表記
+    ///
+    fn peer_label(&self) -> String;
+
+    ///
+    /// 送受信のタイムアウトの設定
+    ///
+    /// # 注記
+    /// タイムアウトを設定できない通信路では何もしない。
+    ///
+    fn set_io_timeout(&self, _timeout: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+}
+
+// Transportトレイトの実装
+impl Transport for TcpStream {
+    fn peer_label(&self) -> String {
+        self.peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|_| "tcp".to_string())
+    }
+
+    fn set_io_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        self.set_read_timeout(timeout)?;
+        self.set_write_timeout(timeout)?;
+        Ok(())
+    }
+}
+
+// Transportトレイトの実装
+#[cfg(unix)]
+impl Transport for UnixStream {
+    fn peer_label(&self) -> String {
+        // 受け付け側では相手のソケットが名前を持たないため固定の表記とする
+        self.peer_addr()
+            .ok()
+            .and_then(|addr| {
+                addr.as_pathname()
+                    .map(|path| format!("{}{}", UNIX_PREFIX, path.display()))
+            })
+            .unwrap_or_else(|| "unix".to_string())
+    }
+
+    fn set_io_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        self.set_read_timeout(timeout)?;
+        self.set_write_timeout(timeout)?;
+        Ok(())
+    }
+}
+
+///
+/// 標準入出力による通信路
+///
+pub(crate) struct StdioTransport {
+    /// 受信に用いる標準入力
+    stdin: Stdin,
+
+    /// 送信に用いる標準出力
+    stdout: Stdout,
+}
+
+impl StdioTransport {
+    ///
+    /// オブジェクトの生成
+    ///
+    /// # 注記
+    /// 標準出力はプロトコルの送信に用いるため、利用中は他の出力を行わないこ
+    /// と。
+    ///
+    pub(crate) fn new() -> Self {
+        Self {
+            stdin: io::stdin(),
+            stdout: io::stdout(),
+        }
+    }
+}
+
+// Readトレイトの実装
+impl Read for StdioTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdin.read(buf)
+    }
+}
+
+// Writeトレイトの実装
+impl Write for StdioTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+// Transportトレイトの実装
+impl Transport for StdioTransport {
+    fn peer_label(&self) -> String {
+        "stdio".to_string()
+    }
+}
+
+///
+/// SSH経由の通信路
+///
+/// # 注記
+/// リモートホストで`pwmgr sync --stdio`を実行する`ssh`を子プロセスとして起動
+/// し、その標準入出力を通信路とする。認証と暗号化はSSHにも任せられるが、同
+/// 期プロトコル自体のハンドシェイクは通常通り行う。
+///
+pub(crate) struct SshTransport {
+    /// 接続先(`[USER@]HOST`)
+    destination: String,
+
+    /// sshの子プロセス
+    child: Child,
+
+    /// 送信に用いる子プロセスの標準入力(破棄時に先に閉じる)
+    stdin: Option<ChildStdin>,
+
+    /// 受信に用いる子プロセスの標準出力
+    stdout: ChildStdout,
+}
+
+impl SshTransport {
+    ///
+    /// sshの起動
+    ///
+    /// # 引数
+    /// * `destination` - 接続先(`[USER@]HOST`)
+    /// * `remote_command` - リモートホストで実行するコマンド
+    ///
+    /// # 戻り値
+    /// 起動したsshの標準入出力による通信路を`Ok()`でラップして返す。
+    ///
+    /// # 注記
+    /// sshの標準エラー出力は引き継ぐため、SSHの認証の問い合わせやリモート側
+    /// のエラーはそのまま端末に表示される。
+    ///
+    pub(crate) fn spawn(destination: &str, remote_command: &str)
+        -> Result<Self>
+    {
+        let mut child = Command::new(SSH_PROGRAM)
+            .arg("-T")
+            .arg(destination)
+            .arg(remote_command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("spawn {} {}", SSH_PROGRAM, destination))?;
+
+        let stdin = child.stdin.take()
+            .ok_or_else(|| anyhow!("ssh stdin is not available"))?;
+        let stdout = child.stdout.take()
+            .ok_or_else(|| anyhow!("ssh stdout is not available"))?;
+
+        Ok(Self {
+            destination: destination.to_string(),
+            child,
+            stdin: Some(stdin),
+            stdout,
+        })
+    }
+}
+
+// Readトレイトの実装
+impl Read for SshTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+// Writeトレイトの実装
+impl Write for SshTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.stdin.as_mut() {
+            Some(stdin) => stdin.write(buf),
+            None => Err(io::Error::from(io::ErrorKind::BrokenPipe)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.stdin.as_mut() {
+            Some(stdin) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// Transportトレイトの実装
+impl Transport for SshTransport {
+    fn peer_label(&self) -> String {
+        format!("{}{}", SSH_PREFIX, self.destination)
+    }
+}
+
+// Dropトレイトの実装
+impl Drop for SshTransport {
+    fn drop(&mut self) {
+        // 標準入力を閉じてリモート側に終端を通知してから終了を待つ
+        drop(self.stdin.take());
+
+        match self.child.wait() {
+            Ok(status) if !status.success() => {
+                warn!("ssh {} exited with {}", self.destination, status);
+            }
+            Ok(_) => {}
+            Err(err) => warn!("ssh {} wait failed: {}", self.destination, err),
+        }
+    }
+}
+
+///
+/// 同期の待ち受け
+///
+pub(crate) enum Listener {
+    /// TCPでの待ち受け
+    Tcp(TcpListener),
+
+    /// Unixドメインソケットでの待ち受け
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    ///
+    /// 待ち受けの開始
+    ///
+    /// # 引数
+    /// * `addr` - 待ち受けアドレス(`unix:PATH`の場合はUnixドメインソケット)
+    ///
+    pub(crate) fn bind(addr: &str) -> Result<Self> {
+        #[cfg(unix)]
+        if let Some(path) = addr.strip_prefix(UNIX_PREFIX) {
+            let listener = UnixListener::bind(path)
+                .with_context(|| format!("bind {}", addr))?;
+            return Ok(Self::Unix(listener, PathBuf::from(path)));
+        }
+
+        Ok(Self::Tcp(
+            TcpListener::bind(addr).with_context(|| format!("bind {}", addr))?
+        ))
+    }
+
+    ///
+    /// TCPで待ち受けている場合の待ち受けアドレス
+    ///
+    /// # 注記
+    /// 探索用の告知はTCPで待ち受けている場合のみ行う。
+    ///
+    pub(crate) fn tcp_addr(&self) -> Result<Option<SocketAddr>> {
+        match self {
+            Self::Tcp(listener) => Ok(Some(listener.local_addr()?)),
+            #[cfg(unix)]
+            Self::Unix(..) => Ok(None),
+        }
+    }
+
+    ///
+    /// ノンブロッキングモードの設定
+    ///
+    pub(crate) fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        match self {
+            Self::Tcp(listener) => listener.set_nonblocking(nonblocking)?,
+            #[cfg(unix)]
+            Self::Unix(listener, _) => listener.set_nonblocking(nonblocking)?,
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// 接続の受け付け
+    ///
+    /// # 戻り値
+    /// 受け付けた通信路と相手の表記を`Ok()`でラップして返す。ノンブロッキン
+    /// グモードで接続が無い場合は`ErrorKind::WouldBlock`のエラーを返す。
+    ///
+    pub(crate) fn accept(&self) -> io::Result<(Box<dyn Transport>, String)> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, peer) = listener.accept()?;
+
+                // 待ち受けソケットのノンブロッキング設定を引き継ぐ環境がある
+                // ため戻す
+                stream.set_nonblocking(false)?;
+                Ok((Box::new(stream), peer.to_string()))
+            }
+
+            #[cfg(unix)]
+            Self::Unix(listener, path) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nonblocking(false)?;
+                Ok((
+                    Box::new(stream),
+                    format!("{}{}", UNIX_PREFIX, path.display()),
+                ))
+            }
+        }
+    }
+}
+
+// Dropトレイトの実装
+impl Drop for Listener {
+    fn drop(&mut self) {
+        // Unixドメインソケットのファイルは待ち受けの終了時に削除する
+        #[cfg(unix)]
+        if let Self::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+///
+/// 同期相手への接続
+///
+/// # 引数
+/// * `addr` - 接続先アドレス(`unix:PATH`の場合はUnixドメインソケット)
+///
+/// # 戻り値
+/// 接続した通信路を`Ok()`でラップして返す。
+///
+pub(crate) fn connect(addr: &str) -> Result<Box<dyn Transport>> {
+    #[cfg(unix)]
+    if let Some(path) = addr.strip_prefix(UNIX_PREFIX) {
+        return Ok(Box::new(
+            UnixStream::connect(path)
+                .with_context(|| format!("connect {}", addr))?
+        ));
+    }
+
+    if addr.starts_with(UNIX_PREFIX) {
+        return Err(anyhow!("unix domain socket is not supported: {}", addr));
+    }
+
+    Ok(Box::new(
+        TcpStream::connect(addr).with_context(|| format!("connect {}", addr))?
+    ))
+}
+
+///
+/// メモリ上の通信路(テスト用)
+///
+#[cfg(test)]
+pub(crate) struct MemoryPipe {
+    /// 送信側のキュー
+    tx: std::sync::mpsc::Sender<Vec<u8>>,
+
+    /// 受信側のキュー
+    rx: std::sync::mpsc::Receiver<Vec<u8>>,
+
+    /// 受信済みで未読のデータ
+    pending: std::collections::VecDeque<u8>,
+}
+
+#[cfg(test)]
+impl MemoryPipe {
+    ///
+    /// 互いに接続された通信路の組の生成
+    ///
+    pub(crate) fn pair() -> (Self, Self) {
+        let (tx_a, rx_b) = std::sync::mpsc::channel();
+        let (tx_b, rx_a) = std::sync::mpsc::channel();
+
+        (
+            Self {tx: tx_a, rx: rx_a, pending: Default::default()},
+            Self {tx: tx_b, rx: rx_b, pending: Default::default()},
+        )
+    }
+}
+
+// Readトレイトの実装
+#[cfg(test)]
+impl Read for MemoryPipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.rx.recv() {
+                Ok(data) => self.pending.extend(data),
+
+                // 相手側が破棄された場合は終端とする
+                Err(_) => return Ok(0),
+            }
+        }
+
+        self.pending.read(buf)
+    }
+}
+
+// Writeトレイトの実装
+#[cfg(test)]
+impl Write for MemoryPipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx.send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Transportトレイトの実装
+#[cfg(test)]
+impl Transport for MemoryPipe {
+    fn peer_label(&self) -> String {
+        "memory".to_string()
+    }
+}
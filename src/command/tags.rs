@@ -38,7 +38,7 @@ impl TagsCommandContext {
     ///
     fn new(opts: &Options, sub_opts: &TagsOpts) -> Result<Self> {
         Ok(Self {
-            manager: RefCell::new(opts.open()?),
+            manager: RefCell::new(opts.open_locked()?),
             opts: sub_opts.clone(),
             json_output: opts.json(),
         })
@@ -0,0 +1,295 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! totpサブコマンドの実装
+//!
+
+use std::cell::RefCell;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use log::info;
+use serde::Serialize;
+
+use crate::cmd_args::{Options, TotpOpts};
+use crate::database::types::{Entry, ServiceId};
+use crate::database::EntryManager;
+use super::otp::{set_uri_counter, OtpKind, OtpSecret};
+use super::{matcher::Matcher, CommandContext};
+
+/// プロパティ名が指定されなかった場合に共有鍵を探すプロパティ名
+const DEFAULT_PROPERTIES: &[&str] = &["totp!", "otp!", "totp", "otp"];
+
+///
+/// 生成したコードの出力内容
+///
+#[derive(Debug, Serialize)]
+struct OtpCode {
+    /// サービスID
+    id: String,
+
+    /// サービス名
+    service: String,
+
+    /// 共有鍵を格納したプロパティ名
+    property: String,
+
+    /// 種別(`totp`/`hotp`)
+    #[serde(rename = "type")]
+    kind: &'static str,
+
+    /// コード
+    code: String,
+
+    /// コードが有効な残り秒数(TOTPのみ)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remaining: Option<u64>,
+
+    /// コードの生成に用いたカウンタ値(HOTPのみ)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    counter: Option<u64>,
+}
+
+///
+/// totpサブコマンドのコンテキスト情報をパックした構造体
+///
+struct TotpCommandContext {
+    /// データベースオブジェクト
+    manager: RefCell<EntryManager>,
+
+    /// サブコマンドオプション
+    opts: TotpOpts,
+
+    /// JSONで出力するか否か
+    json_output: bool,
+}
+
+impl TotpCommandContext {
+    ///
+    /// オブジェクトの生成
+    ///
+    fn new(opts: &Options, sub_opts: &TotpOpts) -> Result<Self> {
+        Ok(Self {
+            manager: RefCell::new(opts.open()?),
+            opts: sub_opts.clone(),
+            json_output: opts.json(),
+        })
+    }
+
+    ///
+    /// キーに該当するエントリの特定
+    ///
+    /// # 注記
+    /// queryサブコマンドと同様に、IDとして解釈できる場合はID検索を行い、
+    /// 見つからない場合はサービス名/別名をマッチャで照合する。該当するエント
+    /// リが1件に定まらない場合はエラーとする。
+    ///
+    fn resolve(&self) -> Result<Entry> {
+        let key = self.opts.key();
+        let mut mgr = self.manager.borrow_mut();
+
+        let by_id = match ServiceId::from_string(&key) {
+            Ok(id) => mgr.get(&id)?.filter(|entry| !entry.is_removed()),
+            Err(_) => None,
+        };
+
+        if let Some(entry) = by_id {
+            return Ok(entry);
+        }
+
+        let matcher = Matcher::new(self.opts.match_mode(), key)?;
+        let mut hits = Vec::new();
+
+        for id in mgr.all_service()? {
+            let entry = match mgr.get(&id)? {
+                Some(entry) if !entry.is_removed() => entry,
+                _ => continue,
+            };
+
+            let service_hit = matcher.is_match(&entry.service())?;
+            let alias_hit = entry.aliases()
+                .iter()
+                .any(|alias| matcher.is_match(alias).unwrap_or(false));
+
+            if service_hit || alias_hit {
+                hits.push(entry);
+            }
+        }
+
+        match hits.len() {
+            0 => Err(anyhow!("該当するエントリが見つかりませんでした")),
+            1 => Ok(hits.remove(0)),
+            _ => Err(anyhow!(
+                "複数のエントリが該当しました:\n{}",
+                hits.iter()
+                    .map(|entry| format!("  {} {}", entry.id(), entry.service()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )),
+        }
+    }
+
+    ///
+    /// 共有鍵を格納したプロパティの選択
+    ///
+    /// # 戻り値
+    /// プロパティ名と値の組を`Ok()`でラップして返す。
+    ///
+    fn select_property(&self, entry: &Entry) -> Result<(String, String)> {
+        let properties = entry.properties();
+
+        let key = match self.opts.property() {
+            Some(key) => key,
+            None => DEFAULT_PROPERTIES.iter()
+                .find(|key| properties.contains_key(**key))
+                .map(|key| key.to_string())
+                .ok_or_else(|| anyhow!(
+                    "共有鍵のプロパティが見つかりません \
+                     (--propertyで指定してください)"
+                ))?,
+        };
+
+        let value = properties.get(&key)
+            .cloned()
+            .ok_or_else(|| anyhow!("プロパティが見つかりません: {}", key))?;
+
+        Ok((key, value))
+    }
+}
+
+// CommandContextトレイトの実装
+impl CommandContext for TotpCommandContext {
+    fn exec(&self) -> Result<()> {
+        let mut entry = self.resolve()?;
+        let (property, value) = self.select_property(&entry)?;
+        let secret = OtpSecret::parse(&value)
+            .with_context(|| format!("{}の解釈に失敗しました", property))?;
+
+        let mut output = OtpCode {
+            id: entry.id().to_string(),
+            service: entry.service(),
+            property: property.clone(),
+            kind: "totp",
+            code: String::new(),
+            remaining: None,
+            counter: None,
+        };
+
+        match secret.kind() {
+            OtpKind::Totp {..} => {
+                let now = Utc::now().timestamp() as u64;
+                let (code, remaining) = secret.totp(now);
+                output.code = code;
+                output.remaining = Some(remaining);
+            }
+
+            OtpKind::Hotp {counter} => {
+                // 同じコードを再び生成しないよう、使用したカウンタを進めて
+                // エントリに書き戻す。revert/undoで使用済みのカウンタに戻ら
+                // ないよう、過去の版と操作ジャーナルには記録しない
+                output.kind = "hotp";
+                output.code = secret.hotp(counter);
+                output.counter = Some(counter);
+
+                let next = set_uri_counter(&value, counter + 1);
+                entry.set_property(&property, next);
+                entry.set_last_update_now();
+                self.manager
+                    .borrow()
+                    .with_write_transaction(|writer| writer.put_untracked(&entry))?;
+                info!("totp: id={}, hotp counter={}", entry.id(), counter + 1);
+            }
+        }
+
+        if self.json_output {
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        } else if let Some(remaining) = output.remaining {
+            println!("{} ({}s remaining)", output.code, remaining);
+        } else if let Some(counter) = output.counter {
+            println!("{} (counter {})", output.code, counter);
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// コマンドコンテキストの生成
+///
+pub(crate) fn build_context(opts: &Options, sub_opts: &TotpOpts)
+    -> Result<Box<dyn CommandContext>>
+{
+    Ok(Box::new(TotpCommandContext::new(opts, sub_opts)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use ulid::Ulid;
+
+    use crate::cmd_args::MatchMode;
+    use crate::database::TransactionReadable;
+
+    fn temp_db_path() -> PathBuf {
+        std::env::temp_dir().join(format!("pwmgr-test-{}.redb", Ulid::new()))
+    }
+
+    ///
+    /// HOTPのコードを生成する度にカウンタが進み、エントリに保存されること
+    /// を確認
+    ///
+    #[test]
+    fn hotp_counter_is_persisted() {
+        let mut mgr = EntryManager::open_for_test(temp_db_path()).unwrap();
+        let id = ServiceId::new();
+        let uri = "otpauth://hotp/Example?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+                   &counter=0";
+
+        mgr.put(&Entry::new(
+            id.clone(),
+            "Example".to_string(),
+            vec![],
+            vec![],
+            BTreeMap::from([("totp!".to_string(), uri.to_string())]),
+        )).unwrap();
+
+        let ctx = TotpCommandContext {
+            manager: RefCell::new(mgr),
+            opts: TotpOpts::new_for_test(MatchMode::Exact, "example", None),
+            json_output: false,
+        };
+
+        ctx.exec().unwrap();
+        ctx.exec().unwrap();
+
+        let entry = ctx.manager.borrow_mut().get(&id).unwrap().unwrap();
+        assert_eq!(
+            entry.properties().get("totp!"),
+            Some(&uri.replace("counter=0", "counter=2"))
+        );
+
+        // カウンタの更新は過去の版として記録されない
+        let revisions = ctx.manager
+            .borrow()
+            .with_read_transaction(|reader| reader.revisions(&id))
+            .unwrap();
+        assert!(revisions.is_empty());
+
+        // 指定したプロパティが無い場合はエラー
+        let ctx = TotpCommandContext {
+            opts: TotpOpts::new_for_test(
+                MatchMode::Exact,
+                "example",
+                Some("otp!".to_string())
+            ),
+            ..ctx
+        };
+        assert!(ctx.exec().is_err());
+    }
+}
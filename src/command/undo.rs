@@ -0,0 +1,260 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! undoサブコマンドの実装
+//!
+
+use std::cell::RefCell;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use log::{debug, info};
+use serde::Serialize;
+
+use crate::cmd_args::{Options, UndoOpts};
+use crate::database::types::JournalRecord;
+use crate::database::{EntryManager, TransactionReadable, TransactionWriter};
+use super::CommandContext;
+
+///
+/// 操作の一覧の要素
+///
+#[derive(Debug, Serialize)]
+struct OperationInfo {
+    /// 記録番号
+    seq: u64,
+
+    /// 操作名
+    operation: String,
+
+    /// 操作日時
+    timestamp: DateTime<Local>,
+
+    /// 変更されたエントリの数
+    entries: usize,
+}
+
+impl OperationInfo {
+    ///
+    /// オブジェクトの生成
+    ///
+    fn new(seq: u64, record: &JournalRecord) -> Self {
+        Self {
+            seq,
+            operation: record.operation(),
+            timestamp: record.timestamp(),
+            entries: record.entries().len(),
+        }
+    }
+}
+
+///
+/// undoサブコマンドのコンテキスト情報をパックした構造体
+///
+struct UndoCommandContext {
+    /// データベースオブジェクト
+    manager: RefCell<EntryManager>,
+
+    /// 一覧表示か否か
+    list: bool,
+
+    /// JSONで出力するか否か
+    json_output: bool,
+}
+
+impl UndoCommandContext {
+    ///
+    /// オブジェクトの生成
+    ///
+    fn new(opts: &Options, sub_opts: &UndoOpts) -> Result<Self> {
+        Ok(Self {
+            manager: RefCell::new(opts.open_locked()?),
+            list: sub_opts.is_list(),
+            json_output: opts.json(),
+        })
+    }
+
+    ///
+    /// 取り消し可能な操作の一覧の表示(新しい順)
+    ///
+    fn print_list(&self) -> Result<()> {
+        let list = self.manager.borrow().with_read_transaction(|reader| {
+            Ok(reader.journal()?
+                .iter()
+                .rev()
+                .map(|(seq, record)| OperationInfo::new(*seq, record))
+                .collect::<Vec<_>>())
+        })?;
+
+        if self.json_output {
+            println!("{}", serde_json::to_string_pretty(&list)?);
+            return Ok(());
+        }
+
+        for info in list {
+            println!(
+                "{}\t{}\t{}\t{} entries",
+                info.seq,
+                info.timestamp.to_rfc3339(),
+                info.operation,
+                info.entries
+            );
+        }
+
+        Ok(())
+    }
+}
+
+// CommandContextトレイトの実装
+impl CommandContext for UndoCommandContext {
+    fn exec(&self) -> Result<()> {
+        if self.list {
+            return self.print_list();
+        }
+
+        let record = self.manager.borrow().with_write_transaction(|writer| {
+            let record = writer.pop_journal()?
+                .ok_or_else(|| anyhow!("取り消せる操作がありません"))?;
+
+            undo_record(writer, &record)?;
+            Ok(record)
+        })?;
+
+        println!(
+            "undone: {} ({}, {} entries)",
+            record.operation(),
+            record.timestamp().to_rfc3339(),
+            record.entries().len()
+        );
+        info!(
+            "undo: operation={}, entries={}",
+            record.operation(),
+            record.entries().len()
+        );
+
+        Ok(())
+    }
+}
+
+///
+/// 操作ジャーナルの記録に従ったエントリの復元
+///
+/// # 引数
+/// * `writer` - 書き込みトランザクション
+/// * `record` - 取り消す操作の記録
+///
+/// # 戻り値
+/// 処理に成功した場合は`Ok(())`を返す。
+///
+/// # 注記
+/// 変更前の状態をローカルでの変更として書き込むため、取り消しは同期相手にも
+/// 変更として伝播する。操作で新たに作られたエントリは`remove --hard`と同様
+/// に、同期相手が居る場合は墓標として残す。秘匿プロパティは暗号化されたまま
+/// 書き戻すため、解錠は不要。
+///
+fn undo_record(writer: &mut TransactionWriter, record: &JournalRecord)
+    -> Result<()>
+{
+    let has_peers = !writer.all_peers()?.is_empty();
+
+    for (id, before) in record.entries() {
+        match before {
+            Some(entry) => {
+                debug!("undo: restore id={}", id);
+                let mut entry = entry.clone();
+                entry.set_last_update_now();
+                writer.put(&entry)?;
+            }
+
+            None if has_peers => {
+                debug!("undo: bury id={}", id);
+                writer.bury(id)?;
+            }
+
+            None => {
+                debug!("undo: remove id={}", id);
+                writer.remove(id)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+///
+/// コマンドコンテキストの生成
+///
+pub(crate) fn build_context(opts: &Options, sub_opts: &UndoOpts)
+    -> Result<Box<dyn CommandContext>>
+{
+    Ok(Box::new(UndoCommandContext::new(opts, sub_opts)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use ulid::Ulid;
+
+    use crate::database::types::{Entry, ServiceId};
+
+    fn temp_db_path() -> PathBuf {
+        std::env::temp_dir().join(format!("pwmgr-test-{}.redb", Ulid::new()))
+    }
+
+    fn make_entry(id: &ServiceId, service: &str) -> Entry {
+        Entry::new(
+            id.clone(),
+            service.to_string(),
+            vec![],
+            vec!["tag".to_string()],
+            BTreeMap::from([
+                ("password!".to_string(), format!("{}-secret", service)),
+            ]),
+        )
+    }
+
+    ///
+    /// 置換モードのインポート相当の操作を取り消すと、削除されたエントリが復
+    /// 元され追加されたエントリが取り除かれることを確認
+    ///
+    #[test]
+    fn undo_restores_replaced_entries() {
+        let mut mgr = EntryManager::open_for_test(temp_db_path()).unwrap();
+        let old = ServiceId::new();
+        let new = ServiceId::new();
+
+        mgr.put(&make_entry(&old, "old")).unwrap();
+
+        mgr.set_operation("import");
+        mgr.with_write_transaction(|writer| {
+            writer.remove(&old)?;
+            writer.put(&make_entry(&new, "new"))
+        }).unwrap();
+
+        mgr.with_write_transaction(|writer| {
+            let record = writer.pop_journal()?.unwrap();
+            assert_eq!(record.operation(), "import");
+            undo_record(writer, &record)
+        }).unwrap();
+
+        mgr.with_read_transaction(|reader| {
+            let restored = reader.get(&old)?.unwrap();
+            assert_eq!(
+                restored.properties().get("password!"),
+                Some(&"old-secret".to_string())
+            );
+            assert_eq!(reader.tagged_services("tag")?, vec![old.clone()]);
+            assert!(reader.get(&new)?.is_none());
+
+            // 取り消し自体は記録されない
+            assert!(reader.journal()?.is_empty());
+            Ok(())
+        }).unwrap();
+    }
+}
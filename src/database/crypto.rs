@@ -0,0 +1,270 @@
+/*
+ * Password manager
+ *
+ *  Copyright (C) 2025 Hiroshi KUWAGATA
+ */
+
+//!
+//! データベースの暗号化処理をまとめたモジュール
+//!
+
+use anyhow::{anyhow, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use zeroize::Zeroizing;
+
+use crate::database::types::{KdfParams, VaultHeader};
+
+/// 導出する鍵の長さ(バイト)
+pub(crate) const KEY_LEN: usize = 32;
+
+/// ソルトの長さ(バイト)
+const SALT_LEN: usize = 16;
+
+/// ノンスの長さ(バイト)
+const NONCE_LEN: usize = 24;
+
+/// パスフレーズ検証用に暗号化して保存する既知の平文
+const VERIFIER_PLAIN: &[u8] = b"pwmgr vault verifier";
+
+///
+/// パスフレーズから導出した暗号鍵
+///
+/// # 注記
+/// 破棄時に鍵の内容はゼロクリアされる。
+///
+#[derive(Clone)]
+pub(crate) struct VaultKey(Zeroizing<[u8; KEY_LEN]>);
+
+impl VaultKey {
+    ///
+    /// パスフレーズからの鍵の導出
+    ///
+    /// # 引数
+    /// * `passphrase` - マスタパスフレーズ
+    /// * `salt` - ソルト
+    /// * `params` - 鍵導出パラメータ
+    ///
+    /// # 戻り値
+    /// 導出に成功した場合は鍵オブジェクトを`Ok()`でラップして返す。
+    ///
+    pub(crate) fn derive(passphrase: &str, salt: &[u8], params: &KdfParams)
+        -> Result<Self>
+    {
+        let params = Params::new(
+            params.m_cost(),
+            params.t_cost(),
+            params.p_cost(),
+            Some(KEY_LEN),
+        ).map_err(|err| anyhow!("invalid kdf parameters: {}", err))?;
+
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+            .map_err(|err| anyhow!("key derivation failed: {}", err))?;
+
+        Ok(Self(key))
+    }
+
+    ///
+    /// バイト列からの鍵の復元
+    ///
+    /// # 引数
+    /// * `bytes` - `as_bytes()`で取り出した鍵のバイト列
+    ///
+    /// # 戻り値
+    /// 長さが正しい場合は鍵オブジェクトを`Ok()`でラップして返す。
+    ///
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != KEY_LEN {
+            return Err(anyhow!("invalid key length: {}", bytes.len()));
+        }
+
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        key.copy_from_slice(bytes);
+
+        Ok(Self(key))
+    }
+
+    ///
+    /// 鍵のバイト列へのアクセサ
+    ///
+    /// # 注記
+    /// 鍵をエージェントとの間で受け渡す場合にのみ用いること。
+    ///
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+
+    ///
+    /// データの暗号化
+    ///
+    /// # 引数
+    /// * `plain` - 暗号化するデータ
+    /// * `aad` - 暗号文を結び付ける付加データ(復号時にも同じ値が必要)
+    ///
+    /// # 戻り値
+    /// ランダムに生成したノンスを先頭に付与した暗号文を`Ok()`でラップして返
+    /// す。
+    ///
+    pub(crate) fn seal(&self, plain: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher()
+            .encrypt(&nonce, Payload {msg: plain, aad})
+            .map_err(|_| anyhow!("encryption failed"))?;
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+
+        Ok(sealed)
+    }
+
+    ///
+    /// データの復号
+    ///
+    /// # 引数
+    /// * `sealed` - `seal()`で生成した暗号文
+    /// * `aad` - 暗号化時に指定した付加データ
+    ///
+    /// # 戻り値
+    /// 復号に成功した場合は平文を`Ok()`でラップして返す。鍵や付加データが一致
+    /// しない場合やデータが改竄されている場合はエラーを返す。
+    ///
+    pub(crate) fn open(&self, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow!("sealed data is too short"));
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher()
+            .decrypt(XNonce::from_slice(nonce), Payload {msg: ciphertext, aad})
+            .map_err(|_| anyhow!("decryption failed"))
+    }
+
+    ///
+    /// 暗号器の生成
+    ///
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(self.0.as_ref().into())
+    }
+}
+
+///
+/// 新規金庫ヘッダの生成
+///
+/// # 引数
+/// * `passphrase` - マスタパスフレーズ
+/// * `params` - 鍵導出パラメータ
+///
+/// # 戻り値
+/// 生成したヘッダと導出した鍵を`Ok()`でラップして返す。
+///
+pub(crate) fn create_header(passphrase: &str, params: KdfParams)
+    -> Result<(VaultHeader, VaultKey)>
+{
+    let mut salt = vec![0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+
+    let key = VaultKey::derive(passphrase, &salt, &params)?;
+    let verifier = key.seal(VERIFIER_PLAIN, &[])?;
+
+    Ok((VaultHeader::new(params, salt, verifier), key))
+}
+
+///
+/// 金庫ヘッダに対するパスフレーズの検証と鍵の導出
+///
+/// # 引数
+/// * `header` - データベースに保存されている金庫ヘッダ
+/// * `passphrase` - マスタパスフレーズ
+///
+/// # 戻り値
+/// パスフレーズが正しい場合は導出した鍵を`Ok()`でラップして返す。誤っている
+/// 場合はエラーを返す。
+///
+pub(crate) fn unlock_header(header: &VaultHeader, passphrase: &str)
+    -> Result<VaultKey>
+{
+    let key = VaultKey::derive(passphrase, header.salt(), header.kdf())?;
+
+    if !verify_key(header, &key) {
+        return Err(anyhow!("パスフレーズが正しくありません"));
+    }
+
+    Ok(key)
+}
+
+///
+/// 鍵が金庫ヘッダに対応するものか否かの検証
+///
+/// # 引数
+/// * `header` - データベースに保存されている金庫ヘッダ
+/// * `key` - 検証する鍵
+///
+/// # 戻り値
+/// ヘッダの検証用暗号文を復号できた場合は`true`を返す。
+///
+pub(crate) fn verify_key(header: &VaultHeader, key: &VaultKey) -> bool {
+    matches!(key.open(header.verifier(), &[]), Ok(plain) if plain == VERIFIER_PLAIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///
+    /// 暗号化→復号で元のデータに戻ることを確認
+    ///
+    #[test]
+    fn seal_open_roundtrip() {
+        let (_, key) = create_header("pass", KdfParams::for_test()).unwrap();
+        let sealed = key.seal(b"secret", b"id").unwrap();
+
+        assert_ne!(&sealed[NONCE_LEN..], b"secret");
+        assert_eq!(key.open(&sealed, b"id").unwrap(), b"secret".to_vec());
+    }
+
+    ///
+    /// 付加データが一致しない場合に復号が失敗することを確認
+    ///
+    #[test]
+    fn open_rejects_wrong_aad() {
+        let (_, key) = create_header("pass", KdfParams::for_test()).unwrap();
+        let sealed = key.seal(b"secret", b"id-a").unwrap();
+
+        assert!(key.open(&sealed, b"id-b").is_err());
+        assert!(key.open(&sealed, &[]).is_err());
+    }
+
+    ///
+    /// 正しいパスフレーズでのみヘッダを解錠できることを確認
+    ///
+    #[test]
+    fn unlock_header_checks_passphrase() {
+        let (header, key) = create_header("pass", KdfParams::for_test())
+            .unwrap();
+        let sealed = key.seal(b"secret", &[]).unwrap();
+
+        let unlocked = unlock_header(&header, "pass").unwrap();
+        assert_eq!(unlocked.open(&sealed, &[]).unwrap(), b"secret".to_vec());
+
+        assert!(unlock_header(&header, "wrong").is_err());
+    }
+
+    ///
+    /// 改竄された暗号文の復号が失敗することを確認
+    ///
+    #[test]
+    fn open_rejects_tampered_data() {
+        let (_, key) = create_header("pass", KdfParams::for_test()).unwrap();
+        let mut sealed = key.seal(b"secret", &[]).unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 0xff;
+
+        assert!(key.open(&sealed, &[]).is_err());
+        assert!(key.open(&sealed[..4], &[]).is_err());
+    }
+}
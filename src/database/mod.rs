@@ -8,20 +8,28 @@
 //! データベース関連処理をまとめたモジュール
 //!
 
+pub(crate) mod crypto;
 pub(crate) mod types;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::marker::PhantomData;
+use std::ops::RangeInclusive;
 use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use redb::{
     Database, MultimapTableDefinition, Range, ReadTransaction, ReadableDatabase,
     ReadableTable, ReadableMultimapTable, StorageError, TableDefinition,
     WriteTransaction
 };
+use ulid::Ulid;
+use zeroize::Zeroizing;
 
-use crate::database::types::{Entry, ServiceId};
+use crate::database::crypto::VaultKey;
+use crate::database::types::{
+    Entry, JournalRecord, KdfParams, NodeIdentity, ServiceId, TrustedPeer,
+    VaultHeader
+};
 
 /// エントリ登録テーブル
 static ENTRIES_TABLE: TableDefinition<ServiceId, Entry> =
@@ -31,6 +39,85 @@ static ENTRIES_TABLE: TableDefinition<ServiceId, Entry> =
 static TAGS_TABLE: MultimapTableDefinition<String, ServiceId> =
     MultimapTableDefinition::new("tags");
 
+/// ヘッダ情報テーブル
+static HEADER_TABLE: TableDefinition<&str, VaultHeader> =
+    TableDefinition::new("header");
+
+/// ヘッダ情報テーブルにおける金庫ヘッダのキー
+const VAULT_HEADER_KEY: &str = "vault";
+
+/// 自ノード情報テーブル
+static NODE_TABLE: TableDefinition<&str, NodeIdentity> =
+    TableDefinition::new("node");
+
+/// 自ノード情報テーブルにおける識別情報のキー
+const NODE_IDENTITY_KEY: &str = "self";
+
+/// ペアリング済み同期相手テーブル
+static PEERS_TABLE: TableDefinition<&str, TrustedPeer> =
+    TableDefinition::new("peers");
+
+/// エントリ毎の最終変更シーケンス番号を記録するテーブル
+static CHANGES_TABLE: TableDefinition<ServiceId, u64> =
+    TableDefinition::new("changes");
+
+/// 同期相手と最後に合意したエントリの版(3方向マージの共通祖先)のテーブル
+static ANCESTORS_TABLE: TableDefinition<ServiceId, Entry> =
+    TableDefinition::new("ancestors");
+
+/// 上書きされたエントリの過去の版のテーブル(キーはサービスIDと版番号)
+static REVISIONS_TABLE: TableDefinition<(ServiceId, u64), Entry> =
+    TableDefinition::new("revisions");
+
+/// エントリ毎に保持する過去の版の最大数
+const REVISION_LIMIT: u64 = 20;
+
+/// 書き込みトランザクション毎の変更前の状態を記録する操作ジャーナルのテーブル
+static JOURNAL_TABLE: TableDefinition<u64, JournalRecord> =
+    TableDefinition::new("journal");
+
+/// 操作ジャーナルに保持する記録の最大数
+const JOURNAL_LIMIT: u64 = 20;
+
+/// カウンタ類を保持するテーブル
+static COUNTERS_TABLE: TableDefinition<&str, u64> =
+    TableDefinition::new("counters");
+
+/// 変更シーケンス番号のカウンタのキー
+const CHANGE_SEQ_KEY: &str = "change_seq";
+
+/// データベースの付帯情報を保持するテーブル
+static META_TABLE: TableDefinition<&str, &str> = TableDefinition::new("meta");
+
+/// 自ノードのIDのキー
+const NODE_ID_KEY: &str = "node_id";
+
+///
+/// エントリの過去の版の検索範囲
+///
+fn revision_range(id: &ServiceId) -> RangeInclusive<(ServiceId, u64)> {
+    (id.clone(), 0)..=(id.clone(), u64::MAX)
+}
+
+///
+/// 読み出したエントリの秘匿プロパティの復号
+///
+/// # 引数
+/// * `key` - 暗号鍵(ロックされている場合は`None`)
+/// * `entry` - データベースから読み出したエントリ
+///
+/// # 戻り値
+/// 解錠済みの場合は秘匿プロパティを復号したエントリを、ロックされている場合
+/// は暗号化されたままのエントリを`Ok()`でラップして返す。
+///
+fn open_entry(key: Option<&VaultKey>, mut entry: Entry) -> Result<Entry> {
+    if let Some(key) = key {
+        entry.unseal_secret_properties(key).context("entry decryption")?;
+    }
+
+    Ok(entry)
+}
+
 ///
 /// 2つのベクタの差分（aにのみ含まれる要素）を返す。差分が空ならNone。
 ///
@@ -155,6 +242,91 @@ pub(crate) trait TransactionReadable {
     ///
     fn tagged_services(&self, id: &str) -> Result<Vec<ServiceId>>;
 
+    ///
+    /// 自ノードの識別情報の取得
+    ///
+    /// # 戻り値
+    /// 識別情報が生成済みの場合は`Some()`でラップして返す。
+    ///
+    /// # 注記
+    /// 継承先で実装を行うこと。
+    ///
+    fn node_identity(&self) -> Result<Option<NodeIdentity>>;
+
+    ///
+    /// ペアリング済み同期相手の取得
+    ///
+    /// # 引数
+    /// * `node_id` - 相手のノードID
+    ///
+    /// # 戻り値
+    /// ペアリング済みの場合は相手の情報を`Some()`でラップして返す。
+    ///
+    /// # 注記
+    /// 継承先で実装を行うこと。
+    ///
+    fn get_peer(&self, node_id: &str) -> Result<Option<TrustedPeer>>;
+
+    ///
+    /// ペアリング済み同期相手の一覧の取得
+    ///
+    /// # 注記
+    /// 継承先で実装を行うこと。
+    ///
+    fn all_peers(&self) -> Result<Vec<TrustedPeer>>;
+
+    ///
+    /// 現在の変更シーケンス番号の取得
+    ///
+    /// # 戻り値
+    /// 最後にエントリを書き込んだ際の番号を返す(書き込みが無い場合は0)。
+    ///
+    /// # 注記
+    /// 継承先で実装を行うこと。
+    ///
+    fn change_seq(&self) -> Result<u64>;
+
+    ///
+    /// 指定した変更シーケンス番号より後に書き込まれたエントリの取得
+    ///
+    /// # 引数
+    /// * `seq` - 起点とする変更シーケンス番号
+    ///
+    /// # 戻り値
+    /// 該当するエントリのサービスIDのリストを`Ok()`でラップして返す。
+    ///
+    /// # 注記
+    /// 継承先で実装を行うこと。
+    ///
+    fn changed_since(&self, seq: u64) -> Result<Vec<ServiceId>>;
+
+    ///
+    /// エントリの過去の版の一覧の取得
+    ///
+    /// # 引数
+    /// * `id` - 対象のサービスID
+    ///
+    /// # 戻り値
+    /// 版番号と上書きされる前のエントリの組のリスト(版番号順)を`Ok()`でラッ
+    /// プして返す。
+    ///
+    /// # 注記
+    /// 版番号は1から始まり、現在のエントリは最後の版番号の次の番号となる。
+    /// 継承先で実装を行うこと。
+    ///
+    fn revisions(&self, id: &ServiceId) -> Result<Vec<(u64, Entry)>>;
+
+    ///
+    /// 操作ジャーナルの記録の一覧の取得
+    ///
+    /// # 戻り値
+    /// 記録番号と記録の組のリスト(古い順)を`Ok()`でラップして返す。
+    ///
+    /// # 注記
+    /// 記録中のエントリは暗号化されたままとなる。継承先で実装を行うこと。
+    ///
+    fn journal(&self) -> Result<Vec<(u64, JournalRecord)>>;
+
     ///
     /// 削除済みを除外/含めるフラグ付きで全サービスのIDのリストの取得
     ///
@@ -200,15 +372,16 @@ pub(crate) trait TransactionReadable {
 ///
 pub(crate) struct TransactionReader {
     tnx: ReadTransaction,
+    key: Option<VaultKey>,
 }
 
 // TransactionReadableの実装
 impl TransactionReadable for TransactionReader {
     fn get(&self, id: &ServiceId) -> Result<Option<Entry>> {
-        Ok(self.tnx.open_table(ENTRIES_TABLE)?
+        self.tnx.open_table(ENTRIES_TABLE)?
             .get(id)?
-            .map(|entry| entry.value())
-        )
+            .map(|entry| open_entry(self.key.as_ref(), entry.value()))
+            .transpose()
     }
 
     fn all_service(&self) -> Result<Vec<ServiceId>> {
@@ -226,6 +399,74 @@ impl TransactionReadable for TransactionReader {
             .collect::<redb::Result<Vec<ServiceId>, StorageError>>()?
         )
     }
+
+    fn node_identity(&self) -> Result<Option<NodeIdentity>> {
+        Ok(self.tnx.open_table(NODE_TABLE)?
+            .get(NODE_IDENTITY_KEY)?
+            .map(|identity| identity.value())
+        )
+    }
+
+    fn get_peer(&self, node_id: &str) -> Result<Option<TrustedPeer>> {
+        Ok(self.tnx.open_table(PEERS_TABLE)?
+            .get(node_id)?
+            .map(|peer| peer.value())
+        )
+    }
+
+    fn all_peers(&self) -> Result<Vec<TrustedPeer>> {
+        Ok(self.tnx.open_table(PEERS_TABLE)?
+            .iter()?
+            .map(|res| res.map(|(_, peer)| peer.value()))
+            .collect::<redb::Result<Vec<TrustedPeer>, StorageError>>()?
+        )
+    }
+
+    fn change_seq(&self) -> Result<u64> {
+        Ok(self.tnx.open_table(COUNTERS_TABLE)?
+            .get(CHANGE_SEQ_KEY)?
+            .map(|seq| seq.value())
+            .unwrap_or(0)
+        )
+    }
+
+    fn changed_since(&self, seq: u64) -> Result<Vec<ServiceId>> {
+        let mut ids = Vec::new();
+
+        for res in self.tnx.open_table(CHANGES_TABLE)?.iter()? {
+            let (id, changed) = res?;
+            if changed.value() > seq {
+                ids.push(id.value());
+            }
+        }
+
+        Ok(ids)
+    }
+
+    fn revisions(&self, id: &ServiceId) -> Result<Vec<(u64, Entry)>> {
+        let mut revisions = Vec::new();
+
+        for res in self.tnx.open_table(REVISIONS_TABLE)?.range(revision_range(id))? {
+            let (key, entry) = res?;
+            revisions.push((
+                key.value().1,
+                open_entry(self.key.as_ref(), entry.value())?,
+            ));
+        }
+
+        Ok(revisions)
+    }
+
+    fn journal(&self) -> Result<Vec<(u64, JournalRecord)>> {
+        let mut records = Vec::new();
+
+        for res in self.tnx.open_table(JOURNAL_TABLE)?.iter()? {
+            let (seq, record) = res?;
+            records.push((seq.value(), record.value()));
+        }
+
+        Ok(records)
+    }
 }
 
 ///
@@ -233,21 +474,293 @@ impl TransactionReadable for TransactionReader {
 ///
 pub(crate) struct TransactionWriter {
     tnx: WriteTransaction,
+    key: Option<VaultKey>,
+    discard: bool,
+
+    /// 操作ジャーナルに記録する操作名(記録しない場合は`None`)
+    operation: Option<String>,
+
+    /// 変更したエントリの変更前の状態
+    journal: BTreeMap<ServiceId, Option<Entry>>,
 }
 
 impl TransactionWriter {
+    ///
+    /// トランザクションの破棄の指示
+    ///
+    /// # 注記
+    /// 指示した場合は処理が成功してもコミットせず、それまでの書き込みを全て
+    /// 破棄する(ドライランで使用する)。
+    ///
+    pub(crate) fn discard(&mut self) {
+        self.discard = true;
+    }
+
     ///
     /// エントリーの書き込み
     ///
+    /// # 注記
+    /// ローカルでの変更として、格納済みのエントリのバージョンベクタを引き継い
+    /// だ上で自ノードのカウンタを進める。上書きされるエントリは過去の版として
+    /// 記録する。
+    ///
     pub(crate) fn put(&mut self, entry: &Entry) -> Result<()> {
+        let node_id = self.local_node_id()?;
+        let existing = self.tnx.open_table(ENTRIES_TABLE)?
+            .get(&entry.id())?
+            .map(|existing| existing.value());
+
+        let base = match existing {
+            Some(existing) => {
+                let version = existing.version();
+                self.push_revision(&existing)?;
+                version
+            }
+            None => Default::default(),
+        };
+
+        let mut entry = entry.clone();
+        entry.bump_version(&base, &node_id);
+
+        self.store(&entry)
+    }
+
+    ///
+    /// 履歴を残さないエントリーの書き込み
+    ///
+    /// # 注記
+    /// `put()`と同様にバージョンベクタを進めるが、上書きされるエントリを過去の
+    /// 版として記録せず、操作ジャーナルにも記録しない。HOTPのカウンタの更新の
+    /// ように、`revert`/`undo`で元に戻すべきでない変更に用いる。
+    ///
+    pub(crate) fn put_untracked(&mut self, entry: &Entry) -> Result<()> {
+        let node_id = self.local_node_id()?;
+        let base = self.tnx.open_table(ENTRIES_TABLE)?
+            .get(&entry.id())?
+            .map(|existing| existing.value().version())
+            .unwrap_or_default();
+
+        let mut entry = entry.clone();
+        entry.bump_version(&base, &node_id);
+
+        let operation = self.operation.take();
+        let result = self.store(&entry);
+        self.operation = operation;
+
+        result
+    }
+
+    ///
+    /// 過去の版の記録
+    ///
+    /// # 引数
+    /// * `entry` - 格納済みの(秘匿プロパティが暗号化された)エントリ
+    ///
+    /// # 注記
+    /// 秘匿プロパティは暗号化されたまま記録するため、解錠は不要。エントリ毎の
+    /// 版数が上限を超えた場合は古いものから破棄する。
+    ///
+    fn push_revision(&mut self, entry: &Entry) -> Result<()> {
+        let id = entry.id();
+        let mut table = self.tnx.open_table(REVISIONS_TABLE)?;
+        let last = table.range(revision_range(&id))?
+            .next_back()
+            .transpose()?
+            .map(|(key, _)| key.value().1)
+            .unwrap_or(0);
+
+        let rev = last + 1;
+        table.insert(&(id.clone(), rev), entry)?;
+
+        if rev > REVISION_LIMIT {
+            table.retain_in(
+                (id.clone(), 0)..=(id, rev - REVISION_LIMIT),
+                |_, _| false
+            )?;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// 過去の版の破棄
+    ///
+    fn drop_revisions(&mut self, id: &ServiceId) -> Result<()> {
+        self.tnx.open_table(REVISIONS_TABLE)?
+            .retain_in(revision_range(id), |_, _| false)?;
+        Ok(())
+    }
+
+    ///
+    /// 同期で受信したエントリーの書き込み
+    ///
+    /// # 注記
+    /// 相手側の版をそのまま格納するため、バージョンベクタは変更しない。相手
+    /// 側の版の採用やマージ結果の適用で置き換えられるローカルのエントリは、
+    /// `put()`と同様に過去の版として記録する(版が同一の場合は記録しない)。
+    ///
+    pub(crate) fn put_synced(&mut self, entry: &Entry) -> Result<()> {
+        let existing = self.tnx.open_table(ENTRIES_TABLE)?
+            .get(&entry.id())?
+            .map(|existing| existing.value())
+            .filter(|existing| existing.version() != entry.version());
+
+        if let Some(existing) = existing {
+            self.push_revision(&existing)?;
+        }
+
+        self.store(entry)
+    }
+
+    ///
+    /// 共通祖先の版の記録
+    ///
+    /// # 引数
+    /// * `entry` - 同期相手と合意した版のエントリ
+    ///
+    /// # 注記
+    /// 3方向マージの基準として使用する。秘匿プロパティはエントリと同様に暗号
+    /// 化して格納する。
+    ///
+    pub(crate) fn put_ancestor(&mut self, entry: &Entry) -> Result<()> {
+        let mut table = self.tnx.open_table(ANCESTORS_TABLE)?;
+
+        if entry.has_plain_secrets() {
+            let key = self.key
+                .as_ref()
+                .ok_or_else(|| anyhow!("データベースがロックされています"))?;
+            let mut sealed = entry.clone();
+            sealed.seal_secret_properties(key)?;
+            table.insert(&entry.id(), &sealed)?;
+
+        } else {
+            table.insert(&entry.id(), entry)?;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// 共通祖先の版の取得
+    ///
+    /// # 引数
+    /// * `id` - 取得対象のサービスID
+    ///
+    /// # 戻り値
+    /// 記録されている場合はその版を、記録が無い場合は`None`を`Ok()`でラップし
+    /// て返す。
+    ///
+    pub(crate) fn get_ancestor(&self, id: &ServiceId) -> Result<Option<Entry>> {
+        self.tnx.open_table(ANCESTORS_TABLE)?
+            .get(id)?
+            .map(|entry| open_entry(self.key.as_ref(), entry.value()))
+            .transpose()
+    }
+
+    ///
+    /// エントリの最終変更時の変更シーケンス番号の取得
+    ///
+    /// # 引数
+    /// * `id` - 対象のサービスID
+    ///
+    /// # 戻り値
+    /// 記録されている場合はその番号を、記録が無い場合(変更シーケンス番号の
+    /// 導入前に書き込まれたエントリ)は`None`を`Ok()`でラップして返す。
+    ///
+    pub(crate) fn entry_seq(&self, id: &ServiceId) -> Result<Option<u64>> {
+        Ok(self.tnx.open_table(CHANGES_TABLE)?
+            .get(id)?
+            .map(|seq| seq.value()))
+    }
+
+    ///
+    /// エントリの墓標化
+    ///
+    /// # 引数
+    /// * `id` - 対象のサービスID
+    ///
+    /// # 戻り値
+    /// 墓標化したエントリを`Ok()`でラップして返す。エントリが無い場合は
+    /// `None`を返す。
+    ///
+    /// # 注記
+    /// サービス名以外の内容(別名、タグ、プロパティ)と共通祖先及び過去の版を
+    /// 破棄し、
+    /// 削除済みとしてローカルでの変更と同様に格納する。同期で削除を伝播させ
+    /// るためにIDとバージョンベクタのみを残すもので、エントリ自体はgcで取り
+    /// 除く。
+    ///
+    pub(crate) fn bury(&mut self, id: &ServiceId) -> Result<Option<Entry>> {
+        let service = match self.get(id)? {
+            Some(entry) => entry.service(),
+            None => return Ok(None),
+        };
+
+        let mut tombstone = Entry::new(
+            id.clone(),
+            service,
+            vec![],
+            vec![],
+            BTreeMap::new(),
+        );
+        tombstone.set_removed(true);
+        tombstone.set_last_update_now();
+
+        self.put(&tombstone)?;
+        self.tnx.open_table(ANCESTORS_TABLE)?.remove(id)?;
+        self.drop_revisions(id)?;
+
+        Ok(Some(tombstone))
+    }
+
+    ///
+    /// 自ノードのIDの取得
+    ///
+    /// # 戻り値
+    /// 自ノードのIDを`Ok()`でラップして返す。
+    ///
+    /// # 注記
+    /// 未生成の場合は新たに生成して登録する。解錠せずに書き込みを行う場合も
+    /// あるため、同期用の鍵ペア(`create_node_identity()`)とは独立して生成す
+    /// る。
+    ///
+    pub(crate) fn local_node_id(&mut self) -> Result<String> {
+        let stored = self.tnx.open_table(META_TABLE)?
+            .get(NODE_ID_KEY)?
+            .map(|node_id| node_id.value().to_string());
+
+        if let Some(node_id) = stored {
+            return Ok(node_id);
+        }
+
+        // 鍵ペアを先に生成済みのデータベースではそのIDを引き継ぐ
+        let node_id = match self.node_identity()? {
+            Some(identity) => identity.node_id(),
+            None => Ulid::new().to_string(),
+        };
+
+        self.tnx.open_table(META_TABLE)?
+            .insert(NODE_ID_KEY, node_id.as_str())?;
+
+        Ok(node_id)
+    }
+
+    ///
+    /// エントリーの格納
+    ///
+    fn store(&mut self, entry: &Entry) -> Result<()> {
         let id = entry.id();
         let mut table = self.tnx.open_table(ENTRIES_TABLE)?;
+        let existing = table.get(&id)?.map(|existing| existing.value());
+
+        if self.operation.is_some() {
+            self.journal.entry(id.clone()).or_insert_with(|| existing.clone());
+        }
 
         /*
          * タグテーブルを更新
          */
-        if let Some(existing) = table.get(&id)? {
-            let existing = existing.value();
+        if let Some(existing) = existing {
             let was_removed = existing.is_removed();
             let now_removed = entry.is_removed();
 
@@ -285,9 +798,26 @@ impl TransactionWriter {
         }
 
         /*
-         * 新規エントリを登録する
+         * 秘匿プロパティを暗号化してエントリを登録する
          */
-        table.insert(&id, entry)?;
+        if entry.has_plain_secrets() {
+            let key = self.key
+                .as_ref()
+                .ok_or_else(|| anyhow!("データベースがロックされています"))?;
+            let mut sealed = entry.clone();
+            sealed.seal_secret_properties(key)?;
+            table.insert(&id, &sealed)?;
+
+        } else {
+            table.insert(&id, entry)?;
+        }
+
+        /*
+         * 差分同期用に変更シーケンス番号を記録する
+         */
+        let seq = self.change_seq()? + 1;
+        self.tnx.open_table(COUNTERS_TABLE)?.insert(CHANGE_SEQ_KEY, seq)?;
+        self.tnx.open_table(CHANGES_TABLE)?.insert(&id, seq)?;
 
         Ok(())
     }
@@ -302,9 +832,15 @@ impl TransactionWriter {
          * タグリストを更新
          */
         if let Some(entry) = table.get(id)? {
+            let entry = entry.value();
+
             // エントリが存在する場合はエントリの持つタグに対応するタグリス
             // トからサービスIDを削除
-            shrink_tag_list(&self.tnx, &id, entry.value().tags())?;
+            shrink_tag_list(&self.tnx, &id, entry.tags())?;
+
+            if self.operation.is_some() {
+                self.journal.entry(id.clone()).or_insert(Some(entry));
+            }
         } else {
             // エントリが無い場合は、何も行わないのでリターン
             return Ok(())
@@ -312,18 +848,245 @@ impl TransactionWriter {
 
         // エントリテーブルからエントリを削除
         table.remove(id)?;
+        self.tnx.open_table(CHANGES_TABLE)?.remove(id)?;
+        self.tnx.open_table(ANCESTORS_TABLE)?.remove(id)?;
+        drop(table);
+        self.drop_revisions(id)?;
 
         Ok(())
     }
-}
 
-// TransactionReadableの実装
-impl TransactionReadable for TransactionWriter {
-    fn get(&self, id: &ServiceId) -> Result<Option<Entry>> {
-        Ok(self.tnx.open_table(ENTRIES_TABLE)?
-            .get(id)?
-            .map(|entry| entry.value())
-        )
+    ///
+    /// 操作ジャーナルの最新の記録の取り出し
+    ///
+    /// # 戻り値
+    /// 最新の記録をジャーナルから取り除いて`Ok()`でラップして返す。記録が無
+    /// い場合は`None`を返す。
+    ///
+    /// # 注記
+    /// 取り出した記録を元に戻す書き込み自体が新たな記録とならないよう、呼び
+    /// 出し以降のこのトランザクションでの変更はジャーナルに記録しない。
+    ///
+    pub(crate) fn pop_journal(&mut self) -> Result<Option<JournalRecord>> {
+        self.operation = None;
+        self.journal.clear();
+
+        let record = self.tnx.open_table(JOURNAL_TABLE)?
+            .pop_last()?
+            .map(|(_, record)| record.value());
+
+        Ok(record)
+    }
+
+    ///
+    /// 操作ジャーナルへの記録の追加
+    ///
+    /// # 注記
+    /// コミットの直前に呼び出す。エントリを変更しなかったトランザクションは
+    /// 記録せず、記録が上限を超えた場合は古いものから破棄する。
+    ///
+    fn write_journal(&mut self) -> Result<()> {
+        let operation = match self.operation.take() {
+            Some(operation) if !self.journal.is_empty() => operation,
+            _ => return Ok(()),
+        };
+
+        let entries = std::mem::take(&mut self.journal).into_iter().collect();
+        let mut table = self.tnx.open_table(JOURNAL_TABLE)?;
+        let seq = table.last()?.map_or(0, |(seq, _)| seq.value()) + 1;
+
+        table.insert(seq, &JournalRecord::new(operation, entries))?;
+
+        if seq > JOURNAL_LIMIT {
+            table.retain_in(..=(seq - JOURNAL_LIMIT), |_, _| false)?;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// 自ノードの識別情報の登録
+    ///
+    /// # 引数
+    /// * `node_id` - ノードID
+    /// * `public_key` - 静的公開鍵
+    /// * `private_key` - 静的秘密鍵
+    ///
+    /// # 戻り値
+    /// 登録した識別情報を`Ok()`でラップして返す。
+    ///
+    /// # 注記
+    /// 秘密鍵は暗号化して格納するため、解錠済みである必要がある。
+    ///
+    pub(crate) fn create_node_identity(
+        &mut self,
+        node_id: String,
+        public_key: Vec<u8>,
+        private_key: &[u8],
+    ) -> Result<NodeIdentity> {
+        let key = self.key
+            .as_ref()
+            .ok_or_else(|| anyhow!("データベースがロックされています"))?;
+        let identity = NodeIdentity::new(node_id, public_key, private_key, key)?;
+        self.put_node_identity(&identity)?;
+
+        Ok(identity)
+    }
+
+    ///
+    /// 自ノードの静的秘密鍵の復号
+    ///
+    pub(crate) fn node_private_key(&self, identity: &NodeIdentity)
+        -> Result<Zeroizing<Vec<u8>>>
+    {
+        let key = self.key
+            .as_ref()
+            .ok_or_else(|| anyhow!("データベースがロックされています"))?;
+        identity.private_key(key).context("node key decryption")
+    }
+
+    ///
+    /// ペアリング済み同期相手の登録
+    ///
+    /// # 注記
+    /// 同じノードIDの相手が登録済みの場合は上書きする。
+    ///
+    pub(crate) fn put_peer(&mut self, peer: &TrustedPeer) -> Result<()> {
+        self.tnx.open_table(PEERS_TABLE)?
+            .insert(peer.node_id().as_str(), peer)?;
+        Ok(())
+    }
+
+    ///
+    /// ペアリング済み同期相手の削除
+    ///
+    /// # 戻り値
+    /// 登録されていた場合は`true`を`Ok()`でラップして返す。
+    ///
+    pub(crate) fn remove_peer(&mut self, node_id: &str) -> Result<bool> {
+        Ok(self.tnx.open_table(PEERS_TABLE)?.remove(node_id)?.is_some())
+    }
+}
+
+impl TransactionWriter {
+    ///
+    /// 全エントリの秘匿プロパティの再暗号化と金庫ヘッダの差し替え
+    ///
+    /// # 引数
+    /// * `key` - 新しい暗号鍵
+    /// * `header` - 新しい暗号鍵に対応する金庫ヘッダ
+    ///
+    /// # 戻り値
+    /// 処理に成功した場合は`Ok(())`を返す。
+    ///
+    /// # 注記
+    /// トランザクションのコミットまでは旧来の鍵とヘッダが有効なままとなる。
+    ///
+    fn rekey(&mut self, key: &VaultKey, header: &VaultHeader) -> Result<()> {
+        /*
+         * 旧鍵で復号したエントリを収集
+         */
+        let mut entries = Vec::new();
+        for id in self.all_service()? {
+            if let Some(entry) = self.get(&id)? {
+                entries.push(entry);
+            }
+        }
+
+        let mut ancestors = Vec::new();
+        for item in self.tnx.open_table(ANCESTORS_TABLE)?.iter()? {
+            let (_, entry) = item?;
+            ancestors.push(open_entry(self.key.as_ref(), entry.value())?);
+        }
+
+        let mut revisions = Vec::new();
+        for item in self.tnx.open_table(REVISIONS_TABLE)?.iter()? {
+            let (key, entry) = item?;
+            revisions.push((
+                key.value(),
+                open_entry(self.key.as_ref(), entry.value())?,
+            ));
+        }
+
+        /*
+         * 新しい鍵で暗号化し直して書き戻す
+         */
+        for (table, entries) in [
+            (ENTRIES_TABLE, entries),
+            (ANCESTORS_TABLE, ancestors),
+        ] {
+            let mut table = self.tnx.open_table(table)?;
+            for mut entry in entries {
+                if entry.has_plain_secrets() {
+                    entry.seal_secret_properties(key)?;
+                    table.insert(&entry.id(), &entry)?;
+                }
+            }
+        }
+
+        let mut table = self.tnx.open_table(REVISIONS_TABLE)?;
+        for (rev_key, mut entry) in revisions {
+            if entry.has_plain_secrets() {
+                entry.seal_secret_properties(key)?;
+                table.insert(&rev_key, &entry)?;
+            }
+        }
+        drop(table);
+
+        let mut records = self.journal()?;
+        for (_, record) in records.iter_mut() {
+            for (_, entry) in record.entries_mut() {
+                if let Some(entry) = entry {
+                    let mut opened = open_entry(self.key.as_ref(), entry.clone())?;
+                    if opened.has_plain_secrets() {
+                        opened.seal_secret_properties(key)?;
+                    }
+                    *entry = opened;
+                }
+            }
+        }
+
+        let mut table = self.tnx.open_table(JOURNAL_TABLE)?;
+        for (seq, record) in records {
+            table.insert(seq, &record)?;
+        }
+        drop(table);
+
+        /*
+         * 自ノードの秘密鍵を暗号化し直す
+         */
+        if let Some(mut identity) = self.node_identity()? {
+            let old_key = self.key
+                .as_ref()
+                .ok_or_else(|| anyhow!("データベースがロックされています"))?;
+            identity.reseal(old_key, key)?;
+            self.put_node_identity(&identity)?;
+        }
+
+        /*
+         * ヘッダの差し替え
+         */
+        self.tnx.open_table(HEADER_TABLE)?.insert(VAULT_HEADER_KEY, header)?;
+
+        Ok(())
+    }
+
+    ///
+    /// 自ノードの識別情報の書き込み
+    ///
+    fn put_node_identity(&mut self, identity: &NodeIdentity) -> Result<()> {
+        self.tnx.open_table(NODE_TABLE)?.insert(NODE_IDENTITY_KEY, identity)?;
+        Ok(())
+    }
+}
+
+// TransactionReadableの実装
+impl TransactionReadable for TransactionWriter {
+    fn get(&self, id: &ServiceId) -> Result<Option<Entry>> {
+        self.tnx.open_table(ENTRIES_TABLE)?
+            .get(id)?
+            .map(|entry| open_entry(self.key.as_ref(), entry.value()))
+            .transpose()
     }
 
     fn all_service(&self) -> Result<Vec<ServiceId>> {
@@ -341,6 +1104,74 @@ impl TransactionReadable for TransactionWriter {
             .collect::<redb::Result<Vec<ServiceId>, StorageError>>()?
         )
     }
+
+    fn node_identity(&self) -> Result<Option<NodeIdentity>> {
+        Ok(self.tnx.open_table(NODE_TABLE)?
+            .get(NODE_IDENTITY_KEY)?
+            .map(|identity| identity.value())
+        )
+    }
+
+    fn get_peer(&self, node_id: &str) -> Result<Option<TrustedPeer>> {
+        Ok(self.tnx.open_table(PEERS_TABLE)?
+            .get(node_id)?
+            .map(|peer| peer.value())
+        )
+    }
+
+    fn all_peers(&self) -> Result<Vec<TrustedPeer>> {
+        Ok(self.tnx.open_table(PEERS_TABLE)?
+            .iter()?
+            .map(|res| res.map(|(_, peer)| peer.value()))
+            .collect::<redb::Result<Vec<TrustedPeer>, StorageError>>()?
+        )
+    }
+
+    fn change_seq(&self) -> Result<u64> {
+        Ok(self.tnx.open_table(COUNTERS_TABLE)?
+            .get(CHANGE_SEQ_KEY)?
+            .map(|seq| seq.value())
+            .unwrap_or(0)
+        )
+    }
+
+    fn changed_since(&self, seq: u64) -> Result<Vec<ServiceId>> {
+        let mut ids = Vec::new();
+
+        for res in self.tnx.open_table(CHANGES_TABLE)?.iter()? {
+            let (id, changed) = res?;
+            if changed.value() > seq {
+                ids.push(id.value());
+            }
+        }
+
+        Ok(ids)
+    }
+
+    fn revisions(&self, id: &ServiceId) -> Result<Vec<(u64, Entry)>> {
+        let mut revisions = Vec::new();
+
+        for res in self.tnx.open_table(REVISIONS_TABLE)?.range(revision_range(id))? {
+            let (key, entry) = res?;
+            revisions.push((
+                key.value().1,
+                open_entry(self.key.as_ref(), entry.value())?,
+            ));
+        }
+
+        Ok(revisions)
+    }
+
+    fn journal(&self) -> Result<Vec<(u64, JournalRecord)>> {
+        let mut records = Vec::new();
+
+        for res in self.tnx.open_table(JOURNAL_TABLE)?.iter()? {
+            let (seq, record) = res?;
+            records.push((seq.value(), record.value()));
+        }
+
+        Ok(records)
+    }
 }
 
 ///
@@ -348,6 +1179,12 @@ impl TransactionReadable for TransactionWriter {
 ///
 pub(crate) struct EntryManager {
     db: Database,
+
+    /// 解錠済みの場合の暗号鍵
+    key: Option<VaultKey>,
+
+    /// 操作ジャーナルに記録する操作名
+    operation: Option<String>,
 }
 
 impl EntryManager {
@@ -362,6 +1199,12 @@ impl EntryManager {
     /// `Ok()`でラップして返す。失敗した場合はエラー情報を `Err()`でラップして返
     /// す。
     ///
+    /// # 注記
+    /// オープン直後はロックされた状態となる。ロックされた状態でもエントリの
+    /// 読み出しは可能だが、秘匿プロパティは暗号化されたままとなる。秘匿プロパ
+    /// ティの参照や書き込みを行う前に`initialize()`もしくは`unlock()`で解錠す
+    /// ること。
+    ///
     pub(crate) fn open<P>(path: P) -> Result<Self> 
     where
         P: AsRef<Path>
@@ -371,8 +1214,17 @@ impl EntryManager {
                 // データベース作成の場合はとりあえずテーブルを作成する
                 let txn = db.begin_write()?;
                 {
+                    let _= txn.open_table(HEADER_TABLE)?;
                     let _= txn.open_table(ENTRIES_TABLE)?;
                     let _= txn.open_multimap_table(TAGS_TABLE)?;
+                    let _= txn.open_table(NODE_TABLE)?;
+                    let _= txn.open_table(PEERS_TABLE)?;
+                    let _= txn.open_table(CHANGES_TABLE)?;
+                    let _= txn.open_table(COUNTERS_TABLE)?;
+                    let _= txn.open_table(META_TABLE)?;
+                    let _= txn.open_table(ANCESTORS_TABLE)?;
+                    let _= txn.open_table(REVISIONS_TABLE)?;
+                    let _= txn.open_table(JOURNAL_TABLE)?;
                 }
                 txn.commit()?;
 
@@ -382,7 +1234,206 @@ impl EntryManager {
             Err(err) => return Err(err.into()),
         };
 
-        Ok(Self {db})
+        let mgr = Self {db, key: None, operation: None};
+
+        // 未初期化の場合はヘッダが無いので現行バージョンとして扱う
+        let version = mgr.header()?
+            .map(|header| header.version())
+            .unwrap_or(VaultHeader::VERSION);
+
+        if version != VaultHeader::VERSION {
+            return Err(anyhow!(
+                "unsupported database format version: {}", version
+            ));
+        }
+
+        Ok(mgr)
+    }
+
+    ///
+    /// テスト用のオープン(低コストな鍵導出で初期化済みの状態で返す)
+    ///
+    #[cfg(test)]
+    pub(crate) fn open_for_test<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>
+    {
+        let mut mgr = Self::open(path)?;
+        mgr.initialize("test", KdfParams::for_test())?;
+        Ok(mgr)
+    }
+
+    ///
+    /// 金庫ヘッダの読み出し
+    ///
+    fn header(&self) -> Result<Option<VaultHeader>> {
+        let tnx = self.db.begin_read()?;
+        Ok(tnx.open_table(HEADER_TABLE)?
+            .get(VAULT_HEADER_KEY)?
+            .map(|header| header.value())
+        )
+    }
+
+    ///
+    /// 暗号化の初期化が済んでいるか否かを返す
+    ///
+    /// # 戻り値
+    /// 金庫ヘッダが存在する場合は`true`を`Ok()`でラップして返す。
+    ///
+    pub(crate) fn is_initialized(&self) -> Result<bool> {
+        Ok(self.header()?.is_some())
+    }
+
+    ///
+    /// 暗号化の初期化
+    ///
+    /// # 引数
+    /// * `passphrase` - 新規に設定するマスタパスフレーズ
+    /// * `params` - 鍵導出パラメータ
+    ///
+    /// # 戻り値
+    /// 初期化に成功した場合は`Ok(())`を返す。初期化後は解錠済みの状態となる。
+    ///
+    /// # 注記
+    /// 暗号化導入前の平文の秘匿プロパティが存在する場合は、同一トランザクショ
+    /// ン内で暗号化する。
+    ///
+    pub(crate) fn initialize(&mut self, passphrase: &str, params: KdfParams)
+        -> Result<()>
+    {
+        if self.is_initialized()? {
+            return Err(anyhow!("database is already initialized"));
+        }
+
+        let (header, key) = crypto::create_header(passphrase, params)?;
+
+        let tnx = self.db.begin_write()?;
+        {
+            /*
+             * 既存エントリの秘匿プロパティを暗号化
+             */
+            let mut table = tnx.open_table(ENTRIES_TABLE)?;
+            let entries = table.range(ServiceId::range_all())?
+                .map(|res| res.map(|(_, entry)| entry.value()))
+                .collect::<redb::Result<Vec<Entry>, StorageError>>()?;
+
+            for mut entry in entries {
+                if entry.has_plain_secrets() {
+                    entry.seal_secret_properties(&key)?;
+                    table.insert(&entry.id(), &entry)?;
+                }
+            }
+
+            /*
+             * ヘッダの書き込み
+             */
+            tnx.open_table(HEADER_TABLE)?.insert(VAULT_HEADER_KEY, &header)?;
+        }
+        tnx.commit()?;
+
+        self.key = Some(key);
+
+        Ok(())
+    }
+
+    ///
+    /// データベースの解錠
+    ///
+    /// # 引数
+    /// * `passphrase` - マスタパスフレーズ
+    ///
+    /// # 戻り値
+    /// パスフレーズが正しい場合は`Ok(())`を返す。誤っている場合はエラー情報を
+    /// `Err()`でラップして返す。
+    ///
+    pub(crate) fn unlock(&mut self, passphrase: &str) -> Result<()> {
+        let header = self.header()?
+            .ok_or_else(|| anyhow!("database is not initialized"))?;
+
+        self.key = Some(crypto::unlock_header(&header, passphrase)?);
+
+        Ok(())
+    }
+
+    ///
+    /// 鍵を直接指定してのデータベースの解錠
+    ///
+    /// # 引数
+    /// * `key` - エージェント等から取得した暗号鍵
+    ///
+    /// # 戻り値
+    /// 鍵が金庫ヘッダに対応するものであれば`Ok(())`を返す。対応しない場合は
+    /// エラー情報を`Err()`でラップして返す。
+    ///
+    pub(crate) fn unlock_with_key(&mut self, key: VaultKey) -> Result<()> {
+        let header = self.header()?
+            .ok_or_else(|| anyhow!("database is not initialized"))?;
+
+        if !crypto::verify_key(&header, &key) {
+            return Err(anyhow!("key does not match the database"));
+        }
+
+        self.key = Some(key);
+
+        Ok(())
+    }
+
+    ///
+    /// 解錠に用いた暗号鍵へのアクセサ
+    ///
+    /// # 戻り値
+    /// 解錠済みの場合は暗号鍵を返す。ロックされている場合は`None`を返す。
+    ///
+    pub(crate) fn vault_key(&self) -> Option<&VaultKey> {
+        self.key.as_ref()
+    }
+
+    ///
+    /// マスタパスフレーズの変更
+    ///
+    /// # 引数
+    /// * `passphrase` - 新しいマスタパスフレーズ
+    /// * `params` - 新しい鍵の導出パラメータ
+    ///
+    /// # 戻り値
+    /// 変更に成功した場合は`Ok(())`を返す。変更後は新しい鍵で解錠済みの状態と
+    /// なる。
+    ///
+    /// # 注記
+    /// 解錠済みの状態で呼び出すこと。全エントリの再暗号化とヘッダの更新は単一
+    /// の書き込みトランザクション内で行うため、処理が中断された場合は旧来のパ
+    /// スフレーズが有効なまま残る。
+    ///
+    pub(crate) fn rekey(&mut self, passphrase: &str, params: KdfParams)
+        -> Result<()>
+    {
+        if !self.is_unlocked() {
+            return Err(anyhow!("データベースがロックされています"));
+        }
+
+        let (header, key) = crypto::create_header(passphrase, params)?;
+        self.with_write_transaction(|writer| writer.rekey(&key, &header))?;
+        self.key = Some(key);
+
+        Ok(())
+    }
+
+    ///
+    /// 操作ジャーナルに記録する操作名の設定
+    ///
+    /// # 注記
+    /// 設定した場合、以降の書き込みトランザクションで変更したエントリの変更
+    /// 前の状態を操作ジャーナルに記録する。未設定の場合は記録しない。
+    ///
+    pub(crate) fn set_operation(&mut self, operation: &str) {
+        self.operation = Some(operation.to_string());
+    }
+
+    ///
+    /// 解錠済みか否かを返す
+    ///
+    pub(crate) fn is_unlocked(&self) -> bool {
+        self.key.is_some()
     }
 
     ///
@@ -449,6 +1500,26 @@ impl EntryManager {
         })
     }
 
+    ///
+    /// ペアリング済み同期相手の一覧を取得
+    ///
+    pub(crate) fn all_peers(&self) -> Result<Vec<TrustedPeer>> {
+        self.with_read_transaction(|reader| reader.all_peers())
+    }
+
+    ///
+    /// ペアリング済み同期相手の削除
+    ///
+    /// # 引数
+    /// * `node_id` - 削除する相手のノードID
+    ///
+    /// # 戻り値
+    /// 登録されていた場合は`true`を`Ok()`でラップして返す。
+    ///
+    pub(crate) fn remove_peer(&mut self, node_id: &str) -> Result<bool> {
+        self.with_write_transaction(|writer| writer.remove_peer(node_id))
+    }
+
     ///
     /// 全タグと件数の一覧を取得
     ///
@@ -481,7 +1552,7 @@ impl EntryManager {
         F: FnOnce(&TransactionReader) -> Result<T>,
     {
         let tnx = self.db.begin_read()?;
-        let reader = TransactionReader { tnx };
+        let reader = TransactionReader { tnx, key: self.key.clone() };
         f(&reader)
     }
 
@@ -493,11 +1564,22 @@ impl EntryManager {
         F: FnOnce(&mut TransactionWriter) -> Result<T>,
     {
         let tnx = self.db.begin_write()?;
-        let mut writer = TransactionWriter { tnx };
+        let mut writer = TransactionWriter {
+            tnx,
+            key: self.key.clone(),
+            discard: false,
+            operation: self.operation.clone(),
+            journal: BTreeMap::new(),
+        };
 
         match f(&mut writer) {
             Ok(val) => {
-                writer.tnx.commit()?;
+                if writer.discard {
+                    writer.tnx.abort()?;
+                } else {
+                    writer.write_journal()?;
+                    writer.tnx.commit()?;
+                }
                 Ok(val)
             }
             Err(err) => Err(err),
@@ -512,7 +1594,7 @@ mod tests {
     use std::path::PathBuf;
     use ulid::Ulid;
 
-    use crate::database::types::{Entry, ServiceId};
+    use crate::database::types::{Causality, Entry, ServiceId};
 
     ///
     /// テスト用の一時DBファイルパスを生成
@@ -539,13 +1621,29 @@ mod tests {
         )
     }
 
+    ///
+    /// 秘匿プロパティ付きのエントリ生成ヘルパ
+    ///
+    fn make_secret_entry(id: ServiceId, service: &str) -> Entry {
+        Entry::new(
+            id,
+            service.to_string(),
+            vec![],
+            vec!["tag1".to_string()],
+            BTreeMap::from([
+                ("user".to_string(), "alice".to_string()),
+                ("password!".to_string(), "PlainSecret42".to_string()),
+            ]),
+        )
+    }
+
     ///
     /// 追加→取得→タグ検索の基本動作を確認
     ///
     #[test]
     fn put_then_get_and_tagged() {
         let path = temp_db_path();
-        let mut mgr = EntryManager::open(&path).unwrap();
+        let mut mgr = EntryManager::open_for_test(&path).unwrap();
         let id = ServiceId::new();
         let entry = make_entry(id.clone(), "svc1", &["alias"], &["tag1"]);
 
@@ -565,7 +1663,7 @@ mod tests {
     #[test]
     fn update_tags_updates_multimap() {
         let path = temp_db_path();
-        let mut mgr = EntryManager::open(&path).unwrap();
+        let mut mgr = EntryManager::open_for_test(&path).unwrap();
         let id = ServiceId::new();
 
         let entry1 = make_entry(id.clone(), "svc", &[], &["tag1", "tag2"]);
@@ -586,7 +1684,7 @@ mod tests {
     #[test]
     fn remove_cleans_tags() {
         let path = temp_db_path();
-        let mut mgr = EntryManager::open(&path).unwrap();
+        let mut mgr = EntryManager::open_for_test(&path).unwrap();
         let id = ServiceId::new();
         let entry = make_entry(id.clone(), "svc", &[], &["tag1"]);
 
@@ -597,13 +1695,80 @@ mod tests {
         assert!(!mgr.tagged_services("tag1").unwrap().contains(&id));
     }
 
+    ///
+    /// 書き込み毎に変更シーケンス番号が進み、差分を取得できること
+    ///
+    #[test]
+    fn change_seq_tracks_writes() {
+        let path = temp_db_path();
+        let mut mgr = EntryManager::open_for_test(&path).unwrap();
+        let id1 = ServiceId::new();
+        let id2 = ServiceId::new();
+
+        mgr.put(&make_entry(id1.clone(), "a", &[], &[])).unwrap();
+        mgr.put(&make_entry(id2.clone(), "b", &[], &[])).unwrap();
+
+        mgr.with_write_transaction(|writer| {
+            assert_eq!(writer.change_seq()?, 2);
+            assert_eq!(writer.changed_since(1)?, vec![id2.clone()]);
+            assert!(writer.changed_since(2)?.is_empty());
+
+            // 更新した既存エントリも差分に含まれる
+            writer.put(&make_entry(id1.clone(), "a2", &[], &[]))?;
+            let mut changed = writer.changed_since(1)?;
+            changed.sort();
+            let mut expected = vec![id1.clone(), id2.clone()];
+            expected.sort();
+            assert_eq!(changed, expected);
+
+            // 物理削除したエントリは差分から除かれる
+            writer.remove(&id2)?;
+            assert_eq!(writer.changed_since(0)?, vec![id1.clone()]);
+            assert_eq!(writer.change_seq()?, 3);
+
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// ローカルの書き込みでのみバージョンベクタが進むこと
+    ///
+    #[test]
+    fn put_bumps_version() {
+        let path = temp_db_path();
+        let mut mgr = EntryManager::open_for_test(&path).unwrap();
+        let id = ServiceId::new();
+
+        mgr.put(&make_entry(id.clone(), "a", &[], &[])).unwrap();
+        mgr.put(&make_entry(id.clone(), "a2", &[], &[])).unwrap();
+
+        mgr.with_write_transaction(|writer| {
+            let node_id = writer.local_node_id()?;
+            let entry = writer.get(&id)?.unwrap();
+            assert_eq!(entry.version().get(&node_id), 2);
+
+            // 同期で受信した版はそのまま格納される
+            let mut remote = make_entry(id.clone(), "remote", &[], &[]);
+            let mut version = entry.version();
+            version.increment("other");
+            remote.set_version(version.clone());
+            writer.put_synced(&remote)?;
+            assert_eq!(writer.get(&id)?.unwrap().version(), version);
+
+            // ノードIDは一度生成したものが維持される
+            assert_eq!(writer.local_node_id()?, node_id);
+
+            Ok(())
+        }).unwrap();
+    }
+
     ///
     /// all_service が登録済みIDをすべて返すこと
     ///
     #[test]
     fn all_service_lists_all_ids() {
         let path = temp_db_path();
-        let mut mgr = EntryManager::open(&path).unwrap();
+        let mut mgr = EntryManager::open_for_test(&path).unwrap();
         let id1 = ServiceId::new();
         let id2 = ServiceId::new();
 
@@ -618,4 +1783,490 @@ mod tests {
 
         assert_eq!(all, expected);
     }
+
+    ///
+    /// 再オープン時に正しいパスフレーズでのみ秘匿プロパティを復号できること
+    ///
+    #[test]
+    fn reopen_requires_passphrase() {
+        let path = temp_db_path();
+        let id = ServiceId::new();
+
+        {
+            let mut mgr = EntryManager::open_for_test(&path).unwrap();
+            mgr.put(&make_secret_entry(id.clone(), "svc")).unwrap();
+        }
+
+        let mut mgr = EntryManager::open(&path).unwrap();
+        assert!(mgr.is_initialized().unwrap());
+        assert!(mgr.unlock("wrong").is_err());
+
+        mgr.unlock("test").unwrap();
+        let entry = mgr.get(&id).unwrap().unwrap();
+        assert!(!entry.is_sealed());
+        assert_eq!(
+            entry.properties().get("password!"),
+            Some(&"PlainSecret42".to_string())
+        );
+    }
+
+    ///
+    /// ロックされた状態でも秘匿プロパティ以外は参照できること
+    ///
+    #[test]
+    fn locked_read_keeps_secrets_sealed() {
+        let path = temp_db_path();
+        let id = ServiceId::new();
+
+        {
+            let mut mgr = EntryManager::open_for_test(&path).unwrap();
+            mgr.put(&make_secret_entry(id.clone(), "svc")).unwrap();
+        }
+
+        let mut mgr = EntryManager::open(&path).unwrap();
+        let entry = mgr.get(&id).unwrap().unwrap();
+        assert!(entry.is_sealed());
+        assert_eq!(entry.service(), "svc");
+        assert_eq!(entry.properties().get("user"), Some(&"alice".to_string()));
+        assert!(!entry.properties().contains_key("password!"));
+        assert!(mgr.tagged_services("tag1").unwrap().contains(&id));
+
+        // 平文の秘匿プロパティはロック中には書き込めない
+        assert!(mgr.put(&make_secret_entry(ServiceId::new(), "x")).is_err());
+    }
+
+    ///
+    /// ロックされた状態で書き戻しても秘匿プロパティが失われないこと
+    ///
+    #[test]
+    fn locked_put_preserves_sealed_secrets() {
+        let path = temp_db_path();
+        let id = ServiceId::new();
+
+        {
+            let mut mgr = EntryManager::open_for_test(&path).unwrap();
+            mgr.put(&make_secret_entry(id.clone(), "svc")).unwrap();
+        }
+
+        {
+            let mut mgr = EntryManager::open(&path).unwrap();
+            let mut entry = mgr.get(&id).unwrap().unwrap();
+            entry.set_removed(true);
+            mgr.put(&entry).unwrap();
+        }
+
+        let mut mgr = EntryManager::open(&path).unwrap();
+        mgr.unlock("test").unwrap();
+        let entry = mgr.get(&id).unwrap().unwrap();
+        assert!(entry.is_removed());
+        assert_eq!(
+            entry.properties().get("password!"),
+            Some(&"PlainSecret42".to_string())
+        );
+    }
+
+    ///
+    /// 取り出した鍵で別のマネージャを解錠できること
+    ///
+    #[test]
+    fn unlock_with_exported_key() {
+        let path = temp_db_path();
+        let other = temp_db_path();
+        let id = ServiceId::new();
+
+        let bytes = {
+            let mut mgr = EntryManager::open_for_test(&path).unwrap();
+            mgr.put(&make_secret_entry(id.clone(), "svc")).unwrap();
+            mgr.vault_key().unwrap().as_bytes().to_vec()
+        };
+
+        let mut mgr = EntryManager::open(&path).unwrap();
+        mgr.unlock_with_key(VaultKey::from_bytes(&bytes).unwrap()).unwrap();
+        assert!(mgr.get(&id).unwrap().unwrap().has_plain_secrets());
+
+        // 別のデータベースの鍵では解錠できないこと
+        let mut mgr = EntryManager::open_for_test(&other).unwrap();
+        mgr.key = None;
+        assert!(
+            mgr.unlock_with_key(VaultKey::from_bytes(&bytes).unwrap()).is_err()
+        );
+    }
+
+    ///
+    /// パスフレーズ変更後は新しいパスフレーズでのみ解錠できること
+    ///
+    #[test]
+    fn rekey_switches_passphrase() {
+        let path = temp_db_path();
+        let id = ServiceId::new();
+
+        {
+            let mut mgr = EntryManager::open_for_test(&path).unwrap();
+            mgr.put(&make_secret_entry(id.clone(), "svc")).unwrap();
+            mgr.rekey("new", KdfParams::for_test()).unwrap();
+
+            // 変更直後も引き続き読み書きできること
+            let entry = mgr.get(&id).unwrap().unwrap();
+            assert_eq!(
+                entry.properties().get("password!"),
+                Some(&"PlainSecret42".to_string())
+            );
+        }
+
+        let mut mgr = EntryManager::open(&path).unwrap();
+        assert!(mgr.rekey("other", KdfParams::for_test()).is_err());
+        assert!(mgr.unlock("test").is_err());
+
+        mgr.unlock("new").unwrap();
+        let entry = mgr.get(&id).unwrap().unwrap();
+        assert_eq!(
+            entry.properties().get("password!"),
+            Some(&"PlainSecret42".to_string())
+        );
+        assert!(mgr.tagged_services("tag1").unwrap().contains(&id));
+    }
+
+    ///
+    /// 秘匿項目がデータベースファイルに平文で書き込まれないこと
+    ///
+    #[test]
+    fn secret_is_not_stored_in_plain() {
+        let path = temp_db_path();
+
+        {
+            let mut mgr = EntryManager::open_for_test(&path).unwrap();
+            mgr.put(&make_secret_entry(ServiceId::new(), "svc")).unwrap();
+        }
+
+        let raw = std::fs::read(&path).unwrap();
+        assert!(!raw.windows(13).any(|w| w == b"PlainSecret42"));
+    }
+
+    ///
+    /// 暗号化導入前のデータベースが初期化時に暗号化されて移行されること
+    ///
+    #[test]
+    fn initialize_migrates_legacy_entries() {
+        let path = temp_db_path();
+        let id = ServiceId::new();
+
+        {
+            let db = Database::create(&path).unwrap();
+            let tnx = db.begin_write().unwrap();
+            {
+                let mut table = tnx.open_table(ENTRIES_TABLE).unwrap();
+                let entry = make_secret_entry(id.clone(), "legacy");
+                table.insert(&id, &entry).unwrap();
+                tnx.open_multimap_table(TAGS_TABLE).unwrap()
+                    .insert("tag1".to_string(), &id)
+                    .unwrap();
+            }
+            tnx.commit().unwrap();
+        }
+
+        {
+            let mut mgr = EntryManager::open(&path).unwrap();
+            assert!(!mgr.is_initialized().unwrap());
+
+            mgr.initialize("test", KdfParams::for_test()).unwrap();
+            assert_eq!(mgr.get(&id).unwrap().unwrap().service(), "legacy");
+            assert!(mgr.tagged_services("tag1").unwrap().contains(&id));
+        }
+
+        let mut mgr = EntryManager::open(&path).unwrap();
+        assert!(mgr.get(&id).unwrap().unwrap().is_sealed());
+    }
+
+    ///
+    /// 同期相手の登録/一覧/削除ができることを確認
+    ///
+    #[test]
+    fn peers_roundtrip() {
+        let path = temp_db_path();
+        let mut mgr = EntryManager::open_for_test(&path).unwrap();
+
+        mgr.with_write_transaction(|writer| {
+            writer.put_peer(&TrustedPeer::new(
+                "node-a".to_string(), vec![1; 32], "127.0.0.1:2456".to_string()
+            ))
+        }).unwrap();
+
+        let peers = mgr.all_peers().unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].node_id(), "node-a");
+        assert_eq!(peers[0].public_key(), &[1; 32]);
+
+        assert!(mgr.remove_peer("node-a").unwrap());
+        assert!(!mgr.remove_peer("node-a").unwrap());
+        assert!(mgr.all_peers().unwrap().is_empty());
+    }
+
+    ///
+    /// 自ノードの秘密鍵が暗号化され、rekey後も復号できることを確認
+    ///
+    #[test]
+    fn node_identity_survives_rekey() {
+        let path = temp_db_path();
+
+        {
+            let mut mgr = EntryManager::open_for_test(&path).unwrap();
+            mgr.with_write_transaction(|writer| {
+                writer.create_node_identity(
+                    "self".to_string(), vec![2; 32], &[3; 32]
+                )
+            }).unwrap();
+            mgr.rekey("new", KdfParams::for_test()).unwrap();
+        }
+
+        let mut mgr = EntryManager::open(&path).unwrap();
+
+        // ロック状態では秘密鍵を復号できないこと
+        mgr.with_write_transaction(|writer| {
+            let identity = writer.node_identity()?.unwrap();
+            assert!(writer.node_private_key(&identity).is_err());
+            Ok(())
+        }).unwrap();
+
+        mgr.unlock("new").unwrap();
+        mgr.with_write_transaction(|writer| {
+            let identity = writer.node_identity()?.unwrap();
+            assert_eq!(identity.node_id(), "self");
+            assert_eq!(*writer.node_private_key(&identity)?, vec![3; 32]);
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// 共通祖先の版が暗号化して保持され、鍵の変更と削除に追従すること
+    ///
+    #[test]
+    fn ancestor_follows_rekey_and_remove() {
+        let path = temp_db_path();
+        let id = ServiceId::new();
+
+        {
+            let mut mgr = EntryManager::open_for_test(&path).unwrap();
+            mgr.with_write_transaction(|writer| {
+                writer.put_ancestor(&make_secret_entry(id.clone(), "svc"))
+            }).unwrap();
+            mgr.rekey("new", KdfParams::for_test()).unwrap();
+        }
+
+        let mut mgr = EntryManager::open(&path).unwrap();
+
+        // ロック状態では秘匿プロパティが暗号化されたままであること
+        mgr.with_write_transaction(|writer| {
+            let ancestor = writer.get_ancestor(&id)?.unwrap();
+            assert!(ancestor.is_sealed());
+            assert!(!ancestor.properties().contains_key("password!"));
+            Ok(())
+        }).unwrap();
+
+        mgr.unlock("new").unwrap();
+        mgr.with_write_transaction(|writer| {
+            let ancestor = writer.get_ancestor(&id)?.unwrap();
+            assert_eq!(
+                ancestor.properties().get("password!"),
+                Some(&"PlainSecret42".to_string())
+            );
+
+            writer.put(&make_secret_entry(id.clone(), "svc"))?;
+            writer.remove(&id)?;
+            assert!(writer.get_ancestor(&id)?.is_none());
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// 破棄を指示したトランザクションの書き込みが反映されないこと
+    ///
+    #[test]
+    fn discarded_transaction_is_not_committed() {
+        let path = temp_db_path();
+        let mgr = EntryManager::open_for_test(&path).unwrap();
+        let id = ServiceId::new();
+
+        mgr.with_write_transaction(|writer| {
+            writer.put(&make_entry(id.clone(), "svc", &[], &["tag1"]))?;
+            writer.discard();
+            Ok(())
+        }).unwrap();
+
+        mgr.with_read_transaction(|reader| {
+            assert!(reader.get(&id)?.is_none());
+            assert_eq!(reader.change_seq()?, 0);
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// 上書きされたエントリが過去の版として記録され、削除と共に破棄されること
+    ///
+    #[test]
+    fn put_records_revisions() {
+        let path = temp_db_path();
+        let id = ServiceId::new();
+
+        {
+            let mut mgr = EntryManager::open_for_test(&path).unwrap();
+            mgr.with_write_transaction(|writer| {
+                writer.put(&make_secret_entry(id.clone(), "v1"))?;
+                writer.put(&make_secret_entry(id.clone(), "v2"))?;
+                writer.put(&make_secret_entry(id.clone(), "v3"))
+            }).unwrap();
+            mgr.rekey("new", KdfParams::for_test()).unwrap();
+        }
+
+        let mut mgr = EntryManager::open(&path).unwrap();
+        mgr.unlock("new").unwrap();
+
+        mgr.with_write_transaction(|writer| {
+            let revisions = writer.revisions(&id)?;
+            assert_eq!(
+                revisions.iter()
+                    .map(|(rev, entry)| (*rev, entry.service()))
+                    .collect::<Vec<_>>(),
+                vec![(1, "v1".to_string()), (2, "v2".to_string())]
+            );
+
+            // 再暗号化後も過去の版の秘匿プロパティを復号できる
+            assert_eq!(
+                revisions[0].1.properties().get("password!"),
+                Some(&"PlainSecret42".to_string())
+            );
+
+            writer.remove(&id)?;
+            assert!(writer.revisions(&id)?.is_empty());
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// 過去の版が上限を超えた場合に古いものから破棄されること
+    ///
+    #[test]
+    fn push_revision_trims_to_limit() {
+        let path = temp_db_path();
+        let mgr = EntryManager::open_for_test(&path).unwrap();
+        let id = ServiceId::new();
+        let other = ServiceId::new();
+
+        mgr.with_write_transaction(|writer| {
+            writer.put(&make_entry(other.clone(), "other", &[], &[]))?;
+            writer.put(&make_entry(other.clone(), "other2", &[], &[]))?;
+
+            for i in 0..(REVISION_LIMIT + 3) {
+                writer.put(&make_entry(id.clone(), &format!("v{}", i), &[], &[]))?;
+            }
+
+            let revs = writer.revisions(&id)?
+                .into_iter()
+                .map(|(rev, entry)| (rev, entry.service()))
+                .collect::<Vec<_>>();
+
+            assert_eq!(revs.len() as u64, REVISION_LIMIT);
+            assert_eq!(revs.first().unwrap(), &(3, "v2".to_string()));
+            assert_eq!(
+                revs.last().unwrap(),
+                &(REVISION_LIMIT + 2, format!("v{}", REVISION_LIMIT + 1))
+            );
+
+            // 他のエントリの過去の版には影響しない
+            assert_eq!(writer.revisions(&other)?.len(), 1);
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// 書き込みトランザクション毎に変更前の状態が記録され、取り出せること
+    /// を確認
+    ///
+    #[test]
+    fn journal_records_before_images() {
+        let path = temp_db_path();
+        let kept = ServiceId::new();
+        let added = ServiceId::new();
+
+        {
+            let mut mgr = EntryManager::open_for_test(&path).unwrap();
+            mgr.put(&make_secret_entry(kept.clone(), "before")).unwrap();
+
+            mgr.set_operation("test");
+            mgr.with_write_transaction(|writer| {
+                writer.put(&make_secret_entry(kept.clone(), "after"))?;
+                writer.put(&make_entry(added.clone(), "new", &[], &["t"]))?;
+                writer.remove(&kept)
+            }).unwrap();
+
+            // エントリを変更しないトランザクションは記録しない
+            mgr.with_write_transaction(|writer| writer.local_node_id()).unwrap();
+            mgr.rekey("new", KdfParams::for_test()).unwrap();
+        }
+
+        let mut mgr = EntryManager::open(&path).unwrap();
+        mgr.unlock("new").unwrap();
+
+        let records = mgr.with_read_transaction(|reader| reader.journal())
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].1.operation(), "test");
+
+        mgr.with_write_transaction(|writer| {
+            let record = writer.pop_journal()?.unwrap();
+            let entries = record.entries();
+            assert_eq!(entries.len(), 2);
+
+            // 最初に変更する前の状態が再暗号化後の鍵で復号できる
+            let (id, before) = entries.iter().find(|(id, _)| *id == kept).unwrap();
+            let before = open_entry(writer.key.as_ref(), before.clone().unwrap())?;
+            assert_eq!(id, &kept);
+            assert_eq!(before.service(), "before");
+            assert_eq!(
+                before.properties().get("password!"),
+                Some(&"PlainSecret42".to_string())
+            );
+            assert!(entries.iter().any(|(id, before)| *id == added && before.is_none()));
+
+            // 取り出し以降の変更は記録されない
+            writer.put(&make_secret_entry(kept.clone(), "restored"))
+        }).unwrap();
+
+        mgr.with_read_transaction(|reader| {
+            assert!(reader.journal()?.is_empty());
+            Ok(())
+        }).unwrap();
+    }
+
+    ///
+    /// 墓標化でバージョンベクタを引き継いで内容が破棄されること
+    ///
+    #[test]
+    fn bury_keeps_version_and_drops_content() {
+        let path = temp_db_path();
+        let mgr = EntryManager::open_for_test(&path).unwrap();
+        let id = ServiceId::new();
+
+        mgr.with_write_transaction(|writer| {
+            writer.put(&make_secret_entry(id.clone(), "svc"))?;
+            writer.put_ancestor(&make_secret_entry(id.clone(), "svc"))?;
+            let before = writer.get(&id)?.unwrap().version();
+
+            let tombstone = writer.bury(&id)?.unwrap();
+            assert!(tombstone.is_removed());
+            assert_eq!(tombstone.service(), "svc");
+            assert!(tombstone.properties().is_empty());
+            assert!(tombstone.tags().is_empty());
+
+            let stored = writer.get(&id)?.unwrap();
+            assert_eq!(stored.version().compare(&before), Causality::After);
+            assert_eq!(writer.entry_seq(&id)?, Some(writer.change_seq()?));
+            assert!(writer.get_ancestor(&id)?.is_none());
+            assert!(writer.revisions(&id)?.is_empty());
+            assert!(writer.tagged_services("tag1")?.is_empty());
+
+            // 存在しないエントリは何もしない
+            assert!(writer.bury(&ServiceId::new())?.is_none());
+            Ok(())
+        }).unwrap();
+    }
 }
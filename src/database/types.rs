@@ -14,11 +14,14 @@ use std::fmt::{Display, Formatter};
 use std::ops::{Deref, RangeInclusive};
 
 use chrono::{DateTime, Duration, Local};
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 use redb::{Key, TypeName, Value};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde::de;
 use ulid::{DecodeError, Ulid};
+use zeroize::Zeroizing;
+
+use crate::database::crypto::VaultKey;
 
 // ローカルモジュール: last_update の人間可読シリアライズ
 mod serde_human_datetime {
@@ -226,11 +229,113 @@ impl<'de> Deserialize<'de> for ServiceId {
     }
 }
 
+///
+/// バージョンベクタの比較結果
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Causality {
+    /// 同一の版
+    Equal,
+
+    /// 比較対象より前の版(比較対象が自身の変更を含む)
+    Before,
+
+    /// 比較対象より後の版(自身が比較対象の変更を含む)
+    After,
+
+    /// 互いに相手の変更を含まない(並行した変更)
+    Concurrent,
+}
+
+///
+/// エントリの版を表すバージョンベクタ
+///
+/// # 注記
+/// ノードID毎に、そのノードでエントリを書き込んだ回数を保持する。
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub(crate) struct VersionVector(BTreeMap<String, u64>);
+
+impl VersionVector {
+    ///
+    /// 空か否か(バージョンベクタ導入前のエントリ)
+    ///
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    ///
+    /// 指定ノードのカウンタの取得
+    ///
+    pub(crate) fn get(&self, node_id: &str) -> u64 {
+        self.0.get(node_id).copied().unwrap_or(0)
+    }
+
+    ///
+    /// 指定ノードのカウンタを進める
+    ///
+    pub(crate) fn increment(&mut self, node_id: &str) {
+        *self.0.entry(node_id.to_string()).or_insert(0) += 1;
+    }
+
+    ///
+    /// 他のバージョンベクタとの併合(ノード毎に大きい方を採る)
+    ///
+    pub(crate) fn merge(&mut self, other: &VersionVector) {
+        for (node_id, count) in &other.0 {
+            let entry = self.0.entry(node_id.clone()).or_insert(0);
+            *entry = (*entry).max(*count);
+        }
+    }
+
+    ///
+    /// 他のバージョンベクタとの前後関係の判定
+    ///
+    /// # 引数
+    /// * `other` - 比較対象
+    ///
+    /// # 戻り値
+    /// 比較対象から見た自身の位置を返す。
+    ///
+    pub(crate) fn compare(&self, other: &VersionVector) -> Causality {
+        let mut less = false;
+        let mut greater = false;
+
+        for node_id in self.0.keys().chain(other.0.keys()) {
+            let a = self.get(node_id);
+            let b = other.get(node_id);
+
+            less |= a < b;
+            greater |= a > b;
+        }
+
+        match (less, greater) {
+            (false, false) => Causality::Equal,
+            (true, false) => Causality::Before,
+            (false, true) => Causality::After,
+            (true, true) => Causality::Concurrent,
+        }
+    }
+}
+
+///
+/// 暗号化された秘匿プロパティ
+///
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct SealedProperties {
+    /// 秘匿プロパティのキーのリスト(マスク表示のため平文で保持する)
+    keys: Vec<String>,
+
+    /// エントリ毎のノンスを先頭に付与した秘匿プロパティの暗号文
+    data: Vec<u8>,
+}
+
 ///
 ///
 /// サービスエントリの定義
 ///
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(crate) struct Entry {
     /// サービスのID
     id: ServiceId,
@@ -247,6 +352,10 @@ pub(crate) struct Entry {
     /// エントリのプロパティ
     properties: BTreeMap<String, String>,
 
+    /// 秘匿プロパティが満たすべきパスワードポリシー
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    policy: Option<PasswordPolicy>,
+
     /// 最終更新日時（ローカル時間、ISO8601文字列でシリアライズ）
     #[serde(
         default,
@@ -259,6 +368,14 @@ pub(crate) struct Entry {
     /// ソフトリムーブフラグ
     #[serde(default, skip_serializing_if = "Option::is_none")]
     removed: Option<bool>,
+
+    /// 同期で変更の前後関係を判定するためのバージョンベクタ
+    #[serde(default, skip_serializing_if = "VersionVector::is_empty")]
+    version: VersionVector,
+
+    /// 暗号化された秘匿プロパティ
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sealed: Option<SealedProperties>,
 }
 
 impl Entry {
@@ -294,8 +411,11 @@ impl Entry {
             aliases,
             tags,
             properties,
+            policy: None,
             removed: None,
             last_update: Some(now_sec()),
+            version: VersionVector::default(),
+            sealed: None,
         }
     }
 
@@ -343,6 +463,31 @@ impl Entry {
         self.properties.clone()
     }
 
+    ///
+    /// プロパティの値の設定
+    ///
+    /// # 引数
+    /// * `key` - プロパティ名
+    /// * `value` - 設定する値
+    ///
+    pub(crate) fn set_property(&mut self, key: &str, value: String) {
+        self.properties.insert(key.to_string(), value);
+    }
+
+    ///
+    /// パスワードポリシーへのアクセサ
+    ///
+    pub(crate) fn policy(&self) -> Option<PasswordPolicy> {
+        self.policy.clone()
+    }
+
+    ///
+    /// パスワードポリシーの設定
+    ///
+    pub(crate) fn set_policy(&mut self, policy: Option<PasswordPolicy>) {
+        self.policy = policy;
+    }
+
     ///
     /// ソフトリムーブフラグへのアクセサ
     ///
@@ -372,6 +517,32 @@ impl Entry {
         self.last_update = Some(dt);
     }
 
+    ///
+    /// バージョンベクタへのアクセサ
+    ///
+    pub(crate) fn version(&self) -> VersionVector {
+        self.version.clone()
+    }
+
+    ///
+    /// バージョンベクタの設定
+    ///
+    pub(crate) fn set_version(&mut self, version: VersionVector) {
+        self.version = version;
+    }
+
+    ///
+    /// ローカルでの書き込みとしてバージョンを進める
+    ///
+    /// # 引数
+    /// * `base` - 書き込み前に格納されていたエントリのバージョンベクタ
+    /// * `node_id` - 書き込みを行うノードのID
+    ///
+    pub(crate) fn bump_version(&mut self, base: &VersionVector, node_id: &str) {
+        self.version.merge(base);
+        self.version.increment(node_id);
+    }
+
     ///
     /// ソフトリムーブフラグを設定
     ///
@@ -382,13 +553,102 @@ impl Entry {
     ///
     /// 秘匿項目をマスク表示用に上書きする
     ///
+    /// # 注記
+    /// 秘匿プロパティが暗号化されている場合は、暗号文を破棄してキーのみをマ
+    /// スクした値で展開する。
+    ///
     pub(crate) fn mask_secret_properties(&mut self) {
+        if let Some(sealed) = self.sealed.take() {
+            for key in sealed.keys {
+                self.properties.insert(key, String::new());
+            }
+        }
+
         for (key, value) in self.properties.iter_mut() {
             if key.ends_with('!') {
                 *value = "<< SECRET >>".to_string();
             }
         }
     }
+
+    ///
+    /// 秘匿プロパティが暗号化された状態か否か
+    ///
+    #[allow(dead_code)]
+    pub(crate) fn is_sealed(&self) -> bool {
+        self.sealed.is_some()
+    }
+
+    ///
+    /// 平文の秘匿プロパティを保持しているか否か
+    ///
+    pub(crate) fn has_plain_secrets(&self) -> bool {
+        self.properties.keys().any(|key| key.ends_with('!'))
+    }
+
+    ///
+    /// 秘匿プロパティの暗号化
+    ///
+    /// # 引数
+    /// * `key` - 暗号鍵
+    ///
+    /// # 戻り値
+    /// 暗号化に成功した場合は`Ok(())`を返す。
+    ///
+    /// # 注記
+    /// キーが`!`で終わるプロパティをまとめて暗号化し、平文のプロパティからは
+    /// 取り除く。既に暗号化済みの秘匿プロパティがある場合は一旦復号してから
+    /// 暗号化し直す(ノンスはその都度新たに生成される)。暗号文を他のエントリ
+    /// に移し替えて復号できないよう、サービスIDを付加データとして暗号化する。
+    ///
+    pub(crate) fn seal_secret_properties(&mut self, key: &VaultKey)
+        -> Result<()>
+    {
+        self.unseal_secret_properties(key)?;
+
+        let (secrets, plain): (BTreeMap<_, _>, BTreeMap<_, _>) =
+            std::mem::take(&mut self.properties)
+                .into_iter()
+                .partition(|(key, _)| key.ends_with('!'));
+
+        self.properties = plain;
+
+        if !secrets.is_empty() {
+            self.sealed = Some(SealedProperties {
+                keys: secrets.keys().cloned().collect(),
+                data: key.seal(
+                    &rmp_serde::to_vec(&secrets)?,
+                    self.id.to_string().as_bytes()
+                )?,
+            });
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// 秘匿プロパティの復号
+    ///
+    /// # 引数
+    /// * `key` - 暗号鍵
+    ///
+    /// # 戻り値
+    /// 復号に成功した場合は`Ok(())`を返す。復号した秘匿プロパティは平文のプ
+    /// ロパティに戻される。
+    ///
+    pub(crate) fn unseal_secret_properties(&mut self, key: &VaultKey)
+        -> Result<()>
+    {
+        if let Some(sealed) = self.sealed.take() {
+            let plain = key.open(&sealed.data, self.id.to_string().as_bytes())?;
+            let secrets: BTreeMap<String, String> =
+                rmp_serde::from_slice(&plain)?;
+
+            self.properties.extend(secrets);
+        }
+
+        Ok(())
+    }
 }
 
 // Valueトレイトの実装
@@ -421,110 +681,1090 @@ impl Value for Entry {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+///
+/// パスワードの文字種
+///
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CharClass {
+    /// 英小文字
+    Lower,
+
+    /// 英大文字
+    Upper,
+
+    /// 数字
+    Digit,
+
+    /// 記号
+    #[serde(rename = "sym")]
+    Symbol,
+}
 
-    #[test]
-    fn service_id_roundtrip_string() {
-        let id = ServiceId::new();
-        let s = id.to_string();
-        let back = ServiceId::from_string(&s).unwrap();
-        assert_eq!(id, back);
+impl CharClass {
+    /// 記号として扱う文字
+    pub(crate) const SYMBOLS: &str = "!#$%&()*+-./:;<=>?@[]^_{}~";
+
+    ///
+    /// 文字種に属する文字の列挙
+    ///
+    pub(crate) fn chars(&self) -> Vec<char> {
+        match self {
+            Self::Lower => ('a'..='z').collect(),
+            Self::Upper => ('A'..='Z').collect(),
+            Self::Digit => ('0'..='9').collect(),
+            Self::Symbol => Self::SYMBOLS.chars().collect(),
+        }
     }
 
     ///
-    /// min < new() < max の順序性をざっくり確認
+    /// 文字が文字種に属するか否か
     ///
-    #[test]
-    fn service_id_ordering() {
-        let min = ServiceId::min();
-        let max = ServiceId::max();
-        let mid = ServiceId::new();
-        assert!(min < mid && mid < max);
+    /// # 注記
+    /// 記号は英数字以外の全ての文字を含むものとして判定する。
+    ///
+    pub(crate) fn contains(&self, ch: char) -> bool {
+        match self {
+            Self::Lower => ch.is_ascii_lowercase(),
+            Self::Upper => ch.is_ascii_uppercase(),
+            Self::Digit => ch.is_ascii_digit(),
+            Self::Symbol => !ch.is_ascii_alphanumeric() && !ch.is_whitespace(),
+        }
     }
 
     ///
-    /// Entry::new が別名/タグをソート＋重複排除することを確認
+    /// 文字種の指定の解釈
     ///
-    #[test]
-    fn entry_new_dedup_sorts() {
-        let id = ServiceId::new();
-        let entry = Entry::new(
-            id.clone(),
-            "svc".to_string(),
-            vec!["b".into(), "a".into(), "a".into()],
-            vec!["tag2".into(), "tag1".into(), "tag1".into()],
-            BTreeMap::new(),
-        );
+    /// # 引数
+    /// * `spec` - `lower`/`upper`/`digit`/`sym`/`alpha`/`alnum`/`all`を`+`
+    ///   で連結した文字列
+    ///
+    /// # 戻り値
+    /// 指定された文字種のリスト(重複なし)を`Ok()`でラップして返す。
+    ///
+    pub(crate) fn parse(spec: &str) -> Result<Vec<Self>> {
+        let mut classes = Vec::new();
+
+        for name in spec.split('+').map(str::trim) {
+            let expanded: &[Self] = match name {
+                "lower" => &[Self::Lower],
+                "upper" => &[Self::Upper],
+                "digit" => &[Self::Digit],
+                "sym" => &[Self::Symbol],
+                "alpha" => &[Self::Lower, Self::Upper],
+                "alnum" => &[Self::Lower, Self::Upper, Self::Digit],
+                "all" => &[Self::Lower, Self::Upper, Self::Digit, Self::Symbol],
+                _ => return Err(anyhow!("不明な文字種です: {}", name)),
+            };
+
+            classes.extend_from_slice(expanded);
+        }
 
-        // aliases/tags がソート済みかつ重複除去されていること
-        assert_eq!(entry.id(), id);
-        assert_eq!(entry.service(), "svc".to_string());
-        assert_eq!(entry.aliases(), vec!["a".to_string(), "b".to_string()]);
-        assert_eq!(entry.tags(), vec!["tag1".to_string(), "tag2".to_string()]);
+        classes.sort();
+        classes.dedup();
+
+        Ok(classes)
+    }
+}
+
+impl Display for CharClass {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Self::Lower => write!(f, "英小文字"),
+            Self::Upper => write!(f, "英大文字"),
+            Self::Digit => write!(f, "数字"),
+            Self::Symbol => write!(f, "記号"),
+        }
     }
+}
+
+///
+/// エントリの秘匿プロパティが満たすべきパスワードポリシー
+///
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub(crate) struct PasswordPolicy {
+    /// 最小文字数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_length: Option<usize>,
+
+    /// 最大文字数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_length: Option<usize>,
+
+    /// 最低1文字を含める必要のある文字種
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    require: Vec<CharClass>,
+
+    /// 使用できない文字
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    forbid: String,
+}
 
+impl PasswordPolicy {
     ///
-    /// 各アクセサがクローンを返すことを確認（ミュータブル参照でない）
+    /// 最小文字数へのアクセサ
     ///
-    #[test]
-    fn entry_accessors_clone() {
-        let id = ServiceId::new();
-        let mut props = BTreeMap::new();
-        props.insert("k".to_string(), "v".to_string());
-        let entry = Entry::new(
-            id.clone(),
-            "svc".to_string(),
-            vec!["a".into()],
-            vec!["tag".into()],
-            props.clone(),
-        );
+    pub(crate) fn min_length(&self) -> Option<usize> {
+        self.min_length
+    }
 
-        // 値が一致していること（同一オブジェクト参照ではなくクローンが返る前提）
-        assert_eq!(entry.id(), id);
-        assert_eq!(entry.service(), "svc".to_string());
-        assert_eq!(entry.aliases(), vec!["a".to_string()]);
-        assert_eq!(entry.tags(), vec!["tag".to_string()]);
-        assert_eq!(entry.properties(), props);
+    ///
+    /// 最大文字数へのアクセサ
+    ///
+    pub(crate) fn max_length(&self) -> Option<usize> {
+        self.max_length
     }
 
     ///
-    /// mask_secret_properties が秘匿項目の値を隠蔽することを確認
+    /// 必須の文字種へのアクセサ
     ///
-    #[test]
-    fn entry_mask_secret_properties_masks_values() {
-        let id = ServiceId::new();
-        let mut props = BTreeMap::new();
-        props.insert("user".to_string(), "alice".to_string());
-        props.insert("password!".to_string(), "secret".to_string());
+    pub(crate) fn require(&self) -> Vec<CharClass> {
+        self.require.clone()
+    }
 
-        let mut entry = Entry::new(
-            id.clone(),
-            "svc".to_string(),
-            vec!["a".into()],
-            vec!["tag".into()],
-            props,
-        );
+    ///
+    /// 使用できない文字へのアクセサ
+    ///
+    pub(crate) fn forbid(&self) -> String {
+        self.forbid.clone()
+    }
 
-        entry.mask_secret_properties();
+    ///
+    /// ポリシー自体の整合性の確認
+    ///
+    /// # 戻り値
+    /// 満たすことのできるポリシーの場合は`Ok(())`を返す。
+    ///
+    pub(crate) fn validate(&self) -> Result<()> {
+        match (self.min_length, self.max_length) {
+            (Some(min), Some(max)) if min > max => return Err(anyhow!(
+                "最小文字数({})が最大文字数({})を超えています", min, max
+            )),
+            _ => {}
+        }
 
-        let properties = entry.properties();
-        assert_eq!(properties.get("user"), Some(&"alice".to_string()));
-        assert_eq!(
-            properties.get("password!"),
-            Some(&"<< SECRET >>".to_string()
-        ));
+        let classes = self.require.len();
+        if let Some(max) = self.max_length.filter(|max| *max < classes) {
+            return Err(anyhow!(
+                "最大文字数({})が必須の文字種の数より少なくなっています", max
+            ));
+        }
+
+        for class in &self.require {
+            if class.chars().iter().all(|ch| self.forbid.contains(*ch)) {
+                return Err(anyhow!(
+                    "{}が全て使用できない文字となっています", class
+                ));
+            }
+        }
+
+        Ok(())
     }
 
     ///
-    /// Value実装のバイト往復が同一IDを再現すること
+    /// パスワードのポリシーへの適合の確認
     ///
-    #[test]
-    fn service_id_value_bytes_roundtrip() {
-        let id = ServiceId::new();
-        let bytes = ServiceId::as_bytes(&id);
-        let back = ServiceId::from_bytes(&bytes);
-        assert_eq!(id, back);
+    /// # 引数
+    /// * `password` - 確認するパスワード
+    ///
+    /// # 戻り値
+    /// 違反している項目の説明のリスト(適合している場合は空)を返す。
+    ///
+    pub(crate) fn violations(&self, password: &str) -> Vec<String> {
+        let mut violations = Vec::new();
+        let len = password.chars().count();
+
+        if let Some(min) = self.min_length.filter(|min| len < *min) {
+            violations.push(format!("{}文字以上が必要です", min));
+        }
+
+        if let Some(max) = self.max_length.filter(|max| len > *max) {
+            violations.push(format!("{}文字以下にしてください", max));
+        }
+
+        for class in &self.require {
+            if !password.chars().any(|ch| class.contains(ch)) {
+                violations.push(format!("{}を含める必要があります", class));
+            }
+        }
+
+        let forbidden = password.chars()
+            .filter(|ch| self.forbid.contains(*ch))
+            .collect::<String>();
+        if !forbidden.is_empty() {
+            violations.push(
+                format!("使用できない文字が含まれています: {}", forbidden)
+            );
+        }
+
+        violations
+    }
+}
+
+///
+/// 鍵導出(Argon2id)のパラメータ
+///
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) struct KdfParams {
+    /// メモリコスト(KiB)
+    m_cost: u32,
+
+    /// 反復回数
+    t_cost: u32,
+
+    /// 並列度
+    p_cost: u32,
+}
+
+impl KdfParams {
+    ///
+    /// メモリコストへのアクセサ
+    ///
+    pub(crate) fn m_cost(&self) -> u32 {
+        self.m_cost
+    }
+
+    ///
+    /// 反復回数へのアクセサ
+    ///
+    pub(crate) fn t_cost(&self) -> u32 {
+        self.t_cost
+    }
+
+    ///
+    /// 並列度へのアクセサ
+    ///
+    pub(crate) fn p_cost(&self) -> u32 {
+        self.p_cost
+    }
+
+    ///
+    /// テスト用の低コストなパラメータ
+    ///
+    #[cfg(test)]
+    pub(crate) fn for_test() -> Self {
+        Self {
+            m_cost: 8,
+            t_cost: 1,
+            p_cost: 1,
+        }
+    }
+}
+
+// Defaultトレイトの実装
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+        }
+    }
+}
+
+///
+/// 暗号化されたデータベースのヘッダ情報
+///
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct VaultHeader {
+    /// フォーマットのバージョン
+    version: u16,
+
+    /// 鍵導出パラメータ
+    kdf: KdfParams,
+
+    /// 鍵導出に用いるソルト
+    salt: Vec<u8>,
+
+    /// パスフレーズ検証用の暗号文
+    verifier: Vec<u8>,
+}
+
+impl VaultHeader {
+    /// 現在のフォーマットバージョン
+    pub(crate) const VERSION: u16 = 3;
+
+    ///
+    /// ヘッダ情報の生成
+    ///
+    /// # 引数
+    /// * `kdf` - 鍵導出パラメータ
+    /// * `salt` - 鍵導出に用いたソルト
+    /// * `verifier` - パスフレーズ検証用の暗号文
+    ///
+    pub(crate) fn new(kdf: KdfParams, salt: Vec<u8>, verifier: Vec<u8>)
+        -> Self
+    {
+        Self {
+            version: Self::VERSION,
+            kdf,
+            salt,
+            verifier,
+        }
+    }
+
+    ///
+    /// フォーマットバージョンへのアクセサ
+    ///
+    pub(crate) fn version(&self) -> u16 {
+        self.version
+    }
+
+    ///
+    /// 鍵導出パラメータへのアクセサ
+    ///
+    pub(crate) fn kdf(&self) -> &KdfParams {
+        &self.kdf
+    }
+
+    ///
+    /// ソルトへのアクセサ
+    ///
+    pub(crate) fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    ///
+    /// パスフレーズ検証用暗号文へのアクセサ
+    ///
+    pub(crate) fn verifier(&self) -> &[u8] {
+        &self.verifier
+    }
+}
+
+// Valueトレイトの実装
+impl Value for VaultHeader {
+    type SelfType<'a> = VaultHeader;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new("VaultHeader")
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a
+    {
+        rmp_serde::from_slice::<VaultHeader>(data)
+            .expect("invalid MessagePack packed bytes")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'b
+    {
+        rmp_serde::to_vec_named(value)
+            .expect("failed to serialize to MessagePack bytes")
+    }
+}
+
+///
+/// 同期に用いる自ノードの識別情報
+///
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct NodeIdentity {
+    /// ノードID
+    node_id: String,
+
+    /// 静的公開鍵
+    public_key: Vec<u8>,
+
+    /// 暗号化した静的秘密鍵
+    sealed_private_key: Vec<u8>,
+}
+
+impl NodeIdentity {
+    ///
+    /// 識別情報の生成
+    ///
+    /// # 引数
+    /// * `node_id` - ノードID
+    /// * `public_key` - 静的公開鍵
+    /// * `private_key` - 静的秘密鍵
+    /// * `key` - 秘密鍵の暗号化に用いる暗号鍵
+    ///
+    /// # 戻り値
+    /// 秘密鍵を暗号化した識別情報を`Ok()`でラップして返す。
+    ///
+    pub(crate) fn new(
+        node_id: String,
+        public_key: Vec<u8>,
+        private_key: &[u8],
+        key: &VaultKey,
+    ) -> Result<Self> {
+        let sealed_private_key = key.seal(private_key, node_id.as_bytes())?;
+
+        Ok(Self {
+            node_id,
+            public_key,
+            sealed_private_key,
+        })
+    }
+
+    ///
+    /// ノードIDへのアクセサ
+    ///
+    pub(crate) fn node_id(&self) -> String {
+        self.node_id.clone()
+    }
+
+    ///
+    /// 静的公開鍵へのアクセサ
+    ///
+    pub(crate) fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    ///
+    /// 静的秘密鍵の復号
+    ///
+    /// # 引数
+    /// * `key` - 暗号鍵
+    ///
+    /// # 戻り値
+    /// 復号した秘密鍵を`Ok()`でラップして返す。
+    ///
+    pub(crate) fn private_key(&self, key: &VaultKey)
+        -> Result<Zeroizing<Vec<u8>>>
+    {
+        Ok(Zeroizing::new(
+            key.open(&self.sealed_private_key, self.node_id.as_bytes())?
+        ))
+    }
+
+    ///
+    /// 静的秘密鍵の再暗号化
+    ///
+    /// # 引数
+    /// * `old_key` - 現在の暗号鍵
+    /// * `new_key` - 新しい暗号鍵
+    ///
+    pub(crate) fn reseal(&mut self, old_key: &VaultKey, new_key: &VaultKey)
+        -> Result<()>
+    {
+        let private_key = self.private_key(old_key)?;
+        self.sealed_private_key =
+            new_key.seal(&private_key, self.node_id.as_bytes())?;
+        Ok(())
+    }
+}
+
+// Valueトレイトの実装
+impl Value for NodeIdentity {
+    type SelfType<'a> = NodeIdentity;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new("NodeIdentity")
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a
+    {
+        rmp_serde::from_slice::<NodeIdentity>(data)
+            .expect("invalid MessagePack packed bytes")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'b
+    {
+        rmp_serde::to_vec_named(value)
+            .expect("failed to serialize to MessagePack bytes")
+    }
+}
+
+///
+/// ペアリング済みの同期相手の情報
+///
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct TrustedPeer {
+    /// 相手のノードID
+    node_id: String,
+
+    /// 相手の静的公開鍵
+    public_key: Vec<u8>,
+
+    /// ペアリング時の相手のアドレス
+    address: String,
+
+    /// 相手を指定する際に用いるラベル
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+
+    /// ペアリング日時
+    paired_at: DateTime<Local>,
+
+    /// 最後に同期が完了した時点の同期位置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    watermark: Option<SyncWatermark>,
+
+    /// 相手が受信済みであることを確認できた自身の変更シーケンス番号
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    acked_seq: Option<u64>,
+}
+
+impl TrustedPeer {
+    ///
+    /// 同期相手情報の生成
+    ///
+    /// # 引数
+    /// * `node_id` - 相手のノードID
+    /// * `public_key` - 相手の静的公開鍵
+    /// * `address` - ペアリング時の相手のアドレス
+    ///
+    pub(crate) fn new(node_id: String, public_key: Vec<u8>, address: String)
+        -> Self
+    {
+        Self {
+            node_id,
+            public_key,
+            address,
+            label: None,
+            paired_at: Local::now(),
+            watermark: None,
+            acked_seq: None,
+        }
+    }
+
+    ///
+    /// ノードIDへのアクセサ
+    ///
+    pub(crate) fn node_id(&self) -> String {
+        self.node_id.clone()
+    }
+
+    ///
+    /// 静的公開鍵へのアクセサ
+    ///
+    pub(crate) fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    ///
+    /// ペアリング時のアドレスへのアクセサ
+    ///
+    pub(crate) fn address(&self) -> String {
+        self.address.clone()
+    }
+
+    ///
+    /// ラベルへのアクセサ
+    ///
+    /// # 戻り値
+    /// ラベルが設定されていない場合は`None`を返す。
+    ///
+    pub(crate) fn label(&self) -> Option<String> {
+        self.label.clone()
+    }
+
+    ///
+    /// ラベルの設定
+    ///
+    pub(crate) fn set_label(&mut self, label: Option<String>) {
+        self.label = label;
+    }
+
+    ///
+    /// ペアリング日時へのアクセサ
+    ///
+    pub(crate) fn paired_at(&self) -> DateTime<Local> {
+        self.paired_at
+    }
+
+    ///
+    /// 相手のアドレスの更新
+    ///
+    pub(crate) fn set_address(&mut self, address: String) {
+        self.address = address;
+    }
+
+    ///
+    /// 同期位置へのアクセサ
+    ///
+    /// # 戻り値
+    /// 同期が完了したことが無い場合は`None`を返す。
+    ///
+    pub(crate) fn watermark(&self) -> Option<SyncWatermark> {
+        self.watermark.clone()
+    }
+
+    ///
+    /// 同期位置の更新
+    ///
+    pub(crate) fn set_watermark(&mut self, watermark: SyncWatermark) {
+        self.watermark = Some(watermark);
+    }
+
+    ///
+    /// 相手が受信済みの自身の変更シーケンス番号へのアクセサ
+    ///
+    /// # 戻り値
+    /// 確認できていない場合は`None`を返す。
+    ///
+    /// # 注記
+    /// 受信の確認を記録する前にクライアントとして同期した相手については、同
+    /// 期位置の送信済みの変更シーケンス番号を用いる。
+    ///
+    pub(crate) fn acked_seq(&self) -> Option<u64> {
+        self.acked_seq
+            .or_else(|| self.watermark.as_ref().map(|mark| mark.local_seq()))
+    }
+
+    ///
+    /// 相手が受信済みの自身の変更シーケンス番号の更新
+    ///
+    /// # 注記
+    /// 古い同期ファイル等によって確認済みの位置が戻らないよう、記録済みの値
+    /// より小さい場合は更新しない。
+    ///
+    pub(crate) fn set_acked_seq(&mut self, seq: u64) {
+        self.acked_seq = Some(self.acked_seq.map_or(seq, |acked| acked.max(seq)));
+    }
+}
+
+///
+/// 同期相手毎の同期位置(差分同期の起点)
+///
+/// # 注記
+/// 変更シーケンス番号はデータベース毎に独立しているため、相手側と自身の双方
+/// の値を保持する。
+///
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct SyncWatermark {
+    /// 相手側の変更シーケンス番号(ここまでの変更は受信済み)
+    remote_seq: u64,
+
+    /// 自身の変更シーケンス番号(ここまでの変更は送信済み)
+    local_seq: u64,
+
+    /// 同期の完了日時
+    synced_at: DateTime<Local>,
+}
+
+impl SyncWatermark {
+    ///
+    /// 同期位置の生成
+    ///
+    /// # 引数
+    /// * `remote_seq` - 相手側の変更シーケンス番号
+    /// * `local_seq` - 自身の変更シーケンス番号
+    ///
+    pub(crate) fn new(remote_seq: u64, local_seq: u64) -> Self {
+        Self {
+            remote_seq,
+            local_seq,
+            synced_at: Local::now(),
+        }
+    }
+
+    ///
+    /// 相手側の変更シーケンス番号へのアクセサ
+    ///
+    pub(crate) fn remote_seq(&self) -> u64 {
+        self.remote_seq
+    }
+
+    ///
+    /// 自身の変更シーケンス番号へのアクセサ
+    ///
+    pub(crate) fn local_seq(&self) -> u64 {
+        self.local_seq
+    }
+
+    ///
+    /// 同期の完了日時へのアクセサ
+    ///
+    pub(crate) fn synced_at(&self) -> DateTime<Local> {
+        self.synced_at
+    }
+}
+
+// Valueトレイトの実装
+impl Value for TrustedPeer {
+    type SelfType<'a> = TrustedPeer;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new("TrustedPeer")
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a
+    {
+        rmp_serde::from_slice::<TrustedPeer>(data)
+            .expect("invalid MessagePack packed bytes")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'b
+    {
+        rmp_serde::to_vec_named(value)
+            .expect("failed to serialize to MessagePack bytes")
+    }
+}
+
+///
+/// 操作ジャーナルの記録
+///
+/// # 注記
+/// 書き込みトランザクション毎に、変更したエントリの変更前の状態を保持する。
+/// エントリは格納されていた状態(秘匿プロパティは暗号化されたまま)で保持し、
+/// 操作の前に存在しなかったエントリは`None`とする。
+///
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct JournalRecord {
+    /// 操作名(サブコマンド名)
+    operation: String,
+
+    /// 操作日時
+    timestamp: DateTime<Local>,
+
+    /// 変更前のエントリ
+    entries: Vec<(ServiceId, Option<Entry>)>,
+}
+
+impl JournalRecord {
+    ///
+    /// 記録の生成
+    ///
+    /// # 引数
+    /// * `operation` - 操作名
+    /// * `entries` - 変更したエントリのIDと変更前のエントリの組
+    ///
+    pub(crate) fn new(
+        operation: String,
+        entries: Vec<(ServiceId, Option<Entry>)>,
+    ) -> Self {
+        Self {
+            operation,
+            timestamp: now_sec(),
+            entries,
+        }
+    }
+
+    ///
+    /// 操作名へのアクセサ
+    ///
+    pub(crate) fn operation(&self) -> String {
+        self.operation.clone()
+    }
+
+    ///
+    /// 操作日時へのアクセサ
+    ///
+    pub(crate) fn timestamp(&self) -> DateTime<Local> {
+        self.timestamp
+    }
+
+    ///
+    /// 変更前のエントリへのアクセサ
+    ///
+    pub(crate) fn entries(&self) -> &[(ServiceId, Option<Entry>)] {
+        &self.entries
+    }
+
+    ///
+    /// 変更前のエントリへのミュータブルなアクセサ
+    ///
+    pub(crate) fn entries_mut(&mut self) -> &mut [(ServiceId, Option<Entry>)] {
+        &mut self.entries
+    }
+}
+
+// Valueトレイトの実装
+impl Value for JournalRecord {
+    type SelfType<'a> = JournalRecord;
+    type AsBytes<'a> = Vec<u8>;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn type_name() -> TypeName {
+        TypeName::new("JournalRecord")
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self::SelfType<'a>
+    where
+        Self: 'a
+    {
+        rmp_serde::from_slice::<JournalRecord>(data)
+            .expect("invalid MessagePack packed bytes")
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'b
+    {
+        rmp_serde::to_vec_named(value)
+            .expect("failed to serialize to MessagePack bytes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_id_roundtrip_string() {
+        let id = ServiceId::new();
+        let s = id.to_string();
+        let back = ServiceId::from_string(&s).unwrap();
+        assert_eq!(id, back);
+    }
+
+    ///
+    /// min < new() < max の順序性をざっくり確認
+    ///
+    #[test]
+    fn service_id_ordering() {
+        let min = ServiceId::min();
+        let max = ServiceId::max();
+        let mid = ServiceId::new();
+        assert!(min < mid && mid < max);
+    }
+
+    ///
+    /// Entry::new が別名/タグをソート＋重複排除することを確認
+    ///
+    #[test]
+    fn entry_new_dedup_sorts() {
+        let id = ServiceId::new();
+        let entry = Entry::new(
+            id.clone(),
+            "svc".to_string(),
+            vec!["b".into(), "a".into(), "a".into()],
+            vec!["tag2".into(), "tag1".into(), "tag1".into()],
+            BTreeMap::new(),
+        );
+
+        // aliases/tags がソート済みかつ重複除去されていること
+        assert_eq!(entry.id(), id);
+        assert_eq!(entry.service(), "svc".to_string());
+        assert_eq!(entry.aliases(), vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(entry.tags(), vec!["tag1".to_string(), "tag2".to_string()]);
+    }
+
+    ///
+    /// 各アクセサがクローンを返すことを確認（ミュータブル参照でない）
+    ///
+    #[test]
+    fn entry_accessors_clone() {
+        let id = ServiceId::new();
+        let mut props = BTreeMap::new();
+        props.insert("k".to_string(), "v".to_string());
+        let entry = Entry::new(
+            id.clone(),
+            "svc".to_string(),
+            vec!["a".into()],
+            vec!["tag".into()],
+            props.clone(),
+        );
+
+        // 値が一致していること（同一オブジェクト参照ではなくクローンが返る前提）
+        assert_eq!(entry.id(), id);
+        assert_eq!(entry.service(), "svc".to_string());
+        assert_eq!(entry.aliases(), vec!["a".to_string()]);
+        assert_eq!(entry.tags(), vec!["tag".to_string()]);
+        assert_eq!(entry.properties(), props);
+    }
+
+    ///
+    /// mask_secret_properties が秘匿項目の値を隠蔽することを確認
+    ///
+    #[test]
+    fn entry_mask_secret_properties_masks_values() {
+        let id = ServiceId::new();
+        let mut props = BTreeMap::new();
+        props.insert("user".to_string(), "alice".to_string());
+        props.insert("password!".to_string(), "secret".to_string());
+
+        let mut entry = Entry::new(
+            id.clone(),
+            "svc".to_string(),
+            vec!["a".into()],
+            vec!["tag".into()],
+            props,
+        );
+
+        entry.mask_secret_properties();
+
+        let properties = entry.properties();
+        assert_eq!(properties.get("user"), Some(&"alice".to_string()));
+        assert_eq!(
+            properties.get("password!"),
+            Some(&"<< SECRET >>".to_string()
+        ));
+    }
+
+    ///
+    /// 秘匿プロパティの暗号化→復号で元のプロパティに戻ることを確認
+    ///
+    #[test]
+    fn entry_seal_unseal_secret_properties() {
+        let (_, key) = crate::database::crypto::create_header(
+            "pass",
+            KdfParams::for_test()
+        ).unwrap();
+
+        let mut props = BTreeMap::new();
+        props.insert("user".to_string(), "alice".to_string());
+        props.insert("password!".to_string(), "secret".to_string());
+
+        let mut entry = Entry::new(
+            ServiceId::new(),
+            "svc".to_string(),
+            vec![],
+            vec![],
+            props.clone(),
+        );
+
+        entry.seal_secret_properties(&key).unwrap();
+        assert!(entry.is_sealed());
+        assert!(!entry.has_plain_secrets());
+        assert_eq!(entry.properties().len(), 1);
+
+        entry.unseal_secret_properties(&key).unwrap();
+        assert!(!entry.is_sealed());
+        assert_eq!(entry.properties(), props);
+    }
+
+    ///
+    /// 暗号文を他のエントリに移し替えた場合は復号できないことを確認
+    ///
+    #[test]
+    fn entry_unseal_rejects_swapped_sealed_data() {
+        let (_, key) = crate::database::crypto::create_header(
+            "pass",
+            KdfParams::for_test()
+        ).unwrap();
+
+        let mut entry = Entry::new(
+            ServiceId::new(),
+            "svc".to_string(),
+            vec![],
+            vec![],
+            BTreeMap::from([("password!".to_string(), "secret".to_string())]),
+        );
+        entry.seal_secret_properties(&key).unwrap();
+
+        let mut other = Entry::new(
+            ServiceId::new(),
+            "other".to_string(),
+            vec![],
+            vec![],
+            BTreeMap::new(),
+        );
+        other.sealed = entry.sealed.clone();
+
+        assert!(other.unseal_secret_properties(&key).is_err());
+    }
+
+    ///
+    /// 暗号化済みの秘匿項目もキー名を残してマスクされることを確認
+    ///
+    #[test]
+    fn entry_mask_sealed_secret_properties() {
+        let (_, key) = crate::database::crypto::create_header(
+            "pass",
+            KdfParams::for_test()
+        ).unwrap();
+
+        let mut entry = Entry::new(
+            ServiceId::new(),
+            "svc".to_string(),
+            vec![],
+            vec![],
+            BTreeMap::from([("password!".to_string(), "secret".to_string())]),
+        );
+
+        entry.seal_secret_properties(&key).unwrap();
+        entry.mask_secret_properties();
+
+        assert!(!entry.is_sealed());
+        assert_eq!(
+            entry.properties().get("password!"),
+            Some(&"<< SECRET >>".to_string())
+        );
+    }
+
+    ///
+    /// Value実装のバイト往復が同一IDを再現すること
+    ///
+    #[test]
+    fn service_id_value_bytes_roundtrip() {
+        let id = ServiceId::new();
+        let bytes = ServiceId::as_bytes(&id);
+        let back = ServiceId::from_bytes(&bytes);
+        assert_eq!(id, back);
+    }
+
+    ///
+    /// バージョンベクタの前後関係と併合を確認
+    ///
+    #[test]
+    fn version_vector_causality() {
+        let base = VersionVector::default();
+
+        let mut a = base.clone();
+        a.increment("node-a");
+
+        let mut b = a.clone();
+        b.increment("node-b");
+
+        let mut c = a.clone();
+        c.increment("node-a");
+
+        assert_eq!(base.compare(&base), Causality::Equal);
+        assert_eq!(base.compare(&a), Causality::Before);
+        assert_eq!(b.compare(&a), Causality::After);
+        assert_eq!(b.compare(&c), Causality::Concurrent);
+
+        // 併合した版は双方の後になる
+        let mut merged = b.clone();
+        merged.merge(&c);
+        assert_eq!(merged.get("node-a"), 2);
+        assert_eq!(merged.get("node-b"), 1);
+        assert_eq!(merged.compare(&b), Causality::After);
+        assert_eq!(merged.compare(&c), Causality::After);
+    }
+
+    ///
+    /// YAMLで記述したパスワードポリシーの解釈と違反の検出を確認
+    ///
+    #[test]
+    fn password_policy_reports_violations() {
+        let policy: PasswordPolicy = serde_yaml_ng::from_str(
+            "min_length: 8\nmax_length: 12\nrequire: [upper, sym]\nforbid: \"#\"\n"
+        ).unwrap();
+        assert!(policy.validate().is_ok());
+        assert_eq!(policy.require(), vec![CharClass::Upper, CharClass::Symbol]);
+
+        assert!(policy.violations("Passw0rd!").is_empty());
+        assert_eq!(policy.violations("pass#").len(), 3);
+        assert_eq!(policy.violations("Password!1234").len(), 1);
+
+        let invalid: PasswordPolicy = serde_yaml_ng::from_str(
+            "min_length: 10\nmax_length: 8\n"
+        ).unwrap();
+        assert!(invalid.validate().is_err());
     }
 }